@@ -0,0 +1,26 @@
+/* The C functions of prettythanks, built with `--features capi` as a cdylib. */
+
+#ifndef PRETTYTHANKS_H
+#define PRETTYTHANKS_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Format the Rust source file `src`, a NUL terminated UTF-8 string.
+ *
+ * On success, returns 0 and points `out` to the formatted source. Otherwise returns -1 and points
+ * `err` to the error message, leaving `out` alone. Both strings belong to the caller, who frees
+ * them with `prettythanks_free`. `out` and `err` may be NULL to ignore them.
+ */
+int prettythanks_format(const char *src, char **out, char **err);
+
+/* Free a string returned by `prettythanks_format`, doing nothing if it is NULL. */
+void prettythanks_free(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,120 @@
+//! Copies of the original sources saved before rewriting them, and their restoration.
+
+use crate::{Error, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{fs, io};
+
+/// Suffix appended to the path of a file to name its backup, unless another one is picked.
+pub const DEFAULT_SUFFIX: &str = ".bak";
+
+/// Path of the backup of `path`.
+fn backup_path(path: &Utf8Path, suffix: &str) -> Utf8PathBuf {
+    format!("{}{}", path, suffix).into()
+}
+
+/// Copy `path` next to itself with `suffix` appended, replacing an older backup.
+pub(crate) fn save(path: &Utf8Path, suffix: &str) -> Result<()> {
+    let backup = backup_path(path, suffix);
+    fs::copy(path, &backup).map_err(|err| Error::io(&backup, err))?;
+    Ok(())
+}
+
+/// Put the backups found in `paths` back in place of the files they were taken from and remove
+/// them, returning the restored files.
+///
+/// Directories are walked recursively, apart from `.git`, and files are restored if they have a
+/// backup, whether they are named by it or by the original file.
+pub fn restore(paths: &[Utf8PathBuf], suffix: &str) -> Result<Vec<Utf8PathBuf>> {
+    if suffix.is_empty() {
+        return Err(Error::Unsupported(
+            "the backup suffix can't be empty".to_string(),
+        ));
+    }
+    let mut restored = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            restore_directory(path, suffix, &mut restored)?;
+        } else {
+            let original = path.as_str().strip_suffix(suffix).unwrap_or(path.as_str());
+            restore_file(Utf8Path::new(original), suffix, &mut restored)?;
+        }
+    }
+    Ok(restored)
+}
+
+fn restore_directory(dir: &Utf8Path, suffix: &str, restored: &mut Vec<Utf8PathBuf>) -> Result<()> {
+    let io_error = |err| Error::io(dir, err);
+    let mut entries = dir
+        .read_dir_utf8()
+        .map_err(io_error)?
+        .collect::<io::Result<Vec<_>>>()
+        .map_err(io_error)?;
+    entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type().map_err(|err| Error::io(path, err))?;
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                restore_directory(path, suffix, restored)?;
+            }
+        } else if let Some(original) = path.as_str().strip_suffix(suffix) {
+            restore_file(Utf8Path::new(original), suffix, restored)?;
+        }
+    }
+    Ok(())
+}
+
+fn restore_file(path: &Utf8Path, suffix: &str, restored: &mut Vec<Utf8PathBuf>) -> Result<()> {
+    let backup = backup_path(path, suffix);
+    // Backups are copies in the encoding of the file, put back byte for byte.
+    let contents = match fs::read(&backup) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(Error::Unsupported(format!("{} has no backup", path)));
+        }
+        Err(err) => return Err(Error::io(&backup, err)),
+    };
+    // The original file may be gone, or be a symlink to follow.
+    let result = if path.exists() {
+        crate::write_atomic(path, &contents)
+    } else {
+        fs::write(path, &contents)
+    };
+    result.map_err(|err| Error::io(path, err))?;
+    fs::remove_file(&backup).map_err(|err| Error::io(&backup, err))?;
+    restored.push(path.to_path_buf());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn restores_backups() {
+        let temp = TempDir::new("backup");
+        fs::create_dir_all(temp.join("src")).unwrap();
+        let file = temp.join("src/lib.rs");
+        fs::write(&file, "fn  a(){}").unwrap();
+        save(&file, DEFAULT_SUFFIX).unwrap();
+        fs::write(&file, "fn a() {}\n").unwrap();
+        assert_eq!(
+            restore(&[temp.to_path_buf()], DEFAULT_SUFFIX).unwrap(),
+            vec![file.clone()]
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn  a(){}");
+        assert!(!temp.join("src/lib.rs.bak").exists());
+        assert!(restore(std::slice::from_ref(&file), DEFAULT_SUFFIX).is_err());
+        // Latin-1 sources are not valid UTF-8.
+        let latin1 = b"// caf\xe9\nfn  a(){}\n";
+        fs::write(&file, latin1).unwrap();
+        save(&file, DEFAULT_SUFFIX).unwrap();
+        fs::write(&file, "// caf\u{e9}\nfn a() {}\n").unwrap();
+        assert_eq!(
+            restore(std::slice::from_ref(&file), DEFAULT_SUFFIX).unwrap(),
+            vec![file.clone()]
+        );
+        assert_eq!(fs::read(&file).unwrap(), latin1);
+    }
+}
@@ -0,0 +1,214 @@
+//! Benchmarking of formatting: the sources of a tree formatted in memory over and over, on one
+//! thread and then on [`Options::jobs`](crate::Options::jobs) ones, to see how fast formatting
+//! is and how well it scales, see [`PrettyThanks::bench`].
+
+use crate::{
+    format_with_policy, markdown, read_source, Error, Options, PrettyThanks, Result, Task,
+};
+use camino::Utf8PathBuf;
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many times the sources are formatted, unless told otherwise.
+pub const DEFAULT_ITERATIONS: usize = 5;
+
+/// How fast the sources were formatted on some threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Throughput {
+    /// The threads formatting them.
+    pub threads: usize,
+    /// The files formatted, over all the iterations.
+    pub files: usize,
+    /// The bytes formatted, over all the iterations.
+    pub bytes: usize,
+    /// The time formatting them took.
+    pub elapsed: Duration,
+}
+
+// Losing precision past 2^52 bytes or files is fine for a benchmark.
+#[allow(clippy::cast_precision_loss)]
+impl Throughput {
+    /// The megabytes formatted per second.
+    pub fn megabytes_per_second(&self) -> f64 {
+        self.bytes as f64 / 1e6 / self.elapsed.as_secs_f64()
+    }
+
+    /// The files formatted per second.
+    pub fn files_per_second(&self) -> f64 {
+        self.files as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// How many times faster than `baseline` formatting was.
+    pub fn speedup(&self, baseline: &Throughput) -> f64 {
+        baseline.elapsed.as_secs_f64() / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>3} {:<8} {:>9.2} MB/s {:>10.1} files/s, {} ms",
+            self.threads,
+            if self.threads == 1 {
+                "thread:"
+            } else {
+                "threads:"
+            },
+            self.megabytes_per_second(),
+            self.files_per_second(),
+            self.elapsed.as_millis()
+        )
+    }
+}
+
+/// Format every source of `sources` `iterations` times on `threads` threads.
+fn measure(
+    sources: &[(Utf8PathBuf, &Options, String)],
+    iterations: usize,
+    threads: usize,
+) -> Throughput {
+    let total = sources.len() * iterations;
+    let next = AtomicUsize::new(0);
+    let work = || loop {
+        let idx = next.fetch_add(1, Ordering::Relaxed);
+        if idx >= total {
+            break;
+        }
+        let (path, options, source) = &sources[idx % sources.len()];
+        // They all formatted before, the output is all that is left to throw away.
+        let _ = format_with_policy(path, source, options);
+    };
+    let start = Instant::now();
+    if threads == 1 {
+        work();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(work);
+            }
+        });
+    }
+    Throughput {
+        threads,
+        files: total,
+        bytes: sources
+            .iter()
+            .map(|(_, _, source)| source.len())
+            .sum::<usize>()
+            * iterations,
+        elapsed: start.elapsed(),
+    }
+}
+
+impl PrettyThanks {
+    /// Format the Rust files found `iterations` times in memory, without writing anything, on
+    /// one thread and then on [`Options::jobs`] threads, printing how fast both went.
+    ///
+    /// The files are read and formatted once beforehand, which warms up the caches and leaves
+    /// out the ones that can't be formatted.
+    ///
+    /// # Errors
+    ///
+    /// With the errors hit reading the files or formatting them the first time, once the others
+    /// are benchmarked.
+    pub fn bench(&self, iterations: usize) -> Result<Vec<Throughput>> {
+        if self.paths.iter().any(|path| path == "-") {
+            return Err(Error::Unsupported(
+                "the benchmark can't be used with stdin".to_string(),
+            ));
+        }
+        let tasks = self.collect();
+        let mut failures = Vec::new();
+        let mut files = Vec::new();
+        for task in &tasks {
+            let (path, options) = match task {
+                Task::File(path, options) if !markdown::is_markdown(path) => (path, options),
+                _ => continue,
+            };
+            let options = options.as_deref().unwrap_or(&self.options);
+            let source = match read_source(path, options) {
+                Ok((source, _, _)) => source,
+                Err(err) => {
+                    failures.push(err);
+                    continue;
+                }
+            };
+            match format_with_policy(path, &source, options) {
+                // Skipped files are not formatted at all.
+                Ok((_, _, true)) => {}
+                Ok((_, _, false)) => files.push((path.clone(), options, source)),
+                Err(err) => failures.push(err),
+            }
+        }
+        let mut throughputs = Vec::new();
+        if !files.is_empty() && iterations > 0 {
+            let size = files
+                .iter()
+                .map(|(_, _, source)| source.len())
+                .sum::<usize>();
+            info!(
+                "formatting {} files, {} bytes, {} times",
+                files.len(),
+                size,
+                iterations
+            );
+            let single = measure(&files, iterations, 1);
+            println!("{}", single);
+            let jobs = self.options.jobs.max(1);
+            if jobs > 1 {
+                let multi = measure(&files, iterations, jobs);
+                println!("{}, {:.2}x faster", multi, multi.speedup(&single));
+                throughputs.push(single);
+                throughputs.push(multi);
+            } else {
+                throughputs.push(single);
+            }
+        }
+        let walking = tasks.into_iter().filter_map(|task| match task {
+            Task::Error(err) => Some(err),
+            _ => None,
+        });
+        let errors = walking.chain(failures).collect();
+        Error::from_errors(errors).map_or(Ok(throughputs), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_throughput() {
+        let throughput = Throughput {
+            threads: 4,
+            files: 20,
+            bytes: 3_000_000,
+            elapsed: Duration::from_millis(500),
+        };
+        assert_eq!(
+            throughput.to_string(),
+            "  4 threads:      6.00 MB/s       40.0 files/s, 500 ms"
+        );
+        let baseline = Throughput {
+            threads: 1,
+            elapsed: Duration::from_secs(2),
+            ..throughput
+        };
+        assert!((throughput.speedup(&baseline) - 4.0).abs() < f64::EPSILON);
+        assert!(baseline.to_string().starts_with("  1 thread:  "));
+
+        let options = Options {
+            jobs: 2,
+            ..Options::default()
+        };
+        let thanks = PrettyThanks::new(&["fixtures/input.rs".to_string()], options).unwrap();
+        let throughputs = thanks.bench(2).unwrap();
+        assert_eq!(throughputs.len(), 2);
+        assert_eq!(throughputs[1].files, 2);
+    }
+}
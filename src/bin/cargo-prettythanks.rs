@@ -0,0 +1,8 @@
+//! The same binary as `prettythanks`, under the name cargo looks for to run `cargo prettythanks`.
+
+#[path = "../main.rs"]
+mod prettythanks_main;
+
+fn main() {
+    prettythanks_main::main();
+}
@@ -0,0 +1,172 @@
+//! Hashes of the sources known to be formatted, so that repeat runs can skip the files that
+//! didn't change since.
+//!
+//! The cache is a text file: a first line naming the version of prettythanks and the options
+//! the sources were formatted with, any change of either invalidating the whole cache, then the
+//! size and hash of a formatted source per line, the hash covering the edition it was parsed as.
+
+use crate::{edition::Edition, Error, Options, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    fs, io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Name of the cache file, created at the root of the tree unless `--no-cache` is passed, see
+/// [`crate::lock::root`].
+pub const FILE_NAME: &str = ".prettythanks-cache";
+
+/// The formatted sources recorded in a cache file, shared by the threads formatting files.
+#[derive(Debug)]
+pub struct Cache {
+    path: Utf8PathBuf,
+    key: String,
+    sources: Mutex<BTreeSet<(usize, u64)>>,
+    dirty: AtomicBool,
+}
+
+impl Cache {
+    /// Load the cache file at `path`, starting afresh if it is missing, unreadable or was
+    /// written by another version or with other options.
+    pub fn load(path: &Utf8Path, options: &Options) -> Self {
+        let key = key(options);
+        let sources = fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| parse(&contents, &key))
+            .unwrap_or_default();
+        Cache {
+            path: path.to_path_buf(),
+            key,
+            sources: Mutex::new(sources),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether `source` was formatted in an earlier run, parsed as `edition`.
+    pub fn contains(&self, source: &str, edition: Option<Edition>) -> bool {
+        let sources = self.sources.lock().expect("poisoned cache");
+        sources.contains(&(source.len(), hash(source, edition)))
+    }
+
+    /// Record that `source` is formatted, parsed as `edition`.
+    pub fn insert(&self, source: &str, edition: Option<Edition>) {
+        let mut sources = self.sources.lock().expect("poisoned cache");
+        if sources.insert((source.len(), hash(source, edition))) {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Write the cache file back, if anything was added to it.
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut contents = format!("{}\n", self.key);
+        for (len, hash) in &*self.sources.lock().expect("poisoned cache") {
+            let _ = writeln!(contents, "{} {:016x}", len, hash);
+        }
+        fs::write(&self.path, contents).map_err(|err| Error::io(&self.path, err))
+    }
+}
+
+/// Remove the cache file at `path`, returning whether there was one.
+pub fn clean(path: &Utf8Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(Error::io(path, err)),
+    }
+}
+
+/// The first line of the cache file: everything that changes how a source is formatted.
+pub(crate) fn key(options: &Options) -> String {
+    format!(
+        "prettythanks {} comment-policy={} parse-mode={} format-generated={} assert-idempotent={} \
+         best-effort={} fallback={} format-macros={} transforms={} edition={} line-ending={} \
+         encoding={} write-utf8={}",
+        env!("CARGO_PKG_VERSION"),
+        options.comment_policy,
+        options.parse_mode,
+        options.format_generated,
+        options.assert_idempotent,
+        options.best_effort,
+        options.fallback,
+        options.format_macros,
+        options.transforms.keys().collect::<Vec<_>>().join(","),
+        options
+            .edition
+            .map_or_else(|| "auto".to_string(), |edition| edition.to_string()),
+        options.line_ending,
+        options.encoding,
+        options.write_utf8
+    )
+}
+
+fn parse(contents: &str, key: &str) -> Option<BTreeSet<(usize, u64)>> {
+    let mut lines = contents.lines();
+    if lines.next()? != key {
+        return None;
+    }
+    lines
+        .map(|line| {
+            let (len, hash) = line.split_once(' ')?;
+            Some((len.parse().ok()?, u64::from_str_radix(hash, 16).ok()?))
+        })
+        .collect()
+}
+
+/// 64-bit FNV-1a of `source` after `edition`, stable across platforms and Rust versions unlike
+/// the std hashers.
+fn hash(source: &str, edition: Option<Edition>) -> u64 {
+    let edition = edition.map_or_else(|| "none".to_string(), |edition| edition.to_string());
+    edition
+        .bytes()
+        .chain([b'\n'])
+        .chain(source.bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comments::CommentPolicy;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn persists_formatted_sources() {
+        let temp = TempDir::new("cache");
+        let path = temp.join(FILE_NAME);
+        let options = Options::default();
+        let cache = Cache::load(&path, &options);
+        let edition = Some(Edition::E2021);
+        assert!(!cache.contains("fn a() {}\n", edition));
+        cache.insert("fn a() {}\n", edition);
+        cache.save().unwrap();
+        let cache = Cache::load(&path, &options);
+        assert!(cache.contains("fn a() {}\n", edition));
+        assert!(!cache.contains("fn b() {}\n", edition));
+        // Moved to a package of another edition.
+        assert!(!cache.contains("fn a() {}\n", Some(Edition::E2015)));
+        assert!(!cache.contains("fn a() {}\n", None));
+        let options = Options {
+            comment_policy: CommentPolicy::Skip,
+            ..Options::default()
+        };
+        assert!(!Cache::load(&path, &options).contains("fn a() {}\n", edition));
+        let options = Options {
+            write_utf8: true,
+            ..Options::default()
+        };
+        assert!(!Cache::load(&path, &options).contains("fn a() {}\n", edition));
+        assert!(key(&Options::default()).contains(" comment-policy=warn "));
+        assert!(clean(&path).unwrap());
+        assert!(!clean(&path).unwrap());
+    }
+}
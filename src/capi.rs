@@ -0,0 +1,104 @@
+//! C functions, for builds as a `cdylib` with the `capi` feature, declared in
+//! `include/prettythanks.h`.
+
+use std::{
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int},
+    panic,
+};
+
+/// `text` as a C string owned by the caller, with the NUL characters it can't hold escaped.
+fn into_c_string(text: String) -> *mut c_char {
+    CString::new(text)
+        .unwrap_or_else(|err| {
+            let text = String::from_utf8_lossy(&err.into_vec()).replace('\0', "\\0");
+            CString::new(text).unwrap_or_default()
+        })
+        .into_raw()
+}
+
+/// Format the Rust source file `src`, a NUL terminated UTF-8 string.
+///
+/// On success, returns 0 and points `out` to the formatted source. Otherwise returns -1 and points
+/// `err` to the error message, leaving `out` alone. Both strings belong to the caller, who frees
+/// them with [`prettythanks_free`]. `out` and `err` may be null to ignore them.
+///
+/// # Safety
+///
+/// `src` must point to a NUL terminated string, and `out` and `err` be null or point to writable
+/// pointers.
+#[no_mangle]
+pub unsafe extern "C" fn prettythanks_format(
+    src: *const c_char,
+    out: *mut *mut c_char,
+    err: *mut *mut c_char,
+) -> c_int {
+    let result = if src.is_null() {
+        Err("the source is null".to_string())
+    } else {
+        match CStr::from_ptr(src).to_str() {
+            Ok(source) => {
+                // Unwinding across the C boundary would abort the process.
+                panic::catch_unwind(|| crate::format_str(source))
+                    .unwrap_or_else(|_| {
+                        Err(crate::Error::Unsupported(
+                            "prettyplease panicked formatting the source".to_string(),
+                        ))
+                    })
+                    .map_err(|err| err.to_string())
+            }
+            Err(utf8) => Err(format!("the source is not valid UTF-8: {}", utf8)),
+        }
+    };
+    match result {
+        Ok(formatted) => {
+            if !out.is_null() {
+                *out = into_c_string(formatted);
+            }
+            0
+        }
+        Err(message) => {
+            if !err.is_null() {
+                *err = into_c_string(message);
+            }
+            -1
+        }
+    }
+}
+
+/// Free a string returned by [`prettythanks_format`], doing nothing if it is null.
+///
+/// # Safety
+///
+/// `text` must be null or a string returned by [`prettythanks_format`], not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn prettythanks_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn formats_c_strings() {
+        let (mut out, mut err) = (ptr::null_mut(), ptr::null_mut());
+        unsafe {
+            let status = prettythanks_format(b"fn main(){}\0".as_ptr().cast(), &mut out, &mut err);
+            assert_eq!(status, 0);
+            assert!(err.is_null());
+            assert_eq!(CStr::from_ptr(out).to_str(), Ok("fn main() {}\n"));
+            prettythanks_free(out);
+            let status = prettythanks_format(b"fn main(\0".as_ptr().cast(), &mut out, &mut err);
+            assert_eq!(status, -1);
+            assert!(CStr::from_ptr(err)
+                .to_str()
+                .unwrap()
+                .contains("failed to parse"));
+            prettythanks_free(err);
+        }
+    }
+}
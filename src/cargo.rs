@@ -0,0 +1,240 @@
+//! Discovery of the sources of a cargo workspace through `cargo metadata`.
+
+use crate::{json, Error, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{env, process::Command};
+
+/// A target of a package: a library, a binary, an example, a test, a bench or a build script.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+    /// The crate root of the target.
+    pub src_path: Utf8PathBuf,
+}
+
+/// A member of a workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    pub manifest_path: Utf8PathBuf,
+    pub targets: Vec<Target>,
+}
+
+impl Package {
+    /// Files and directories holding the sources of all the targets, without overlaps.
+    ///
+    /// Crate roots sitting next to the manifest, like `build.rs`, are listed alone, otherwise
+    /// their whole directory is, to pick up their modules too.
+    pub fn source_roots(&self) -> Vec<Utf8PathBuf> {
+        let root = self.manifest_path.parent().unwrap_or(Utf8Path::new("."));
+        source_roots(
+            self.targets
+                .iter()
+                .map(|target| match target.src_path.parent() {
+                    Some(dir) if dir != root => dir.to_path_buf(),
+                    _ => target.src_path.clone(),
+                }),
+        )
+    }
+}
+
+/// The members of a workspace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Workspace {
+    pub root: Utf8PathBuf,
+    pub members: Vec<Package>,
+}
+
+impl Workspace {
+    /// The members of the workspace containing `dir`.
+    pub fn discover(dir: &Utf8Path) -> Result<Self> {
+        let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let output = Command::new(cargo)
+            .args(["metadata", "--format-version", "1", "--no-deps"])
+            .current_dir(dir)
+            .output()
+            .map_err(|err| Error::Cargo(format!("failed to run cargo metadata: {}", err)))?;
+        if !output.status.success() {
+            return Err(Error::Cargo(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        parse_metadata(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// The members selected the same way `cargo fmt` does: the `packages` listed if any, all of
+    /// them but the `exclude`d ones with `workspace`, otherwise the package containing `cwd`, or
+    /// all of them from the workspace root.
+    pub fn select(
+        &self,
+        packages: &[String],
+        workspace: bool,
+        exclude: &[String],
+        cwd: &Utf8Path,
+    ) -> Result<Vec<&Package>> {
+        for name in packages.iter().chain(exclude) {
+            if !self.members.iter().any(|package| &package.name == name) {
+                return Err(Error::Cargo(format!(
+                    "package `{}` not found in the workspace",
+                    name
+                )));
+            }
+        }
+        if !packages.is_empty() {
+            return Ok(self
+                .members
+                .iter()
+                .filter(|package| packages.contains(&package.name))
+                .collect());
+        }
+        if workspace || !exclude.is_empty() || cwd == self.root {
+            return Ok(self
+                .members
+                .iter()
+                .filter(|package| !exclude.contains(&package.name))
+                .collect());
+        }
+        let current = self
+            .members
+            .iter()
+            .filter(|package| {
+                package
+                    .manifest_path
+                    .parent()
+                    .map_or(false, |dir| cwd.starts_with(dir))
+            })
+            .max_by_key(|package| package.manifest_path.as_str().len());
+        Ok(match current {
+            Some(package) => vec![package],
+            None => self.members.iter().collect(),
+        })
+    }
+}
+
+/// Sort and dedup `paths`, dropping the ones inside another.
+pub fn source_roots(paths: impl IntoIterator<Item = Utf8PathBuf>) -> Vec<Utf8PathBuf> {
+    let mut paths = paths.into_iter().collect::<Vec<_>>();
+    paths.sort();
+    let mut roots: Vec<Utf8PathBuf> = Vec::with_capacity(paths.len());
+    for path in paths {
+        if !roots.iter().any(|root| path.starts_with(root)) {
+            roots.push(path);
+        }
+    }
+    roots
+}
+
+fn parse_metadata(metadata: &str) -> Result<Workspace> {
+    let invalid = |what: &str| Error::Cargo(format!("invalid cargo metadata: {}", what));
+    let metadata = json::parse(metadata).map_err(|err| invalid(&err))?;
+    let str_field = |value: &json::Value, key: &str| {
+        value
+            .get(key)
+            .and_then(json::Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| invalid(&format!("missing `{}`", key)))
+    };
+    let packages = metadata
+        .get("packages")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| invalid("missing `packages`"))?;
+    let members = packages
+        .iter()
+        .map(|package| {
+            let targets = package
+                .get("targets")
+                .and_then(json::Value::as_array)
+                .unwrap_or_default()
+                .iter()
+                .map(|target| {
+                    Ok(Target {
+                        name: str_field(target, "name")?,
+                        kind: target
+                            .get("kind")
+                            .and_then(json::Value::as_array)
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(json::Value::as_str)
+                            .map(str::to_string)
+                            .collect(),
+                        src_path: str_field(target, "src_path")?.into(),
+                    })
+                })
+                .collect::<Result<_>>()?;
+            Ok(Package {
+                name: str_field(package, "name")?,
+                manifest_path: str_field(package, "manifest_path")?.into(),
+                targets,
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(Workspace {
+        root: str_field(&metadata, "workspace_root")?.into(),
+        members,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_source_roots() {
+        let workspace = parse_metadata(
+            r#"{"packages": [{"name": "foo", "manifest_path": "/ws/foo/Cargo.toml", "targets": [
+                {"name": "foo", "kind": ["lib"], "src_path": "/ws/foo/src/lib.rs"},
+                {"name": "cli", "kind": ["bin"], "src_path": "/ws/foo/src/bin/cli.rs"},
+                {"name": "it", "kind": ["test"], "src_path": "/ws/foo/tests/it.rs"},
+                {"name": "build-script-build", "kind": ["custom-build"],
+                 "src_path": "/ws/foo/build.rs"}
+            ]}], "workspace_root": "/ws"}"#,
+        )
+        .unwrap();
+        assert_eq!(workspace.members[0].name, "foo");
+        assert_eq!(
+            workspace.members[0].source_roots(),
+            ["/ws/foo/build.rs", "/ws/foo/src", "/ws/foo/tests"]
+        );
+    }
+
+    #[test]
+    fn selects_packages() {
+        let package = |name: &str| Package {
+            name: name.to_string(),
+            manifest_path: format!("/ws/{}/Cargo.toml", name).into(),
+            targets: Vec::new(),
+        };
+        let workspace = Workspace {
+            root: "/ws".into(),
+            members: vec![package("a"), package("b"), package("c")],
+        };
+        let select = |packages: &[&str], all: bool, exclude: &[&str], cwd: &str| {
+            let strings = |names: &[&str]| {
+                names
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>()
+            };
+            workspace
+                .select(
+                    &strings(packages),
+                    all,
+                    &strings(exclude),
+                    Utf8Path::new(cwd),
+                )
+                .map(|packages| {
+                    packages
+                        .into_iter()
+                        .map(|package| package.name.clone())
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(select(&["b"], false, &[], "/ws").unwrap(), ["b"]);
+        assert_eq!(select(&[], true, &["a"], "/ws/a").unwrap(), ["b", "c"]);
+        assert_eq!(select(&[], false, &[], "/ws").unwrap(), ["a", "b", "c"]);
+        assert_eq!(select(&[], false, &[], "/ws/c/src").unwrap(), ["c"]);
+        assert!(select(&["d"], false, &[], "/ws").is_err());
+    }
+}
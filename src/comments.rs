@@ -0,0 +1,480 @@
+//! Detection of the comments `prettyplease` drops.
+//!
+//! Doc comments are attributes and survive formatting, while regular `//` and `/* */` comments
+//! never make it into the syntax tree. The scanner below finds them in the original source,
+//! skipping over string and character literals so that `"// not a comment"` is left alone.
+
+use std::{fmt, str::FromStr};
+
+/// What to do with files whose regular comments would be lost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommentPolicy {
+    /// Format the file anyway, printing a warning with the number of lost comments.
+    #[default]
+    Warn,
+    /// Leave the file untouched.
+    Skip,
+    /// Format the file anyway, silently.
+    Allow,
+    /// Put the comments back next to the code they were attached to, best effort. Comments
+    /// that can't be placed safely are dropped with a warning.
+    Preserve,
+}
+
+impl FromStr for CommentPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "warn" => Ok(CommentPolicy::Warn),
+            "skip" => Ok(CommentPolicy::Skip),
+            "allow" => Ok(CommentPolicy::Allow),
+            "preserve" => Ok(CommentPolicy::Preserve),
+            _ => Err(format!(
+                "unknown comment policy `{}`, expected warn, skip, allow or preserve",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for CommentPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CommentPolicy::Warn => "warn",
+            CommentPolicy::Skip => "skip",
+            CommentPolicy::Allow => "allow",
+            CommentPolicy::Preserve => "preserve",
+        })
+    }
+}
+
+/// A regular, non-doc, comment found in a source file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Comment<'a> {
+    /// The comment, delimiters included.
+    pub text: &'a str,
+    /// Byte offset of the comment in the source.
+    pub offset: usize,
+    /// Line the comment starts on, starting from 1.
+    pub line: usize,
+}
+
+/// `1 comment`, `2 comments`.
+pub(crate) fn count(comments: usize) -> String {
+    if comments == 1 {
+        "1 comment".to_string()
+    } else {
+        format!("{} comments", comments)
+    }
+}
+
+fn is_ident(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// Anything that is not a comment, compared by text when aligning sources.
+    Token,
+    Comment,
+    DocComment,
+}
+
+/// A token or a comment, as far as comment placement is concerned.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Lexeme<'a> {
+    pub(crate) kind: Kind,
+    pub(crate) text: &'a str,
+    pub(crate) offset: usize,
+    pub(crate) line: usize,
+}
+
+impl Lexeme<'_> {
+    pub(crate) fn end(&self) -> usize {
+        self.offset + self.text.len()
+    }
+}
+
+/// Split `source` into tokens and comments, whitespace is dropped.
+///
+/// The tokens are only approximately Rust ones, what matters is that the same source always
+/// splits the same way however it is laid out.
+pub(crate) fn lex(source: &str) -> Vec<Lexeme<'_>> {
+    let bytes = source.as_bytes();
+    let mut lexemes = Vec::new();
+    let mut line = 1;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let rest = &bytes[idx..];
+        let (kind, end) = match rest {
+            [b'\n', ..] => {
+                line += 1;
+                idx += 1;
+                continue;
+            }
+            [byte, ..] if byte.is_ascii_whitespace() => {
+                idx += 1;
+                continue;
+            }
+            [b'/', b'/', ..] => {
+                let end = rest
+                    .iter()
+                    .position(|b| *b == b'\n')
+                    .map_or(bytes.len(), |len| idx + len);
+                let text = &source[idx..end];
+                let doc = (text.starts_with("///") && !text.starts_with("////"))
+                    || text.starts_with("//!");
+                (if doc { Kind::DocComment } else { Kind::Comment }, end)
+            }
+            [b'/', b'*', ..] => {
+                let mut depth = 0;
+                let mut end = idx;
+                while end < bytes.len() {
+                    match &bytes[end..] {
+                        [b'/', b'*', ..] => {
+                            depth += 1;
+                            end += 2;
+                        }
+                        [b'*', b'/', ..] => {
+                            depth -= 1;
+                            end += 2;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => end += 1,
+                    }
+                }
+                let end = end.min(bytes.len());
+                let text = &source[idx..end];
+                let doc = (text.starts_with("/**")
+                    && !text.starts_with("/***")
+                    && !text.starts_with("/**/"))
+                    || text.starts_with("/*!");
+                (if doc { Kind::DocComment } else { Kind::Comment }, end)
+            }
+            [b'"', ..] => (Kind::Token, string_end(bytes, idx + 1)),
+            [b'\'', ..] => (Kind::Token, char_end(source, idx)),
+            [byte, ..] if is_ident(*byte) => {
+                let end = raw_string_end(bytes, idx).unwrap_or_else(|| {
+                    let mut end = idx + rest.iter().take_while(|b| is_ident(**b)).count();
+                    // Keep the fractional part of float literals, but not ranges like `0..1`.
+                    if byte.is_ascii_digit()
+                        && bytes.get(end) == Some(&b'.')
+                        && bytes.get(end + 1).map_or(false, u8::is_ascii_digit)
+                    {
+                        end += 1 + bytes[end + 1..]
+                            .iter()
+                            .take_while(|b| is_ident(**b))
+                            .count();
+                    }
+                    end
+                });
+                (Kind::Token, end)
+            }
+            _ => {
+                let len = source[idx..].chars().next().map_or(1, char::len_utf8);
+                (Kind::Token, idx + len)
+            }
+        };
+        let text = &source[idx..end];
+        lexemes.push(Lexeme {
+            kind,
+            text,
+            offset: idx,
+            line,
+        });
+        line += text.matches('\n').count();
+        idx = end;
+    }
+    lexemes
+}
+
+/// Find all the regular comments of `source`, which `prettyplease` would drop.
+pub fn regular_comments(source: &str) -> Vec<Comment<'_>> {
+    lex(source)
+        .into_iter()
+        .filter(|lexeme| lexeme.kind == Kind::Comment)
+        .map(|lexeme| Comment {
+            text: lexeme.text,
+            offset: lexeme.offset,
+            line: lexeme.line,
+        })
+        .collect()
+}
+
+/// Where a comment goes, relative to the index of a token of the original source.
+#[derive(Clone, Copy, Debug)]
+enum Anchor {
+    /// On its own line, before the token.
+    Before(usize),
+    /// On the same line, right before the token.
+    Inline(usize),
+    /// At the end of the line of the token.
+    After(usize),
+}
+
+/// Match the `old` tokens with the `new` ones, `None` being tokens prettyplease dropped.
+///
+/// Formatting mostly adds and removes commas and semicolons, so a greedy walk that resyncs on
+/// the closest point where a few tokens agree again is good enough, and much cheaper than a
+/// proper diff on large files.
+fn align(old: &[&str], new: &[&str]) -> Vec<Option<usize>> {
+    const WINDOW: usize = 16;
+    const AGREE: usize = 3;
+    let agree = |a: usize, b: usize| (0..AGREE).all(|off| old.get(a + off) == new.get(b + off));
+    let mut map = vec![None; old.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            map[i] = Some(j);
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let resync = (1..=WINDOW).find_map(|dist| {
+            (0..=dist)
+                .map(|skip| (i + skip, j + dist - skip))
+                .find(|(a, b)| *a <= old.len() && *b <= new.len() && agree(*a, *b))
+        });
+        (i, j) = resync.unwrap_or((i + 1, j + 1));
+    }
+    map
+}
+
+/// Put the regular comments of `original` back into `formatted`, the output of prettyplease for
+/// it, next to the tokens they were attached to.
+///
+/// Returns `None` if the comments could not all be placed without changing the tokens of the
+/// formatted source.
+pub fn reattach(original: &str, formatted: &str) -> Option<String> {
+    let old = lex(original);
+    let mut comments = Vec::new();
+    let mut tokens = Vec::new();
+    for (idx, lexeme) in old.iter().enumerate() {
+        match lexeme.kind {
+            Kind::Token => tokens.push(lexeme.text),
+            Kind::DocComment => {}
+            Kind::Comment => {
+                let last_line =
+                    |lexeme: &Lexeme<'_>| lexeme.line + lexeme.text.matches('\n').count();
+                let previous = old[..idx].iter().rev().find(|l| l.kind == Kind::Token);
+                let next = old.get(idx + 1);
+                let anchor = if lexeme.text.starts_with("/*")
+                    && next.map_or(false, |next| {
+                        next.kind == Kind::Token && next.line == last_line(lexeme)
+                    }) {
+                    Anchor::Inline(tokens.len())
+                } else if previous.map_or(false, |prev| last_line(prev) == lexeme.line) {
+                    Anchor::After(tokens.len() - 1)
+                } else {
+                    Anchor::Before(tokens.len())
+                };
+                comments.push((anchor, lexeme.text));
+            }
+        }
+    }
+    if comments.is_empty() {
+        return Some(formatted.to_string());
+    }
+
+    let new = lex(formatted);
+    // Index in `new` of every token.
+    let positions = new
+        .iter()
+        .enumerate()
+        .filter(|(_, lexeme)| lexeme.kind == Kind::Token)
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    let new_tokens = positions
+        .iter()
+        .map(|idx| new[*idx].text)
+        .collect::<Vec<_>>();
+    let map = align(&tokens, &new_tokens);
+    let forward = |idx: usize| map[idx..].iter().find_map(|new| *new);
+    let backward = |idx: usize| map[..=idx].iter().rev().find_map(|new| *new);
+    // Offset of the gap between `new[idx - 1]` and `new[idx]`.
+    let gap = |idx: usize| {
+        let start = idx.checked_sub(1).map_or(0, |prev| new[prev].end());
+        let end = new.get(idx).map_or(formatted.len(), |lexeme| lexeme.offset);
+        (start, end)
+    };
+    let line_start = |token: usize| {
+        (0..=positions[token])
+            .rev()
+            .find_map(|idx| {
+                let (start, end) = gap(idx);
+                formatted[start..end].rfind('\n').map(|pos| start + pos + 1)
+            })
+            .unwrap_or(0)
+    };
+    let line_end = |token: usize| {
+        (positions[token] + 1..=new.len())
+            .find_map(|idx| {
+                let (start, end) = gap(idx);
+                formatted[start..end].find('\n').map(|pos| start + pos)
+            })
+            .unwrap_or(formatted.len())
+    };
+    let at_end = |comment: &str| {
+        let newline = if formatted.ends_with('\n') { "" } else { "\n" };
+        (formatted.len(), format!("{}{}\n", newline, comment))
+    };
+
+    let mut inserts = comments
+        .iter()
+        .map(|(anchor, comment)| match *anchor {
+            Anchor::After(idx) => match backward(idx) {
+                Some(token) => (line_end(token), format!(" {}", comment)),
+                None => (0, format!("{}\n", comment)),
+            },
+            Anchor::Inline(idx) => match forward(idx) {
+                Some(token) => (new[positions[token]].offset, format!("{} ", comment)),
+                None => at_end(comment),
+            },
+            Anchor::Before(idx) => match forward(idx) {
+                Some(token) => {
+                    let start = line_start(token);
+                    let indent = formatted[start..]
+                        .bytes()
+                        .take_while(|b| *b == b' ' || *b == b'\t')
+                        .count();
+                    (
+                        start,
+                        format!("{}{}\n", &formatted[start..start + indent], comment),
+                    )
+                }
+                None => at_end(comment),
+            },
+        })
+        .collect::<Vec<_>>();
+    inserts.sort_by_key(|(offset, _)| *offset);
+
+    let mut output = String::with_capacity(
+        formatted.len() + inserts.iter().map(|(_, text)| text.len()).sum::<usize>(),
+    );
+    let mut last = 0;
+    for (offset, text) in &inserts {
+        output.push_str(&formatted[last..*offset]);
+        output.push_str(text);
+        last = *offset;
+    }
+    output.push_str(&formatted[last..]);
+
+    // Make sure every comment ended up in a comment and nothing else moved.
+    let check = lex(&output);
+    let same_tokens = check
+        .iter()
+        .filter(|lexeme| lexeme.kind == Kind::Token)
+        .map(|lexeme| lexeme.text)
+        .eq(new_tokens.iter().copied());
+    let placed = check
+        .iter()
+        .filter(|lexeme| lexeme.kind == Kind::Comment)
+        .count();
+    (same_tokens && placed == comments.len()).then_some(output)
+}
+
+/// End of a string literal whose contents start at `idx`, past the closing quote.
+fn string_end(bytes: &[u8], mut idx: usize) -> usize {
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'"' => return idx + 1,
+            _ => idx += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// End of a raw string literal (`r"..."`, `br#"..."#`, `cr"..."`) starting at `idx`, if any.
+fn raw_string_end(bytes: &[u8], idx: usize) -> Option<usize> {
+    let prefix = match &bytes[idx..] {
+        [b'r', ..] => 1,
+        [b'b' | b'c', b'r', ..] => 2,
+        _ => return None,
+    };
+    let hashes = bytes[idx + prefix..]
+        .iter()
+        .take_while(|b| **b == b'#')
+        .count();
+    let open = idx + prefix + hashes;
+    if bytes.get(open) != Some(&b'"') {
+        return None;
+    }
+    let closing = vec![b'#'; hashes];
+    let end = (open + 1..bytes.len())
+        .find(|end| bytes[*end] == b'"' && bytes[end + 1..].starts_with(&closing))
+        .map_or(bytes.len(), |end| end + 1 + hashes);
+    Some(end)
+}
+
+/// End of the character literal or lifetime starting at `idx`.
+fn char_end(source: &str, idx: usize) -> usize {
+    let rest = &source[idx + 1..];
+    let mut chars = rest.char_indices();
+    match chars.next() {
+        // An escaped character literal, `'\n'` or `'\u{1F600}'`.
+        Some((_, '\\')) => rest
+            .get(2..)
+            .and_then(|escaped| escaped.find('\''))
+            .map_or(source.len(), |len| idx + 3 + len + 1),
+        Some((_, c)) => {
+            if let Some((len, '\'')) = chars.next() {
+                return idx + 1 + len + 1;
+            }
+            // A lifetime or a label.
+            let name = rest.bytes().take_while(|b| is_ident(*b)).count();
+            idx + 1 + name.max(c.len_utf8())
+        }
+        None => source.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_regular_comments() {
+        let source = r##"//! crate docs
+/// item docs
+// regular
+fn main() {
+    let a = "// not a comment"; /* block /* nested */ */
+    let b = r#"/* "not" either */"#;
+    let c = '"'; // after a char '\''
+    let e = '\''; // escaped
+    let d: &'static str = "\"";
+    //// four slashes
+    /** doc */ /***/
+}
+"##;
+        let comments = regular_comments(source)
+            .into_iter()
+            .map(|comment| (comment.line, comment.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            vec![
+                (3, "// regular"),
+                (5, "/* block /* nested */ */"),
+                (7, "// after a char '\\''"),
+                (8, "// escaped"),
+                (10, "//// four slashes"),
+                (11, "/***/"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reattaches_comments() {
+        let original = "// header\n\nuse std::fs;\n\nfn main(){\n    // leading\n    let a=1; // trailing\n    call(/* inline */ a,\n    b);\n}\n";
+        let formatted = crate::format_str(original).unwrap();
+        assert_eq!(
+            reattach(original, &formatted).unwrap(),
+            "// header\nuse std::fs;\nfn main() {\n    // leading\n    let a = 1; // trailing\n    call(/* inline */ a, b);\n}\n"
+        );
+    }
+}
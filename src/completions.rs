@@ -0,0 +1,679 @@
+//! Completion scripts for the shells, generated from the description of the command line argh
+//! derives, so they never get out of sync with it.
+
+use argh::{CommandInfoWithArgs, FlagInfoKind, Optionality};
+use std::{fmt, fmt::Write, str::FromStr};
+
+/// The shells completion scripts are generated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+    Elvish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "powershell" => Ok(Shell::PowerShell),
+            "elvish" => Ok(Shell::Elvish),
+            _ => Err(format!(
+                "unknown shell `{}`, expected bash, zsh, fish, powershell or elvish",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::PowerShell => "powershell",
+            Shell::Elvish => "elvish",
+        })
+    }
+}
+
+/// What the value of an option or a positional argument is completed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// Nothing is suggested.
+    Any,
+    /// Paths of files and directories.
+    Path,
+    /// One of these words.
+    Choices(&'static [&'static str]),
+}
+
+struct Flag {
+    /// With the leading dashes.
+    long: &'static str,
+    short: Option<char>,
+    description: String,
+    /// `None` for switches.
+    value: Option<Value>,
+    repeating: bool,
+}
+
+struct Command {
+    /// Empty for the top-level command.
+    name: &'static str,
+    description: String,
+    flags: Vec<Flag>,
+    /// What the positional arguments are, `None` without any.
+    positional: Option<Value>,
+}
+
+/// The first line of a description, without the final period.
+fn summary(description: &str) -> String {
+    let summary = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    summary.trim_end_matches('.').to_string()
+}
+
+fn command(
+    name: &'static str,
+    info: &CommandInfoWithArgs,
+    value: &dyn Fn(&str) -> Value,
+) -> Command {
+    let flags = info
+        .flags
+        .iter()
+        .filter(|flag| !flag.hidden)
+        .map(|flag| Flag {
+            long: flag.long,
+            short: flag.short,
+            description: summary(flag.description),
+            value: match flag.kind {
+                FlagInfoKind::Switch => None,
+                FlagInfoKind::Option { .. } => Some(value(flag.long)),
+            },
+            repeating: flag.optionality == Optionality::Repeating,
+        })
+        .collect();
+    Command {
+        name,
+        description: summary(info.description),
+        flags,
+        positional: info
+            .positionals
+            .iter()
+            .find(|positional| !positional.hidden)
+            .map(|positional| value(positional.name)),
+    }
+}
+
+/// The completion script of the program `bin` for `shell`, `value` telling how the options and
+/// positional arguments are completed from their name, like `--config` or `paths`.
+pub fn generate(
+    shell: Shell,
+    bin: &str,
+    info: &CommandInfoWithArgs,
+    value: &dyn Fn(&str) -> Value,
+) -> String {
+    let mut commands = vec![command("", info, value)];
+    commands.extend(
+        info.commands
+            .iter()
+            .map(|sub| command(sub.name, &sub.command, value)),
+    );
+    let mut out = String::new();
+    match shell {
+        Shell::Bash => bash(&mut out, bin, &commands),
+        Shell::Zsh => zsh(&mut out, bin, &commands),
+        Shell::Fish => fish(&mut out, bin, &commands),
+        Shell::PowerShell => powershell(&mut out, bin, &commands),
+        Shell::Elvish => elvish(&mut out, bin, &commands),
+    }
+    .expect("writing to a string can't fail");
+    out
+}
+
+/// `bin` as a shell function name.
+fn function(bin: &str) -> String {
+    format!(
+        "_{}",
+        bin.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    )
+}
+
+fn subcommands(commands: &[Command]) -> Vec<&str> {
+    commands[1..].iter().map(|command| command.name).collect()
+}
+
+fn names(flag: &Flag) -> Vec<String> {
+    let mut names = flag
+        .short
+        .map(|short| vec![format!("-{}", short)])
+        .unwrap_or_default();
+    names.push(flag.long.to_string());
+    names
+}
+
+fn bash(out: &mut String, bin: &str, commands: &[Command]) -> fmt::Result {
+    let function = function(bin);
+    writeln!(out, "{}() {{", function)?;
+    writeln!(out, "    local cur prev command i")?;
+    writeln!(out, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(out, "    command=\"\"")?;
+    writeln!(out, "    for ((i = 1; i < COMP_CWORD; i++)); do")?;
+    writeln!(out, "        case \"${{COMP_WORDS[i]}}\" in")?;
+    writeln!(out, "            {})", subcommands(commands).join("|"))?;
+    writeln!(out, "                command=\"${{COMP_WORDS[i]}}\"")?;
+    writeln!(out, "                break")?;
+    writeln!(out, "                ;;")?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out, "    case \"$command\" in")?;
+    for command in commands {
+        writeln!(out, "        \"{}\")", command.name)?;
+        let options = command
+            .flags
+            .iter()
+            .filter_map(|flag| flag.value.map(|value| (names(flag).join("|"), value)))
+            .collect::<Vec<_>>();
+        if !options.is_empty() {
+            writeln!(out, "            case \"$prev\" in")?;
+            for (names, value) in options {
+                writeln!(out, "                {})", names)?;
+                writeln!(out, "                    COMPREPLY=({})", bash_words(value))?;
+                writeln!(out, "                    return")?;
+                writeln!(out, "                    ;;")?;
+            }
+            writeln!(out, "            esac")?;
+        }
+        let flags = command.flags.iter().flat_map(names).collect::<Vec<_>>();
+        let mut words = Vec::new();
+        if command.name.is_empty() && commands.len() > 1 {
+            words.push(format!(
+                "$(compgen -W \"{}\" -- \"$cur\")",
+                subcommands(commands).join(" ")
+            ));
+        }
+        if let Some(value) = command.positional {
+            words.push(bash_words(value));
+        }
+        writeln!(out, "            if [[ \"$cur\" == -* ]]; then")?;
+        writeln!(
+            out,
+            "                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+            flags.join(" ")
+        )?;
+        writeln!(out, "            else")?;
+        writeln!(out, "                COMPREPLY=({})", words.join(" "))?;
+        writeln!(out, "            fi")?;
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "complete -o filenames -F {} {}", function, bin)
+}
+
+fn bash_words(value: Value) -> String {
+    match value {
+        Value::Any => String::new(),
+        Value::Path => "$(compgen -f -- \"$cur\")".to_string(),
+        Value::Choices(choices) => format!("$(compgen -W \"{}\" -- \"$cur\")", choices.join(" ")),
+    }
+}
+
+fn zsh(out: &mut String, bin: &str, commands: &[Command]) -> fmt::Result {
+    let function = function(bin);
+    writeln!(out, "#compdef {}", bin)?;
+    writeln!(out)?;
+    writeln!(out, "{}() {{", function)?;
+    writeln!(out, "    local curcontext=\"$curcontext\" state line")?;
+    writeln!(out, "    _arguments -C -s \\")?;
+    for flag in &commands[0].flags {
+        writeln!(out, "        {} \\", zsh_flag(flag))?;
+    }
+    writeln!(out, "        '*:: :->args'")?;
+    writeln!(out, "    case $state in")?;
+    writeln!(out, "        args)")?;
+    writeln!(out, "            case $words[1] in")?;
+    for command in &commands[1..] {
+        writeln!(out, "                {})", command.name)?;
+        let mut specs = command.flags.iter().map(zsh_flag).collect::<Vec<_>>();
+        if let Some(value) = command.positional {
+            specs.push(format!("'*:argument:{}'", zsh_action(value)));
+        }
+        writeln!(out, "                    _arguments -s \\")?;
+        writeln!(
+            out,
+            "                        {}",
+            specs.join(" \\\n                        ")
+        )?;
+        writeln!(out, "                    ;;")?;
+    }
+    writeln!(out, "                *)")?;
+    writeln!(out, "                    if (( CURRENT == 1 )); then")?;
+    writeln!(out, "                        local commands=(")?;
+    for command in &commands[1..] {
+        writeln!(
+            out,
+            "                            '{}:{}'",
+            command.name,
+            command.description.replace('\'', "'\\''")
+        )?;
+    }
+    writeln!(out, "                        )")?;
+    writeln!(
+        out,
+        "                        _describe -t commands command commands"
+    )?;
+    writeln!(out, "                    fi")?;
+    if let Some(value) = commands[0].positional {
+        let command = match value {
+            Value::Any => ":".to_string(),
+            Value::Path => "_files".to_string(),
+            Value::Choices(choices) => format!("compadd -- {}", choices.join(" ")),
+        };
+        writeln!(out, "                    {}", command)?;
+    }
+    writeln!(out, "                    ;;")?;
+    writeln!(out, "            esac")?;
+    writeln!(out, "            ;;")?;
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "{} \"$@\"", function)
+}
+
+fn zsh_action(value: Value) -> String {
+    match value {
+        Value::Any => String::new(),
+        Value::Path => "_files".to_string(),
+        Value::Choices(choices) => format!("({})", choices.join(" ")),
+    }
+}
+
+/// The `_arguments` spec of `flag`.
+fn zsh_flag(flag: &Flag) -> String {
+    let description = flag
+        .description
+        .replace('\\', "\\\\")
+        .replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:");
+    let value = flag.value.map_or(String::new(), |value| {
+        format!(
+            ":{}:{}",
+            flag.long.trim_start_matches('-'),
+            zsh_action(value)
+        )
+    });
+    let names = names(flag);
+    let repeat = if flag.repeating { "'*'" } else { "" };
+    match flag.short {
+        Some(_) if flag.repeating => format!(
+            "{}{{{}}}'[{}]{}'",
+            repeat,
+            names.join(","),
+            description,
+            value
+        ),
+        Some(_) => format!(
+            "'({})'{{{}}}'[{}]{}'",
+            names.join(" "),
+            names.join(","),
+            description,
+            value
+        ),
+        None if flag.repeating => format!("'*{}[{}]{}'", flag.long, description, value),
+        None => format!("'{}[{}]{}'", flag.long, description, value),
+    }
+}
+
+fn fish(out: &mut String, bin: &str, commands: &[Command]) -> fmt::Result {
+    let quote = |text: &str| format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"));
+    let subcommands = subcommands(commands).join(" ");
+    for command in commands {
+        let condition = if command.name.is_empty() {
+            format!("not __fish_seen_subcommand_from {}", subcommands)
+        } else {
+            format!("__fish_seen_subcommand_from {}", command.name)
+        };
+        let prefix = format!("complete -c {} -n '{}'", bin, condition);
+        for flag in &command.flags {
+            let mut line = prefix.clone();
+            if let Some(short) = flag.short {
+                let _ = write!(line, " -s {}", short);
+            }
+            let _ = write!(line, " -l {}", flag.long.trim_start_matches('-'));
+            match flag.value {
+                None => {}
+                Some(Value::Any) => line.push_str(" -x"),
+                Some(Value::Path) => line.push_str(" -r -F"),
+                Some(Value::Choices(choices)) => {
+                    let _ = write!(line, " -x -a {}", quote(&choices.join(" ")));
+                }
+            }
+            writeln!(out, "{} -d {}", line, quote(&flag.description))?;
+        }
+        if command.name.is_empty() {
+            // Files are still completed next to the subcommands, as positional arguments.
+            for sub in &commands[1..] {
+                writeln!(
+                    out,
+                    "{} -a {} -d {}",
+                    prefix,
+                    sub.name,
+                    quote(&sub.description)
+                )?;
+            }
+        }
+        match command.positional {
+            Some(Value::Choices(choices)) => {
+                writeln!(out, "{} -f -a {}", prefix, quote(&choices.join(" ")))?;
+            }
+            Some(Value::Any) | None if !command.name.is_empty() => {
+                writeln!(out, "{} -f", prefix)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn powershell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn powershell(out: &mut String, bin: &str, commands: &[Command]) -> fmt::Result {
+    let quote = powershell_quote;
+    let list = |words: &[&str]| {
+        words
+            .iter()
+            .map(|word| quote(word))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    writeln!(out, "using namespace System.Management.Automation")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "Register-ArgumentCompleter -Native -CommandName {} -ScriptBlock {{",
+        quote(bin)
+    )?;
+    writeln!(
+        out,
+        "    param($wordToComplete, $commandAst, $cursorPosition)"
+    )?;
+    writeln!(out, "    $words = @($commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object {{ $_.ToString() }})")?;
+    writeln!(out, "    if ($wordToComplete) {{")?;
+    writeln!(
+        out,
+        "        $words = @($words | Select-Object -SkipLast 1)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    $commands = @({})", list(&subcommands(commands)))?;
+    writeln!(out, "    $command = ''")?;
+    writeln!(out, "    foreach ($word in $words) {{")?;
+    writeln!(out, "        if ($commands -contains $word) {{")?;
+    writeln!(out, "            $command = $word")?;
+    writeln!(out, "            break")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(
+        out,
+        "    $previous = if ($words.Count -gt 0) {{ $words[-1] }} else {{ '' }}"
+    )?;
+    writeln!(out, "    $choices = @{{")?;
+    let mut values = Vec::new();
+    for command in commands {
+        for flag in &command.flags {
+            match flag.value {
+                Some(Value::Choices(choices)) => {
+                    for name in names(flag) {
+                        writeln!(out, "        {} = @({})", quote(&name), list(choices))?;
+                    }
+                }
+                Some(_) => values.extend(names(flag)),
+                None => {}
+            }
+        }
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "    if ($choices.ContainsKey($previous)) {{")?;
+    writeln!(out, "        $choices[$previous] | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{")?;
+    writeln!(
+        out,
+        "            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)"
+    )?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        return")?;
+    writeln!(out, "    }}")?;
+    let values = values.iter().map(String::as_str).collect::<Vec<_>>();
+    writeln!(out, "    # Left to PowerShell, which completes paths.")?;
+    writeln!(out, "    if (@({}) -contains $previous) {{", list(&values))?;
+    writeln!(out, "        return")?;
+    writeln!(out, "    }}")?;
+    powershell_flags(out, commands)?;
+    writeln!(out, "    $arguments = switch ($command) {{")?;
+    for command in commands {
+        let mut words = Vec::new();
+        if command.name.is_empty() {
+            words.extend(subcommands(commands));
+        }
+        if let Some(Value::Choices(choices)) = command.positional {
+            words.extend(choices);
+        }
+        let label = if command.name.is_empty() {
+            "default".to_string()
+        } else {
+            quote(command.name)
+        };
+        writeln!(out, "        {} {{ @({}) }}", label, list(&words))?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(
+        out,
+        "    $arguments | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{"
+    )?;
+    writeln!(
+        out,
+        "        [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+/// The flags completed by PowerShell for each command.
+fn powershell_flags(out: &mut String, commands: &[Command]) -> fmt::Result {
+    let quote = powershell_quote;
+    writeln!(out, "    if ($wordToComplete.StartsWith('-')) {{")?;
+    writeln!(out, "        $flags = switch ($command) {{")?;
+    for command in commands {
+        let label = if command.name.is_empty() {
+            "default".to_string()
+        } else {
+            quote(command.name)
+        };
+        writeln!(out, "            {} {{", label)?;
+        writeln!(out, "                [ordered]@{{")?;
+        for flag in &command.flags {
+            for name in names(flag) {
+                writeln!(
+                    out,
+                    "                    {} = {}",
+                    quote(&name),
+                    quote(&flag.description)
+                )?;
+            }
+        }
+        writeln!(out, "                }}")?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "        }}")?;
+    writeln!(out, "        $flags.GetEnumerator() | Where-Object {{ $_.Key -like \"$wordToComplete*\" }} | ForEach-Object {{")?;
+    writeln!(out, "            [CompletionResult]::new($_.Key, $_.Key, [CompletionResultType]::ParameterName, $_.Value)")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        return")?;
+    writeln!(out, "    }}")?;
+    Ok(())
+}
+
+fn elvish(out: &mut String, bin: &str, commands: &[Command]) -> fmt::Result {
+    let quote = |text: &str| format!("'{}'", text.replace('\'', "''"));
+    let list = |words: &[&str]| {
+        words
+            .iter()
+            .map(|word| quote(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    writeln!(out, "use str")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "set edit:completion:arg-completer[{}] = {{|@words|",
+        bin
+    )?;
+    writeln!(out, "    var commands = [{}]", list(&subcommands(commands)))?;
+    writeln!(out, "    var command = ''")?;
+    writeln!(out, "    for word $words[1..-1] {{")?;
+    writeln!(out, "        if (has-value $commands $word) {{")?;
+    writeln!(out, "            set command = $word")?;
+    writeln!(out, "            break")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    var current = $words[-1]")?;
+    writeln!(out, "    var previous = $words[-2]")?;
+    let mut paths = Vec::new();
+    let mut values = Vec::new();
+    writeln!(out, "    var choices = [")?;
+    for command in commands {
+        for flag in &command.flags {
+            match flag.value {
+                Some(Value::Choices(choices)) => {
+                    for name in names(flag) {
+                        writeln!(out, "        &{}=[{}]", quote(&name), list(choices))?;
+                    }
+                }
+                Some(Value::Path) => paths.extend(names(flag)),
+                Some(Value::Any) => values.extend(names(flag)),
+                None => {}
+            }
+        }
+    }
+    writeln!(out, "    ]")?;
+    let paths = paths.iter().map(String::as_str).collect::<Vec<_>>();
+    let values = values.iter().map(String::as_str).collect::<Vec<_>>();
+    writeln!(out, "    if (has-key $choices $previous) {{")?;
+    writeln!(out, "        all $choices[$previous]")?;
+    writeln!(
+        out,
+        "    }} elif (has-value [{}] $previous) {{",
+        list(&paths)
+    )?;
+    writeln!(out, "        edit:complete-filename $current")?;
+    writeln!(
+        out,
+        "    }} elif (has-value [{}] $previous) {{",
+        list(&values)
+    )?;
+    for command in commands {
+        let flags = command.flags.iter().flat_map(names).collect::<Vec<_>>();
+        let flags = flags.iter().map(String::as_str).collect::<Vec<_>>();
+        writeln!(out, "    }} elif (eq $command {}) {{", quote(command.name))?;
+        writeln!(out, "        if (str:has-prefix $current -) {{")?;
+        writeln!(out, "            all [{}]", list(&flags))?;
+        writeln!(out, "        }} else {{")?;
+        if command.name.is_empty() {
+            writeln!(out, "            all $commands")?;
+        }
+        match command.positional {
+            Some(Value::Path) => writeln!(out, "            edit:complete-filename $current")?,
+            Some(Value::Choices(choices)) => writeln!(out, "            all [{}]", list(choices))?,
+            Some(Value::Any) | None => {}
+        }
+        writeln!(out, "        }}")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use argh::{FlagInfo, PositionalInfo, SubCommandInfo};
+
+    fn info() -> CommandInfoWithArgs {
+        CommandInfoWithArgs {
+            flags: &[
+                FlagInfo {
+                    kind: FlagInfoKind::Option { arg_name: "config" },
+                    optionality: Optionality::Optional,
+                    long: "--config",
+                    short: Some('c'),
+                    description: "use this configuration file.",
+                    hidden: false,
+                },
+                FlagInfo {
+                    kind: FlagInfoKind::Option { arg_name: "color" },
+                    optionality: Optionality::Optional,
+                    long: "--color",
+                    short: None,
+                    description: "when to color [the] output.",
+                    hidden: false,
+                },
+            ],
+            positionals: &[PositionalInfo {
+                name: "paths",
+                description: "paths to format.",
+                optionality: Optionality::Repeating,
+                hidden: false,
+            }],
+            commands: vec![SubCommandInfo {
+                name: "restore",
+                command: CommandInfoWithArgs {
+                    description: "Put back the files.",
+                    ..CommandInfoWithArgs::default()
+                },
+            }],
+            ..CommandInfoWithArgs::default()
+        }
+    }
+
+    fn value(name: &str) -> Value {
+        match name {
+            "--color" => Value::Choices(&["auto", "never"]),
+            _ => Value::Path,
+        }
+    }
+
+    #[test]
+    fn generates_scripts() {
+        let generate = |shell| generate(shell, "prettythanks", &info(), &value);
+        let bash = generate(Shell::Bash);
+        assert!(bash.contains("                -c|--config)\n                    COMPREPLY=($(compgen -f -- \"$cur\"))\n"));
+        assert!(bash.contains(
+            "COMPREPLY=($(compgen -W \"restore\" -- \"$cur\") $(compgen -f -- \"$cur\"))"
+        ));
+        assert!(bash.ends_with("complete -o filenames -F _prettythanks prettythanks\n"));
+        let zsh = generate(Shell::Zsh);
+        assert!(zsh.contains(
+            "'(-c --config)'{-c,--config}'[use this configuration file]:config:_files' \\\n"
+        ));
+        assert!(zsh.contains("'--color[when to color \\[the\\] output]:color:(auto never)' \\\n"));
+        assert!(zsh.contains("'restore:Put back the files'"));
+        let fish = generate(Shell::Fish);
+        assert!(fish.contains("complete -c prettythanks -n 'not __fish_seen_subcommand_from restore' -l color -x -a 'auto never' -d 'when to color [the] output'\n"));
+        assert!(fish.contains("-n '__fish_seen_subcommand_from restore' -f\n"));
+        assert!(generate(Shell::PowerShell).contains("        '--color' = @('auto', 'never')\n"));
+        assert!(generate(Shell::Elvish).contains("        &'--color'=['auto' 'never']\n"));
+        assert_eq!("powershell".parse(), Ok(Shell::PowerShell));
+    }
+}
@@ -0,0 +1,588 @@
+//! Loading of `prettythanks.toml` configuration files.
+//!
+//! The configuration is discovered by walking up from the formatted path, the first file found, or
+//! manifest with `prettythanks` metadata, wins. `PRETTYTHANKS_*` environment variables take
+//! precedence over the values in the file, and command line flags over both, except for the
+//! formatting settings of the configuration files of nested directories, see
+//! [`crate::Options::with_config`].
+
+use crate::{
+    comments::CommentPolicy,
+    edition::Edition,
+    encoding::Encoding,
+    json, log,
+    term::ColorChoice,
+    toml,
+    transform::{self, DocComments, FormatDoctests, Pipeline, SortImports},
+    Emit, Error, Fallback, LineEnding, OutputFormat, ReadOnlyPolicy, Result, SymlinkPolicy,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{collections::BTreeMap, fmt::Write, fs};
+
+/// File names looked up in every directory, in order.
+pub const CONFIG_FILES: [&str; 2] = ["prettythanks.toml", ".prettythanks.toml"];
+
+/// Manifests whose `[package.metadata.prettythanks]` and `[workspace.metadata.prettythanks]`
+/// tables are read when their directory has no configuration file.
+pub const MANIFEST: &str = "Cargo.toml";
+
+/// Prefix of the environment variables setting configuration keys, see [`Config::apply_env`].
+pub const ENV_PREFIX: &str = "PRETTYTHANKS_";
+
+/// Where the value of a key was read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The configuration file.
+    File,
+    /// The environment variable of this name.
+    Env(String),
+}
+
+/// A setting of a run and where its value comes from, see [`Config::settings`].
+#[derive(Debug, PartialEq)]
+pub struct Setting {
+    pub key: &'static str,
+    /// `null` if unset.
+    pub value: json::Value,
+    /// `default`, `command line`, the path of the configuration file or the name of the
+    /// environment variable.
+    pub source: String,
+}
+
+/// Settings read from a configuration file, `None` means the key was not set.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Location of the configuration file.
+    pub path: Option<Utf8PathBuf>,
+    /// Where the keys set were read from.
+    pub sources: BTreeMap<String, Source>,
+    /// Gitignore-style patterns to exclude, relative to the configuration file directory.
+    pub exclude: Vec<String>,
+    /// Directory `exclude` is relative to without a configuration file.
+    pub exclude_root: Option<Utf8PathBuf>,
+    pub verbose: Option<bool>,
+    pub color: Option<ColorChoice>,
+    pub check: Option<bool>,
+    pub diff: Option<bool>,
+    pub no_ignore: Option<bool>,
+    /// File extensions to format, without the leading dot.
+    pub extensions: Option<Vec<String>>,
+    pub include_markdown: Option<bool>,
+    pub jobs: Option<usize>,
+    pub output_format: Option<OutputFormat>,
+    pub comment_policy: Option<CommentPolicy>,
+    pub format_generated: Option<bool>,
+    pub follow_mods: Option<bool>,
+    pub follow_symlinks: Option<SymlinkPolicy>,
+    pub max_depth: Option<usize>,
+    pub cache: Option<bool>,
+    pub assert_idempotent: Option<bool>,
+    pub best_effort: Option<bool>,
+    pub fallback: Option<Fallback>,
+    pub format_macros: Option<bool>,
+    pub sort_imports: Option<bool>,
+    pub transforms: Vec<String>,
+    pub doc_width: Option<usize>,
+    pub format_doctests: Option<bool>,
+    pub edition: Option<Edition>,
+    pub line_ending: Option<LineEnding>,
+    pub encoding: Option<Encoding>,
+    pub write_utf8: Option<bool>,
+    pub stats: Option<bool>,
+    pub error_on_unformatted: Option<bool>,
+    pub fail_fast: Option<bool>,
+    pub max_file_size: Option<u64>,
+    pub timeout_per_file: Option<u64>,
+    pub backup: Option<bool>,
+    pub backup_suffix: Option<String>,
+    pub readonly: Option<ReadOnlyPolicy>,
+    pub emit: Option<Emit>,
+}
+
+impl Config {
+    /// Walk up from `start` looking for a configuration file.
+    pub fn discover(start: &Utf8Path) -> Result<Option<Self>> {
+        let start = start
+            .canonicalize_utf8()
+            .unwrap_or_else(|_| start.to_path_buf());
+        for dir in start.ancestors() {
+            if let Some(config) = Self::find(dir)? {
+                return Ok(Some(config));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The configuration of the directory `dir`: its configuration file or, without one, the
+    /// `prettythanks` metadata of its manifest.
+    pub fn find(dir: &Utf8Path) -> Result<Option<Self>> {
+        for name in CONFIG_FILES {
+            let path = dir.join(name);
+            if path.is_file() {
+                return Self::load(&path).map(Some);
+            }
+        }
+        let path = dir.join(MANIFEST);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path).map_err(|err| Error::io(&path, err))?;
+        // Manifests the reader can't parse can't be told apart from ones without settings.
+        let manifest = match toml::parse(&contents) {
+            Ok(manifest) => manifest,
+            Err(_) => return Ok(None),
+        };
+        let config = Self::from_manifest(&manifest).map_err(|message| Error::Config {
+            path: path.clone(),
+            message,
+        })?;
+        Ok(config.map(|config| Config {
+            path: Some(path),
+            ..config
+        }))
+    }
+
+    /// Load the configuration file at `path`, a manifest being read for its `prettythanks`
+    /// metadata.
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        let config = if path.file_name() == Some(MANIFEST) {
+            toml::parse(&contents)
+                .and_then(|manifest| Self::from_manifest(&manifest))
+                .map(Option::unwrap_or_default)
+        } else {
+            Self::parse(&contents)
+        };
+        let mut config = config.map_err(|message| Error::Config {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Parse the contents of a configuration file.
+    pub fn parse(contents: &str) -> std::result::Result<Self, String> {
+        let mut config = Config::default();
+        config.set_all(&toml::parse(contents)?)?;
+        Ok(config)
+    }
+
+    /// The settings of the `[workspace.metadata.prettythanks]` and
+    /// `[package.metadata.prettythanks]` tables of `manifest`, the latter applying over the
+    /// former, `None` if it has neither.
+    fn from_manifest(manifest: &toml::Table) -> std::result::Result<Option<Self>, String> {
+        fn table(value: Option<&toml::Value>) -> Option<&toml::Table> {
+            match value {
+                Some(toml::Value::Table(table)) => Some(table),
+                _ => None,
+            }
+        }
+        let tables = ["workspace", "package"]
+            .iter()
+            .filter_map(|section| {
+                let metadata = table(manifest.get(*section))?.get("metadata");
+                table(table(metadata)?.get("prettythanks"))
+            })
+            .collect::<Vec<_>>();
+        if tables.is_empty() {
+            return Ok(None);
+        }
+        let mut config = Config::default();
+        for table in tables {
+            config.set_all(table)?;
+        }
+        Ok(Some(config))
+    }
+
+    /// Set the keys of `table`, read from the configuration file.
+    fn set_all(&mut self, table: &toml::Table) -> std::result::Result<(), String> {
+        for (key, value) in table {
+            self.set(key, value, false)?;
+            self.sources.insert(key.clone(), Source::File);
+        }
+        Ok(())
+    }
+
+    /// Set the values of the `PRETTYTHANKS_*` variables of `vars`, like `PRETTYTHANKS_JOBS=4`,
+    /// over the ones of the file, the keys being written in upper case with underscores. Booleans
+    /// are `1`, `0`, `true` or `false` and lists are separated by commas. Without a configuration
+    /// file, excluded patterns are relative to `cwd`.
+    ///
+    /// # Errors
+    ///
+    /// If a variable sets an unknown key or an invalid value.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+        cwd: &Utf8Path,
+    ) -> Result<()> {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(ENV_PREFIX) {
+                Some(key) if name != log::ENV_VAR => key.to_ascii_lowercase().replace('_', "-"),
+                _ => continue,
+            };
+            self.set(&key, &toml::Value::String(value), true)
+                .map_err(|message| Error::Unsupported(format!("invalid {}: {}", name, message)))?;
+            if key == "exclude" && self.path.is_none() {
+                self.exclude_root = Some(cwd.to_path_buf());
+            }
+            self.sources.insert(key, Source::Env(name));
+        }
+        Ok(())
+    }
+
+    /// Set `key` to `value`, or to the text of `value` parsed as the key needs if it comes
+    /// `from_env`.
+    fn set(
+        &mut self,
+        key: &str,
+        value: &toml::Value,
+        from_env: bool,
+    ) -> std::result::Result<(), String> {
+        let text = value.as_str().filter(|_| from_env);
+        let bool_value = || {
+            let parsed = text.and_then(|text| match text {
+                "1" | "true" => Some(true),
+                "0" | "false" | "" => Some(false),
+                _ => None,
+            });
+            parsed
+                .or_else(|| value.as_bool())
+                .ok_or_else(|| match text {
+                    Some(_) => format!("`{}` must be 1, 0, true or false", key),
+                    None => format!("`{}` must be a boolean, found {}", key, value.type_name()),
+                })
+        };
+        let str_value = || {
+            value
+                .as_str()
+                .ok_or_else(|| format!("`{}` must be a string, found {}", key, value.type_name()))
+        };
+        let integer = || text.map_or(value.as_integer(), |text| text.parse().ok());
+        let usize_value = || {
+            integer()
+                .and_then(|value| usize::try_from(value).ok())
+                .ok_or_else(|| format!("`{}` must be a positive integer", key))
+        };
+        let u64_value = || {
+            integer()
+                .and_then(|value| u64::try_from(value).ok())
+                .ok_or_else(|| format!("`{}` must be a positive integer", key))
+        };
+        let list_value = || {
+            let split = text.map(|text| text.split(',').map(|item| item.trim().to_string()));
+            split
+                .map(|items| items.filter(|item| !item.is_empty()).collect())
+                .or_else(|| value.as_str_array())
+                .ok_or_else(|| format!("`{}` must be an array of strings", key))
+        };
+        match key {
+            "exclude" => self.exclude = list_value()?,
+            "verbose" => self.verbose = Some(bool_value()?),
+            "check" => self.check = Some(bool_value()?),
+            "diff" => self.diff = Some(bool_value()?),
+            "no-ignore" => self.no_ignore = Some(bool_value()?),
+            "format-generated" => self.format_generated = Some(bool_value()?),
+            "follow-mods" => self.follow_mods = Some(bool_value()?),
+            "follow-symlinks" => self.follow_symlinks = Some(str_value()?.parse()?),
+            "assert-idempotent" => self.assert_idempotent = Some(bool_value()?),
+            "best-effort" => self.best_effort = Some(bool_value()?),
+            "fallback" => self.fallback = Some(str_value()?.parse()?),
+            "format-macros" => self.format_macros = Some(bool_value()?),
+            "sort-imports" => self.sort_imports = Some(bool_value()?),
+            "transforms" => {
+                self.transforms = list_value()?;
+                for name in &self.transforms {
+                    transform::builtin(name)?;
+                }
+            }
+            "format-doctests" => self.format_doctests = Some(bool_value()?),
+            "doc-width" => self.doc_width = Some(usize_value()?),
+            "edition" => self.edition = Some(str_value()?.parse()?),
+            "line-ending" => self.line_ending = Some(str_value()?.parse()?),
+            "encoding" => self.encoding = Some(str_value()?.parse()?),
+            "write-utf8" => self.write_utf8 = Some(bool_value()?),
+            "stats" => self.stats = Some(bool_value()?),
+            "error-on-unformatted" => self.error_on_unformatted = Some(bool_value()?),
+            "fail-fast" => self.fail_fast = Some(bool_value()?),
+            "max-file-size" => self.max_file_size = Some(u64_value()?),
+            "timeout-per-file" => self.timeout_per_file = Some(u64_value()?),
+            "backup" => self.backup = Some(bool_value()?),
+            "backup-suffix" => self.backup_suffix = Some(str_value()?.to_string()),
+            "readonly" => self.readonly = Some(str_value()?.parse()?),
+            "extensions" => self.extensions = Some(list_value()?),
+            "include-markdown" => self.include_markdown = Some(bool_value()?),
+            "jobs" => self.jobs = Some(usize_value()?),
+            "max-depth" => self.max_depth = Some(usize_value()?),
+            "cache" => self.cache = Some(bool_value()?),
+            "output-format" => self.output_format = Some(str_value()?.parse()?),
+            "comment-policy" => self.comment_policy = Some(str_value()?.parse()?),
+            "color" => self.color = Some(str_value()?.parse()?),
+            "emit" => self.emit = Some(str_value()?.parse()?),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// The transforms listed in `transforms`, followed by the ones enabled on their own.
+    pub fn transforms(&self) -> Pipeline {
+        let mut pipeline = Pipeline::default();
+        for name in &self.transforms {
+            if let Ok(transform) = transform::builtin(name) {
+                pipeline.push_arc(transform);
+            }
+        }
+        if self.sort_imports == Some(true) {
+            pipeline.push(SortImports);
+        }
+        if let Some(width) = self.doc_width {
+            pipeline.push(DocComments { width: Some(width) });
+        }
+        if self.format_doctests == Some(true) {
+            pipeline.push(FormatDoctests);
+        }
+        pipeline
+    }
+
+    /// The `effective` settings of a run, like [`Options::settings`](crate::Options::settings)
+    /// lists them, with where their values come from. The ones differing from `configured`, the
+    /// settings given by this configuration alone, come from the command line.
+    pub fn settings(
+        &self,
+        configured: &[(&'static str, json::Value)],
+        effective: Vec<(&'static str, json::Value)>,
+    ) -> Vec<Setting> {
+        // Transforms enabled on their own are listed along with the others.
+        let aliases = |key| match key {
+            "transforms" => &["sort-imports", "doc-width", "format-doctests"][..],
+            "backup-suffix" => &["backup"][..],
+            _ => &[][..],
+        };
+        let patterns = self.exclude.iter().map(|pattern| pattern.as_str().into());
+        let exclude = json::Value::Array(patterns.collect());
+        let configured = [("exclude", exclude.clone())]
+            .into_iter()
+            .chain(configured.iter().cloned());
+        let configured = configured.collect::<BTreeMap<_, _>>();
+        [("exclude", exclude)]
+            .into_iter()
+            .chain(effective)
+            .map(|(key, value)| {
+                let source = if configured.get(key) == Some(&value) {
+                    let source = std::iter::once(key)
+                        .chain(aliases(key).iter().copied())
+                        .find_map(|key| self.sources.get(key));
+                    match source {
+                        Some(Source::File) => self.path.as_ref().map_or_else(
+                            || "configuration file".to_string(),
+                            Utf8PathBuf::to_string,
+                        ),
+                        Some(Source::Env(name)) => name.clone(),
+                        None => "default".to_string(),
+                    }
+                } else {
+                    "command line".to_string()
+                };
+                Setting { key, value, source }
+            })
+            .collect()
+    }
+
+    /// Directory the excludes are relative to.
+    pub fn root(&self) -> Option<&Utf8Path> {
+        let parent = self.path.as_deref().and_then(Utf8Path::parent);
+        parent.or(self.exclude_root.as_deref())
+    }
+}
+
+/// `settings` as a TOML configuration file, with where each value comes from in a comment.
+pub fn settings_toml(settings: &[Setting]) -> String {
+    let mut toml = String::new();
+    for setting in settings {
+        // JSON strings, numbers, booleans and arrays of them are valid TOML.
+        if setting.value == json::Value::Null {
+            let _ = writeln!(toml, "# {} is unset, {}", setting.key, setting.source);
+        } else {
+            let _ = writeln!(
+                toml,
+                "{} = {} # {}",
+                setting.key, setting.value, setting.source
+            );
+        }
+    }
+    toml
+}
+
+/// `settings` as a JSON object of `{"value": .., "source": ..}` objects.
+pub fn settings_json(settings: &[Setting]) -> json::Value {
+    json::Value::Object(
+        settings
+            .iter()
+            .map(|setting| {
+                let value = json::Value::object([
+                    ("value", setting.value.clone()),
+                    ("source", setting.source.as_str().into()),
+                ]);
+                (setting.key.to_string(), value)
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn parses_known_keys() {
+        let config = Config::parse(
+            "exclude = [\"src/proto/**\"]\ncheck = true\nextensions = [\"rs\", \"rs.in\"]\noutput-format = \"json\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.exclude, vec!["src/proto/**"]);
+        assert_eq!(config.check, Some(true));
+        assert_eq!(config.verbose, None);
+        assert_eq!(config.extensions.unwrap(), vec!["rs", "rs.in"]);
+        assert_eq!(config.output_format, Some(OutputFormat::Json));
+    }
+
+    #[test]
+    fn rejects_unknown_and_mistyped_keys() {
+        assert_eq!(
+            Config::parse("colour = true").unwrap_err(),
+            "unknown key `colour`"
+        );
+        assert_eq!(
+            Config::parse("check = \"yes\"").unwrap_err(),
+            "`check` must be a boolean, found string"
+        );
+        assert_eq!(
+            Config::parse("transforms = [\"sort\"]").unwrap_err(),
+            "unknown transform `sort`, expected sort-imports, doc-comments or format-doctests"
+        );
+    }
+
+    #[test]
+    fn reads_environment_variables() {
+        let vars = |vars: &[(&str, &str)]| {
+            let vars = vars
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()));
+            let mut config = Config::parse("check = false\njobs = 2\n").unwrap();
+            config
+                .apply_env(vars, Utf8Path::new("/repo"))
+                .map(|_| config)
+        };
+        let config = vars(&[
+            ("PRETTYTHANKS_CHECK", "1"),
+            ("PRETTYTHANKS_JOBS", "4"),
+            ("PRETTYTHANKS_EXCLUDE", "src/proto/**, gen"),
+            ("PRETTYTHANKS_COMMENT_POLICY", "allow"),
+            ("PRETTYTHANKS_LOG", "debug"),
+            ("PATH", "/bin"),
+        ])
+        .unwrap();
+        assert_eq!(config.check, Some(true));
+        assert_eq!(config.jobs, Some(4));
+        assert_eq!(config.exclude, vec!["src/proto/**", "gen"]);
+        assert_eq!(config.root(), Some(Utf8Path::new("/repo")));
+        assert_eq!(config.comment_policy, Some(CommentPolicy::Allow));
+        assert_eq!(
+            vars(&[("PRETTYTHANKS_CHECK", "yes")])
+                .unwrap_err()
+                .to_string(),
+            "invalid PRETTYTHANKS_CHECK: `check` must be 1, 0, true or false"
+        );
+        assert_eq!(
+            vars(&[("PRETTYTHANKS_COLOUR", "never")])
+                .unwrap_err()
+                .to_string(),
+            "invalid PRETTYTHANKS_COLOUR: unknown key `colour`"
+        );
+    }
+
+    #[test]
+    fn tells_where_settings_come_from() {
+        let mut config = Config::parse("check = true\nsort-imports = true\n").unwrap();
+        config.path = Some("prettythanks.toml".into());
+        let vars = [("PRETTYTHANKS_JOBS".to_string(), "4".to_string())];
+        config.apply_env(vars, Utf8Path::new("/repo")).unwrap();
+        let configured = [
+            ("check", true.into()),
+            (
+                "transforms",
+                json::Value::Array(vec!["sort-imports".into()]),
+            ),
+            ("jobs", 4_usize.into()),
+            ("diff", false.into()),
+            ("max-depth", json::Value::Null),
+        ];
+        let mut effective = configured.to_vec();
+        effective[3].1 = true.into();
+        let settings = config.settings(&configured, effective);
+        assert_eq!(
+            settings_toml(&settings),
+            "exclude = [] # default\ncheck = true # prettythanks.toml\n\
+             transforms = [\"sort-imports\"] # prettythanks.toml\njobs = 4 # PRETTYTHANKS_JOBS\n\
+             diff = true # command line\n# max-depth is unset, default\n"
+        );
+        let json = settings_json(&settings);
+        let jobs = json.get("jobs").unwrap();
+        assert_eq!(jobs.get("value").and_then(json::Value::as_integer), Some(4));
+        assert_eq!(
+            jobs.get("source").and_then(json::Value::as_str),
+            Some("PRETTYTHANKS_JOBS")
+        );
+    }
+
+    #[test]
+    fn reads_manifest_metadata() {
+        let temp = TempDir::new("manifest-config");
+        fs::create_dir_all(temp.join("member/src")).unwrap();
+        fs::write(
+            temp.join(MANIFEST),
+            "[workspace]\nmembers = [\"member\"]\n\n[workspace.metadata.prettythanks]\n\
+             check = true\njobs = 2\n\n[package]\nname = \"root\"\n\n\
+             [package.metadata.prettythanks]\njobs = 4\n",
+        )
+        .unwrap();
+        // Without settings, the manifest of the member is walked past.
+        fs::write(
+            temp.join("member").join(MANIFEST),
+            "[package]\nname = \"member\"\n",
+        )
+        .unwrap();
+        let config = Config::discover(&temp.join("member/src")).unwrap().unwrap();
+        assert_eq!((config.check, config.jobs), (Some(true), Some(4)));
+        assert_eq!(config.root().unwrap(), temp.canonicalize_utf8().unwrap());
+        fs::write(temp.join("member").join(CONFIG_FILES[0]), "jobs = 1\n").unwrap();
+        let config = Config::discover(&temp.join("member")).unwrap().unwrap();
+        assert_eq!((config.check, config.jobs), (None, Some(1)));
+        fs::write(
+            temp.join("member").join(MANIFEST),
+            "[package.metadata.prettythanks]\njobs = \"all\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Config::load(&temp.join("member").join(MANIFEST))
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "failed to parse config {}: `jobs` must be a positive integer",
+                temp.join("member").join(MANIFEST)
+            )
+        );
+    }
+
+    #[test]
+    fn discovers_upwards() {
+        let temp = TempDir::new("config");
+        fs::create_dir_all(temp.join("a/b")).unwrap();
+        fs::write(temp.join(".prettythanks.toml"), "verbose = true\n").unwrap();
+        let config = Config::discover(&temp.join("a/b")).unwrap().unwrap();
+        assert_eq!(config.verbose, Some(true));
+        assert_eq!(config.root().unwrap(), temp.canonicalize_utf8().unwrap());
+    }
+}
@@ -0,0 +1,250 @@
+//! A long running formatter answering JSON-RPC requests on a Unix socket, sparing editors and
+//! build tools formatting many small snippets the startup of a process per snippet.
+//!
+//! Every connection carries requests and responses one JSON object per line:
+//!
+//! - `format_text`, with `text`, and optionally a `mode` as `--parse-mode` and a `path` naming
+//!   the text in errors, returns the formatted `text` and whether it `changed`.
+//! - `format_file`, with `path`, and optionally `check` to leave the file untouched, formats the
+//!   file in place and returns whether it `changed`.
+//! - `shutdown` closes its connection and stops the daemon, once the other connections are
+//!   closed.
+
+use crate::{
+    format_file, format_with_policy,
+    json::{self, Value},
+    lsp::{error_response, response},
+    Error, Options, ParseMode, Result,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Formatting failed, the message being the one the command line would print.
+const FORMAT_FAILED: i64 = -32000;
+
+/// The socket listened on unless another one is picked: `prettythanks.sock` in
+/// `$XDG_RUNTIME_DIR`, or in the temporary directory.
+pub fn default_socket() -> Utf8PathBuf {
+    let dir = match env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => dir.into(),
+        _ => env::temp_dir(),
+    };
+    Utf8PathBuf::try_from(dir.join("prettythanks.sock"))
+        .unwrap_or_else(|_| "prettythanks.sock".into())
+}
+
+/// Listen on the Unix socket `socket` until a `shutdown` request, formatting with `options`.
+#[cfg(unix)]
+pub fn serve(socket: &Utf8Path, options: &Options) -> Result<()> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::{UnixListener, UnixStream},
+        thread,
+    };
+
+    if UnixStream::connect(socket).is_ok() {
+        return Err(Error::Unsupported(format!(
+            "a daemon is already listening on {}",
+            socket
+        )));
+    }
+    // Left behind by a daemon that didn't shut down.
+    let _ = std::fs::remove_file(socket);
+    let listener = UnixListener::bind(socket).map_err(|err| Error::io(socket, err))?;
+    let stop = AtomicBool::new(false);
+    let serve_connection = |stream: UnixStream| {
+        let mut writer = &stream;
+        for line in BufReader::new(&stream).lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            let reply = handle(&line, options, &stop);
+            if writeln!(writer, "{}", reply).is_err() || stop.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+        if stop.load(Ordering::Relaxed) {
+            // Wake up the listener blocked accepting connections, so that it sees the flag.
+            let _ = UnixStream::connect(socket);
+        }
+    };
+    let serve_connection = &serve_connection;
+    let result = thread::scope(|scope| {
+        for stream in listener.incoming() {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || serve_connection(stream));
+                }
+                Err(err) => return Err(Error::io(socket, err)),
+            }
+        }
+        Ok(())
+    });
+    let _ = std::fs::remove_file(socket);
+    result
+}
+
+/// Named pipes would need the Windows API, which no dependency of ours wraps.
+#[cfg(not(unix))]
+pub fn serve(_socket: &Utf8Path, _options: &Options) -> Result<()> {
+    Err(Error::Unsupported(
+        "the daemon needs Unix sockets, which this platform lacks".to_string(),
+    ))
+}
+
+/// Answer the request `line`, setting `stop` for `shutdown`.
+fn handle(line: &str, options: &Options, stop: &AtomicBool) -> Value {
+    let request = match json::parse(line) {
+        Ok(request) => request,
+        Err(err) => return error_response(Value::Null, PARSE_ERROR, err),
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let method = match request.get("method").and_then(Value::as_str) {
+        Some(method) => method,
+        None => return error_response(id, INVALID_REQUEST, "requests need a method"),
+    };
+    let params = request.get("params").unwrap_or(&Value::Null);
+    let param = |name: &str| params.get(name).and_then(Value::as_str);
+    let missing = |name: &str| format!("missing parameter {}", name);
+    match method {
+        "format_text" => {
+            let text = match param("text") {
+                Some(text) => text,
+                None => return error_response(id, INVALID_PARAMS, missing("text")),
+            };
+            let parse_mode = match param("mode").map(str::parse::<ParseMode>) {
+                Some(Ok(mode)) => mode,
+                Some(Err(err)) => return error_response(id, INVALID_PARAMS, err),
+                None => ParseMode::File,
+            };
+            let options = Options {
+                parse_mode,
+                ..options.clone()
+            };
+            let path = Utf8Path::new(param("path").unwrap_or("<text>"));
+            match format_with_policy(path, text, &options) {
+                Ok((formatted, _, _)) => response(
+                    id,
+                    Value::object([
+                        ("changed", (formatted != text).into()),
+                        ("text", formatted.into()),
+                    ]),
+                ),
+                Err(err) => error_response(id, FORMAT_FAILED, err.to_string()),
+            }
+        }
+        "format_file" => {
+            let path = match param("path") {
+                Some(path) => Utf8Path::new(path),
+                None => return error_response(id, INVALID_PARAMS, missing("path")),
+            };
+            let options = Options {
+                check: params.get("check").and_then(Value::as_bool) == Some(true),
+                ..options.clone()
+            };
+            match format_file(path, &options) {
+                Ok(outcome) => response(
+                    id,
+                    Value::object([
+                        ("changed", outcome.changed.into()),
+                        ("skipped", outcome.skipped.into()),
+                        ("dropped_comments", outcome.dropped_comments.into()),
+                    ]),
+                ),
+                Err(err) => error_response(id, FORMAT_FAILED, err.to_string()),
+            }
+        }
+        "shutdown" => {
+            stop.store(true, Ordering::Relaxed);
+            response(id, Value::Null)
+        }
+        _ => error_response(
+            id,
+            METHOD_NOT_FOUND,
+            format!("unsupported method {}", method),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_requests() {
+        let stop = AtomicBool::new(false);
+        let options = Options::default();
+        let handle = |line: &str| handle(line, &options, &stop).to_string();
+        assert_eq!(
+            handle(
+                r#"{"jsonrpc":"2.0","id":1,"method":"format_text","params":{"text":"fn  a(){}"}}"#
+            ),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"changed":true,"text":"fn a() {}"}}"#
+        );
+        assert_eq!(
+            handle(
+                r#"{"jsonrpc":"2.0","id":2,"method":"format_text","params":{"text":"1+1","mode":"expr"}}"#
+            ),
+            r#"{"jsonrpc":"2.0","id":2,"result":{"changed":true,"text":"1 + 1"}}"#
+        );
+        assert!(
+            handle(r#"{"id":3,"method":"format_text","params":{"text":"fn"}}"#)
+                .contains(r#""code":-32000"#)
+        );
+        assert!(handle(r#"{"id":4,"method":"format"}"#).contains(r#""code":-32601"#));
+        assert!(!stop.load(Ordering::Relaxed));
+        assert_eq!(
+            handle(r#"{"id":5,"method":"shutdown"}"#),
+            r#"{"jsonrpc":"2.0","id":5,"result":null}"#
+        );
+        assert!(stop.load(Ordering::Relaxed));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_a_socket() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            os::unix::net::UnixStream,
+            thread,
+            time::Duration,
+        };
+
+        let temp = crate::temp::TempDir::new("daemon");
+        let socket = temp.join("test.sock");
+        let daemon = {
+            let socket = socket.clone();
+            thread::spawn(move || serve(&socket, &Options::default()))
+        };
+        let stream = loop {
+            match UnixStream::connect(&socket) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        let mut lines = BufReader::new(&stream).lines();
+        let mut request = |line: &str| {
+            writeln!(&stream, "{}", line).unwrap();
+            lines.next().unwrap().unwrap()
+        };
+        assert!(
+            request(r#"{"id":1,"method":"format_text","params":{"text":"fn  a(){}"}}"#)
+                .contains(r#""changed":true"#)
+        );
+        request(r#"{"id":2,"method":"shutdown"}"#);
+        daemon.join().unwrap().unwrap();
+        assert!(!socket.exists());
+    }
+}
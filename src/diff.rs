@@ -0,0 +1,426 @@
+//! Minimal line based unified diff, good enough to review what prettyplease would change.
+
+use std::fmt::Write;
+
+/// Number of unchanged lines printed around every hunk, same default as `diff -u`.
+pub const CONTEXT_LINES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Tag {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// A single line edit, with the position in both files *before* the edit is applied.
+#[derive(Clone, Copy, Debug)]
+struct Edit {
+    tag: Tag,
+    old: usize,
+    new: usize,
+}
+
+/// The largest edit distance searched for, more different inputs being diffed as the removal of
+/// every line of the one followed by the addition of every line of the other, which keeps huge
+/// rewrites from taking forever.
+const MAX_EDITS: usize = 10_000;
+
+/// The most lines of both inputs whose changes are counted, see [`line_counts`].
+pub const MAX_COUNTED_LINES: usize = 100_000;
+
+/// Compute the shortest edit script between `a` and `b` using Myers' algorithm, in linear space:
+/// the middle of the path is searched from both ends, then each half of it is in turn.
+fn edits(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let mut script = Vec::with_capacity(a.len().max(b.len()));
+    diff_ranges(a, b, (0, a.len()), (0, b.len()), &mut script);
+    script
+}
+
+/// Push the edits turning the lines `a.0..a.1` of `old` into the lines `b.0..b.1` of `new`.
+fn diff_ranges(
+    old: &[&str],
+    new: &[&str],
+    (mut a0, mut a1): (usize, usize),
+    (mut b0, mut b1): (usize, usize),
+    script: &mut Vec<Edit>,
+) {
+    while a0 < a1 && b0 < b1 && old[a0] == new[b0] {
+        script.push(Edit {
+            tag: Tag::Equal,
+            old: a0,
+            new: b0,
+        });
+        a0 += 1;
+        b0 += 1;
+    }
+    let mut suffix = 0;
+    while a0 < a1 - suffix && b0 < b1 - suffix && old[a1 - suffix - 1] == new[b1 - suffix - 1] {
+        suffix += 1;
+    }
+    (a1, b1) = (a1 - suffix, b1 - suffix);
+    let split = if a0 == a1 || b0 == b1 {
+        None
+    } else {
+        middle(&old[a0..a1], &new[b0..b1])
+    };
+    // Both halves are closer than the whole, the ends of the ranges being different lines.
+    if let Some((x, y)) = split {
+        diff_ranges(old, new, (a0, a0 + x), (b0, b0 + y), script);
+        diff_ranges(old, new, (a0 + x, a1), (b0 + y, b1), script);
+    } else {
+        {
+            script.extend((a0..a1).map(|old| Edit {
+                tag: Tag::Delete,
+                old,
+                new: b0,
+            }));
+            script.extend((b0..b1).map(|new| Edit {
+                tag: Tag::Insert,
+                old: a1,
+                new,
+            }));
+        }
+    }
+    script.extend((0..suffix).map(|line| Edit {
+        tag: Tag::Equal,
+        old: a1 + line,
+        new: b1 + line,
+    }));
+}
+
+/// A point of a shortest path from the start of `a` and `b` to their end, halfway through it,
+/// `None` if it is more than [`MAX_EDITS`] long or the only ones are the start or the end.
+///
+/// The furthest reaching paths are extended with `d` edits from both ends at once, along every
+/// diagonal `k` where `x - y = k`, until a forward one and a backward one overlap.
+#[allow(clippy::many_single_char_names)]
+fn middle(a: &[&str], b: &[&str]) -> Option<(usize, usize)> {
+    let (n, m) = (to_isize(a.len()), to_isize(b.len()));
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let len = 2 * max_d + 2;
+    // How far along `a` the paths on each diagonal reach, from the start and from the end.
+    let (mut forward, mut backward) = (vec![-1; to_usize(len)], vec![-1; to_usize(len)]);
+    forward[to_usize(offset) + 1] = 0;
+    backward[to_usize(offset) + 1] = 0;
+    let delta = n - m;
+    // With an odd delta the paths overlap while extending the forward ones.
+    let front = delta % 2 != 0;
+    // Diagonals left out once their paths ran past the end of `a` or `b`.
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    let at = |v: &[isize], k: isize| v[to_usize(offset + k)];
+    for d in 0..max_d.min(to_isize(MAX_EDITS / 2 + 1)) {
+        let mut k1 = k1_start - d;
+        while k1 <= d - k1_end {
+            let mut x1 = if k1 == -d || (k1 != d && at(&forward, k1 - 1) < at(&forward, k1 + 1)) {
+                at(&forward, k1 + 1)
+            } else {
+                at(&forward, k1 - 1) + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && a[to_usize(x1)] == b[to_usize(y1)] {
+                x1 += 1;
+                y1 += 1;
+            }
+            forward[to_usize(offset + k1)] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if front {
+                let k2 = offset + delta - k1;
+                if (0..len).contains(&k2)
+                    && backward[to_usize(k2)] != -1
+                    && x1 >= n - backward[to_usize(k2)]
+                {
+                    return split(x1, y1, n, m);
+                }
+            }
+            k1 += 2;
+        }
+        let mut k2 = k2_start - d;
+        while k2 <= d - k2_end {
+            let mut x2 = if k2 == -d || (k2 != d && at(&backward, k2 - 1) < at(&backward, k2 + 1)) {
+                at(&backward, k2 + 1)
+            } else {
+                at(&backward, k2 - 1) + 1
+            };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && a[to_usize(n - x2 - 1)] == b[to_usize(m - y2 - 1)] {
+                x2 += 1;
+                y2 += 1;
+            }
+            backward[to_usize(offset + k2)] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !front {
+                let k1 = offset + delta - k2;
+                if (0..len).contains(&k1) && forward[to_usize(k1)] != -1 {
+                    let x1 = forward[to_usize(k1)];
+                    let y1 = offset + x1 - k1;
+                    if x1 >= n - x2 {
+                        return split(x1, y1, n, m);
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    None
+}
+
+/// The point `(x, y)` of a path splitting it in two, `None` at one of its ends.
+fn split(x: isize, y: isize, n: isize, m: isize) -> Option<(usize, usize)> {
+    let inner = (x, y) != (0, 0) && (x, y) != (n, m);
+    inner.then_some((to_usize(x), to_usize(y)))
+}
+
+/// A diagonal or line index to look up, never negative.
+fn to_usize(index: isize) -> usize {
+    usize::try_from(index).expect("a negative index")
+}
+
+/// `len` as the signed type the diagonals are numbered with.
+fn to_isize(len: usize) -> isize {
+    isize::try_from(len).expect("more lines than memory can hold")
+}
+
+/// Group edits into hunks, returning index ranges into the edit script.
+fn hunks(script: &[Edit], context: usize) -> Vec<std::ops::Range<usize>> {
+    let changes = script
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| edit.tag != Tag::Equal)
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    let mut hunks: Vec<std::ops::Range<usize>> = Vec::new();
+    for idx in changes {
+        let start = idx.saturating_sub(context);
+        let end = (idx + context + 1).min(script.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.end => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+    hunks
+}
+
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+fn push_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    if let Some(line) = line.strip_suffix('\n') {
+        out.push_str(line);
+        out.push('\n');
+    } else {
+        out.push_str(line);
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+/// Render a unified diff between `old` and `new`, labelling the two sides with the given names.
+///
+/// Returns an empty string if the two inputs are identical.
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str, context: usize) -> String {
+    if old == new {
+        return String::new();
+    }
+    let a = old.split_inclusive('\n').collect::<Vec<_>>();
+    let b = new.split_inclusive('\n').collect::<Vec<_>>();
+    let script = edits(&a, &b);
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for hunk in hunks(&script, context) {
+        let edits = &script[hunk];
+        let old_len = edits.iter().filter(|e| e.tag != Tag::Insert).count();
+        let new_len = edits.iter().filter(|e| e.tag != Tag::Delete).count();
+        let _ = writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(edits[0].old, old_len),
+            range(edits[0].new, new_len)
+        );
+        for edit in edits {
+            match edit.tag {
+                Tag::Equal => push_line(&mut out, ' ', a[edit.old]),
+                Tag::Delete => push_line(&mut out, '-', a[edit.old]),
+                Tag::Insert => push_line(&mut out, '+', b[edit.new]),
+            }
+        }
+    }
+    out
+}
+
+/// The number of lines of `old` removed and of lines added to it to get `new`. Inputs of more
+/// than [`MAX_COUNTED_LINES`] are counted as replaced as a whole.
+pub fn line_counts(old: &str, new: &str) -> (usize, usize) {
+    if old == new {
+        return (0, 0);
+    }
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    if old.len() + new.len() > MAX_COUNTED_LINES {
+        return (old.len(), new.len());
+    }
+    edits(&old, &new)
+        .into_iter()
+        .fold((0, 0), |(removed, added), edit| match edit.tag {
+            Tag::Equal => (removed, added),
+            Tag::Delete => (removed + 1, added),
+            Tag::Insert => (removed, added + 1),
+        })
+}
+
+/// A block of changed lines, the `old` lines being replaced by the `new` ones.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub old: std::ops::Range<usize>,
+    pub new: std::ops::Range<usize>,
+}
+
+/// The blocks of lines changed from `old` to `new`, in order.
+pub fn changes(old: &[&str], new: &[&str]) -> Vec<Change> {
+    let mut changes: Vec<Change> = Vec::new();
+    let mut in_change = false;
+    for edit in edits(old, new) {
+        if edit.tag == Tag::Equal {
+            in_change = false;
+            continue;
+        }
+        if !in_change {
+            changes.push(Change {
+                old: edit.old..edit.old,
+                new: edit.new..edit.new,
+            });
+            in_change = true;
+        }
+        let change = changes.last_mut().expect("change pushed above");
+        if edit.tag == Tag::Delete {
+            change.old.end += 1;
+        } else {
+            change.new.end += 1;
+        }
+    }
+    changes
+}
+
+/// Render a diff between `old` and `new` as a git patch of the file at `path`, relative to the
+/// root of the repository, which `git apply` accepts.
+///
+/// Returns an empty string if the two inputs are identical.
+pub fn patch(old: &str, new: &str, path: &str) -> String {
+    let diff = unified(
+        old,
+        new,
+        &format!("a/{}", path),
+        &format!("b/{}", path),
+        CONTEXT_LINES,
+    );
+    if diff.is_empty() {
+        return diff;
+    }
+    format!("diff --git a/{} b/{}\n{}", path, path, diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_inputs_have_no_diff() {
+        assert_eq!(unified("a\nb\n", "a\nb\n", "old", "new", CONTEXT_LINES), "");
+    }
+
+    #[test]
+    fn renders_hunks_with_context() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n11\n";
+        let diff = unified(old, new, "a.rs", "b.rs", 1);
+        assert_eq!(
+            diff,
+            "--- a.rs\n+++ b.rs\n@@ -4,3 +4,3 @@\n 4\n-5\n+five\n 6\n@@ -10 +10,2 @@\n 10\n+11\n"
+        );
+    }
+
+    #[test]
+    fn renders_git_patches() {
+        assert_eq!(
+            patch("a\n", "b\n", "src/lib.rs"),
+            "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1 +1 @@\n-a\n+b\n"
+        );
+        assert_eq!(patch("a\n", "a\n", "src/lib.rs"), "");
+    }
+
+    #[test]
+    fn lists_changed_blocks() {
+        let old = ["1\n", "2\n", "3\n", "4\n", "5\n"];
+        let new = ["1\n", "two\n", "3\n", "4\n", "5\n", "6\n"];
+        assert_eq!(
+            changes(&old, &new),
+            [
+                Change {
+                    old: 1..2,
+                    new: 1..2
+                },
+                Change {
+                    old: 5..5,
+                    new: 5..6
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_large_inputs() {
+        // A single line split into many, like generated code formatted for the first time.
+        let old = (0..20_000)
+            .map(|i| format!("let a{} = 0;", i))
+            .collect::<String>();
+        let new = (0..20_000)
+            .map(|i| format!("    let a{} = 0;\n", i))
+            .collect::<String>();
+        assert_eq!(line_counts(&old, &new), (1, 20_000));
+        let diff = unified(&old, &new, "old", "new", CONTEXT_LINES);
+        assert!(diff.starts_with("--- old\n+++ new\n@@ -1 +1,20000 @@\n"));
+        // More different than the edit distance searched for, replaced as a whole.
+        let old = (0..MAX_EDITS)
+            .map(|i| format!("{}\n", i))
+            .collect::<String>();
+        let new = (0..MAX_EDITS)
+            .map(|i| format!("{}\n", i + MAX_EDITS))
+            .collect::<String>();
+        assert_eq!(line_counts(&old, &new), (MAX_EDITS, MAX_EDITS));
+        let old = "a\nb\nc\nd\ne\n".split_inclusive('\n').collect::<Vec<_>>();
+        let new = "b\nx\nd\ne\nf\n".split_inclusive('\n').collect::<Vec<_>>();
+        let script = edits(&old, &new);
+        let tags = script.iter().map(|edit| edit.tag).collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            [
+                Tag::Delete,
+                Tag::Equal,
+                Tag::Delete,
+                Tag::Insert,
+                Tag::Equal,
+                Tag::Equal,
+                Tag::Insert
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_missing_final_newline() {
+        let diff = unified("a", "a\n", "old", "new", CONTEXT_LINES);
+        assert_eq!(
+            diff,
+            "--- old\n+++ new\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+a\n"
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! Normalization of doc comments, see [`normalize`].
+
+use proc_macro2::{Delimiter, Group, Literal, TokenStream, TokenTree};
+use quote::ToTokens;
+
+/// The lines of the block doc comment `text`, without the blank lines opening and closing it and
+/// the `*` the other lines may start with.
+fn block_lines(text: &str) -> Vec<&str> {
+    let mut lines = text.lines().collect::<Vec<_>>();
+    if lines.len() > 1 && lines.last().map_or(false, |line| line.trim().is_empty()) {
+        lines.pop();
+    }
+    // The first line is only decorated if it is not on the line opening the comment.
+    let mut first = 1;
+    if lines.len() > 1 && lines[0].trim().is_empty() {
+        lines.remove(0);
+        first = 0;
+    }
+    let decorated = |line: &&str| line.trim_start().starts_with('*');
+    if lines.len() > first && lines[first..].iter().all(decorated) {
+        for line in &mut lines[first..] {
+            *line = &line.trim_start()[1..];
+        }
+    }
+    lines
+}
+
+/// The value of the doc attribute whose bracketed tokens are `attr`, if it is one.
+fn doc_value(attr: &Group) -> Option<String> {
+    let mut tokens = attr.stream().into_iter();
+    match (tokens.next(), tokens.next(), tokens.next(), tokens.next()) {
+        (
+            Some(TokenTree::Ident(name)),
+            Some(TokenTree::Punct(eq)),
+            Some(TokenTree::Literal(lit)),
+            None,
+        ) if name == "doc" && eq.as_char() == '=' => {
+            let lit = syn::parse2::<syn::LitStr>(lit.into_token_stream()).ok()?;
+            Some(lit.value())
+        }
+        _ => None,
+    }
+}
+
+/// The doc attribute starting at `trees[idx]`: its value, whether it is an inner one and how many
+/// trees it spans.
+fn doc_attribute(trees: &[TokenTree], idx: usize) -> Option<(String, bool, usize)> {
+    // `#`, maybe `!`, then `[doc = "..."]`.
+    let bang = matches!(trees.get(idx + 1), Some(TokenTree::Punct(p)) if p.as_char() == '!');
+    match (trees.get(idx)?, trees.get(idx + 1 + usize::from(bang))?) {
+        (TokenTree::Punct(pound), TokenTree::Group(attr))
+            if pound.as_char() == '#' && attr.delimiter() == Delimiter::Bracket =>
+        {
+            Some((doc_value(attr)?, bang, 2 + usize::from(bang)))
+        }
+        _ => None,
+    }
+}
+
+/// Whether the doc line `text` must be kept as is: a heading, a table, a quote, HTML, a link
+/// definition or indented code.
+fn is_verbatim(text: &str) -> bool {
+    let trimmed = text.trim_start();
+    text.starts_with("    ")
+        || ["#", "|", ">", "<"]
+            .iter()
+            .any(|start| trimmed.starts_with(start))
+        || (trimmed.starts_with('[') && trimmed.contains("]:"))
+}
+
+/// The length of the list marker starting the doc line `text`, its indentation included.
+fn list_marker(text: &str) -> Option<usize> {
+    let indent = text.len() - text.trim_start().len();
+    let rest = &text[indent..];
+    let marker = if rest.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let numbered = digits > 0 && rest[digits..].starts_with(['.', ')']);
+        if !numbered {
+            return None;
+        }
+        digits + 1
+    };
+    rest[marker..].starts_with(' ').then(|| indent + marker + 1)
+}
+
+/// A paragraph being rewrapped.
+struct Paragraph {
+    /// Printed before the first line, and the next ones.
+    first: String,
+    rest: String,
+    words: Vec<String>,
+}
+
+impl Paragraph {
+    fn wrap(self, width: usize, out: &mut Vec<String>) {
+        let mut line = self.first;
+        let mut empty = true;
+        for word in self.words {
+            if !empty && line.chars().count() + 1 + word.chars().count() > width {
+                out.push(std::mem::replace(&mut line, self.rest.clone()));
+                empty = true;
+            }
+            if !empty {
+                line.push(' ');
+            }
+            line.push_str(&word);
+            empty = false;
+        }
+        out.push(line);
+    }
+}
+
+/// The doc lines `lines` with their paragraphs and list items rewrapped to `width` characters,
+/// leaving code, headings and the like alone.
+fn reflow(lines: &[String], width: usize) -> Vec<String> {
+    let mut out = Vec::with_capacity(lines.len());
+    let mut paragraph: Option<Paragraph> = None;
+    let mut fence: Option<&str> = None;
+    for line in lines {
+        // Doc comments usually start with a space, kept out of the text.
+        let (space, text) = match line.strip_prefix(' ') {
+            Some(text) => (" ", text),
+            None => ("", line.as_str()),
+        };
+        let trimmed = text.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            out.push(line.clone());
+            continue;
+        }
+        let opens = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+        let marker = list_marker(text);
+        let continues = paragraph.is_some() && marker.is_none();
+        if opens.is_some() || trimmed.is_empty() || is_verbatim(text) && !continues {
+            if let Some(paragraph) = paragraph.take() {
+                paragraph.wrap(width, &mut out);
+            }
+            fence = opens;
+            out.push(line.clone());
+            continue;
+        }
+        let words = trimmed.split_whitespace().map(String::from);
+        match (&mut paragraph, marker) {
+            (Some(paragraph), None) => paragraph.words.extend(words),
+            (_, marker) => {
+                if let Some(paragraph) = paragraph.take() {
+                    paragraph.wrap(width, &mut out);
+                }
+                let indent = text.len() - trimmed.len();
+                let (first, rest) = match marker {
+                    Some(len) => (
+                        format!("{}{}", space, &text[..len]),
+                        format!("{}{}", space, " ".repeat(len)),
+                    ),
+                    None => (
+                        format!("{}{}", space, &text[..indent]),
+                        format!("{}{}", space, &text[..indent]),
+                    ),
+                };
+                let words = match marker {
+                    Some(len) => text[len..].split_whitespace().map(String::from).collect(),
+                    None => words.collect(),
+                };
+                paragraph = Some(Paragraph { first, rest, words });
+            }
+        }
+        // A backslash ends a line on purpose.
+        if trimmed.ends_with('\\') {
+            if let Some(paragraph) = paragraph.take() {
+                paragraph.wrap(width, &mut out);
+            }
+        }
+    }
+    if let Some(paragraph) = paragraph {
+        paragraph.wrap(width, &mut out);
+    }
+    out
+}
+
+/// A rewrite of the doc attributes of a syntax tree, given the values of a run of them and how many
+/// blocks deep they are, returning the values of the attributes replacing them or `None` to leave
+/// them alone.
+pub(crate) type Rewrite<'a> = &'a dyn Fn(&[String], usize) -> Option<Vec<String>>;
+
+/// Push to `out` the run of doc attributes starting at `trees[idx]`, inner ones if `inner`,
+/// rewritten, returning the index of the tree following them.
+fn rewrite_run(
+    trees: &[TokenTree],
+    start: usize,
+    inner: bool,
+    depth: usize,
+    rewrite: Rewrite<'_>,
+    out: &mut TokenStream,
+) -> usize {
+    let mut values = Vec::new();
+    let mut idx = start;
+    while let Some((value, bang, len)) = doc_attribute(trees, idx) {
+        if bang != inner {
+            break;
+        }
+        values.push(value);
+        idx += len;
+    }
+    let values = if let Some(values) = rewrite(&values, depth) {
+        values
+    } else {
+        out.extend(trees[start..idx].iter().cloned());
+        return idx;
+    };
+    for value in values {
+        let lit = Literal::string(&value);
+        out.extend(if inner {
+            quote::quote!(#![doc = #lit])
+        } else {
+            quote::quote!(#[doc = #lit])
+        });
+    }
+    idx
+}
+
+/// `stream`, nested `depth` blocks deep, with its runs of doc attributes rewritten.
+fn rewrite_stream(stream: TokenStream, depth: usize, rewrite: Rewrite<'_>) -> TokenStream {
+    let trees = stream.into_iter().collect::<Vec<_>>();
+    let mut out = TokenStream::new();
+    let mut idx = 0;
+    while idx < trees.len() {
+        if let Some((_, inner, _)) = doc_attribute(&trees, idx) {
+            idx = rewrite_run(&trees, idx, inner, depth, rewrite, &mut out);
+            continue;
+        }
+        out.extend([match &trees[idx] {
+            TokenTree::Group(group) => {
+                let depth = depth + usize::from(group.delimiter() == Delimiter::Brace);
+                let stream = rewrite_stream(group.stream(), depth, rewrite);
+                let mut rewritten = Group::new(group.delimiter(), stream);
+                rewritten.set_span(group.span());
+                TokenTree::Group(rewritten)
+            }
+            tree => tree.clone(),
+        }]);
+        idx += 1;
+    }
+    out
+}
+
+/// Rewrite the runs of consecutive doc attributes of `file` with `rewrite`.
+pub(crate) fn rewrite(file: &mut syn::File, rewrite: Rewrite<'_>) {
+    let stream = rewrite_stream(file.to_token_stream(), 0, rewrite);
+    // Printing the file leaves its shebang out.
+    if let Ok(rewritten) = syn::parse2::<syn::File>(stream) {
+        *file = syn::File {
+            shebang: file.shebang.take(),
+            ..rewritten
+        };
+    }
+}
+
+/// Doc comments are never rewrapped narrower than this, however deep they are.
+const MIN_WIDTH: usize = 40;
+
+/// Turn the block doc comments of `file`, `/** */` and `/*! */`, into line ones, `///` and `//!`,
+/// dropping the `*` decorating their lines and trimming the whitespace ending them. With `width`,
+/// paragraphs and list items are also rewrapped to fit in as many columns, leaving code blocks,
+/// headings, tables and the like alone.
+///
+/// prettyplease prints doc attributes spanning several lines as block comments, and single line
+/// ones as line comments, so this splits the former into one attribute per line.
+pub fn normalize(file: &mut syn::File, width: Option<usize>) {
+    rewrite(file, &|values, depth| {
+        let lines = values
+            .iter()
+            .flat_map(|value| block_lines(value))
+            .map(|line| line.trim_end().to_string())
+            .collect::<Vec<_>>();
+        Some(match width {
+            // prettyplease indents every block by 4 columns, and `///` takes 3.
+            Some(width) => reflow(&lines, width.saturating_sub(depth * 4 + 3).max(MIN_WIDTH)),
+            None => lines,
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_block_docs() {
+        let mut file = syn::parse_file(
+            "/*! Crate.\n *\n * More. */\n/**\n * Hello\n *   world\n */\nfn a() {}\n/** One. */\n\
+             mod m { #[doc = \"Two\\nlines.\"] fn b() {} }",
+        )
+        .unwrap();
+        normalize(&mut file, None);
+        assert_eq!(
+            prettyplease::unparse(&file),
+            "//! Crate.\n//!\n//! More.\n/// Hello\n///   world\nfn a() {}\n/// One.\nmod m {\n    \
+             ///Two\n    ///lines.\n    fn b() {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn rewraps_paragraphs() {
+        let lines = [
+            " Some words that are",
+            " going to be joined and wrapped.",
+            "",
+            " - a list item running",
+            "   on two lines",
+            " 10. numbered",
+            "",
+            " ```",
+            " let code = \"stays as it is\";",
+            " ```",
+            " # Heading that would not fit",
+        ];
+        let lines = lines
+            .iter()
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reflow(&lines, 24),
+            [
+                " Some words that are",
+                " going to be joined and",
+                " wrapped.",
+                "",
+                " - a list item running",
+                "   on two lines",
+                " 10. numbered",
+                "",
+                " ```",
+                " let code = \"stays as it is\";",
+                " ```",
+                " # Heading that would not fit",
+            ]
+        );
+        let mut file = syn::parse_file(
+            "mod m {\n    /// One two three four five six seven eight nine ten eleven twelve.\n    \
+             fn a() {}\n}\n",
+        )
+        .unwrap();
+        normalize(&mut file, Some(50));
+        assert_eq!(
+            prettyplease::unparse(&file),
+            "mod m {\n    /// One two three four five six seven eight\n    /// nine ten eleven \
+             twelve.\n    fn a() {}\n}\n"
+        );
+    }
+}
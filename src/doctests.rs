@@ -0,0 +1,208 @@
+//! Formatting of the Rust code blocks of doc comments, see [`format`].
+
+use crate::{comments, docs, format_snippet, ParseMode};
+
+/// The attributes of the info string `info` of a code block, like `rust` and `no_run`.
+fn attributes(info: &str) -> impl Iterator<Item = &str> {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|attribute| !attribute.is_empty())
+}
+
+/// Whether the info string `info` of a code block marks it as Rust, as rustdoc takes code blocks
+/// without one or with only its own attributes to be Rust.
+fn is_rust(info: &str) -> bool {
+    attributes(info).all(|attribute| {
+        let error_code = attribute.len() > 1
+            && attribute.starts_with('E')
+            && attribute[1..].bytes().all(|b| b.is_ascii_digit());
+        matches!(
+            attribute,
+            "rust" | "ignore" | "should_panic" | "no_run" | "compile_fail" | "test_harness"
+        ) || attribute.starts_with("edition")
+            || attribute.starts_with("ignore-")
+            || error_code
+    })
+}
+
+/// The indentation, the marker and the info string of the fence opening a code block on `line`, if
+/// it does.
+fn fence(line: &str) -> Option<(&str, &str, &str)> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let fence = ['`', '~']
+        .iter()
+        .map(|c| trimmed.len() - trimmed.trim_start_matches(*c).len())
+        .find(|len| *len >= 3)?;
+    let (marker, info) = trimmed.split_at(fence);
+    // Backticks can't be part of the info string of a backtick fence.
+    let valid = !(marker.starts_with('`') && info.contains('`'));
+    valid.then_some((indent, marker, info))
+}
+
+/// The code `source` formatted as a file, or as the statements of a function body, with its
+/// regular comments put back.
+fn format_code(source: &str) -> Option<String> {
+    let formatted = format_snippet(source, ParseMode::File)
+        .or_else(|_| format_snippet(source, ParseMode::Stmt))
+        .ok()?;
+    if comments::regular_comments(source).is_empty() {
+        Some(formatted)
+    } else {
+        comments::reattach(source, &formatted)
+    }
+}
+
+/// Whether each line of `formatted`, the formatted `source`, is hidden, from whether each line of
+/// `source` is. `None` if a line mixes hidden and visible code.
+fn hidden_lines(source: &str, hidden: &[bool], formatted: &str) -> Option<Vec<bool>> {
+    let old = comments::lex(source);
+    let mut lines = vec![None; formatted.lines().count()];
+    let mut idx = 0;
+    let mut previous = false;
+    for lexeme in comments::lex(formatted) {
+        // prettyplease adds and removes a few tokens, like trailing commas, resync past them.
+        let matching = old[idx..]
+            .iter()
+            .take(8)
+            .position(|old| old.text == lexeme.text);
+        if let Some(skipped) = matching {
+            previous = hidden[old[idx + skipped].line - 1];
+            idx += skipped + 1;
+        }
+        let last = lexeme.line + lexeme.text.matches('\n').count();
+        for line in &mut lines[lexeme.line - 1..last] {
+            match line {
+                Some(hidden) if *hidden != previous => return None,
+                _ => *line = Some(previous),
+            }
+        }
+    }
+    Some(lines.into_iter().map(|line| line == Some(true)).collect())
+}
+
+/// The lines `code` of a code block whose fence is indented by `indent`, formatted, keeping the
+/// lines rustdoc hides hidden. `None` if they can't be formatted.
+fn format_block(code: &[&str], indent: &str) -> Option<Vec<String>> {
+    let mut source = String::new();
+    let mut hidden = Vec::with_capacity(code.len());
+    for line in code {
+        let line = line
+            .strip_prefix(indent)
+            .unwrap_or_else(|| line.trim_start());
+        let trimmed = line.trim_start();
+        // `##` escapes a `#` starting a line that is not hidden, leave those to the authors.
+        if trimmed.starts_with("##") {
+            return None;
+        }
+        let (is_hidden, text) = match trimmed.strip_prefix('#') {
+            Some("") => (true, ""),
+            Some(text) if text.starts_with(' ') => (true, &text[1..]),
+            _ => (false, line),
+        };
+        hidden.push(is_hidden);
+        source.push_str(text);
+        source.push('\n');
+    }
+    let formatted = format_code(&source)?;
+    let hidden = hidden_lines(&source, &hidden, &formatted)?;
+    let lines =
+        formatted
+            .lines()
+            .zip(hidden)
+            .map(|(line, hidden)| match (hidden, line.is_empty()) {
+                (true, true) => format!("{}#", indent),
+                (true, false) => format!("{}# {}", indent, line),
+                (false, true) => indent.trim_end().to_string(),
+                (false, false) => format!("{}{}", indent, line),
+            });
+    Some(lines.collect())
+}
+
+/// The lines `lines` with their Rust code blocks formatted, `None` if none changed. Blocks without
+/// an info string are only taken to be Rust ones with `bare_rust`, like in doc comments.
+pub(crate) fn format_blocks(lines: &[&str], bare_rust: bool) -> Option<Vec<String>> {
+    let mut out = Vec::with_capacity(lines.len());
+    let mut changed = false;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        out.push(line.to_string());
+        idx += 1;
+        let (indent, marker, info) = match fence(line) {
+            Some(fence) => fence,
+            None => continue,
+        };
+        let end = match lines[idx..]
+            .iter()
+            .position(|line| line.trim_start().starts_with(marker))
+        {
+            Some(len) => idx + len,
+            // An unclosed block runs to the end of the comment.
+            None => lines.len(),
+        };
+        let code = &lines[idx..end];
+        let tagged = attributes(info).any(|attribute| attribute == "rust");
+        let formatted = if is_rust(info) && (bare_rust || tagged) {
+            format_block(code, indent)
+        } else {
+            None
+        };
+        match formatted {
+            Some(formatted) if formatted.iter().ne(code.iter()) => {
+                out.extend(formatted);
+                changed = true;
+            }
+            _ => out.extend(code.iter().map(|line| (*line).to_string())),
+        }
+        out.extend(lines.get(end).map(|line| (*line).to_string()));
+        idx = end + 1;
+    }
+    changed.then_some(out)
+}
+
+/// Format the Rust code blocks of the doc comments of `file`, the ones without an info string or
+/// marked `rust` or with rustdoc attributes like `no_run`, as prettyplease would.
+///
+/// Lines starting with `#`, which rustdoc hides, are formatted with the others and stay hidden.
+/// Blocks that don't parse as a file or as the body of a function, such as fragments, are left
+/// alone, as are blocks whose comments can't be put back or whose hidden lines would end up on
+/// the same line as visible code.
+pub fn format(file: &mut syn::File) {
+    docs::rewrite(file, &|values, _| {
+        let lines = values
+            .iter()
+            .flat_map(|value| value.split('\n'))
+            .collect::<Vec<_>>();
+        let formatted = format_blocks(&lines, true)?;
+        // A single attribute is a block comment, or prettyplease will print it as one.
+        Some(if values.len() == 1 {
+            vec![formatted.join("\n")]
+        } else {
+            formatted
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_code_blocks() {
+        let source = "/// Example:\n///\n/// ```\n/// # use std::io;\n/// # fn main() -> \
+                      io::Result<()> {\n/// let x=vec![1,2];// two\n/// # Ok(()) }\n/// ```\n\
+                      ///\n/// ```text\n/// not   rust\n/// ```\n/// ```no_run\n/// let  \
+                      y = foo(\n/// ```\nfn a() {}\n";
+        let mut file = syn::parse_file(source).unwrap();
+        format(&mut file);
+        assert_eq!(
+            prettyplease::unparse(&file),
+            "/// Example:\n///\n/// ```\n/// # use std::io;\n/// # fn main() -> \
+             io::Result<()> {\n///     let x = vec![1, 2]; // two\n/// #     Ok(())\n/// # }\n\
+             /// ```\n///\n/// ```text\n/// not   rust\n/// ```\n/// ```no_run\n/// let  y = \
+             foo(\n/// ```\nfn a() {}\n"
+        );
+        assert!(is_rust("rust,should_panic") && is_rust("") && is_rust("compile_fail,E0308"));
+        assert!(!is_rust("toml") && !is_rust("rust,sh"));
+    }
+}
@@ -0,0 +1,232 @@
+//! Rust editions, picked on the command line or found in the nearest cargo manifest, see
+//! [`detect`].
+
+use crate::toml::{self, Value};
+use camino::{Utf8Path, Utf8PathBuf};
+use proc_macro2::{Group, Ident, TokenStream, TokenTree};
+use std::{
+    fmt, fs,
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+};
+
+/// A Rust edition.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    /// The edition of crates that don't pick one, and of rustc.
+    #[default]
+    E2015,
+    E2018,
+    E2021,
+    E2024,
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            "2024" => Ok(Edition::E2024),
+            _ => Err(format!(
+                "unknown edition `{}`, expected 2015, 2018, 2021 or 2024",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        })
+    }
+}
+
+/// The keywords of later editions that are identifiers in 2015.
+const KEYWORDS_2015: [&str; 3] = ["async", "await", "try"];
+
+/// The value at `key` of the table `value`.
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Table(table) => table.get(key),
+        _ => None,
+    }
+}
+
+/// The `edition`s of the `[package]` and `[workspace.package]` tables of the manifest `manifest`,
+/// the former being `None` if it is inherited from the workspace.
+fn manifest_editions(manifest: &Utf8Path) -> Option<(Option<Edition>, Option<Edition>)> {
+    let contents = fs::read_to_string(manifest).ok()?;
+    let table = toml::parse(&contents).ok()?;
+    let edition = |table: Option<&Value>| match get(table?, "edition")? {
+        Value::String(edition) => Some(edition.parse().ok()),
+        // `edition.workspace = true`.
+        _ => Some(None),
+    };
+    let package = edition(table.get("package"));
+    let workspace = table
+        .get("workspace")
+        .and_then(|workspace| edition(get(workspace, "package")))
+        .flatten();
+    // Packages without an edition are 2015 ones.
+    let package = match package {
+        Some(edition) => edition,
+        None if table.contains_key("package") => Some(Edition::E2015),
+        None => None,
+    };
+    Some((package, workspace))
+}
+
+/// The edition of the package whose manifest is `manifest`, looking for the workspace it
+/// inherits it from above it if needed.
+fn package_edition(manifest: &Utf8Path) -> Option<Edition> {
+    let (package, workspace) = manifest_editions(manifest)?;
+    if package.is_some() {
+        return package;
+    }
+    if workspace.is_some() {
+        return workspace;
+    }
+    manifest
+        .parent()?
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join("Cargo.toml"))
+        .filter(|manifest| manifest.is_file())
+        .find_map(|manifest| manifest_editions(&manifest)?.1)
+}
+
+/// The editions found for the directories already looked at.
+static DETECTED: Mutex<Vec<(Utf8PathBuf, Option<Edition>)>> = Mutex::new(Vec::new());
+
+/// The edition of the package the file `path` belongs to, from the nearest `Cargo.toml` above it,
+/// `None` if there's none.
+pub fn detect(path: &Utf8Path) -> Option<Edition> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_str().is_empty() => dir,
+        _ => Utf8Path::new("."),
+    };
+    let mut detected = DETECTED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, edition)) = detected.iter().find(|(known, _)| known == dir) {
+        return *edition;
+    }
+    let absolute = dir
+        .canonicalize_utf8()
+        .unwrap_or_else(|_| dir.to_path_buf());
+    let edition = absolute
+        .ancestors()
+        .map(|dir| dir.join("Cargo.toml"))
+        .find(|manifest| manifest.is_file())
+        .and_then(|manifest| package_edition(&manifest));
+    detected.push((dir.to_path_buf(), edition));
+    edition
+}
+
+/// `stream` with the identifiers that are keywords after 2015 turned into raw identifiers.
+fn raw_keywords(stream: TokenStream) -> TokenStream {
+    stream
+        .into_iter()
+        .map(|tree| match tree {
+            TokenTree::Ident(ident) if KEYWORDS_2015.iter().any(|keyword| ident == keyword) => {
+                TokenTree::Ident(Ident::new_raw(&ident.to_string(), ident.span()))
+            }
+            TokenTree::Group(group) => {
+                let mut raw = Group::new(group.delimiter(), raw_keywords(group.stream()));
+                raw.set_span(group.span());
+                TokenTree::Group(raw)
+            }
+            tree => tree,
+        })
+        .collect()
+}
+
+/// Whether [`parse_file`] may make identifiers of the source file `source` of the edition
+/// `edition` raw: it is a 2015 one that has no raw keywords of its own.
+fn makes_raw(source: &str, edition: Edition) -> bool {
+    edition == Edition::E2015
+        && !KEYWORDS_2015
+            .iter()
+            .any(|keyword| source.contains(&format!("r#{}", keyword)))
+}
+
+/// Parse the source file `source` of the edition `edition`.
+///
+/// syn parses the syntax of the latest edition, where `async`, `await` and `try` are keywords. They
+/// are identifiers in 2015 sources, which are parsed again with them as raw identifiers if they
+/// don't parse, see [`unraw_keywords`].
+pub(crate) fn parse_file(source: &str, edition: Edition) -> syn::Result<syn::File> {
+    let err = match syn::parse_file(source) {
+        Ok(file) => return Ok(file),
+        Err(err) => err,
+    };
+    if !makes_raw(source, edition) {
+        return Err(err);
+    }
+    let stream = source.parse::<TokenStream>().map_err(|_| err.clone())?;
+    syn::parse2(raw_keywords(stream)).map_err(|_| err)
+}
+
+/// The source printed for a file parsed by [`parse_file`] as an `edition` source, with the
+/// identifiers it made raw back to what they were.
+pub(crate) fn unraw_keywords(printed: String, source: &str, edition: Edition) -> String {
+    if !makes_raw(source, edition) || !printed.contains("r#") {
+        return printed;
+    }
+    KEYWORDS_2015.iter().fold(printed, |printed, keyword| {
+        printed.replace(&format!("r#{}", keyword), keyword)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn parses_2015_identifiers() {
+        let source = "fn async(try: u8) -> u8 { try }";
+        assert!(parse_file(source, Edition::E2021).is_err());
+        let file = parse_file(source, Edition::E2015).unwrap();
+        assert_eq!(
+            unraw_keywords(prettyplease::unparse(&file), source, Edition::E2015),
+            "fn async(try: u8) -> u8 {\n    try\n}\n"
+        );
+    }
+
+    #[test]
+    fn detects_editions() {
+        let temp = TempDir::new("edition");
+        for dir in ["a/src", "b/src", "c/src"] {
+            fs::create_dir_all(temp.join(dir)).unwrap();
+        }
+        let manifests = [
+            (
+                "Cargo.toml",
+                "[workspace]\nmembers = [\"a\", \"b\"]\n[workspace.package]\nedition = \"2024\"\n",
+            ),
+            (
+                "a/Cargo.toml",
+                "[package]\nname = \"a\"\nedition = \"2018\"\n",
+            ),
+            (
+                "b/Cargo.toml",
+                "[package]\nname = \"b\"\nedition.workspace = true\n",
+            ),
+            ("c/Cargo.toml", "[package]\nname = \"c\"\n"),
+        ];
+        for (path, contents) in manifests {
+            fs::write(temp.join(path), contents).unwrap();
+        }
+        assert_eq!(detect(&temp.join("a/src/lib.rs")), Some(Edition::E2018));
+        assert_eq!(detect(&temp.join("b/src/lib.rs")), Some(Edition::E2024));
+        assert_eq!(detect(&temp.join("c/src/lib.rs")), Some(Edition::E2015));
+        assert_eq!("2021".parse(), Ok(Edition::E2021));
+    }
+}
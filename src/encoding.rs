@@ -0,0 +1,132 @@
+//! Sources that are not UTF-8, transcoded for parsing and written back in their encoding.
+//!
+//! UTF-16 files are recognized by their byte order mark, and files that are not valid UTF-8 are
+//! taken as Latin-1, where every byte is a character. The byte order mark of UTF-16 files is
+//! kept as a leading `U+FEFF`, so that it is written back too.
+
+use std::{fmt, io, str::FromStr};
+
+/// The encoding of source files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Guessed from the byte order mark, UTF-8 if there is none and the file is valid UTF-8,
+    /// Latin-1 otherwise.
+    #[default]
+    Auto,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Encoding::Auto),
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "latin1" | "latin-1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            _ => Err(format!(
+                "unknown encoding `{}`, expected auto, utf-8, utf-16le, utf-16be or latin1",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Auto => "auto",
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin1",
+        })
+    }
+}
+
+fn invalid(encoding: Encoding) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("source is not valid {}", encoding),
+    )
+}
+
+/// Decode `bytes` as `encoding`, returning the text and the encoding it was actually in.
+pub(crate) fn decode(bytes: Vec<u8>, encoding: Encoding) -> io::Result<(String, Encoding)> {
+    let encoding = match encoding {
+        Encoding::Auto => match bytes.as_slice() {
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ if std::str::from_utf8(&bytes).is_ok() => Encoding::Utf8,
+            _ => Encoding::Latin1,
+        },
+        encoding => encoding,
+    };
+    let text = match encoding {
+        Encoding::Auto | Encoding::Utf8 => {
+            String::from_utf8(bytes).map_err(|_| invalid(Encoding::Utf8))?
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if bytes.len() % 2 != 0 {
+                return Err(invalid(encoding));
+            }
+            let units = bytes.chunks_exact(2).map(|pair| {
+                let pair = [pair[0], pair[1]];
+                if encoding == Encoding::Utf16Le {
+                    u16::from_le_bytes(pair)
+                } else {
+                    u16::from_be_bytes(pair)
+                }
+            });
+            char::decode_utf16(units)
+                .collect::<Result<String, _>>()
+                .map_err(|_| invalid(encoding))?
+        }
+        Encoding::Latin1 => bytes.into_iter().map(char::from).collect(),
+    };
+    Ok((text, encoding))
+}
+
+/// Encode `text` as `encoding`, failing on characters it can't represent.
+pub(crate) fn encode(text: &str, encoding: Encoding) -> io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Auto | Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+        Encoding::Utf16Le => Ok(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+        Encoding::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        Encoding::Latin1 => text
+            .chars()
+            .map(|c| u8::try_from(c).map_err(|_| invalid(encoding)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let utf16 = [0xff, 0xfe, b'f', 0, b'n', 0];
+        let (text, encoding) = decode(utf16.to_vec(), Encoding::Auto).unwrap();
+        assert_eq!((text.as_str(), encoding), ("\u{feff}fn", Encoding::Utf16Le));
+        assert_eq!(encode(&text, encoding).unwrap(), utf16);
+
+        let latin1 = b"const A: &str = \"caf\xe9\";".to_vec();
+        let (text, encoding) = decode(latin1.clone(), Encoding::Auto).unwrap();
+        assert_eq!(
+            (text.as_str(), encoding),
+            ("const A: &str = \"café\";", Encoding::Latin1)
+        );
+        assert_eq!(encode(&text, encoding).unwrap(), latin1);
+        assert!(encode("\u{2013}", Encoding::Latin1).is_err());
+
+        let (text, encoding) = decode("café".into(), Encoding::Auto).unwrap();
+        assert_eq!((text.as_str(), encoding), ("café", Encoding::Utf8));
+        assert!(decode(vec![0xe9], Encoding::Utf8).is_err());
+    }
+}
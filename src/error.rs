@@ -0,0 +1,499 @@
+//! The error type shared by the library and the binary.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    fmt::{self, Write},
+    io,
+    path::PathBuf,
+    str::FromStr,
+};
+
+/// Everything that can go wrong while formatting, paths being `-` for stdin.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Reading or writing a file or directory failed.
+    Io {
+        path: Utf8PathBuf,
+        source: io::Error,
+    },
+    /// The source is not valid Rust, `path` is `None` for in-memory sources.
+    Parse {
+        path: Option<Utf8PathBuf>,
+        source: syn::Error,
+        /// Where the error is in the source, `None` if unknown.
+        location: Option<Location>,
+    },
+    /// The path is neither a Rust file nor a directory.
+    NotRustPath(Utf8PathBuf),
+    /// The path doesn't exist, with the existing paths close to it that may have been meant.
+    NotFound {
+        path: Utf8PathBuf,
+        suggestions: Vec<Utf8PathBuf>,
+    },
+    /// The directory has no Rust file to format.
+    NoRustFiles(Utf8PathBuf),
+    /// The file would be written to the same `destination` under
+    /// [`Options::out_dir`](crate::Options::out_dir) as `other`, found before it.
+    SameDestination {
+        path: Utf8PathBuf,
+        other: Utf8PathBuf,
+        destination: Utf8PathBuf,
+    },
+    /// The path can't be represented as UTF-8.
+    NonUtf8Path(PathBuf),
+    /// Files are not formatted and running in check mode, or with
+    /// [`Options::error_on_unformatted`](crate::Options::error_on_unformatted).
+    Unformatted(Utf8PathBuf),
+    /// More than `max` files are not formatted, counting them with
+    /// [`Options::count`](crate::Options::count).
+    TooManyUnformatted { count: usize, max: usize },
+    /// The file is read-only, with [`ReadOnlyPolicy::Error`](crate::ReadOnlyPolicy::Error).
+    ReadOnly(Utf8PathBuf),
+    /// Formatting would change the tokens of the file, not only their layout.
+    TokensChanged(Utf8PathBuf),
+    /// prettyplease misbehaves on this many files, see
+    /// [`PrettyThanks::verify`](crate::PrettyThanks::verify).
+    Misbehaving(usize),
+    /// Formatting the formatted source again changes it, `diff` going from the first pass to the
+    /// second one.
+    NotIdempotent { path: Utf8PathBuf, diff: String },
+    /// The configuration file is invalid.
+    Config { path: Utf8PathBuf, message: String },
+    /// Running git failed.
+    Git(String),
+    /// Running `cargo metadata` failed.
+    Cargo(String),
+    /// Running rustfmt failed.
+    Rustfmt(String),
+    /// The combination of options is not supported.
+    Unsupported(String),
+    /// The run was interrupted by `signal`, after formatting `formatted` of the `total` files.
+    Interrupted {
+        signal: i32,
+        formatted: usize,
+        total: usize,
+    },
+    /// The run was cancelled by the library user, after formatting `formatted` of the `total`
+    /// files, see [`PrettyThanks::with_cancel`](crate::PrettyThanks::with_cancel).
+    Cancelled { formatted: usize, total: usize },
+    /// Another run of process `pid`, `None` if unknown, holds the lock file `path`, see
+    /// [`lock::Lock`](crate::lock::Lock).
+    Locked { path: Utf8PathBuf, pid: Option<u32> },
+    /// Several errors hit during a single run, in traversal order.
+    Multiple(Vec<Error>),
+}
+
+/// Where a parse error is, with the source lines around it to show in diagnostics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// Line the error starts at, from 1.
+    pub line: usize,
+    /// Column the error starts at, from 1, counting characters.
+    pub column: usize,
+    /// Line the error ends at, from 1.
+    pub end_line: usize,
+    /// Column right after the end of the error, from 1, counting characters.
+    pub end_column: usize,
+    /// The lines from the one before the error to the one after it, with their number.
+    pub context: Vec<(usize, String)>,
+}
+
+impl Location {
+    /// Locate `err` in `source`, the text it was parsed from.
+    ///
+    /// Must be called before the spans of the parse are invalidated, see
+    /// [`proc_macro2::extra::invalidate_current_thread_spans`].
+    pub fn find(source: &str, err: &syn::Error) -> Self {
+        let lines = source.lines().collect::<Vec<_>>();
+        let (line, column, end_line, end_column) =
+            if err.to_string().starts_with("unexpected end of input") {
+                // Reported at the call site, point past the last character instead.
+                let last = source.trim_end().lines().count().max(1);
+                let end = lines.get(last - 1).map_or(0, |line| line.chars().count()) + 1;
+                (last, end, last, end + 1)
+            } else {
+                let span = err.span();
+                let (start, end) = (span.start(), span.end());
+                let end_column = if end == start {
+                    end.column + 2
+                } else {
+                    end.column + 1
+                };
+                (
+                    start.line.max(1),
+                    start.column + 1,
+                    end.line.max(1),
+                    end_column,
+                )
+            };
+        let first = line.saturating_sub(1).max(1);
+        let last = (end_line + 1).min(lines.len());
+        let context = (first..=last)
+            .map(|number| (number, lines[number - 1].to_string()))
+            .collect();
+        Location {
+            line,
+            column,
+            end_line,
+            end_column,
+            context,
+        }
+    }
+}
+
+/// How the binary prints the errors failing a run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// The message, followed by the source lines of parse errors, see [`Error::snippet`].
+    #[default]
+    Human,
+    /// One JSON object per line, see [`Error::to_json`].
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "unknown error format `{}`, expected human or json",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ErrorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorFormat::Human => "human",
+            ErrorFormat::Json => "json",
+        })
+    }
+}
+
+/// Placeholder path for errors writing to stdout, `-` being stdin.
+pub(crate) const STDOUT: &str = "<stdout>";
+
+/// `file <path>`, or `stdin` and `stdout` for the standard streams.
+pub(crate) fn describe(path: &Utf8Path) -> String {
+    match path.as_str() {
+        "-" => "stdin".to_string(),
+        STDOUT => "stdout".to_string(),
+        _ => format!("file {}", path),
+    }
+}
+
+/// The message of [`Error::Locked`].
+fn locked(f: &mut fmt::Formatter<'_>, path: &Utf8Path, pid: Option<u32>) -> fmt::Result {
+    f.write_str("another run ")?;
+    if let Some(pid) = pid {
+        write!(f, "of process {} ", pid)?;
+    }
+    write!(
+        f,
+        "is formatting this tree, holding {}: pass --wait-lock to wait for it, or --no-lock to \
+         format anyway",
+        path
+    )
+}
+
+impl Error {
+    pub(crate) fn io(path: &Utf8Path, source: io::Error) -> Self {
+        Error::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// The error parsing `text`, located while its spans are still valid.
+    pub(crate) fn parse(path: Option<&Utf8Path>, text: &str, source: syn::Error) -> Self {
+        Error::Parse {
+            path: path.map(Utf8Path::to_path_buf),
+            location: Some(Location::find(text, &source)),
+            source,
+        }
+    }
+
+    /// Where a parse error is in the source.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::Parse { location, .. } => location.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// The source lines a parse error is about, the error underlined with carets, to print after
+    /// the message.
+    pub fn snippet(&self) -> Option<String> {
+        let location = self.location()?;
+        let name = match self.path().map(Utf8Path::as_str) {
+            Some("-") => "<stdin>",
+            Some(path) => path,
+            None => "<source>",
+        };
+        let width = location
+            .context
+            .last()
+            .map_or(1, |(number, _)| number.to_string().len());
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{:width$}--> {}:{}:{}",
+            "",
+            name,
+            location.line,
+            location.column,
+            width = width
+        );
+        let _ = writeln!(out, "{:width$} |", "", width = width);
+        for (number, line) in &location.context {
+            let _ = writeln!(out, "{:>width$} | {}", number, line, width = width);
+            if *number != location.line {
+                continue;
+            }
+            // Keep the tabs, so that the carets line up whatever their width.
+            let indent = line
+                .chars()
+                .take(location.column - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+            let carets = if location.end_line == location.line {
+                location.end_column.saturating_sub(location.column)
+            } else {
+                line.chars().count() + 1 - location.column
+            };
+            let _ = writeln!(
+                out,
+                "{:width$} | {}{}",
+                "",
+                indent,
+                "^".repeat(carets.max(1)),
+                width = width
+            );
+        }
+        Some(out)
+    }
+
+    /// The path the error is about, if any.
+    pub fn path(&self) -> Option<&Utf8Path> {
+        match self {
+            Error::Io { path, .. }
+            | Error::NotRustPath(path)
+            | Error::NotFound { path, .. }
+            | Error::NoRustFiles(path)
+            | Error::SameDestination { path, .. }
+            | Error::Unformatted(path)
+            | Error::ReadOnly(path)
+            | Error::TokensChanged(path)
+            | Error::NotIdempotent { path, .. }
+            | Error::Config { path, .. } => Some(path),
+            Error::Parse { path, .. } => path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The exit code of the binary failing with this error, the highest one for several errors:
+    ///
+    /// - 1: files are not formatted.
+    /// - 2: files can't be formatted, because they don't parse or formatting them isn't safe.
+    /// - 3: files can't be read or written, and anything else preventing the run.
+    /// - 4: a directory given has no Rust files.
+    /// - 128 plus the signal number: the run was interrupted, like shells report it.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Interrupted { signal, .. } => 128 + signal,
+            Error::Unformatted(_) | Error::TooManyUnformatted { .. } => 1,
+            Error::Parse { .. }
+            | Error::TokensChanged(_)
+            | Error::NotIdempotent { .. }
+            | Error::Misbehaving(_) => 2,
+            Error::NoRustFiles(_) => 4,
+            Error::Multiple(errors) => errors.iter().map(Error::exit_code).max().unwrap_or(1),
+            _ => 3,
+        }
+    }
+
+    /// Flatten `errors` into a single error, `None` if there are none.
+    pub fn from_errors(mut errors: Vec<Error>) -> Option<Self> {
+        match errors.len() {
+            0 => None,
+            1 => errors.pop(),
+            _ => Some(Error::Multiple(errors)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => {
+                write!(f, "failed to access {}: {}", describe(path), source)
+            }
+            Error::Parse {
+                path: Some(path),
+                source,
+                ..
+            } => {
+                write!(f, "failed to parse {}: {}", describe(path), source)
+            }
+            Error::Parse {
+                path: None, source, ..
+            } => write!(f, "failed to parse source: {}", source),
+            Error::NotRustPath(path) => {
+                write!(f, "path {} is not a file, symlink or directory", path)
+            }
+            Error::NotFound { path, suggestions } => {
+                write!(f, "path {} doesn't exist", path)?;
+                if !suggestions.is_empty() {
+                    let suggestions = suggestions.iter().map(|path| path.as_str());
+                    write!(
+                        f,
+                        ", did you mean {}?",
+                        suggestions.collect::<Vec<_>>().join(" or ")
+                    )?;
+                }
+                Ok(())
+            }
+            Error::NoRustFiles(path) => write!(f, "no .rs files found in {}", path),
+            Error::SameDestination {
+                path,
+                other,
+                destination,
+            } => write!(
+                f,
+                "{} would be written to {} like {}, leaving it out",
+                path, destination, other
+            ),
+            Error::NonUtf8Path(path) => write!(f, "path {} is not valid UTF-8", path.display()),
+            Error::Unformatted(path) => write!(f, "{} is not formatted", describe(path)),
+            Error::TooManyUnformatted { count, max } => write!(
+                f,
+                "{} files are not formatted, more than the maximum of {}",
+                count, max
+            ),
+            Error::ReadOnly(path) => write!(
+                f,
+                "{} is read-only, `--readonly force` formats it anyway",
+                describe(path)
+            ),
+            Error::TokensChanged(path) => write!(
+                f,
+                "formatting {} would change its tokens, leaving it untouched",
+                describe(path)
+            ),
+            Error::Misbehaving(files) => {
+                write!(f, "prettyplease misbehaves on {} files", files)
+            }
+            Error::NotIdempotent { path, diff } => write!(
+                f,
+                "formatting {} is not idempotent, a second pass changes it:\n{}",
+                describe(path),
+                diff.trim_end()
+            ),
+            Error::Config { path, message } => {
+                write!(f, "failed to parse config {}: {}", path, message)
+            }
+            Error::Git(message)
+            | Error::Cargo(message)
+            | Error::Rustfmt(message)
+            | Error::Unsupported(message) => f.write_str(message),
+            Error::Interrupted {
+                signal,
+                formatted,
+                total,
+            } => write!(
+                f,
+                "interrupted by {}, {} of {} files formatted",
+                crate::signal::name(*signal),
+                formatted,
+                total
+            ),
+            Error::Locked { path, pid } => locked(f, path, *pid),
+            Error::Cancelled { formatted, total } => {
+                write!(f, "cancelled, {} of {} files formatted", formatted, total)
+            }
+            Error::Multiple(errors) => {
+                for (idx, err) in errors.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_str("\n")?;
+                    }
+                    write!(f, "{}", err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<camino::FromPathBufError> for Error {
+    fn from(err: camino::FromPathBufError) -> Self {
+        Error::NonUtf8Path(err.into_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        let unformatted = || Error::Unformatted("a.rs".into());
+        let parse = || Error::Parse {
+            path: None,
+            source: syn::Error::new(proc_macro2::Span::call_site(), "expected fn"),
+            location: None,
+        };
+        assert_eq!(unformatted().exit_code(), 1);
+        assert_eq!(parse().exit_code(), 2);
+        assert_eq!(Error::NotRustPath("a".into()).exit_code(), 3);
+        assert_eq!(
+            Error::Multiple(vec![unformatted(), parse(), unformatted()]).exit_code(),
+            2
+        );
+        let interrupted = Error::Interrupted {
+            signal: crate::signal::SIGINT,
+            formatted: 1,
+            total: 2,
+        };
+        assert_eq!(
+            interrupted.to_string(),
+            "interrupted by SIGINT, 1 of 2 files formatted"
+        );
+        assert_eq!(Error::Multiple(vec![parse(), interrupted]).exit_code(), 130);
+    }
+
+    #[test]
+    fn renders_snippets() {
+        let source = "fn main() {\n    let x = 1\n    let y = 2;\n}\n";
+        let err = crate::format_str(source).unwrap_err();
+        let location = err.location().unwrap();
+        assert_eq!(
+            (location.line, location.column, location.end_column),
+            (3, 5, 8)
+        );
+        assert_eq!(
+            err.snippet().unwrap(),
+            " --> <source>:3:5\n  |\n2 |     let x = 1\n3 |     let y = 2;\n  |     ^^^\n4 | }\n"
+        );
+        let err = crate::format_str("fn").unwrap_err();
+        assert_eq!(
+            err.snippet().unwrap(),
+            " --> <source>:1:3\n  |\n1 | fn\n  |   ^\n"
+        );
+    }
+}
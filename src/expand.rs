@@ -0,0 +1,175 @@
+//! Macro expansion of a crate through the compiler, pretty-printed, see [`expand`].
+
+use crate::{cargo::Workspace, format_str, Error, Result};
+use camino::Utf8Path;
+use std::{env, process::Command};
+use syn::{Item, Type};
+
+/// The name of `item`, if it has one.
+pub(crate) fn name(item: &Item) -> Option<&syn::Ident> {
+    Some(match item {
+        Item::Const(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::ExternCrate(item) => &item.ident,
+        Item::Fn(item) => &item.sig.ident,
+        Item::Macro(item) => item.ident.as_ref()?,
+        Item::Mod(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        _ => return None,
+    })
+}
+
+/// Whether `item` is an implementation for the type called `name`, like the ones derives expand
+/// to.
+pub(crate) fn implements(item: &Item, name: &str) -> bool {
+    match item {
+        Item::Impl(item) => match &*item.self_ty {
+            Type::Path(ty) => ty
+                .path
+                .segments
+                .last()
+                .map_or(false, |segment| segment.ident == name),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The items of `file` at `path`, like `module::Type`: the item called that way, and the
+/// implementations for it next to it.
+///
+/// # Errors
+///
+/// If there's no such item.
+pub fn select(file: syn::File, path: &str) -> Result<syn::File> {
+    let not_found = || Error::Unsupported(format!("no item `{}` in the expansion", path));
+    let mut segments = path.split("::").filter(|segment| !segment.is_empty());
+    // The expansion is the crate root, however the path starts.
+    let mut last = segments.next().ok_or_else(not_found)?;
+    if last == "crate" {
+        last = segments.next().ok_or_else(not_found)?;
+    }
+    let mut items = file.items;
+    for segment in segments {
+        let module = items.into_iter().find_map(|item| match item {
+            Item::Mod(module) if module.ident == last => module.content,
+            _ => None,
+        });
+        items = module.ok_or_else(not_found)?.1;
+        last = segment;
+    }
+    let selected = items
+        .into_iter()
+        .filter(|item| name(item).map_or(false, |name| name == last) || implements(item, last))
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        return Err(not_found());
+    }
+    Ok(syn::File {
+        shebang: None,
+        attrs: Vec::new(),
+        items: selected,
+    })
+}
+
+/// The library of the package `package` of the workspace containing `dir`, or of the package
+/// containing `dir`, or its first binary if it has no library, with its macros expanded and
+/// pretty-printed. With `item`, only the items at that path are, see [`select`].
+///
+/// The compiler prints the expansion with `-Zunpretty=expanded`, a nightly option enabled on
+/// stable toolchains the way `cargo expand` does it.
+///
+/// # Errors
+///
+/// If the package can't be found or built, or if `item` is not in the expansion.
+pub fn expand(dir: &Utf8Path, package: Option<&str>, item: Option<&str>) -> Result<String> {
+    let workspace = Workspace::discover(dir)?;
+    let packages = package.map(str::to_string).into_iter().collect::<Vec<_>>();
+    let cwd = dir.canonicalize_utf8().map_err(|err| Error::io(dir, err))?;
+    let package = match workspace.select(&packages, false, &[], &cwd)?[..] {
+        [package] => package,
+        _ => {
+            return Err(Error::Cargo(
+                "the workspace has several packages, pick one with --package".to_string(),
+            ))
+        }
+    };
+    let is_lib = |kind: &String| ["lib", "rlib", "proc-macro"].contains(&kind.as_str());
+    let target = match package
+        .targets
+        .iter()
+        .find(|target| target.kind.iter().any(is_lib))
+    {
+        Some(_) => vec!["--lib".to_string()],
+        None => match package
+            .targets
+            .iter()
+            .find(|target| target.kind.iter().any(|kind| kind == "bin"))
+        {
+            Some(bin) => vec!["--bin".to_string(), bin.name.clone()],
+            None => {
+                return Err(Error::Cargo(format!(
+                    "package `{}` has no library or binary to expand",
+                    package.name
+                )))
+            }
+        },
+    };
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["rustc", "--quiet", "--profile=check", "--color=never", "-p"])
+        .arg(&package.name)
+        .args(target)
+        .args(["--", "-Zunpretty=expanded"])
+        .env("RUSTC_BOOTSTRAP", "1")
+        .current_dir(dir)
+        .output()
+        .map_err(|err| Error::Cargo(format!("failed to run cargo rustc: {}", err)))?;
+    if !output.status.success() {
+        return Err(Error::Cargo(format!(
+            "expanding the macros of {} failed: {}",
+            package.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let expanded = String::from_utf8_lossy(&output.stdout);
+    match item {
+        Some(item) => {
+            let file =
+                syn::parse_file(&expanded).map_err(|err| Error::parse(None, &expanded, err))?;
+            Ok(prettyplease::unparse(&select(file, item)?))
+        }
+        None => format_str(&expanded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selects_items() {
+        let file = || {
+            syn::parse_file(
+                "struct A; impl Clone for A { fn clone(&self) -> A { A } } mod m { pub fn b() {} \
+                 fn c() {} } impl B {}",
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            prettyplease::unparse(&select(file(), "A").unwrap()),
+            "struct A;\nimpl Clone for A {\n    fn clone(&self) -> A {\n        A\n    }\n}\n"
+        );
+        assert_eq!(
+            prettyplease::unparse(&select(file(), "crate::m::b").unwrap()),
+            "pub fn b() {}\n"
+        );
+        assert!(select(file(), "m::d").is_err());
+        assert!(select(file(), "n::b").is_err());
+    }
+}
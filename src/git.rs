@@ -0,0 +1,291 @@
+//! Git integration, shelling out to the `git` binary.
+
+use crate::{comments::CommentPolicy, diff, Error, Options, Outcome, Result, Timings};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::Write,
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+/// Run `git` in `dir`, returning its stdout.
+pub(crate) fn git(dir: &Utf8Path, args: &[&str]) -> Result<String> {
+    git_with_input(dir, args, None)
+}
+
+/// Run `git` in `dir` feeding it `input` on stdin, returning its stdout.
+fn git_with_input(dir: &Utf8Path, args: &[&str], input: Option<&str>) -> Result<String> {
+    let spawn_error = |err| Error::Git(format!("failed to run git: {}", err));
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes()).map_err(spawn_error)?;
+    }
+    let output = child.wait_with_output().map_err(spawn_error)?;
+    if !output.status.success() {
+        return Err(Error::Git(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Git(format!("git {} printed invalid UTF-8", args.join(" "))))
+}
+
+/// Root of the work tree containing `dir`.
+pub fn toplevel(dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    let root = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let root = Utf8PathBuf::from(root.trim_end_matches(['\n', '\r']));
+    root.canonicalize_utf8()
+        .map_err(|err| Error::io(&root, err))
+}
+
+/// Absolute paths of the files of the work tree containing `dir` changed since `rev`, or since
+/// `HEAD` if `None`, including untracked files but not deleted ones.
+///
+/// Against another branch the changes are taken since the merge base, so `origin/main` lists
+/// what the current branch changed, not what happened upstream in the meantime.
+pub fn changed_files(dir: &Utf8Path, rev: Option<&str>) -> Result<BTreeSet<Utf8PathBuf>> {
+    let root = toplevel(dir)?;
+    let base = match rev {
+        Some(rev) => Some(git(&root, &["merge-base", rev, "HEAD"])?.trim().to_string()),
+        // A repository without commits yet has no HEAD to compare with.
+        None => git(&root, &["rev-parse", "--verify", "--quiet", "HEAD"])
+            .ok()
+            .map(|_| "HEAD".to_string()),
+    };
+    let mut names = match &base {
+        Some(base) => git(
+            &root,
+            &["diff", "--name-only", "-z", "--diff-filter=ACMR", base],
+        )?,
+        None => git(&root, &["ls-files", "--cached", "-z"])?,
+    };
+    names.push_str(&git(
+        &root,
+        &["ls-files", "--others", "--exclude-standard", "-z"],
+    )?);
+    Ok(names
+        .split('\0')
+        .filter(|name| !name.is_empty())
+        .map(|name| root.join(name))
+        .collect())
+}
+
+/// Absolute paths of the files of the work tree containing `dir` with staged changes.
+pub fn staged_files(dir: &Utf8Path) -> Result<BTreeSet<Utf8PathBuf>> {
+    let root = toplevel(dir)?;
+    Ok(git(
+        &root,
+        &[
+            "diff",
+            "--cached",
+            "--name-only",
+            "-z",
+            "--diff-filter=ACMR",
+        ],
+    )?
+    .split('\0')
+    .filter(|name| !name.is_empty())
+    .map(|name| root.join(name))
+    .collect())
+}
+
+/// Format the staged contents of `path` instead of the file itself, updating both the index and
+/// the work tree.
+///
+/// Unstaged changes are kept by applying the formatting to the work tree as a patch, which fails
+/// without touching anything if they overlap with it.
+pub fn format_staged(path: &Utf8Path, options: &Options) -> Result<Outcome> {
+    let start = Instant::now();
+    let absolute = path
+        .canonicalize_utf8()
+        .map_err(|err| Error::io(path, err))?;
+    let root = toplevel(absolute.parent().unwrap_or(&absolute))?;
+    let relative = absolute
+        .strip_prefix(&root)
+        .map_err(|_| Error::Git(format!("{} is outside of {}", path, root)))?
+        .as_str();
+    let staged = git(&root, &["show", &format!(":{}", relative)])?;
+    let read = start.elapsed();
+    // Forget about whatever ran on this thread before.
+    crate::take_phases();
+    let formatting = Instant::now();
+    let (formatted, dropped_comments, skipped) = crate::format_with_policy(path, &staged, options)?;
+    let format = formatting.elapsed();
+    let (parse, print) = crate::take_phases();
+    let output = options.output(path, &staged, &formatted);
+    let changed = staged != formatted;
+    let writing = Instant::now();
+    if changed && options.writes() {
+        let worktree = fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        if let Some(suffix) = &options.backup {
+            crate::backup::save(path, suffix)?;
+        }
+        if worktree == staged {
+            crate::write_atomic(path, &formatted).map_err(|err| Error::io(path, err))?;
+        } else {
+            let formatting =
+                diff::unified(&staged, &formatted, relative, relative, diff::CONTEXT_LINES);
+            git_with_input(&root, &["apply", "-p0", "-"], Some(&formatting)).map_err(|_| {
+                Error::Git(format!(
+                    "failed to format the staged changes of {}, they overlap with unstaged ones",
+                    path
+                ))
+            })?;
+        }
+        let mode = git(&root, &["ls-files", "--stage", "--", relative])?;
+        let mode = mode.split(' ').next().unwrap_or("100644");
+        let blob = git_with_input(
+            &root,
+            &["hash-object", "-w", "--stdin", "--path", relative],
+            Some(&formatted),
+        )?;
+        git(
+            &root,
+            &[
+                "update-index",
+                "--cacheinfo",
+                &format!("{},{},{}", mode, blob.trim(), relative),
+            ],
+        )?;
+    }
+    let (removed_lines, added_lines) = options.line_counts(&staged, &formatted);
+    Ok(Outcome {
+        original: staged.len(),
+        formatted: formatted.len(),
+        changed,
+        dropped_comments,
+        comments_allowed: options.comment_policy == CommentPolicy::Allow,
+        skipped,
+        cached: false,
+        elapsed: start.elapsed(),
+        timings: Timings {
+            read,
+            parse,
+            print,
+            format,
+            write: writing.elapsed(),
+        },
+        output,
+        transcoded: None,
+        removed_lines,
+        added_lines,
+        skip_reason: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use crate::{Options, PrettyThanks};
+    use std::fs;
+
+    #[test]
+    fn lists_changed_files() {
+        let temp = TempDir::new("git");
+        fs::create_dir_all(temp.join("b")).unwrap();
+        let run = |args: &[&str]| {
+            let mut full = vec!["-c", "user.name=test", "-c", "user.email=test@example.com"];
+            full.extend(args);
+            git(&temp, &full).unwrap();
+        };
+        run(&["init", "--quiet"]);
+        fs::write(temp.join("a.rs"), "fn a(){}").unwrap();
+        fs::write(temp.join("b/c.rs"), "fn c(){}").unwrap();
+        assert_eq!(changed_files(&temp, None).unwrap().len(), 2);
+        run(&["add", "."]);
+        run(&["commit", "--quiet", "-m", "initial"]);
+        run(&["tag", "base"]);
+        fs::write(temp.join("b/c.rs"), "fn c(){ }").unwrap();
+        run(&["commit", "--quiet", "-am", "change"]);
+        fs::write(temp.join("d.rs"), "fn d(){}").unwrap();
+        let root = temp.canonicalize_utf8().unwrap();
+        let changed = changed_files(&temp.join("b"), None).unwrap();
+        assert_eq!(changed.into_iter().collect::<Vec<_>>(), [root.join("d.rs")]);
+        let changed = changed_files(&temp, Some("base")).unwrap();
+        assert_eq!(
+            changed.iter().collect::<Vec<_>>(),
+            [&root.join("b/c.rs"), &root.join("d.rs")]
+        );
+
+        let options = Options {
+            only: Some(changed),
+            ..Options::default()
+        };
+        let report = PrettyThanks::new(&[temp.to_string()], options)
+            .unwrap()
+            .report();
+        assert_eq!(report.changed().count(), 2);
+        assert_eq!(fs::read_to_string(temp.join("a.rs")).unwrap(), "fn a(){}");
+        assert_eq!(fs::read_to_string(temp.join("d.rs")).unwrap(), "fn d() {}");
+    }
+
+    #[test]
+    fn formats_staged_contents() {
+        let temp = TempDir::new("staged");
+        git(&temp, &["init", "--quiet"]).unwrap();
+        let staged = "fn a(){}\nfn b() {}\nfn c() {}\nfn d() {}\nfn e() {}\nfn f() {}\n";
+        fs::write(temp.join("a.rs"), staged).unwrap();
+        fs::write(temp.join("b.rs"), "fn b(){}").unwrap();
+        git(&temp, &["add", "a.rs", "b.rs"]).unwrap();
+        // An unstaged edit far enough from the staged code to be kept.
+        fs::write(
+            temp.join("a.rs"),
+            staged.replace("fn f() {}", "fn f(){ 1 }"),
+        )
+        .unwrap();
+        assert_eq!(staged_files(&temp).unwrap().len(), 2);
+
+        let options = Options {
+            staged: true,
+            ..Options::default()
+        };
+        assert!(format_staged(&temp.join("a.rs"), &options).unwrap().changed);
+        assert!(format_staged(&temp.join("b.rs"), &options).unwrap().changed);
+        assert_eq!(
+            git(&temp, &["show", ":a.rs"]).unwrap(),
+            staged.replace("fn a(){}", "fn a() {}")
+        );
+        assert_eq!(
+            fs::read_to_string(temp.join("a.rs")).unwrap(),
+            staged
+                .replace("fn a(){}", "fn a() {}")
+                .replace("fn f() {}", "fn f(){ 1 }")
+        );
+        assert_eq!(git(&temp, &["show", ":b.rs"]).unwrap(), "fn b() {}");
+        assert_eq!(fs::read_to_string(temp.join("b.rs")).unwrap(), "fn b() {}");
+    }
+
+    #[test]
+    fn checks_nothing_staged() {
+        let temp = TempDir::new("nothing-staged");
+        git(&temp, &["init", "--quiet"]).unwrap();
+        fs::write(temp.join("a.rs"), "fn a(){}").unwrap();
+        // What the pre-commit hook runs, for a commit staging no Rust file.
+        let options = Options {
+            check: true,
+            staged: true,
+            only: Some(staged_files(&temp).unwrap()),
+            ..Options::default()
+        };
+        let thanks = PrettyThanks::new(&[temp.to_string()], options).unwrap();
+        thanks.run().unwrap();
+        assert_eq!(thanks.report().files().count(), 0);
+    }
+}
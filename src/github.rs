@@ -0,0 +1,146 @@
+//! GitHub Actions workflow commands, so that the outcome shows up inline in pull requests.
+
+use crate::{comments, patch_path, Entry, Location, Report};
+use camino::Utf8Path;
+use std::fmt::Write;
+
+/// `text` escaped as the message of a workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// `text` escaped as the value of a workflow command property.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl Report {
+    /// The report as GitHub Actions annotations: an error for every file that is not formatted
+    /// or can't be formatted and every error hit while walking the tree, a warning for dropped
+    /// comments and orphan files. Parse errors are annotated on the lines they are about.
+    pub fn to_github(&self) -> String {
+        let mut out = String::new();
+        let mut annotate = |level: &str,
+                            path: Option<&str>,
+                            location: Option<&Location>,
+                            title: &str,
+                            message: &str| {
+            let mut file = path.map_or_else(String::new, |path| {
+                format!("file={},", escape_property(&patch_path(path.into())))
+            });
+            if let (Some(location), true) = (location, path.is_some()) {
+                let _ = write!(
+                    file,
+                    "line={},col={},endLine={},endColumn={},",
+                    location.line, location.column, location.end_line, location.end_column
+                );
+            }
+            let _ = writeln!(
+                out,
+                "::{} {}title={}::{}",
+                level,
+                file,
+                escape_property(title),
+                escape_data(message)
+            );
+        };
+        for entry in &self.entries {
+            match entry {
+                Entry::File(file) => {
+                    let path = Some(file.path.as_str()).filter(|path| *path != "-");
+                    match &file.result {
+                        Ok(outcome) => {
+                            if outcome.changed {
+                                let message = format!("{} is not formatted", file.path);
+                                annotate("error", path, None, "unformatted file", &message);
+                            }
+                            if outcome.dropped_comments > 0 && !outcome.comments_allowed {
+                                let message = format!(
+                                    "formatting {} drops {}",
+                                    file.path,
+                                    comments::count(outcome.dropped_comments)
+                                );
+                                annotate("warning", path, None, "dropped comments", &message);
+                            }
+                        }
+                        Err(err) => annotate(
+                            "error",
+                            path,
+                            err.location(),
+                            "formatting failed",
+                            &err.to_string(),
+                        ),
+                    }
+                }
+                Entry::Orphan(path) => annotate(
+                    "warning",
+                    Some(path.as_str()),
+                    None,
+                    "orphan file",
+                    &format!("{} is not reachable from any crate root", path),
+                ),
+                Entry::Error(err) => annotate(
+                    "error",
+                    err.path().map(Utf8Path::as_str),
+                    err.location(),
+                    "formatting failed",
+                    &err.to_string(),
+                ),
+                Entry::NonUtf8(path) => annotate(
+                    "warning",
+                    None,
+                    None,
+                    "non-UTF-8 path",
+                    &format!("skipping {}, its name is not valid UTF-8", path.display()),
+                ),
+                Entry::Ignored(_) | Entry::Revisited(_) | Entry::Duplicate(_) => {}
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, FileReport, Outcome, Timings};
+    use std::time::Duration;
+
+    #[test]
+    fn renders_annotations() {
+        let report = Report {
+            entries: vec![
+                Entry::File(FileReport {
+                    path: "src/a,b.rs".into(),
+                    result: Ok(Outcome {
+                        original: 10,
+                        formatted: 10,
+                        changed: true,
+                        dropped_comments: 2,
+                        comments_allowed: false,
+                        skipped: false,
+                        cached: false,
+                        elapsed: Duration::ZERO,
+                        timings: Timings::default(),
+                        output: String::new(),
+                        transcoded: None,
+                        removed_lines: 0,
+                        added_lines: 0,
+                        skip_reason: None,
+                    }),
+                }),
+                Entry::Error(Error::Unsupported("100%\nwrong".to_string())),
+            ],
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(
+            report.to_github(),
+            "::error file=src/a%2Cb.rs,title=unformatted file::src/a,b.rs is not formatted\n\
+             ::warning file=src/a%2Cb.rs,title=dropped comments::formatting src/a,b.rs drops 2 \
+             comments\n\
+             ::error title=formatting failed::100%25%0Awrong\n"
+        );
+    }
+}
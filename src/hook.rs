@@ -0,0 +1,108 @@
+//! Installation of a git pre-commit hook checking the staged Rust files.
+
+use crate::{git, Error, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::fs;
+
+/// Comment marking the hooks written by [`install`], so [`uninstall`] never removes anything else.
+const MARKER: &str = "# Installed by `prettythanks install-hook`.";
+
+/// The pre-commit hook, failing if any staged Rust file is not formatted.
+pub fn script() -> String {
+    format!(
+        "#!/bin/sh\n{}\nexec prettythanks --staged --check\n",
+        MARKER
+    )
+}
+
+/// Snippet of a `.pre-commit-config.yaml` running the same check through the pre-commit framework.
+pub const PRE_COMMIT_CONFIG: &str = "repos:
+  - repo: local
+    hooks:
+      - id: prettythanks
+        name: prettythanks
+        entry: prettythanks --check
+        language: system
+        types: [rust]
+";
+
+/// Path of the pre-commit hook of the repository containing `dir`, honoring `core.hooksPath`.
+fn hook_path(dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    let path = git::git(dir, &["rev-parse", "--git-path", "hooks/pre-commit"])?;
+    let path = Utf8PathBuf::from(path.trim_end_matches(['\n', '\r']));
+    // Relative to `dir`, unless `core.hooksPath` is absolute.
+    Ok(dir.join(path))
+}
+
+fn is_ours(path: &Utf8Path) -> Result<bool> {
+    match fs::read_to_string(path) {
+        Ok(script) => Ok(script.contains(MARKER)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(Error::io(path, err)),
+    }
+}
+
+/// Write the pre-commit hook of the repository containing `dir`, returning its path.
+///
+/// An existing hook not written by prettythanks is only replaced with `force`.
+pub fn install(dir: &Utf8Path, force: bool) -> Result<Utf8PathBuf> {
+    let path = hook_path(dir)?;
+    if path.exists() && !force && !is_ours(&path)? {
+        return Err(Error::Unsupported(format!(
+            "{} already exists, use --force to replace it",
+            path
+        )));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| Error::io(parent, err))?;
+    }
+    fs::write(&path, script()).map_err(|err| Error::io(&path, err))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .map_err(|err| Error::io(&path, err))?;
+    }
+    Ok(path)
+}
+
+/// Remove the pre-commit hook of the repository containing `dir`, returning its path.
+///
+/// Hooks not written by prettythanks are left alone.
+pub fn uninstall(dir: &Utf8Path) -> Result<Utf8PathBuf> {
+    let path = hook_path(dir)?;
+    if !path.exists() {
+        return Err(Error::Unsupported(format!("{} is not installed", path)));
+    }
+    if !is_ours(&path)? {
+        return Err(Error::Unsupported(format!(
+            "{} was not installed by prettythanks, leaving it alone",
+            path
+        )));
+    }
+    fs::remove_file(&path).map_err(|err| Error::io(&path, err))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn installs_and_uninstalls() {
+        let temp = TempDir::new("hook");
+        git::git(&temp, &["init", "--quiet"]).unwrap();
+        let path = install(&temp, false).unwrap();
+        assert_eq!(path, temp.join(".git/hooks/pre-commit"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), script());
+        uninstall(&temp).unwrap();
+        assert!(!path.exists());
+
+        fs::write(&path, "#!/bin/sh\nmake lint\n").unwrap();
+        assert!(install(&temp, false).is_err());
+        assert!(uninstall(&temp).is_err());
+        install(&temp, true).unwrap();
+        assert!(is_ours(&path).unwrap());
+    }
+}
@@ -0,0 +1,362 @@
+//! A tiny HTTP service formatting the sources posted to it, for web IDEs and tools formatting
+//! remotely, see [`serve`].
+//!
+//! `POST /format` with a Rust source as its body returns the formatted source as plain text, or a
+//! JSON `error` object as `--error-format json` prints it, with the line and column of parse
+//! errors. A `mode` query parameter, like `/format?mode=expr`, parses the body as `--parse-mode`
+//! does. Every connection carries one request, read within [`REQUEST_TIMEOUT`], and at most
+//! [`MAX_CONNECTIONS`] are served at once.
+
+use crate::{format_with_policy, json::Value, Error, Options, ParseMode, Result};
+use camino::Utf8Path;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The address listened on unless another one is picked.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8090";
+
+/// The largest body accepted, larger ones being refused rather than read into memory.
+const MAX_BODY: usize = 16 << 20;
+
+/// The longest request line or header accepted.
+const MAX_LINE: usize = 8 << 10;
+
+/// The most headers accepted, and their largest size along with the request line.
+const MAX_HEADERS: usize = 100;
+const MAX_HEAD: usize = 64 << 10;
+
+/// How long a client has to send its whole request, and to read the response.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many connections are served at once, the next ones waiting to be accepted.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// The name of posted sources in errors.
+const SOURCE_NAME: &str = "<request>";
+
+/// A response: its status code and reason, content type and body.
+struct Response {
+    status: (u16, &'static str),
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn text(body: String) -> Self {
+        Response {
+            status: (200, "OK"),
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+
+    /// An error response with a JSON `error` object holding at least a `message`.
+    fn error(status: (u16, &'static str), error: Value) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            body: format!("{}\n", Value::object([("error", error)])),
+        }
+    }
+
+    fn message(status: (u16, &'static str), message: &str) -> Self {
+        Response::error(status, Value::object([("message", message.into())]))
+    }
+}
+
+/// Listen on `addr`, like `127.0.0.1:8090`, until the process is stopped, formatting with
+/// `options`.
+///
+/// # Errors
+///
+/// If `addr` can't be listened on.
+pub fn serve(addr: &str, options: &Options) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|err| Error::Unsupported(format!("failed to listen on {}: {}", addr, err)))?;
+    if let Ok(local) = listener.local_addr() {
+        info!("listening on http://{}", local);
+    }
+    let (active, finished) = (Mutex::new(0), Condvar::new());
+    thread::scope(|scope| {
+        // A client giving up before being accepted doesn't concern the others.
+        for stream in listener.incoming().flatten() {
+            let mut count = active.lock().expect("poisoned connection count");
+            while *count >= MAX_CONNECTIONS {
+                count = finished.wait(count).expect("poisoned connection count");
+            }
+            *count += 1;
+            drop(count);
+            let (active, finished) = (&active, &finished);
+            scope.spawn(move || {
+                serve_connection(&stream, options);
+                *active.lock().expect("poisoned connection count") -= 1;
+                finished.notify_one();
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Answer the request of `stream`, closing it afterwards.
+fn serve_connection(stream: &TcpStream, options: &Options) {
+    let mut reader = BufReader::new(Deadline {
+        stream,
+        until: Instant::now() + REQUEST_TIMEOUT,
+    });
+    let response =
+        read_request(&mut reader).map_or_else(|err| err, |request| respond(&request, options));
+    if stream.set_write_timeout(Some(REQUEST_TIMEOUT)).is_ok() {
+        let _ = write_response(stream, &response);
+    }
+}
+
+/// A stream read until a deadline, for slow clients not to hold a connection for longer.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// A request: its method, path without the query, query and body.
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+    body: String,
+}
+
+impl Request {
+    /// The value of the query parameter `name`, if it is there.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Read the request of `reader`, or the response refusing it.
+fn read_request(reader: &mut impl BufRead) -> std::result::Result<Request, Response> {
+    let bad_request = |message: &str| Response::message((400, "Bad Request"), message);
+    let failed = |err: io::Error, message: &str| match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Response::message(
+            (408, "Request Timeout"),
+            &format!("requests must be sent within {:?}", REQUEST_TIMEOUT),
+        ),
+        _ => bad_request(message),
+    };
+    let too_large = || {
+        Response::message(
+            (431, "Request Header Fields Too Large"),
+            &format!(
+                "lines are limited to {} bytes, headers to {} and {} bytes in all",
+                MAX_LINE, MAX_HEADERS, MAX_HEAD
+            ),
+        )
+    };
+    let mut line = String::new();
+    let mut left = MAX_HEAD;
+    let mut read_line = |reader: &mut dyn BufRead, line: &mut String| {
+        line.clear();
+        let limit = left.min(MAX_LINE);
+        match reader.take(limit as u64).read_line(line) {
+            Ok(read) if read == limit && !line.ends_with('\n') => Err(too_large()),
+            Ok(0) => Err(bad_request("incomplete request")),
+            Ok(read) => {
+                left -= read;
+                Ok(())
+            }
+            Err(err) => Err(failed(err, "incomplete request")),
+        }
+    };
+    read_line(reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(bad_request("malformed request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let mut length = None;
+    for headers in 0.. {
+        read_line(reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers == MAX_HEADERS {
+            return Err(too_large());
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                let value = value.trim().parse::<usize>();
+                length = Some(value.map_err(|_| bad_request("malformed Content-Length"))?);
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(Response::message(
+                    (411, "Length Required"),
+                    "chunked bodies are not supported, send a Content-Length",
+                ));
+            }
+        }
+    }
+    let mut body = Vec::new();
+    match length {
+        Some(length) if length > MAX_BODY => {
+            return Err(Response::message(
+                (413, "Payload Too Large"),
+                &format!("bodies are limited to {} bytes", MAX_BODY),
+            ))
+        }
+        Some(length) => {
+            body.resize(length, 0);
+            reader
+                .read_exact(&mut body)
+                .map_err(|err| failed(err, "incomplete body"))?;
+        }
+        None if method == "POST" => {
+            return Err(Response::message(
+                (411, "Length Required"),
+                "requests need a Content-Length",
+            ))
+        }
+        None => {}
+    }
+    let body = String::from_utf8(body).map_err(|_| bad_request("the body is not valid UTF-8"))?;
+    Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    })
+}
+
+/// Answer `request`, formatting with `options`.
+fn respond(request: &Request, options: &Options) -> Response {
+    if request.path != "/format" {
+        return Response::message((404, "Not Found"), "the only endpoint is POST /format");
+    }
+    if request.method != "POST" {
+        return Response::message((405, "Method Not Allowed"), "/format only accepts POST");
+    }
+    let parse_mode = match request.param("mode").map(str::parse::<ParseMode>) {
+        Some(Ok(mode)) => mode,
+        Some(Err(err)) => return Response::message((400, "Bad Request"), &err),
+        None => ParseMode::File,
+    };
+    let options = Options {
+        parse_mode,
+        ..options.clone()
+    };
+    match format_with_policy(Utf8Path::new(SOURCE_NAME), &request.body, &options) {
+        Ok((formatted, _, _)) => Response::text(formatted),
+        Err(err) => Response::error((422, "Unprocessable Entity"), err.to_json()),
+    }
+}
+
+fn write_response(mut writer: impl Write, response: &Response) -> std::io::Result<()> {
+    let (code, reason) = response.status;
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use std::{io::Read, net::SocketAddr};
+
+    fn request(raw: &str) -> (u16, String) {
+        let response = read_request(&mut raw.as_bytes())
+            .map_or_else(|err| err, |request| respond(&request, &Options::default()));
+        (response.status.0, response.body)
+    }
+
+    #[test]
+    fn answers_requests() {
+        assert_eq!(
+            request("POST /format HTTP/1.1\r\nContent-Length: 9\r\n\r\nfn  a(){}"),
+            (200, "fn a() {}".to_string())
+        );
+        assert_eq!(
+            request("POST /format?mode=expr HTTP/1.1\r\nContent-Length: 3\r\n\r\n1+1"),
+            (200, "1 + 1".to_string())
+        );
+        let (status, body) = request("POST /format HTTP/1.1\r\ncontent-length: 7\r\n\r\nfn a(){");
+        assert_eq!(status, 422);
+        let error = json::parse(&body).unwrap();
+        let error = error.get("error").unwrap();
+        assert_eq!(error.get("path").and_then(Value::as_str), Some(SOURCE_NAME));
+        assert_eq!(error.get("line").and_then(Value::as_integer), Some(1));
+        assert_eq!(request("GET /format HTTP/1.1\r\n\r\n").0, 405);
+        assert_eq!(request("DELETE /other HTTP/1.1\r\n\r\n").0, 404);
+        assert_eq!(request("POST /format HTTP/1.1\r\n\r\n").0, 411);
+        assert_eq!(
+            request("POST /format?mode=x HTTP/1.1\r\nContent-Length: 0\r\n\r\n").0,
+            400
+        );
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert_eq!(request(&long).0, 431);
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "A: b\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert_eq!(request(&many).0, 431);
+        let few = format!("GET / HTTP/1.1\r\n{}\r\n", "A: b\r\n".repeat(MAX_HEADERS));
+        assert_eq!(request(&few).0, 404);
+        let header = format!("A: {}\r\n", "b".repeat(MAX_LINE / 2));
+        let large = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            header.repeat(MAX_HEAD / MAX_LINE * 2)
+        );
+        assert_eq!(request(&large).0, 431);
+    }
+
+    #[test]
+    fn serves_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr: SocketAddr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve_connection(&stream, &Options::default());
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST /format HTTP/1.1\r\nHost: {}\r\nContent-Length: 9\r\n\r\nfn  a(){{}}",
+            addr
+        )
+        .unwrap();
+        let mut response = String::new();
+        BufReader::new(&stream)
+            .read_to_string(&mut response)
+            .unwrap();
+        server.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nfn a() {}"));
+    }
+}
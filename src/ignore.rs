@@ -0,0 +1,390 @@
+//! Gitignore-style path filtering for the directory walker.
+//!
+//! Only the subset of the gitignore syntax that makes sense for a formatter is supported: comments,
+//! negation, directory-only and anchored patterns and the `*`, `?`, `[...]` and `**` wildcards.
+
+use crate::toml;
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{env, fs, process::Command, sync::Arc};
+
+/// Outcome of matching a path against a set of patterns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Match {
+    /// No pattern matched the path.
+    None,
+    /// The path is ignored.
+    Ignore,
+    /// The path was explicitly re-included with a `!` pattern.
+    Whitelist,
+}
+
+#[derive(Clone, Debug)]
+struct Pattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// Patterns loaded from a single ignore file, relative to the directory containing it.
+#[derive(Clone, Debug)]
+pub struct Gitignore {
+    base: Utf8PathBuf,
+    patterns: Vec<Pattern>,
+}
+
+impl Gitignore {
+    /// Parse the gitignore `contents`, matching paths relative to `base`.
+    pub fn parse(base: &Utf8Path, contents: &str) -> Self {
+        let patterns = contents.lines().filter_map(Self::parse_line).collect();
+        Gitignore {
+            base: base.to_path_buf(),
+            patterns,
+        }
+    }
+
+    /// Load an ignore file, returning `None` if it does not exist or can't be read.
+    pub fn from_file(base: &Utf8Path, path: &Utf8Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        Some(Self::parse(base, &contents))
+    }
+
+    /// Load the `ignore` list of the rustfmt configuration file of the directory `dir`, returning
+    /// `None` if there's none or it can't be read.
+    pub fn from_rustfmt(dir: &Utf8Path) -> Option<Self> {
+        let contents = RUSTFMT_FILES
+            .iter()
+            .find_map(|name| fs::read_to_string(dir.join(name)).ok())?;
+        let config = toml::parse(&contents).ok()?;
+        let patterns = match config.get("ignore")? {
+            toml::Value::Array(patterns) => patterns
+                .iter()
+                .filter_map(|pattern| match pattern {
+                    toml::Value::String(pattern) => Some(pattern.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+            _ => return None,
+        };
+        Some(Self::parse(dir, &patterns.join("\n")))
+    }
+
+    fn parse_line(line: &str) -> Option<Pattern> {
+        let mut line = line.trim_end_matches(['\r', '\n']);
+        // Trailing spaces are ignored unless escaped.
+        while line.ends_with(' ') && !line.ends_with("\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line.strip_prefix("\\!").map_or(line, |_| &line[1..])),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(Pattern {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Match `path` against the patterns of this file, the last matching pattern wins.
+    pub fn matched(&self, path: &Utf8Path, is_dir: bool) -> Match {
+        let relative = match path.strip_prefix(&self.base) {
+            Ok(relative) => relative.as_str().chars().collect::<Vec<_>>(),
+            Err(_) => return Match::None,
+        };
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .chars()
+            .collect::<Vec<_>>();
+        for pattern in self.patterns.iter().rev() {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            let text = if pattern.anchored { &relative } else { &name };
+            if glob_match(&pattern.glob, text) {
+                return if pattern.negated {
+                    Match::Whitelist
+                } else {
+                    Match::Ignore
+                };
+            }
+        }
+        Match::None
+    }
+}
+
+/// Match `text` against a gitignore glob, where wildcards never cross a `/` except for `**`.
+pub fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            if let Some(rest) = rest.strip_prefix(&['/']) {
+                // `**/` matches zero or more directories.
+                glob_match(rest, text)
+                    || text
+                        .iter()
+                        .enumerate()
+                        .any(|(idx, c)| *c == '/' && glob_match(rest, &text[idx + 1..]))
+            } else {
+                (0..=text.len()).any(|idx| glob_match(rest, &text[idx..]))
+            }
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for idx in 0..=text.len() {
+                if glob_match(rest, &text[idx..]) {
+                    return true;
+                }
+                if text.get(idx) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => match text.first() {
+            Some(c) if *c != '/' => glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some('[') => match (
+            text.first(),
+            class_match(&pattern[1..], text.first().copied()),
+        ) {
+            (Some(c), Some((true, len))) if *c != '/' => {
+                glob_match(&pattern[1 + len..], &text[1..])
+            }
+            (_, None) => text.first() == Some(&'[') && glob_match(&pattern[1..], &text[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_match(&pattern[1..], &text[1..]),
+    }
+}
+
+/// Match a character class (the part after `[`), returning whether it matched and its length
+/// including the closing `]`, or `None` if the class is not terminated.
+fn class_match(class: &[char], c: Option<char>) -> Option<(bool, usize)> {
+    let (negated, mut idx) = match class.first() {
+        Some('!' | '^') => (true, 1),
+        _ => (false, 0),
+    };
+    let mut matched = false;
+    let mut first = true;
+    while idx < class.len() {
+        let start = class[idx];
+        if start == ']' && !first {
+            return Some((matched != negated, idx + 1));
+        }
+        first = false;
+        if idx + 2 < class.len() && class[idx + 1] == '-' && class[idx + 2] != ']' {
+            let end = class[idx + 2];
+            matched |= c.map_or(false, |c| start <= c && c <= end);
+            idx += 3;
+        } else {
+            matched |= c == Some(start);
+            idx += 1;
+        }
+    }
+    None
+}
+
+/// Name of the formatter specific ignore file, using the gitignore syntax.
+pub const IGNORE_FILE: &str = ".prettythanksignore";
+
+/// Names of the rustfmt configuration files, whose `ignore` lists of gitignore patterns are honored
+/// like ignore files.
+pub const RUSTFMT_FILES: [&str; 2] = ["rustfmt.toml", ".rustfmt.toml"];
+
+/// A chain of ignore files, from the least specific (global excludes) to the most specific (the
+/// `.prettythanksignore` of the directory being walked), shared by the directories queued for
+/// walking. The `ignore` lists of `rustfmt.toml` files are loaded along with them.
+#[derive(Debug, Default)]
+pub struct Ignores {
+    parent: Option<Arc<Ignores>>,
+    files: Vec<Gitignore>,
+    /// Whether VCS ignore files are honored, `.prettythanksignore` and `rustfmt.toml` files always
+    /// are.
+    vcs: bool,
+}
+
+impl Ignores {
+    /// Build the ignore chain that applies to the directory `root`, loading the global excludes,
+    /// `.git/info/exclude` and all the ignore files between the repository root and `root`.
+    pub fn for_root(root: &Utf8Path, vcs: bool) -> Ignores {
+        let mut files = Vec::new();
+        let root = root
+            .canonicalize_utf8()
+            .unwrap_or_else(|_| root.to_path_buf());
+        let repo = root.ancestors().find(|dir| dir.join(".git").exists());
+        if let Some(repo) = repo {
+            if vcs {
+                if let Some(global) = global_excludes() {
+                    files.extend(Gitignore::from_file(repo, &global));
+                }
+                files.extend(Gitignore::from_file(repo, &repo.join(".git/info/exclude")));
+            }
+            let mut dirs = root
+                .ancestors()
+                .take_while(|dir| dir.starts_with(repo))
+                .collect::<Vec<_>>();
+            dirs.reverse();
+            // The root itself is loaded by the walker when it descends into it.
+            dirs.pop();
+            for dir in dirs {
+                files.extend(Self::load_dir(dir, vcs));
+            }
+        }
+        Ignores {
+            parent: None,
+            files,
+            vcs,
+        }
+    }
+
+    fn load_dir(dir: &Utf8Path, vcs: bool) -> impl Iterator<Item = Gitignore> + '_ {
+        let gitignore = if vcs {
+            Gitignore::from_file(dir, &dir.join(".gitignore"))
+        } else {
+            None
+        };
+        gitignore
+            .into_iter()
+            .chain(Gitignore::from_rustfmt(dir))
+            .chain(Gitignore::from_file(dir, &dir.join(IGNORE_FILE)))
+    }
+
+    /// Return a new chain for the directory `dir`, loading its ignore files if present.
+    #[must_use]
+    pub fn child(self: &Arc<Self>, dir: &Utf8Path) -> Ignores {
+        let files = Self::load_dir(dir, self.vcs).collect();
+        Ignores {
+            parent: Some(Arc::clone(self)),
+            files,
+            vcs: self.vcs,
+        }
+    }
+
+    /// Check whether `path` is ignored, the most specific ignore file wins.
+    pub fn is_ignored(&self, path: &Utf8Path, is_dir: bool) -> bool {
+        let mut current = Some(self);
+        while let Some(ignores) = current {
+            for file in ignores.files.iter().rev() {
+                match file.matched(path, is_dir) {
+                    Match::Ignore => return true,
+                    Match::Whitelist => return false,
+                    Match::None => {}
+                }
+            }
+            current = ignores.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// Directories that are never worth formatting: VCS metadata and cargo build directories.
+pub fn is_builtin_ignored(path: &Utf8Path) -> bool {
+    match path.file_name() {
+        Some(".git") => true,
+        Some("target") => {
+            path.join("CACHEDIR.TAG").exists()
+                || path
+                    .parent()
+                    .map_or(false, |parent| parent.join("Cargo.toml").exists())
+        }
+        _ => false,
+    }
+}
+
+/// Location of the global git excludes file, from `core.excludesFile` or the XDG default.
+fn global_excludes() -> Option<Utf8PathBuf> {
+    let configured = Command::new("git")
+        .args(["config", "--path", "--get", "core.excludesFile"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty());
+    if let Some(path) = configured {
+        return Some(path.into());
+    }
+    let config = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => Utf8PathBuf::from(dir),
+        _ => Utf8PathBuf::from(env::var("HOME").ok()?).join(".config"),
+    };
+    Some(config.join("git/ignore"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        glob_match(
+            &pattern.chars().collect::<Vec<_>>(),
+            &text.chars().collect::<Vec<_>>(),
+        )
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("src/**/*.rs", "src/main.rs"));
+        assert!(glob("src/**/*.rs", "src/proto/gen/a.rs"));
+        assert!(glob("**/gen", "a/b/gen"));
+        assert!(glob("src/**", "src/a/b.rs"));
+        assert!(glob("fo?.r[st]", "foo.rs"));
+        assert!(!glob("fo?.r[!st]", "foo.rs"));
+        assert!(glob("[a-c]*", "build.rs"));
+    }
+
+    #[test]
+    fn gitignore_precedence() {
+        let base = Utf8Path::new("/repo");
+        let ignore = Gitignore::parse(base, "# comment\n*.rs\n!keep.rs\n/generated/\nout/\n");
+        assert_eq!(ignore.matched(&base.join("src/a.rs"), false), Match::Ignore);
+        assert_eq!(
+            ignore.matched(&base.join("src/keep.rs"), false),
+            Match::Whitelist
+        );
+        assert_eq!(ignore.matched(&base.join("generated"), true), Match::Ignore);
+        assert_eq!(
+            ignore.matched(&base.join("src/generated"), true),
+            Match::None
+        );
+        assert_eq!(ignore.matched(&base.join("src/out"), true), Match::Ignore);
+        assert_eq!(ignore.matched(&base.join("src/out"), false), Match::None);
+    }
+
+    #[test]
+    fn rustfmt_ignore_lists() {
+        let temp = TempDir::new("rustfmt");
+        fs::write(
+            temp.join("rustfmt.toml"),
+            "edition = \"2021\"\nignore = [\"src/gen.rs\", \"vendor\"]\n",
+        )
+        .unwrap();
+        let ignores = Arc::new(Ignores::default()).child(&temp);
+        assert!(ignores.is_ignored(&temp.join("src/gen.rs"), false));
+        assert!(ignores.is_ignored(&temp.join("vendor"), true));
+        assert!(!ignores.is_ignored(&temp.join("src/lib.rs"), false));
+    }
+}
@@ -0,0 +1,287 @@
+//! Sorting, grouping and merging of `use` declarations, see [`sort`].
+
+use proc_macro2::TokenStream;
+use quote::ToTokens;
+use std::collections::BTreeMap;
+use syn::{
+    punctuated::Punctuated, token, Ident, Item, ItemUse, UseGlob, UseGroup, UseName, UsePath,
+    UseRename, UseTree,
+};
+
+/// The groups declarations are sorted in, in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    /// `std`, `core`, `alloc`, `proc_macro` and `test`.
+    Std,
+    External,
+    /// `crate`, `self` and `super`.
+    Crate,
+}
+
+fn group(item: &ItemUse) -> Group {
+    let root = match &item.tree {
+        UseTree::Path(UsePath { ident, .. })
+        | UseTree::Name(UseName { ident })
+        | UseTree::Rename(UseRename { ident, .. }) => ident,
+        UseTree::Glob(_) | UseTree::Group(_) => return Group::External,
+    };
+    if item.leading_colon.is_some() {
+        Group::External
+    } else if ["std", "core", "alloc", "proc_macro", "test"]
+        .iter()
+        .any(|name| root == name)
+    {
+        Group::Std
+    } else if ["crate", "self", "super"].iter().any(|name| root == name) {
+        Group::Crate
+    } else {
+        Group::External
+    }
+}
+
+/// What a path imports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Leaf {
+    Name(Ident),
+    Rename(Ident, Ident),
+    Glob,
+}
+
+/// Imported paths, merged by their common prefixes.
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, (Ident, Node)>,
+    leaves: Vec<Leaf>,
+}
+
+impl Node {
+    fn insert(&mut self, tree: &UseTree) {
+        let leaf = match tree {
+            UseTree::Path(path) => {
+                let child = self
+                    .children
+                    .entry(path.ident.to_string())
+                    .or_insert_with(|| (path.ident.clone(), Node::default()));
+                return child.1.insert(&path.tree);
+            }
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.insert(tree);
+                }
+                return;
+            }
+            UseTree::Name(name) => Leaf::Name(name.ident.clone()),
+            UseTree::Rename(rename) => Leaf::Rename(rename.ident.clone(), rename.rename.clone()),
+            UseTree::Glob(_) => Leaf::Glob,
+        };
+        if !self.leaves.contains(&leaf) {
+            self.leaves.push(leaf);
+        }
+    }
+
+    /// The trees importing everything under this node, sorted.
+    fn trees(self) -> Vec<UseTree> {
+        let mut trees = Vec::with_capacity(self.children.len() + self.leaves.len());
+        for leaf in self.leaves {
+            trees.push(match leaf {
+                Leaf::Name(ident) => UseTree::Name(UseName { ident }),
+                Leaf::Rename(ident, rename) => UseTree::Rename(UseRename {
+                    ident,
+                    as_token: token::As::default(),
+                    rename,
+                }),
+                Leaf::Glob => UseTree::Glob(UseGlob {
+                    star_token: token::Star::default(),
+                }),
+            });
+        }
+        for (ident, node) in self.children.into_values() {
+            trees.push(UseTree::Path(UsePath {
+                ident,
+                colon2_token: token::PathSep::default(),
+                tree: Box::new(node.tree()),
+            }));
+        }
+        trees.sort_by_cached_key(key);
+        trees
+    }
+
+    /// The tree importing everything under this node.
+    fn tree(self) -> UseTree {
+        let mut trees = self.trees();
+        // `a::self` is not a path, `a::{self}` is.
+        let is_self = |tree: &UseTree| match tree {
+            UseTree::Name(UseName { ident }) | UseTree::Rename(UseRename { ident, .. }) => {
+                ident == "self"
+            }
+            _ => false,
+        };
+        if trees.len() == 1 && !is_self(&trees[0]) {
+            return trees.remove(0);
+        }
+        UseTree::Group(UseGroup {
+            brace_token: token::Brace::default(),
+            items: trees.into_iter().collect::<Punctuated<_, token::Comma>>(),
+        })
+    }
+}
+
+/// How trees are sorted: `self`, `super` and `crate` first, then lowercase names like modules,
+/// other names like types, and globs, names being imported before the paths under them.
+fn key(tree: &UseTree) -> (u8, String, u8) {
+    let (ident, kind) = match tree {
+        UseTree::Name(UseName { ident }) => (ident, 0),
+        UseTree::Rename(UseRename { ident, .. }) => (ident, 1),
+        UseTree::Path(UsePath { ident, .. }) => (ident, 2),
+        UseTree::Glob(_) => return (6, String::new(), 0),
+        UseTree::Group(_) => return (7, String::new(), 0),
+    };
+    let name = ident.to_string();
+    let class = match name.as_str() {
+        "self" => 0,
+        "super" => 1,
+        "crate" => 2,
+        _ if name
+            .trim_start_matches("r#")
+            .starts_with(char::is_lowercase) =>
+        {
+            3
+        }
+        _ => 4,
+    };
+    (class, name, kind)
+}
+
+/// The keys along the first path of `tree`, for sorting whole declarations.
+fn path_key(mut tree: &UseTree) -> Vec<(u8, String, u8)> {
+    let mut keys = Vec::new();
+    loop {
+        match tree {
+            UseTree::Path(path) => {
+                keys.push(key(tree));
+                tree = &path.tree;
+            }
+            UseTree::Group(group) => match group.items.first() {
+                Some(first) => tree = first,
+                None => return keys,
+            },
+            tree => {
+                keys.push(key(tree));
+                return keys;
+            }
+        }
+    }
+}
+
+/// Whether a declaration merges with the others: it has no attributes, so it doesn't depend on
+/// a `cfg` or carry documentation.
+fn is_mergeable(item: &ItemUse) -> bool {
+    item.attrs.is_empty()
+}
+
+/// Sort the contiguous runs of `use` declarations of `items`, recursing into inline modules.
+fn sort_items(items: &mut Vec<Item>) {
+    let mut sorted = Vec::with_capacity(items.len());
+    let mut run = Vec::new();
+    for item in items.drain(..) {
+        match item {
+            Item::Use(item) if !crate::skip::is_skip(&item.attrs) => run.push(item),
+            mut item => {
+                sorted.extend(
+                    sort_run(std::mem::take(&mut run))
+                        .into_iter()
+                        .map(Item::Use),
+                );
+                if let Item::Mod(module) = &mut item {
+                    if let Some((_, items)) = &mut module.content {
+                        sort_items(items);
+                    }
+                }
+                sorted.push(item);
+            }
+        }
+    }
+    sorted.extend(sort_run(run).into_iter().map(Item::Use));
+    *items = sorted;
+}
+
+/// The declarations of `run` merged by crate and sorted by group.
+fn sort_run(run: Vec<ItemUse>) -> Vec<ItemUse> {
+    // By group, visibility, leading `::` and crate.
+    let mut merged = BTreeMap::<_, (TokenStream, Node)>::new();
+    let mut others = Vec::new();
+    for item in run {
+        if !is_mergeable(&item) {
+            others.push(item);
+            continue;
+        }
+        let root = match &item.tree {
+            UseTree::Path(UsePath { ident, .. }) => ident.to_string(),
+            _ => String::new(),
+        };
+        let mut prefix = item.vis.to_token_stream();
+        item.use_token.to_tokens(&mut prefix);
+        item.leading_colon.to_tokens(&mut prefix);
+        let entry = (group(&item), prefix.to_string(), root);
+        let (_, node) = merged
+            .entry(entry)
+            .or_insert_with(|| (prefix, Node::default()));
+        node.insert(&item.tree);
+    }
+    let mut items = others;
+    for (prefix, node) in merged.into_values() {
+        // Top level groups would read `use {a, b};`.
+        for tree in node.trees() {
+            items.push(syn::parse_quote!(#prefix #tree;));
+        }
+    }
+    items.sort_by_cached_key(|item| (group(item), path_key(&item.tree)));
+    items
+}
+
+/// Sort the `use` declarations of `file` into groups, `std` and its siblings first, then other
+/// crates and last the current one, merging the declarations importing from the same crate and
+/// sorting the names they import.
+///
+/// Only contiguous declarations are sorted, so that they stay next to the items around them.
+/// Declarations with attributes are sorted but never merged.
+pub fn sort(file: &mut syn::File) {
+    sort_items(&mut file.items);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(source: &str) -> String {
+        let mut file = syn::parse_file(source).unwrap();
+        sort(&mut file);
+        prettyplease::unparse(&file)
+    }
+
+    #[test]
+    fn sorts_and_merges() {
+        assert_eq!(
+            sorted(
+                "use crate::b; use std::io::Write; use syn::Item; use std::{fmt, io::{self, \
+                 Read}}; use camino::Utf8Path; pub use std::fmt::Display; use super::*; use \
+                 std::fmt; #[cfg(test)] use std::time::Instant; fn f() {} use b; use a;"
+            ),
+            "use std::{fmt, io::{self, Read, Write}};\n\
+             pub use std::fmt::Display;\n\
+             #[cfg(test)]\n\
+             use std::time::Instant;\n\
+             use camino::Utf8Path;\n\
+             use syn::Item;\n\
+             use super::*;\n\
+             use crate::b;\n\
+             fn f() {}\n\
+             use a;\n\
+             use b;\n"
+        );
+        assert_eq!(
+            sorted("mod m { use a::{self}; use a::{B, c, self as d}; }"),
+            "mod m {\n    use a::{self, self as d, c, B};\n}\n"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Confirmation of every change before it is written, for adopting the style of prettyplease one
+//! file at a time.
+
+use std::io::{self, BufRead, Write};
+
+/// What to do with the changes to a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Answer {
+    /// Write this file.
+    Yes,
+    /// Leave this file untouched.
+    No,
+    /// Write this file and every other one, without asking again.
+    All,
+    /// Leave this file and every other one untouched.
+    Quit,
+}
+
+impl Answer {
+    fn parse(line: &str) -> Option<Self> {
+        match line.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" => Some(Answer::Yes),
+            "n" | "no" => Some(Answer::No),
+            "a" | "all" => Some(Answer::All),
+            "q" | "quit" => Some(Answer::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// Ask on `output` whether to write the changes to `path` until `input` answers, the end of
+/// `input` meaning [`Answer::Quit`].
+pub(crate) fn ask(
+    path: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> io::Result<Answer> {
+    loop {
+        write!(
+            output,
+            "write the changes to {}? [y]es/[n]o/[a]ll/[q]uit ",
+            path
+        )?;
+        output.flush()?;
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        if let Some(answer) = Answer::parse(&line) {
+            return Ok(answer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asks_until_answered() {
+        let mut output = Vec::new();
+        let answer = ask("a.rs", &mut "maybe\nA\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answer, Answer::All);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "write the changes to a.rs? [y]es/[n]o/[a]ll/[q]uit ".repeat(2)
+        );
+        let answer = ask("a.rs", &mut "".as_bytes(), &mut io::sink()).unwrap();
+        assert_eq!(answer, Answer::Quit);
+        assert_eq!(Answer::parse(" no\n"), Some(Answer::No));
+    }
+}
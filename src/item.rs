@@ -0,0 +1,174 @@
+//! Formatting of a single item of a file, found by its path, see [`format`].
+
+use crate::{
+    error,
+    expand::{implements, name},
+    Error, Result,
+};
+use camino::Utf8Path;
+use proc_macro2::Span;
+use syn::{spanned::Spanned, Ident, ImplItem, Item, TraitItem};
+
+/// The name of the associated item `item`, if it has one.
+fn impl_item_name(item: &ImplItem) -> Option<&Ident> {
+    match item {
+        ImplItem::Const(item) => Some(&item.ident),
+        ImplItem::Fn(item) => Some(&item.sig.ident),
+        ImplItem::Type(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+/// The name of the trait item `item`, if it has one.
+fn trait_item_name(item: &TraitItem) -> Option<&Ident> {
+    match item {
+        TraitItem::Const(item) => Some(&item.ident),
+        TraitItem::Fn(item) => Some(&item.sig.ident),
+        TraitItem::Type(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+/// The span of the item at the path `segments` in `items`, pushing to `blocks` the opening lines
+/// of blocks printed like the ones it is in, outermost first.
+fn find(items: &[Item], segments: &[&str], blocks: &mut Vec<&'static str>) -> Option<Span> {
+    let (first, rest) = segments.split_first()?;
+    let named = |ident: Option<&Ident>| ident.map_or(false, |ident| ident == first);
+    if rest.is_empty() {
+        return items
+            .iter()
+            .find(|item| named(name(item)))
+            .map(Spanned::span);
+    }
+    let is_last =
+        |ident: Option<&Ident>| rest.len() == 1 && ident.map_or(false, |ident| ident == rest[0]);
+    for item in items {
+        match item {
+            Item::Mod(module) if module.ident == first => {
+                let (_, items) = module.content.as_ref()?;
+                blocks.push("mod m {");
+                return find(items, rest, blocks);
+            }
+            // Methods are found in any implementation for their type.
+            Item::Impl(imp) if implements(item, first) => {
+                if let Some(item) = imp.items.iter().find(|item| is_last(impl_item_name(item))) {
+                    blocks.push("impl _ {");
+                    return Some(item.span());
+                }
+            }
+            Item::Trait(trait_) if trait_.ident == first => {
+                let item = trait_
+                    .items
+                    .iter()
+                    .find(|item| is_last(trait_item_name(item)))?;
+                blocks.push("trait T {");
+                return Some(item.span());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Format the item of `source`, the source file `path` parsed as `file`, at the path `item`, like
+/// `module::Type::method`, with `format`, leaving the rest of it untouched.
+///
+/// Items in modules, implementations and traits are formatted in blocks like theirs, and indented
+/// like the line they start on.
+///
+/// # Errors
+///
+/// If there's no such item, or if `format` fails.
+pub(crate) fn format(
+    path: &Utf8Path,
+    source: &str,
+    file: &syn::File,
+    item: &str,
+    format: impl FnOnce(&str) -> Result<(String, usize, bool)>,
+) -> Result<(String, usize, bool)> {
+    let segments = item
+        .split("::")
+        .filter(|segment| !segment.is_empty())
+        .skip_while(|segment| *segment == "crate")
+        .collect::<Vec<_>>();
+    let mut blocks = Vec::new();
+    let span = find(&file.items, &segments, &mut blocks).ok_or_else(|| {
+        Error::Unsupported(format!("no item `{}` in {}", item, error::describe(path)))
+    })?;
+    let range = span.byte_range();
+    let mut wrapped = String::new();
+    for block in &blocks {
+        wrapped.push_str(block);
+        wrapped.push('\n');
+    }
+    wrapped.push_str(&source[range.clone()]);
+    for _ in &blocks {
+        wrapped.push_str("\n}");
+    }
+    wrapped.push('\n');
+    let (formatted, dropped, skipped) = format(&wrapped)?;
+    if skipped {
+        return Ok((source.to_string(), dropped, true));
+    }
+    // The item is indented like the line it starts on, rather than like prettyplease would.
+    let line_start = source[..range.start].rfind('\n').map_or(0, |idx| idx + 1);
+    let indent = &source[line_start..range.start];
+    let indent = if indent.trim().is_empty() { indent } else { "" };
+    let printed_indent = " ".repeat(4 * blocks.len());
+    // prettyplease prints the opening and closing lines of the blocks on lines of their own.
+    let lines = formatted.lines().collect::<Vec<_>>();
+    let mut inner = String::new();
+    for (idx, line) in lines[blocks.len()..lines.len() - blocks.len()]
+        .iter()
+        .enumerate()
+    {
+        if idx > 0 {
+            inner.push('\n');
+            if !line.is_empty() {
+                inner.push_str(indent);
+            }
+        }
+        inner.push_str(line.strip_prefix(printed_indent.as_str()).unwrap_or(line));
+    }
+    let spliced = format!(
+        "{}{}{}",
+        &source[..range.start],
+        inner,
+        &source[range.end..]
+    );
+    Ok((spliced, dropped, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_named_items() {
+        let source = "fn  a(){}\nmod m {\n  struct  S;\n  impl S {\n    fn  new()->S{S}\n  }\n}\n\
+                      trait T { fn  t(&self){} }\n";
+        let file = syn::parse_file(source).unwrap();
+        let format = |item| {
+            super::format(Utf8Path::new("a.rs"), source, &file, item, |wrapped| {
+                crate::format_str(wrapped).map(|formatted| (formatted, 0, false))
+            })
+            .map(|(formatted, _, _)| formatted)
+        };
+        assert_eq!(
+            format("crate::m::S::new").unwrap(),
+            "fn  a(){}\nmod m {\n  struct  S;\n  impl S {\n    fn new() -> S {\n        S\n    }\n  \
+             }\n}\ntrait T { fn  t(&self){} }\n"
+        );
+        assert_eq!(
+            format("a").unwrap(),
+            "fn a() {}\nmod m {\n  struct  S;\n  impl S {\n    fn  new()->S{S}\n  }\n}\n\
+             trait T { fn  t(&self){} }\n"
+        );
+        assert_eq!(
+            format("T::t").unwrap(),
+            "fn  a(){}\nmod m {\n  struct  S;\n  impl S {\n    fn  new()->S{S}\n  }\n}\n\
+             trait T { fn t(&self) {} }\n"
+        );
+        assert!(format("m::S::old").is_err());
+    }
+}
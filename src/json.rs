@@ -0,0 +1,477 @@
+//! A minimal JSON reader and writer, for the machine readable reports and `cargo metadata`.
+//!
+//! Object keys keep their insertion order, so reports always list fields the same way.
+
+use crate::{Entry, Error, Report};
+use std::fmt::{self, Display, Write};
+
+/// A JSON value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Build an object out of `(key, value)` pairs.
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Self {
+        Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The value of `key`, if this is an object having it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find_map(|(name, value)| (name == key).then_some(value)),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Integer(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+}
+
+impl From<u128> for Value {
+    fn from(value: u128) -> Self {
+        Value::Integer(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Write `value` as a quoted JSON string.
+fn write_str(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if u32::from(c) < 0x20 => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Integer(value) => write!(f, "{}", value),
+            // JSON has no representation for infinities and NaN.
+            Value::Float(value) if !value.is_finite() => f.write_str("null"),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::String(value) => write_str(f, value),
+            Value::Array(values) => {
+                f.write_char('[')?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(fields) => {
+                f.write_char('{')?;
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Parse a JSON document.
+pub fn parse(src: &str) -> Result<Value, String> {
+    let mut parser = Parser { src, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < src.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{} at byte {}", message, self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value, String> {
+        if self.src[self.pos..].starts_with(keyword) {
+            self.pos += keyword.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(self.error("unexpected end of input")),
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Boolean(true)),
+            Some('f') => self.keyword("false", Value::Boolean(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.bump();
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.bump();
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.bump() {
+                        Some(',') => {}
+                        Some(']') => return Ok(Value::Array(values)),
+                        _ => return Err(self.error("expected `,` or `]`")),
+                    }
+                }
+            }
+            Some('{') => {
+                self.bump();
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                    return Ok(Value::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err(self.error("expected a key"));
+                    }
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.bump() {
+                        Some(',') => {}
+                        Some('}') => return Ok(Value::Object(fields)),
+                        _ => return Err(self.error("expected `,` or `}`")),
+                    }
+                }
+            }
+            Some(_) => self.number(),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('0'..='9' | '-' | '+' | '.' | 'e' | 'E')) {
+            self.bump();
+        }
+        let text = &self.src[start..self.pos];
+        if let Ok(value) = text.parse::<i64>() {
+            return Ok(Value::Integer(value));
+        }
+        text.parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("truncated escape"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let c = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let mut code = self.hex()?;
+                            // Characters outside of the BMP are escaped as surrogate pairs.
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.src[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex()?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    value.push(c);
+                }
+                Some(c) => value.push(c),
+            }
+        }
+    }
+}
+
+impl Error {
+    /// The error as a JSON object: its `message`, the `path` it is about and, for parse errors,
+    /// where it is in the source.
+    pub fn to_json(&self) -> Value {
+        let mut fields = vec![
+            ("message", self.to_string().into()),
+            ("path", self.path().map(camino::Utf8Path::as_str).into()),
+        ];
+        if let Some(location) = self.location() {
+            fields.push(("line", location.line.into()));
+            fields.push(("column", location.column.into()));
+            fields.push(("end_line", location.end_line.into()));
+            fields.push(("end_column", location.end_column.into()));
+        }
+        Value::object(fields)
+    }
+}
+
+impl Report {
+    /// The report as a JSON document: one entry per file in traversal order, the errors hit while
+    /// walking the tree and an overall summary.
+    pub fn to_json(&self) -> Value {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for entry in &self.entries {
+            match entry {
+                Entry::File(file) => {
+                    let mut fields = vec![("path", file.path.as_str().into())];
+                    match &file.result {
+                        Ok(outcome) => {
+                            let status = if outcome.skipped {
+                                "skipped"
+                            } else if outcome.changed {
+                                "changed"
+                            } else {
+                                "unchanged"
+                            };
+                            fields.push(("status", status.into()));
+                            fields.push(("original_size", outcome.original.into()));
+                            fields.push(("formatted_size", outcome.formatted.into()));
+                            fields.push(("dropped_comments", outcome.dropped_comments.into()));
+                            fields.push(("duration_ms", outcome.elapsed.as_millis().into()));
+                            fields.push(("removed_lines", outcome.removed_lines.into()));
+                            fields.push(("added_lines", outcome.added_lines.into()));
+                            if let Some(encoding) = outcome.transcoded {
+                                fields.push(("encoding", encoding.to_string().into()));
+                            }
+                            if !outcome.output.is_empty() {
+                                fields.push(("output", outcome.output.as_str().into()));
+                            }
+                        }
+                        Err(err) => {
+                            fields.push(("status", "error".into()));
+                            fields.push(("error", err.to_string().into()));
+                            if let Some(location) = err.location() {
+                                fields.push(("line", location.line.into()));
+                                fields.push(("column", location.column.into()));
+                            }
+                        }
+                    }
+                    files.push(Value::object(fields));
+                }
+                Entry::Ignored(path) => files.push(Value::object([
+                    ("path", path.as_str().into()),
+                    ("status", "ignored".into()),
+                ])),
+                Entry::Orphan(path) => files.push(Value::object([
+                    ("path", path.as_str().into()),
+                    ("status", "orphan".into()),
+                ])),
+                Entry::Revisited(path) => files.push(Value::object([
+                    ("path", path.as_str().into()),
+                    ("status", "revisited".into()),
+                ])),
+                Entry::Duplicate(path) => files.push(Value::object([
+                    ("path", path.as_str().into()),
+                    ("status", "duplicate".into()),
+                ])),
+                Entry::NonUtf8(path) => files.push(Value::object([
+                    ("path", path.to_string_lossy().into_owned().into()),
+                    ("status", "non-utf8".into()),
+                ])),
+                Entry::Error(err) => errors.push(Value::from(err.to_string())),
+            }
+        }
+        let summary = Value::object([
+            ("files", self.files().count().into()),
+            ("changed", self.changed().count().into()),
+            ("errors", self.errors().count().into()),
+            ("original_size", self.original_size().into()),
+            ("formatted_size", self.formatted_size().into()),
+            ("duration_ms", self.elapsed.as_millis().into()),
+            (
+                "unchanged",
+                (self.outcomes().count() - self.changed().count()).into(),
+            ),
+            ("removed_lines", self.removed_lines().into()),
+            ("added_lines", self.added_lines().into()),
+        ]);
+        Value::object([
+            ("files", Value::Array(files)),
+            ("errors", Value::Array(errors)),
+            ("summary", summary),
+            ("stats", self.stats_json()),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_values() {
+        let value = Value::object([
+            ("name", "a \"quoted\"\n\u{1}".into()),
+            ("size", 42usize.into()),
+            ("ok", true.into()),
+            ("none", Option::<&str>::None.into()),
+            ("list", Value::Array(vec![Value::Float(1.5), Value::Null])),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"name":"a \"quoted\"\n\u0001","size":42,"ok":true,"none":null,"list":[1.5,null]}"#
+        );
+    }
+
+    #[test]
+    fn parses_values() {
+        let value = parse(
+            r#" {"name": "a \"quoted\"\n\u00e9\ud83d\ude00", "size": 42, "ratio": -1.25e1,
+                "list": [true, false, null, {}, []]} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("name").unwrap().as_str(),
+            Some("a \"quoted\"\né😀")
+        );
+        assert_eq!(value.get("size").unwrap().as_integer(), Some(42));
+        assert_eq!(value.get("ratio"), Some(&Value::Float(-12.5)));
+        assert_eq!(value.get("list").unwrap().as_array().unwrap().len(), 5);
+        assert_eq!(parse(&value.to_string()).unwrap(), value);
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{} x").is_err());
+    }
+}
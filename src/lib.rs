@@ -0,0 +1,4129 @@
+//! A simple frontend to the [`prettyplease`] library.
+//!
+//! Besides the `prettythanks` binary, the same traversal and error aggregation logic is available
+//! to build scripts and code generators:
+//!
+//! ```no_run
+//! use camino::Utf8Path;
+//!
+//! let formatted = prettythanks::format_str("fn main(){}").unwrap();
+//! assert_eq!(formatted, "fn main() {}\n");
+//!
+//! let report = prettythanks::format_tree(Utf8Path::new("src"), &prettythanks::Options::default());
+//! assert!(report.is_ok());
+//! ```
+
+// Positional format arguments are used throughout, keep it consistent.
+#![allow(
+    clippy::uninlined_format_args,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use comments::CommentPolicy;
+use config::Config;
+use edition::Edition;
+use encoding::Encoding;
+use ignore::{Gitignore, Ignores};
+use skip::Skip;
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, BTreeSet},
+    env, fmt, fs,
+    io::{self, Read, Write},
+    ops::{Bound, RangeInclusive},
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+pub use error::{Error, ErrorFormat, Location};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Log a message at the given [`log::Level`].
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => (
+        $crate::log::log($crate::log::Level::$level, ::std::format_args!($($arg)*))
+    )
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => (log!(Error, $($arg)*))
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => (log!(Warn, $($arg)*))
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => (log!(Info, $($arg)*))
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => (log!(Debug, $($arg)*))
+}
+
+pub mod backup;
+pub mod bench;
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cargo;
+pub mod comments;
+pub mod completions;
+pub mod config;
+pub mod daemon;
+pub mod diff;
+pub mod docs;
+pub mod doctests;
+pub mod edition;
+pub mod encoding;
+mod error;
+pub mod expand;
+pub mod git;
+pub mod github;
+pub mod hook;
+pub mod http;
+pub mod ignore;
+pub mod imports;
+mod interactive;
+mod item;
+pub mod json;
+pub mod lock;
+pub mod log;
+pub mod lsp;
+pub mod macros;
+pub mod markdown;
+mod metadata;
+pub mod modules;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+mod recover;
+pub mod reporter;
+pub mod resume;
+pub mod rustfmt;
+pub mod sarif;
+pub mod signal;
+pub mod skip;
+pub mod stats;
+mod suggest;
+#[cfg(test)]
+mod temp;
+pub mod term;
+mod tokens;
+mod toml;
+pub mod transform;
+#[cfg(feature = "tui")]
+mod tui;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod watch;
+pub mod xml;
+
+/// How the outcome of a run is printed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Diffs and `--verbose` messages, meant for humans.
+    #[default]
+    Human,
+    /// A single JSON document on stdout, see [`Report::to_json`].
+    Json,
+    /// A Checkstyle XML document on stdout, see [`Report::to_checkstyle`].
+    Checkstyle,
+    /// GitHub Actions workflow commands on stdout, see [`Report::to_github`].
+    Github,
+    /// A SARIF log on stdout, see [`Report::to_sarif`].
+    Sarif,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            "checkstyle" => Ok(OutputFormat::Checkstyle),
+            "github" => Ok(OutputFormat::Github),
+            "sarif" => Ok(OutputFormat::Sarif),
+            _ => Err(format!(
+                "unknown output format `{}`, expected human, json, checkstyle, github or sarif",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Human => "human",
+            OutputFormat::Json => "json",
+            OutputFormat::Checkstyle => "checkstyle",
+            OutputFormat::Github => "github",
+            OutputFormat::Sarif => "sarif",
+        })
+    }
+}
+
+/// What formatting files produces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Emit {
+    /// The files are rewritten.
+    #[default]
+    Files,
+    /// A single git patch on stdout, with the changes of every file, see [`diff::patch`].
+    Patch,
+    /// Every formatted file on stdout, after a line with its path.
+    Stdout,
+    /// A unified diff of every file on stdout, like [`Options::diff`].
+    Diff,
+    /// Nothing, formatting only tells whether files can be formatted.
+    None,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "files" => Ok(Emit::Files),
+            "patch" => Ok(Emit::Patch),
+            "stdout" => Ok(Emit::Stdout),
+            "diff" => Ok(Emit::Diff),
+            "none" => Ok(Emit::None),
+            _ => Err(format!(
+                "unknown emit mode `{}`, expected files, patch, stdout, diff or none",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Emit::Files => "files",
+            Emit::Patch => "patch",
+            Emit::Stdout => "stdout",
+            Emit::Diff => "diff",
+            Emit::None => "none",
+        })
+    }
+}
+
+/// How files that don't parse are formatted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fallback {
+    /// They are not, failing.
+    #[default]
+    None,
+    /// By rustfmt, which may know syntax syn doesn't yet, see [`rustfmt::format`].
+    Rustfmt,
+}
+
+impl FromStr for Fallback {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "none" => Ok(Fallback::None),
+            "rustfmt" => Ok(Fallback::Rustfmt),
+            _ => Err(format!(
+                "unknown fallback `{}`, expected none or rustfmt",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Fallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Fallback::None => "none",
+            Fallback::Rustfmt => "rustfmt",
+        })
+    }
+}
+
+/// What is done with the read-only files formatting changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadOnlyPolicy {
+    /// They are left untouched, with a warning.
+    #[default]
+    Skip,
+    /// They are reported as errors.
+    Error,
+    /// They are rewritten anyway, staying read-only.
+    Force,
+}
+
+impl FromStr for ReadOnlyPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "skip" => Ok(ReadOnlyPolicy::Skip),
+            "error" => Ok(ReadOnlyPolicy::Error),
+            "force" => Ok(ReadOnlyPolicy::Force),
+            _ => Err(format!(
+                "unknown read-only policy `{}`, expected skip, error or force",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ReadOnlyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReadOnlyPolicy::Skip => "skip",
+            ReadOnlyPolicy::Error => "error",
+            ReadOnlyPolicy::Force => "force",
+        })
+    }
+}
+
+/// Which symlinks are followed while walking directories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Symlinks are skipped.
+    Never,
+    /// Symlinked files are formatted, but symlinked directories are not walked.
+    #[default]
+    Files,
+    /// Symlinked directories are walked too, once each, to avoid loops.
+    All,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "never" => Ok(SymlinkPolicy::Never),
+            "files" => Ok(SymlinkPolicy::Files),
+            "all" => Ok(SymlinkPolicy::All),
+            _ => Err(format!(
+                "unknown symlink policy `{}`, expected never, files or all",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SymlinkPolicy::Never => "never",
+            SymlinkPolicy::Files => "files",
+            SymlinkPolicy::All => "all",
+        })
+    }
+}
+
+/// The line endings of formatted sources.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// The ones most lines of the original source end with, the last line ending without one if
+    /// it does in the source.
+    #[default]
+    Auto,
+    /// `\n`.
+    Lf,
+    /// `\r\n`.
+    Crlf,
+}
+
+impl LineEnding {
+    /// Whether lines of `source` end with `\r\n`, `Auto` picking the most common line ending.
+    fn is_crlf(self, source: &str) -> bool {
+        match self {
+            LineEnding::Auto => {
+                let crlf = source.matches("\r\n").count();
+                crlf > source.matches('\n').count() - crlf
+            }
+            LineEnding::Lf => false,
+            LineEnding::Crlf => true,
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(LineEnding::Auto),
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            _ => Err(format!(
+                "unknown line ending `{}`, expected auto, lf or crlf",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Auto => "auto",
+            LineEnding::Lf => "lf",
+            LineEnding::Crlf => "crlf",
+        })
+    }
+}
+
+/// What the sources to format are made of.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// A whole source file.
+    #[default]
+    File,
+    /// A single item, like a function or a struct.
+    Item,
+    /// A single expression.
+    Expr,
+    /// A sequence of statements, like the body of a function.
+    Stmt,
+    /// A block, braces included.
+    Block,
+}
+
+impl FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "file" => Ok(ParseMode::File),
+            "item" => Ok(ParseMode::Item),
+            "expr" => Ok(ParseMode::Expr),
+            "stmt" => Ok(ParseMode::Stmt),
+            "block" => Ok(ParseMode::Block),
+            _ => Err(format!(
+                "unknown parse mode `{}`, expected file, item, expr, stmt or block",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ParseMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseMode::File => "file",
+            ParseMode::Item => "item",
+            ParseMode::Expr => "expr",
+            ParseMode::Stmt => "stmt",
+            ParseMode::Block => "block",
+        })
+    }
+}
+
+/// Knobs controlling how files are formatted.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Options {
+    /// Print a unified diff instead of rewriting files.
+    pub diff: bool,
+    /// Only check that files are formatted, without rewriting them.
+    pub check: bool,
+    /// Only print the paths of the files that are not formatted, without rewriting them.
+    pub list: bool,
+    /// Only print the number of files that are not formatted, without rewriting them.
+    pub count: bool,
+    /// Fail with [`Error::TooManyUnformatted`] when more files than this are not formatted, with
+    /// [`Options::count`], to keep their number from growing.
+    pub max_unformatted: Option<usize>,
+    /// Fail with [`Error::Unformatted`] for files that were not formatted, even when rewriting
+    /// them.
+    pub error_on_unformatted: bool,
+    /// Don't skip ignored files and build directories.
+    pub no_ignore: bool,
+    /// File extensions considered Rust sources while walking directories.
+    pub extensions: Vec<String>,
+    /// Format the Rust code blocks of the Markdown files found while walking directories too, see
+    /// [`markdown::format`]. Markdown files passed in are always formatted.
+    pub include_markdown: bool,
+    /// Extra excludes, from the configuration file and the command line.
+    pub exclude: Vec<Gitignore>,
+    /// Only format the files matching these patterns while walking directories, all of them if
+    /// `None`.
+    pub include: Option<Gitignore>,
+    /// Number of files formatted in parallel.
+    pub jobs: usize,
+    /// How the outcome is printed by [`PrettyThanks::run`].
+    pub output_format: OutputFormat,
+    /// What to do with files whose regular comments would be dropped.
+    pub comment_policy: CommentPolicy,
+    /// Format files marked as generated too, see [`skip::is_generated`].
+    pub format_generated: bool,
+    /// Only format these files, as absolute paths, see [`git::changed_files`].
+    pub only: Option<BTreeSet<Utf8PathBuf>>,
+    /// Format the staged contents of the files, see [`git::format_staged`].
+    pub staged: bool,
+    /// Only format the top level items overlapping these ranges of lines, counted from 1, leaving
+    /// the rest of the files as it is, if there's any.
+    pub lines: Vec<RangeInclusive<usize>>,
+    /// Only format the item at this path, like `module::Type::method`, leaving the rest of the
+    /// files as it is, if any.
+    pub item: Option<String>,
+    /// Only format the files of directories reachable from their crate roots, see
+    /// [`modules::reachable`].
+    pub follow_mods: bool,
+    /// Which symlinks are followed while walking directories.
+    pub follow_symlinks: SymlinkPolicy,
+    /// How deep directories are walked, `Some(0)` formatting only the files directly in the
+    /// directories passed in. Deeper directories are skipped.
+    pub max_depth: Option<usize>,
+    /// What the sources are made of, usually only changed for stdin.
+    pub parse_mode: ParseMode,
+    /// The path of the file read from stdin, which errors and reports are about instead of
+    /// stdin, and the edition of the package of is used, for editors formatting their buffers.
+    /// Formatting changing it fails with [`Error::Unformatted`], for editors to tell it changed.
+    pub stdin_filename: Option<Utf8PathBuf>,
+    /// Print stdin back unchanged when it doesn't parse, with a warning, rather than failing, so
+    /// that editors replacing their buffer with the output never wipe it.
+    pub tolerant: bool,
+    /// Format every source a second time, failing if the result is not the same.
+    pub assert_idempotent: bool,
+    /// Format the top level items that parse of files that don't, leaving the others untouched,
+    /// instead of failing.
+    pub best_effort: bool,
+    /// How files that don't parse are formatted, before trying [`Options::best_effort`].
+    pub fallback: Fallback,
+    /// Format the arguments and bodies of macro invocations that parse as Rust code too, see
+    /// [`macros::format`].
+    pub format_macros: bool,
+    /// The rewrites of the parsed sources run before printing them, see [`transform`].
+    pub transforms: transform::Pipeline,
+    /// The edition of the sources, found for each of them with [`edition::detect`] if `None`.
+    pub edition: Option<Edition>,
+    /// The line endings of formatted sources, the final newline included.
+    pub line_ending: LineEnding,
+    /// The encoding of the files, see [`encoding`].
+    pub encoding: Encoding,
+    /// Write files that are not UTF-8 back as UTF-8, instead of in their own encoding.
+    pub write_utf8: bool,
+    /// Draw a progress bar on stderr from [`PrettyThanks::run`], as long as both stdout and
+    /// stderr are terminals.
+    pub progress: bool,
+    /// Stop at the first error, instead of reporting it and formatting everything else.
+    pub fail_fast: bool,
+    /// Leave the files larger than this many bytes untouched, without reading them.
+    pub max_file_size: Option<u64>,
+    /// Leave the files taking longer than this to format untouched.
+    ///
+    /// Formatting them can't be interrupted, it goes on on a background thread until it is done.
+    /// At most [`MAX_ABANDONED`] of these threads run at once, the next file waiting for one of
+    /// them to finish before it starts.
+    pub timeout_per_file: Option<Duration>,
+    /// Copy files to their path with this suffix appended before rewriting them, see
+    /// [`backup::restore`].
+    pub backup: Option<String>,
+    /// What is done with the read-only files formatting changes.
+    pub readonly: ReadOnlyPolicy,
+    /// Write the formatted files to this directory instead, mirroring their path under the path
+    /// they were found in, see [`format_file_to`]. Files landing on the path of another one are
+    /// left out, with [`Error::SameDestination`].
+    pub out_dir: Option<Utf8PathBuf>,
+    /// Rewrite the files, or print a patch of the changes instead.
+    pub emit: Emit,
+    /// Skip the files recorded as formatted in this cache file and record the newly formatted
+    /// ones, see [`cache::Cache`]. Not used with [`Options::staged`] or [`Options::out_dir`].
+    pub cache: Option<Utf8PathBuf>,
+    /// Hold this lock file while files are written, so that no other run writes them at the same
+    /// time, see [`lock::Lock`].
+    pub lock: Option<Utf8PathBuf>,
+    /// Wait for the run holding [`Options::lock`] to finish, instead of failing.
+    pub wait_lock: bool,
+    /// Record the files formatted in this journal as they are, for an interrupted run to be
+    /// resumed, see [`resume::Journal`]. Only kept by the runs formatting at least
+    /// [`resume::MIN_FILES`] files, or with [`Options::resume`].
+    pub journal: Option<Utf8PathBuf>,
+    /// Skip the files recorded in [`Options::journal`] by the interrupted run this one resumes.
+    pub resume: bool,
+    /// Also write a `JUnit` report to this file from [`PrettyThanks::run`], see
+    /// [`Report::to_junit`].
+    pub report_junit: Option<Utf8PathBuf>,
+    /// Format everything without writing anything, [`PrettyThanks::run`] printing a summary of
+    /// what would change and never failing, see [`Report::summary_table`].
+    pub dry_run: bool,
+    /// Print the diff of every file [`PrettyThanks::run`] would rewrite and ask whether to write
+    /// it, on stderr and stdin.
+    pub interactive: bool,
+    /// Print statistics once everything is formatted from [`PrettyThanks::run`], see
+    /// [`Report::stats_table`].
+    pub stats: bool,
+    /// Print where the time went for the slowest and the largest files once everything is
+    /// formatted from [`PrettyThanks::run`], see [`Report::profile_table`].
+    pub profile: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            diff: false,
+            check: false,
+            list: false,
+            count: false,
+            max_unformatted: None,
+            error_on_unformatted: false,
+            no_ignore: false,
+            extensions: vec!["rs".to_string()],
+            include_markdown: false,
+            exclude: Vec::new(),
+            include: None,
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+            output_format: OutputFormat::default(),
+            comment_policy: CommentPolicy::default(),
+            format_generated: false,
+            only: None,
+            staged: false,
+            lines: Vec::new(),
+            item: None,
+            follow_mods: false,
+            follow_symlinks: SymlinkPolicy::default(),
+            max_depth: None,
+            parse_mode: ParseMode::File,
+            stdin_filename: None,
+            tolerant: false,
+            assert_idempotent: false,
+            best_effort: false,
+            fallback: Fallback::default(),
+            format_macros: false,
+            transforms: transform::Pipeline::default(),
+            edition: None,
+            line_ending: LineEnding::default(),
+            encoding: Encoding::default(),
+            write_utf8: false,
+            progress: false,
+            fail_fast: false,
+            max_file_size: None,
+            timeout_per_file: None,
+            backup: None,
+            readonly: ReadOnlyPolicy::default(),
+            out_dir: None,
+            emit: Emit::Files,
+            cache: None,
+            lock: None,
+            wait_lock: false,
+            journal: None,
+            resume: false,
+            report_junit: None,
+            dry_run: false,
+            interactive: false,
+            stats: false,
+            profile: false,
+        }
+    }
+}
+
+impl Options {
+    /// Options set in a configuration file, falling back to the defaults.
+    pub fn from_config(config: &Config) -> Self {
+        let defaults = Options::default();
+        let exclude = match config.root() {
+            Some(root) if !config.exclude.is_empty() => {
+                vec![Gitignore::parse(root, &config.exclude.join("\n"))]
+            }
+            _ => Vec::new(),
+        };
+        Options {
+            diff: config.diff.unwrap_or(defaults.diff),
+            check: config.check.unwrap_or(defaults.check),
+            list: defaults.list,
+            count: defaults.count,
+            max_unformatted: defaults.max_unformatted,
+            error_on_unformatted: config
+                .error_on_unformatted
+                .unwrap_or(defaults.error_on_unformatted),
+            no_ignore: config.no_ignore.unwrap_or(defaults.no_ignore),
+            extensions: config.extensions.clone().unwrap_or(defaults.extensions),
+            include_markdown: config.include_markdown.unwrap_or(defaults.include_markdown),
+            exclude,
+            include: defaults.include,
+            jobs: config.jobs.unwrap_or(defaults.jobs),
+            output_format: config.output_format.unwrap_or(defaults.output_format),
+            comment_policy: config.comment_policy.unwrap_or(defaults.comment_policy),
+            format_generated: config.format_generated.unwrap_or(defaults.format_generated),
+            only: None,
+            staged: false,
+            lines: Vec::new(),
+            item: None,
+            follow_mods: config.follow_mods.unwrap_or(defaults.follow_mods),
+            follow_symlinks: config.follow_symlinks.unwrap_or(defaults.follow_symlinks),
+            max_depth: config.max_depth.or(defaults.max_depth),
+            parse_mode: defaults.parse_mode,
+            stdin_filename: defaults.stdin_filename,
+            tolerant: defaults.tolerant,
+            assert_idempotent: config
+                .assert_idempotent
+                .unwrap_or(defaults.assert_idempotent),
+            best_effort: config.best_effort.unwrap_or(defaults.best_effort),
+            fallback: config.fallback.unwrap_or(defaults.fallback),
+            format_macros: config.format_macros.unwrap_or(defaults.format_macros),
+            transforms: config.transforms(),
+            edition: config.edition.or(defaults.edition),
+            line_ending: config.line_ending.unwrap_or(defaults.line_ending),
+            encoding: config.encoding.unwrap_or(defaults.encoding),
+            write_utf8: config.write_utf8.unwrap_or(defaults.write_utf8),
+            progress: defaults.progress,
+            fail_fast: config.fail_fast.unwrap_or(defaults.fail_fast),
+            max_file_size: config.max_file_size.or(defaults.max_file_size),
+            timeout_per_file: config
+                .timeout_per_file
+                .map(Duration::from_secs)
+                .or(defaults.timeout_per_file),
+            backup: match config.backup {
+                Some(true) => Some(
+                    config
+                        .backup_suffix
+                        .clone()
+                        .unwrap_or_else(|| backup::DEFAULT_SUFFIX.to_string()),
+                ),
+                _ => defaults.backup,
+            },
+            readonly: config.readonly.unwrap_or(defaults.readonly),
+            out_dir: defaults.out_dir,
+            emit: config.emit.unwrap_or(defaults.emit),
+            cache: match config.cache {
+                Some(true) => Some(cache::FILE_NAME.into()),
+                _ => defaults.cache,
+            },
+            lock: defaults.lock,
+            wait_lock: defaults.wait_lock,
+            journal: defaults.journal,
+            resume: defaults.resume,
+            report_junit: defaults.report_junit,
+            dry_run: defaults.dry_run,
+            interactive: defaults.interactive,
+            stats: config.stats.unwrap_or(defaults.stats),
+            profile: defaults.profile,
+        }
+    }
+
+    /// These options with the formatting settings of `config` applying over them, for the files
+    /// under a nested configuration file. Settings of the whole run, like `check`, `jobs` or
+    /// `exclude`, are only read from the configuration the run starts with.
+    #[must_use]
+    pub fn with_config(&self, config: &Config) -> Self {
+        let transforms_set = !config.transforms.is_empty()
+            || config.sort_imports.is_some()
+            || config.doc_width.is_some()
+            || config.format_doctests.is_some();
+        Options {
+            comment_policy: config.comment_policy.unwrap_or(self.comment_policy),
+            format_generated: config.format_generated.unwrap_or(self.format_generated),
+            assert_idempotent: config.assert_idempotent.unwrap_or(self.assert_idempotent),
+            best_effort: config.best_effort.unwrap_or(self.best_effort),
+            fallback: config.fallback.unwrap_or(self.fallback),
+            format_macros: config.format_macros.unwrap_or(self.format_macros),
+            transforms: if transforms_set {
+                config.transforms()
+            } else {
+                self.transforms.clone()
+            },
+            edition: config.edition.or(self.edition),
+            line_ending: config.line_ending.unwrap_or(self.line_ending),
+            encoding: config.encoding.unwrap_or(self.encoding),
+            write_utf8: config.write_utf8.unwrap_or(self.write_utf8),
+            max_file_size: config.max_file_size.or(self.max_file_size),
+            timeout_per_file: config
+                .timeout_per_file
+                .map(Duration::from_secs)
+                .or(self.timeout_per_file),
+            readonly: config.readonly.unwrap_or(self.readonly),
+            ..self.clone()
+        }
+    }
+
+    /// The values of the configuration keys in these options, `null` for the unset ones, in the
+    /// order of the configuration file documentation. Transforms enabled on their own, like
+    /// `sort-imports`, are listed in `transforms`.
+    pub fn settings(&self) -> Vec<(&'static str, json::Value)> {
+        let text = |value: &dyn fmt::Display| json::Value::String(value.to_string());
+        let list = |items: &mut dyn Iterator<Item = &str>| {
+            json::Value::Array(items.map(json::Value::from).collect())
+        };
+        vec![
+            (
+                "extensions",
+                list(&mut self.extensions.iter().map(String::as_str)),
+            ),
+            ("include-markdown", self.include_markdown.into()),
+            ("check", self.check.into()),
+            ("diff", self.diff.into()),
+            ("no-ignore", self.no_ignore.into()),
+            ("format-generated", self.format_generated.into()),
+            ("follow-mods", self.follow_mods.into()),
+            ("follow-symlinks", text(&self.follow_symlinks)),
+            ("max-depth", self.max_depth.into()),
+            ("assert-idempotent", self.assert_idempotent.into()),
+            ("best-effort", self.best_effort.into()),
+            ("fallback", text(&self.fallback)),
+            ("format-macros", self.format_macros.into()),
+            ("transforms", list(&mut self.transforms.names())),
+            (
+                "edition",
+                self.edition.map(|edition| edition.to_string()).into(),
+            ),
+            ("line-ending", text(&self.line_ending)),
+            ("encoding", text(&self.encoding)),
+            ("write-utf8", self.write_utf8.into()),
+            ("stats", self.stats.into()),
+            ("error-on-unformatted", self.error_on_unformatted.into()),
+            ("fail-fast", self.fail_fast.into()),
+            ("max-file-size", self.max_file_size.map(u128::from).into()),
+            (
+                "timeout-per-file",
+                self.timeout_per_file
+                    .map(|timeout| u128::from(timeout.as_secs()))
+                    .into(),
+            ),
+            ("backup", self.backup.is_some().into()),
+            ("backup-suffix", self.backup.clone().into()),
+            ("readonly", text(&self.readonly)),
+            ("emit", text(&self.emit)),
+            ("cache", self.cache.is_some().into()),
+            ("jobs", self.jobs.into()),
+            ("output-format", text(&self.output_format)),
+            ("comment-policy", text(&self.comment_policy)),
+        ]
+    }
+
+    /// Whether formatted files are written back, instead of only being reported.
+    pub fn writes(&self) -> bool {
+        !self.diff
+            && !self.check
+            && !self.list
+            && !self.count
+            && !self.dry_run
+            && !self.interactive
+            && self.emit == Emit::Files
+    }
+
+    /// Whether unified diffs of the changes are printed.
+    pub(crate) fn shows_diff(&self) -> bool {
+        self.diff || self.emit == Emit::Diff
+    }
+
+    /// The lines formatting `original` into `formatted` removes and adds, only counted for the
+    /// reports showing them, [`Options::stats`] and the JSON output, and zero otherwise.
+    pub(crate) fn line_counts(&self, original: &str, formatted: &str) -> (usize, usize) {
+        if self.stats || self.output_format == OutputFormat::Json {
+            diff::line_counts(original, formatted)
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// What is printed about the file `path` formatted from `original` to `formatted`.
+    pub(crate) fn output(&self, path: &Utf8Path, original: &str, formatted: &str) -> String {
+        if self.shows_diff() || self.interactive {
+            diff::unified(
+                original,
+                formatted,
+                path.as_str(),
+                path.as_str(),
+                diff::CONTEXT_LINES,
+            )
+        } else if self.emit == Emit::Patch {
+            diff::patch(original, formatted, &patch_path(path))
+        } else if self.emit == Emit::Stdout {
+            format!("{}:\n\n{}", path, formatted)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Whether `path` has one of the configured extensions.
+    pub fn is_rust_file(&self, path: &Utf8Path) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        self.extensions.iter().any(|ext| {
+            name.strip_suffix(ext.as_str())
+                .map_or(false, |stem| stem.len() > 1 && stem.ends_with('.'))
+        })
+    }
+
+    /// Whether `path` is formatted while walking directories: it is a Rust file, or a Markdown one
+    /// with [`Options::include_markdown`].
+    pub fn is_walked_file(&self, path: &Utf8Path) -> bool {
+        self.is_rust_file(path) || (self.include_markdown && markdown::is_markdown(path))
+    }
+
+    /// The edition of the source file `path`: [`Options::edition`], or the one of the package it
+    /// belongs to.
+    pub fn edition_for(&self, path: &Utf8Path) -> Option<Edition> {
+        self.edition.or_else(|| edition::detect(path))
+    }
+}
+
+/// Outcome of formatting a single file.
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Outcome {
+    /// Size of the original source, in bytes.
+    pub original: usize,
+    /// Size of the formatted source, in bytes.
+    pub formatted: usize,
+    /// Whether formatting changed the source.
+    pub changed: bool,
+    /// Number of regular comments dropped by formatting, the ones of the source missing from the
+    /// formatted one, or that formatting would drop if `skipped` because of them.
+    pub dropped_comments: usize,
+    /// The dropped comments are allowed by [`CommentPolicy::Allow`], so not worth a warning.
+    pub comments_allowed: bool,
+    /// The source was left untouched, because of a skip marker or [`CommentPolicy::Skip`].
+    pub skipped: bool,
+    /// The source was found formatted in [`Options::cache`], so not read through the steps
+    /// `timings` measure, which are all zero.
+    pub cached: bool,
+    pub elapsed: Duration,
+    /// Where the time went.
+    pub timings: Timings,
+    /// Text meant for stdout: the unified diff in diff mode, the patch with `--emit patch`, the
+    /// formatted source for stdin.
+    pub output: String,
+    /// The encoding the file was transcoded from, if it is not UTF-8.
+    pub transcoded: Option<Encoding>,
+    /// Number of lines formatting removed, counted for [`Options::stats`] and the JSON output
+    /// only.
+    pub removed_lines: usize,
+    /// Number of lines formatting added, counted like `removed_lines`.
+    pub added_lines: usize,
+    /// Why the file was `skipped`, if it is not because of a skip marker or the comment policy.
+    pub skip_reason: Option<SkipReason>,
+}
+
+impl Outcome {
+    /// The outcome of a file of `size` bytes left untouched for `reason`, formatting it started
+    /// at `start`.
+    fn untouched(size: usize, start: Instant, reason: SkipReason) -> Self {
+        Outcome {
+            original: size,
+            formatted: size,
+            changed: false,
+            dropped_comments: 0,
+            comments_allowed: false,
+            skipped: true,
+            cached: false,
+            elapsed: start.elapsed(),
+            timings: Timings::default(),
+            output: String::new(),
+            transcoded: None,
+            removed_lines: 0,
+            added_lines: 0,
+            skip_reason: Some(reason),
+        }
+    }
+}
+
+/// Why a file was left untouched, reported with a warning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// It changed while it was being formatted, like when an editor saves it, and keeps its new
+    /// contents rather than being overwritten.
+    ChangedMeanwhile,
+    /// It is read-only, see [`ReadOnlyPolicy::Skip`].
+    ReadOnly,
+    /// It is larger than [`Options::max_file_size`], and was not even read.
+    TooLarge,
+    /// Formatting it took longer than [`Options::timeout_per_file`].
+    TimedOut,
+    /// It doesn't parse, and is printed back as it is, see [`Options::tolerant`].
+    Unparsable,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::ChangedMeanwhile => "it changed during formatting",
+            SkipReason::ReadOnly => "it is read-only, `--readonly force` formats it anyway",
+            SkipReason::TooLarge => "it is larger than the maximum file size",
+            SkipReason::TimedOut => "formatting it took longer than the timeout",
+            SkipReason::Unparsable => "it doesn't parse",
+        })
+    }
+}
+
+/// Time spent on each step of formatting a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timings {
+    /// Reading the source.
+    pub read: Duration,
+    /// Parsing it, part of `format`.
+    pub parse: Duration,
+    /// Printing it back, part of `format`.
+    pub print: Duration,
+    /// Parsing and printing it, with the comment policy and the safety checks.
+    pub format: Duration,
+    /// Writing it back.
+    pub write: Duration,
+}
+
+impl Timings {
+    /// The structured fields logging them, in microseconds.
+    fn fields(&self) -> [(&'static str, json::Value); 5] {
+        [
+            ("read_us", self.read.as_micros().into()),
+            ("parse_us", self.parse.as_micros().into()),
+            ("print_us", self.print.as_micros().into()),
+            ("format_us", self.format.as_micros().into()),
+            ("write_us", self.write.as_micros().into()),
+        ]
+    }
+}
+
+thread_local! {
+    /// Time spent parsing and printing whole files on this thread, see [`take_phases`].
+    static PHASES: Cell<(Duration, Duration)> = const { Cell::new((Duration::ZERO, Duration::ZERO)) };
+}
+
+/// The time spent parsing and printing whole files on this thread since the last call.
+pub(crate) fn take_phases() -> (Duration, Duration) {
+    PHASES.with(|phases| phases.replace((Duration::ZERO, Duration::ZERO)))
+}
+
+/// A formatted file, `-` being stdin.
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: Utf8PathBuf,
+    pub result: Result<Outcome>,
+}
+
+/// A single entry of a [`Report`], in traversal order.
+#[derive(Debug)]
+pub enum Entry {
+    File(FileReport),
+    /// A path skipped because of the ignore files or the excludes.
+    Ignored(Utf8PathBuf),
+    /// A file skipped because no crate root reaches it, see [`Options::follow_mods`].
+    Orphan(Utf8PathBuf),
+    /// A symlinked directory skipped because it leads to a directory already walked, which
+    /// could loop forever, see [`Options::follow_symlinks`].
+    Revisited(Utf8PathBuf),
+    /// A file skipped because it was already reached through another path, like a hard link or
+    /// a symlink, so that it is formatted once.
+    Duplicate(Utf8PathBuf),
+    /// A file or directory skipped because its name is not valid UTF-8.
+    NonUtf8(PathBuf),
+    /// An error hit while walking the tree.
+    Error(Error),
+}
+
+/// Everything that happened while formatting a set of paths.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub entries: Vec<Entry>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// All the formatted files, successfully or not.
+    pub fn files(&self) -> impl Iterator<Item = &FileReport> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::File(file) => Some(file),
+            _ => None,
+        })
+    }
+
+    fn outcomes(&self) -> impl Iterator<Item = &Outcome> {
+        self.files().filter_map(|file| file.result.as_ref().ok())
+    }
+
+    /// Total size of the original sources, in bytes.
+    pub fn original_size(&self) -> usize {
+        self.outcomes().map(|outcome| outcome.original).sum()
+    }
+
+    /// Total size of the formatted sources, in bytes.
+    pub fn formatted_size(&self) -> usize {
+        self.outcomes().map(|outcome| outcome.formatted).sum()
+    }
+
+    /// Files whose formatted output differs from the original.
+    pub fn changed(&self) -> impl Iterator<Item = &FileReport> {
+        self.files().filter(|file| {
+            file.result
+                .as_ref()
+                .map_or(false, |outcome| outcome.changed)
+        })
+    }
+
+    /// All the errors, both from walking the tree and formatting files.
+    pub fn errors(&self) -> impl Iterator<Item = &Error> {
+        self.entries.iter().filter_map(|entry| match entry {
+            Entry::File(FileReport {
+                result: Err(err), ..
+            })
+            | Entry::Error(err) => Some(err),
+            _ => None,
+        })
+    }
+
+    /// Whether everything was formatted without errors.
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    /// What a dry run would have done, as a table with one count per line.
+    pub fn summary_table(&self) -> String {
+        use std::fmt::Write as _;
+
+        let parse_errors = self
+            .errors()
+            .filter(|err| matches!(err, Error::Parse { .. }))
+            .count();
+        let changed = self.changed().count();
+        let skipped = self
+            .outcomes()
+            .filter(|outcome| outcome.skipped && !outcome.changed)
+            .count();
+        let delta = i128::try_from(self.formatted_size()).unwrap_or(i128::MAX)
+            - i128::try_from(self.original_size()).unwrap_or(i128::MAX);
+        let rows = [
+            ("files scanned", self.files().count().to_string()),
+            ("would change", changed.to_string()),
+            (
+                "unchanged",
+                (self.outcomes().count() - changed - skipped).to_string(),
+            ),
+            ("skipped", skipped.to_string()),
+            ("parse errors", parse_errors.to_string()),
+            (
+                "other errors",
+                (self.errors().count() - parse_errors).to_string(),
+            ),
+            ("size change", format!("{:+} bytes", delta)),
+        ];
+        let mut table = String::new();
+        for (name, value) in rows {
+            let _ = writeln!(table, "{:<14}{:>12}", name, value);
+        }
+        table
+    }
+}
+
+/// Format a Rust source file held in memory.
+pub fn format_str(source: &str) -> Result<String> {
+    format_transformed(source, Edition::default(), |_| {})
+}
+
+/// Format a Rust source file of the edition `edition` held in memory, applying `transform` to it
+/// once parsed.
+fn format_transformed(
+    source: &str,
+    edition: Edition,
+    transform: impl FnOnce(&mut syn::File),
+) -> Result<String> {
+    let start = Instant::now();
+    let mut ast =
+        edition::parse_file(source, edition).map_err(|err| Error::parse(None, source, err))?;
+    transform(&mut ast);
+    let parsed = Instant::now();
+    let formatted = edition::unraw_keywords(prettyplease::unparse(&ast), source, edition);
+    PHASES.with(|phases| {
+        let (parse, print) = phases.get();
+        phases.set((parse + (parsed - start), print + parsed.elapsed()));
+    });
+    Ok(formatted)
+}
+
+/// Name of the function wrapping statements and blocks, which prettyplease can't print alone.
+const SNIPPET_FN: &str = "__prettythanks_snippet";
+
+/// Format a piece of Rust source held in memory, made of what `mode` says.
+pub fn format_snippet(source: &str, mode: ParseMode) -> Result<String> {
+    let parse_error = |err| Error::parse(None, source, err);
+    let unparse = |item: syn::Item| {
+        prettyplease::unparse(&syn::File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: vec![item],
+        })
+    };
+    let function = |block: syn::Block| {
+        let name = syn::Ident::new(SNIPPET_FN, proc_macro2::Span::call_site());
+        unparse(syn::parse_quote!(fn #name() #block))
+    };
+    match mode {
+        ParseMode::File => format_str(source),
+        ParseMode::Item => Ok(unparse(syn::parse_str(source).map_err(parse_error)?)),
+        ParseMode::Expr => {
+            let expr = syn::parse_str::<syn::Expr>(source).map_err(parse_error)?;
+            // Top level items are not indented, unlike statements.
+            let printed = unparse(syn::parse_quote!(const _: () = #expr;));
+            let printed = printed
+                .strip_prefix("const _: () = ")
+                .and_then(|printed| printed.strip_suffix(";\n"))
+                .unwrap_or(&printed);
+            Ok(format!("{}\n", printed))
+        }
+        ParseMode::Block => {
+            let block = syn::parse_str::<syn::Block>(source).map_err(parse_error)?;
+            let printed = function(block);
+            let prefix = format!("fn {}() ", SNIPPET_FN);
+            Ok(printed
+                .strip_prefix(&prefix)
+                .unwrap_or(&printed)
+                .to_string())
+        }
+        ParseMode::Stmt => {
+            let stmts = syn::parse::Parser::parse_str(syn::Block::parse_within, source)
+                .map_err(parse_error)?;
+            let printed = function(syn::Block {
+                brace_token: syn::token::Brace::default(),
+                stmts,
+            });
+            let lines = printed.lines().collect::<Vec<_>>();
+            let body = lines
+                .get(1..lines.len().saturating_sub(1))
+                .unwrap_or_default();
+            let mut output = String::with_capacity(printed.len());
+            for line in body {
+                output.push_str(line.strip_prefix("    ").unwrap_or(line));
+                output.push('\n');
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// Format `source` according to the skip markers and the comment policy, returning the formatted
+/// source, the number of dropped comments and whether it was skipped, in which case `source` is
+/// returned as is.
+pub(crate) fn format_with_policy(
+    path: &Utf8Path,
+    source: &str,
+    options: &Options,
+) -> Result<(String, usize, bool)> {
+    if !options.format_generated && skip::is_generated(source) {
+        return Ok((source.to_string(), 0, true));
+    }
+    let crlf = options.line_ending.is_crlf(source);
+    // prettyplease always ends with one, which `Auto` leaves out when the source has none.
+    let final_newline =
+        options.line_ending != LineEnding::Auto || source.is_empty() || source.ends_with('\n');
+    let original = source;
+    let (prefix, source) = if options.parse_mode == ParseMode::File {
+        split_prefix(source)
+    } else {
+        ("", source)
+    };
+    // prettyplease prints `\n`, and `\r\n` means the same in literals.
+    let normalized;
+    let source = if source.contains("\r\n") {
+        normalized = source.replace("\r\n", "\n");
+        &normalized
+    } else {
+        source
+    };
+    // Markdown files have no tokens to check or comments to lose.
+    let result = if markdown::is_markdown(path) {
+        Ok((markdown::format(source), 0, false))
+    } else if !options.lines.is_empty() && options.parse_mode == ParseMode::File {
+        format_lines(path, source, options)
+    } else if let Some(item) = options.item.as_deref() {
+        format_item(path, source, options, item)
+    } else {
+        match format_checked(path, source, options) {
+            Err(err @ Error::Parse { .. }) if options.parse_mode == ParseMode::File => {
+                fall_back(path, source, options, err)
+            }
+            result => result,
+        }
+    };
+    let result = result.map(|(formatted, dropped, skipped)| {
+        if skipped {
+            return (original.to_string(), dropped, true);
+        }
+        let formatted = match formatted.strip_suffix('\n') {
+            Some(stripped) if !final_newline => stripped.to_string(),
+            _ => formatted,
+        };
+        let formatted = if crlf {
+            formatted.replace('\n', "\r\n")
+        } else {
+            formatted
+        };
+        (format!("{}{}", prefix, formatted), dropped, false)
+    });
+    // Span locations keep a copy of every parsed source, don't let them pile up across files.
+    proc_macro2::extra::invalidate_current_thread_spans();
+    result
+}
+
+/// Format the top level items of `source` overlapping [`Options::lines`], leaving the rest of it
+/// byte for byte as it is.
+fn format_lines(path: &Utf8Path, source: &str, options: &Options) -> Result<(String, usize, bool)> {
+    // The whole file must parse, for its errors to be reported where they are in it.
+    let edition = options.edition_for(path).unwrap_or_default();
+    let parse_error = |err| Error::parse(Some(path), source, err);
+    edition::parse_file(source, edition).map_err(parse_error)?;
+    if let Skip::File = skip::scan(source).map_err(parse_error)? {
+        return Ok((source.to_string(), 0, true));
+    }
+    let (formatted, dropped) = recover::format_lines(source, &options.lines, |item| {
+        format_checked(path, item, options)
+    })?;
+    Ok((formatted, dropped, false))
+}
+
+/// Format the item at the path `item` of `source`, leaving the rest of it byte for byte as it is.
+fn format_item(
+    path: &Utf8Path,
+    source: &str,
+    options: &Options,
+    item: &str,
+) -> Result<(String, usize, bool)> {
+    let edition = options.edition_for(path).unwrap_or_default();
+    let parse_error = |err| Error::parse(Some(path), source, err);
+    let file = edition::parse_file(source, edition).map_err(parse_error)?;
+    if let Skip::File = skip::scan(source).map_err(parse_error)? {
+        return Ok((source.to_string(), 0, true));
+    }
+    item::format(path, source, &file, item, |wrapped| {
+        format_checked(path, wrapped, options)
+    })
+}
+
+/// Format `source`, which failed to parse with `err`, with [`Options::fallback`], or only its items
+/// that parse with [`Options::best_effort`], failing with `err` otherwise.
+fn fall_back(
+    path: &Utf8Path,
+    source: &str,
+    options: &Options,
+    err: Error,
+) -> Result<(String, usize, bool)> {
+    if options.fallback == Fallback::Rustfmt {
+        let edition = options.edition_for(path).unwrap_or_default();
+        match rustfmt::format(path, source, edition) {
+            // rustfmt keeps comments.
+            Ok(formatted) => {
+                warn!("{}, formatting it with rustfmt instead", err);
+                return Ok((formatted, 0, false));
+            }
+            Err(rustfmt) => debug!("{}", rustfmt),
+        }
+    }
+    if options.best_effort {
+        if let Some((formatted, dropped)) =
+            recover::format(source, |item| format_checked(path, item, options))
+        {
+            warn!("{}, only formatting the items that parse", err);
+            return Ok((formatted, dropped, false));
+        }
+    }
+    Err(err)
+}
+
+/// Split the byte order mark and the shebang line starting `source` off it, as prettyplease
+/// would drop the former and can't print comments after the latter.
+///
+/// The newline ending the shebang line is both in the prefix and the rest of the source, so that
+/// parse errors are still reported on the right line.
+fn split_prefix(source: &str) -> (&str, &str) {
+    let bom = if source.starts_with('\u{feff}') {
+        '\u{feff}'.len_utf8()
+    } else {
+        0
+    };
+    let rest = &source[bom..];
+    // `#![attribute]` is an inner attribute, not a shebang.
+    let shebang = rest.starts_with("#!") && !rest[2..].trim_start().starts_with('[');
+    if !shebang {
+        return source.split_at(bom);
+    }
+    match rest.find('\n').map(|end| bom + end) {
+        Some(end) => (&source[..=end], &source[end..]),
+        None => (source, ""),
+    }
+}
+
+/// Format `source` according to the skip markers and the comment policy, making sure that the
+/// tokens stay the same and, if asked, that formatting is idempotent.
+fn format_checked(
+    path: &Utf8Path,
+    source: &str,
+    options: &Options,
+) -> Result<(String, usize, bool)> {
+    let (formatted, dropped, skipped) = apply_policy(path, source, options)?;
+    // A prettyplease bug must never change what the code means.
+    // Only whole files are checked, snippets are never written anywhere.
+    let check = !skipped && options.parse_mode == ParseMode::File;
+    if check
+        && formatted != source
+        && !tokens::same_tokens(
+            source,
+            &formatted,
+            options.edition_for(path).unwrap_or_default(),
+            |file| options.transforms.apply(file),
+        )
+    {
+        return Err(Error::TokensChanged(path.to_path_buf()));
+    }
+    if options.assert_idempotent && !skipped {
+        let (again, _, _) = apply_policy(path, &formatted, options)?;
+        if again != formatted {
+            return Err(Error::NotIdempotent {
+                path: path.to_path_buf(),
+                diff: diff::unified(
+                    &formatted,
+                    &again,
+                    path.as_str(),
+                    path.as_str(),
+                    diff::CONTEXT_LINES,
+                ),
+            });
+        }
+    }
+    Ok((formatted, dropped, skipped))
+}
+
+fn apply_policy(path: &Utf8Path, source: &str, options: &Options) -> Result<(String, usize, bool)> {
+    let policy = options.comment_policy;
+    let skip = if options.parse_mode == ParseMode::File {
+        skip::scan(source).map_err(|err| Error::parse(Some(path), source, err))?
+    } else {
+        Skip::Nothing
+    };
+    let masked = match &skip {
+        Skip::File => return Ok((source.to_string(), 0, true)),
+        Skip::Nothing => source,
+        Skip::Items(masked) => &masked.source,
+    };
+    let comments = comments::regular_comments(masked).len();
+    if policy == CommentPolicy::Skip && comments > 0 {
+        return Ok((source.to_string(), comments, true));
+    }
+    let mut formatted = format_source(path, masked, options)?;
+    if policy == CommentPolicy::Preserve && comments > 0 {
+        if let Some(reattached) = comments::reattach(masked, &formatted) {
+            formatted = reattached;
+        }
+    }
+    // Only the comments of the items formatted, the skipped ones are put back as they are.
+    let dropped = comments.saturating_sub(comments::regular_comments(&formatted).len());
+    if let Skip::Items(masked) = &skip {
+        formatted = masked.restore(&formatted).ok_or_else(|| {
+            Error::Unsupported(format!("failed to restore skipped items in {}", path))
+        })?;
+    }
+    Ok((formatted, dropped, false))
+}
+
+/// Format `source`, blaming `path` for parse errors.
+fn format_source(path: &Utf8Path, source: &str, options: &Options) -> Result<String> {
+    let edition = options.edition_for(path);
+    let formatted = match options.parse_mode {
+        ParseMode::File => format_transformed(source, edition.unwrap_or_default(), |file| {
+            options.transforms.apply(file);
+        })
+        .map(|formatted| {
+            if options.format_macros {
+                macros::format(&formatted)
+            } else {
+                formatted
+            }
+        }),
+        mode => format_snippet(source, mode),
+    };
+    formatted.map_err(|err| match err {
+        Error::Parse {
+            source, location, ..
+        } => Error::Parse {
+            path: Some(path.to_path_buf()),
+            source: match edition {
+                Some(edition) => {
+                    syn::Error::new(source.span(), format!("{} (edition {})", source, edition))
+                }
+                None => source,
+            },
+            location,
+        },
+        err => err,
+    })
+}
+
+/// The outcome of leaving `path` alone, without reading it, if it is larger than
+/// [`Options::max_file_size`].
+fn too_large(path: &Utf8Path, options: &Options, start: Instant) -> Result<Option<Outcome>> {
+    let max = match options.max_file_size {
+        Some(max) => max,
+        None => return Ok(None),
+    };
+    let len = fs::metadata(path)
+        .map_err(|err| Error::io(path, err))?
+        .len();
+    let size = usize::try_from(len).unwrap_or(usize::MAX);
+    Ok((len > max).then(|| Outcome::untouched(size, start, SkipReason::TooLarge)))
+}
+
+/// Format a single file, writing it back unless running in check or diff mode.
+///
+/// Files that are already formatted are never rewritten, so their modification time is kept.
+pub fn format_file(path: &Utf8Path, options: &Options) -> Result<Outcome> {
+    format_file_to(path, path, options)
+}
+
+/// Format the file `path`, writing the result to `target` unless running in check or diff mode.
+///
+/// The outcome is about `path`: a formatted source is still written to a different `target`,
+/// which is only left alone if it already has the formatted contents.
+pub fn format_file_to(path: &Utf8Path, target: &Utf8Path, options: &Options) -> Result<Outcome> {
+    let start = Instant::now();
+    if let Some(outcome) = too_large(path, options, start)? {
+        return Ok(outcome);
+    }
+    let (original, size, encoding) = read_source(path, options)?;
+    let read = start.elapsed();
+    // Forget about whatever ran on this thread before.
+    take_phases();
+    let formatting = Instant::now();
+    let formatted = match options.timeout_per_file {
+        Some(timeout) => format_with_timeout(path, &original, options, timeout)?,
+        None => Some((format_with_policy(path, &original, options)?, take_phases())),
+    };
+    let ((formatted, dropped_comments, skipped), (parse, print)) = match formatted {
+        Some(formatted) => formatted,
+        None => return Ok(Outcome::untouched(size, start, SkipReason::TimedOut)),
+    };
+    let format = formatting.elapsed();
+    let output = options.output(path, &original, &formatted);
+    let (removed_lines, added_lines) = options.line_counts(&original, &formatted);
+    let transcoded = Some(encoding).filter(|encoding| *encoding != Encoding::Utf8);
+    let to_utf8 = transcoded.is_some() && options.write_utf8 && !skipped;
+    let changed = original != formatted || to_utf8;
+    let contents = if to_utf8 {
+        // UTF-8 files don't need a byte order mark.
+        formatted.trim_start_matches('\u{feff}').as_bytes().to_vec()
+    } else {
+        encoding::encode(&formatted, encoding).map_err(|err| Error::io(path, err))?
+    };
+    let writing = Instant::now();
+    let mut skip_reason = None;
+    if target != path {
+        if options.writes() {
+            write_copy(target, &contents).map_err(|err| Error::io(target, err))?;
+        }
+    } else if changed && options.writes() {
+        let read_only = options.readonly != ReadOnlyPolicy::Force
+            && fs::metadata(path).map_or(false, |metadata| metadata.permissions().readonly());
+        if read_only && options.readonly == ReadOnlyPolicy::Error {
+            return Err(Error::ReadOnly(path.to_path_buf()));
+        }
+        if read_only {
+            skip_reason = Some(SkipReason::ReadOnly);
+        } else if read_source(path, options).map_or(true, |(current, _, _)| current != original) {
+            // Overwriting them would lose the changes saved since the file was read.
+            skip_reason = Some(SkipReason::ChangedMeanwhile);
+        } else {
+            if let Some(suffix) = &options.backup {
+                backup::save(path, suffix)?;
+            }
+            write_atomic(path, &contents).map_err(|err| Error::io(path, err))?;
+        }
+    }
+    Ok(Outcome {
+        original: size,
+        formatted: contents.len(),
+        changed,
+        dropped_comments,
+        comments_allowed: options.comment_policy == CommentPolicy::Allow,
+        skipped: skipped || skip_reason.is_some(),
+        cached: false,
+        elapsed: start.elapsed(),
+        timings: Timings {
+            read,
+            parse,
+            print,
+            format,
+            write: writing.elapsed(),
+        },
+        output,
+        transcoded,
+        removed_lines,
+        added_lines,
+        skip_reason,
+    })
+}
+
+/// How many files given up on with [`Options::timeout_per_file`] can still be formatted in the
+/// background at once, threads that can't be stopped.
+pub const MAX_ABANDONED: usize = 4;
+
+/// The threads formatting files given up on, and the signal that one of them finished.
+static ABANDONED: Mutex<usize> = Mutex::new(0);
+static ABANDONED_FINISHED: Condvar = Condvar::new();
+
+/// [`format_with_policy`] on another thread, giving up on it after `timeout`, with the time spent
+/// parsing and printing, see [`take_phases`]. Formatting goes on in the background, its result
+/// being dropped. When [`MAX_ABANDONED`] threads are still formatting files given up on, it waits
+/// for one of them to finish first.
+#[allow(clippy::type_complexity)]
+fn format_with_timeout(
+    path: &Utf8Path,
+    source: &str,
+    options: &Options,
+    timeout: Duration,
+) -> Result<Option<((String, usize, bool), (Duration, Duration))>> {
+    {
+        let mut abandoned = ABANDONED.lock().expect("poisoned abandoned count");
+        while *abandoned >= MAX_ABANDONED {
+            abandoned = ABANDONED_FINISHED
+                .wait(abandoned)
+                .expect("poisoned abandoned count");
+        }
+    }
+    let (sender, receiver) = mpsc::channel();
+    // Only changed with the count locked, like the result is sent, so that giving up on a thread
+    // that is done already is noticed.
+    let given_up = Arc::new(AtomicBool::new(false));
+    let (path, source, options) = (path.to_path_buf(), source.to_string(), options.clone());
+    let worker = Arc::clone(&given_up);
+    thread::spawn(move || {
+        let result = format_with_policy(&path, &source, &options);
+        let result = result.map(|formatted| (formatted, take_phases()));
+        let mut abandoned = ABANDONED.lock().expect("poisoned abandoned count");
+        if worker.load(Ordering::Relaxed) {
+            *abandoned -= 1;
+            ABANDONED_FINISHED.notify_one();
+        } else {
+            let _ = sender.send(result);
+        }
+    });
+    if let Ok(result) = receiver.recv_timeout(timeout) {
+        return result.map(Some);
+    }
+    let mut abandoned = ABANDONED.lock().expect("poisoned abandoned count");
+    if let Ok(result) = receiver.try_recv() {
+        return result.map(Some);
+    }
+    given_up.store(true, Ordering::Relaxed);
+    *abandoned += 1;
+    Ok(None)
+}
+
+/// Read the source file `path` in the encoding of `options`, returning it with its size in bytes
+/// and the encoding it was in.
+fn read_source(path: &Utf8Path, options: &Options) -> Result<(String, usize, Encoding)> {
+    let bytes = fs::read(path).map_err(|err| Error::io(path, err))?;
+    let size = bytes.len();
+    let (source, encoding) =
+        encoding::decode(bytes, options.encoding).map_err(|err| Error::io(path, err))?;
+    Ok((source, size, encoding))
+}
+
+/// The path that is not valid UTF-8 `err` is about, if it is one of camino's errors.
+fn non_utf8_path(err: &io::Error) -> Option<PathBuf> {
+    let err = err.get_ref()?.downcast_ref::<camino::FromPathBufError>()?;
+    Some(err.as_path().to_path_buf())
+}
+
+/// `path` as named in a patch: relative to the current directory when possible, with forward
+/// slashes and without a leading `./`.
+fn patch_path(path: &Utf8Path) -> String {
+    let cwd = env::current_dir()
+        .ok()
+        .and_then(|cwd| Utf8PathBuf::try_from(cwd).ok());
+    let path = cwd
+        .as_deref()
+        .and_then(|cwd| path.strip_prefix(cwd).ok())
+        .unwrap_or(path);
+    let components = path
+        .components()
+        .filter(|component| *component != Utf8Component::CurDir)
+        .map(|component| component.as_str())
+        .collect::<Vec<_>>();
+    components.join("/")
+}
+
+/// Write `contents` to `path`, creating its parent directories, unless it already has them.
+fn write_copy(path: &Utf8Path, contents: &[u8]) -> io::Result<()> {
+    match fs::read(path) {
+        Ok(existing) if existing == contents => Ok(()),
+        Ok(_) => write_atomic(path, contents),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent().filter(|parent| !parent.as_str().is_empty()) {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Replace the contents of `path` through a temporary file renamed over it, so that a crash
+/// never leaves a half-written source behind, keeping its metadata. Files with several hard
+/// links are overwritten instead.
+pub(crate) fn write_atomic(path: &Utf8Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    // Renaming over a symlink would replace the link itself, write to its target instead.
+    let path = path.canonicalize_utf8()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        // Renaming would split hard links apart, which are written in place instead.
+        if fs::metadata(&path)?.nlink() > 1 {
+            return fs::write(&path, contents);
+        }
+    }
+    let dir = path.parent().unwrap_or_else(|| Utf8Path::new("."));
+    let temp = dir.join(format!(
+        ".{}.prettythanks-{}.tmp",
+        path.file_name().unwrap_or_default(),
+        std::process::id()
+    ));
+    let result = (|| {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(contents.as_ref())?;
+        metadata::copy(&fs::File::open(&path)?, &file)?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Recursively format a file or a directory, honoring the ignore files.
+pub fn format_tree(path: &Utf8Path, options: &Options) -> Report {
+    let thanks = PrettyThanks {
+        paths: vec![path.to_path_buf()],
+        options: options.clone(),
+        reporter: None,
+        cancel: None,
+    };
+    thanks.report()
+}
+
+/// Like [`format_tree`], stopping once `cancel` is set, with a report of the files formatted until
+/// then ending with [`Error::Cancelled`].
+pub fn format_tree_cancellable(
+    path: &Utf8Path,
+    options: &Options,
+    cancel: Arc<AtomicBool>,
+) -> Report {
+    let thanks = PrettyThanks {
+        paths: vec![path.to_path_buf()],
+        options: options.clone(),
+        reporter: None,
+        cancel: Some(cancel),
+    };
+    thanks.report()
+}
+
+/// What tells files apart whatever the path they are reached through: their device and inode.
+#[cfg(unix)]
+fn file_id(path: &Utf8Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// What tells files apart whatever the path they are reached through: their canonical path,
+/// hard links going unnoticed.
+#[cfg(not(unix))]
+fn file_id(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    path.canonicalize_utf8().ok()
+}
+
+/// A unit of work, collected upfront so that files can be formatted in parallel while the
+/// output is still reported in order.
+enum Task {
+    Stdin,
+    /// A file, with the options of the nested configuration file applying to it, if any.
+    File(Utf8PathBuf, Option<Arc<Options>>),
+    Ignored(Utf8PathBuf),
+    Orphan(Utf8PathBuf),
+    Revisited(Utf8PathBuf),
+    Duplicate(Utf8PathBuf),
+    NonUtf8(PathBuf),
+    Error(Error),
+}
+
+impl Task {
+    /// What is reported about a task formatting nothing: the paths left out and the errors hit
+    /// walking the tree.
+    fn into_entry(self) -> Entry {
+        match self {
+            Task::Ignored(path) => Entry::Ignored(path),
+            Task::Orphan(path) => Entry::Orphan(path),
+            Task::Revisited(path) => Entry::Revisited(path),
+            Task::Duplicate(path) => Entry::Duplicate(path),
+            Task::NonUtf8(path) => Entry::NonUtf8(path),
+            Task::Error(err) => Entry::Error(err),
+            Task::Stdin | Task::File(..) => unreachable!("sources are formatted, not skipped"),
+        }
+    }
+}
+
+/// The entries of a run as they are done, see [`PrettyThanks::iter_results`].
+pub struct Results {
+    receiver: mpsc::Receiver<Entry>,
+    stop: Arc<AtomicBool>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl Iterator for Results {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for Results {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Formats a set of paths, printing the outcome like the command line does.
+pub struct PrettyThanks {
+    paths: Vec<Utf8PathBuf>,
+    options: Options,
+    reporter: Option<Arc<dyn reporter::Reporter>>,
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl PrettyThanks {
+    /// Format `paths`, or the current directory if empty, `-` meaning stdin.
+    pub fn new(paths: &[String], options: Options) -> Result<Self> {
+        let paths = if paths.is_empty() {
+            let cwd = env::current_dir().map_err(|err| Error::io(Utf8Path::new("."), err))?;
+            let cwd = cwd
+                .canonicalize()
+                .map_err(|err| Error::io(Utf8Path::new("."), err))?;
+            vec![cwd.try_into()?]
+        } else {
+            paths.iter().map(Utf8PathBuf::from).collect()
+        };
+        let stdin = paths.iter().any(|path| path == "-");
+        if options.out_dir.is_some() && (options.staged || stdin) {
+            return Err(Error::Unsupported(
+                "--out-dir can't be used with stdin or --staged".to_string(),
+            ));
+        }
+        if options.stdin_filename.is_some() && !stdin {
+            return Err(Error::Unsupported(
+                "--stdin-filename only applies to stdin".to_string(),
+            ));
+        }
+        if options.tolerant && !stdin {
+            return Err(Error::Unsupported(
+                "--tolerant only applies to stdin".to_string(),
+            ));
+        }
+        if options.emit == Emit::Patch && stdin {
+            return Err(Error::Unsupported(
+                "--emit patch can't be used with stdin".to_string(),
+            ));
+        }
+        if options.interactive && (options.staged || stdin) {
+            return Err(Error::Unsupported(
+                "--interactive can't be used with stdin or --staged".to_string(),
+            ));
+        }
+        if options.resume && (options.journal.is_none() || !options.writes()) {
+            return Err(Error::Unsupported(
+                "--resume only applies to runs writing files".to_string(),
+            ));
+        }
+        if options.max_unformatted.is_some() && !options.count {
+            return Err(Error::Unsupported(
+                "--max-unformatted only applies to --count".to_string(),
+            ));
+        }
+        let reports =
+            options.diff || options.check || options.list || options.count || options.dry_run;
+        if options.interactive && (reports || options.emit != Emit::Files) {
+            return Err(Error::Unsupported(
+                "--interactive only applies when rewriting files".to_string(),
+            ));
+        }
+        Ok(PrettyThanks {
+            paths,
+            options,
+            reporter: None,
+            cancel: None,
+        })
+    }
+
+    /// Stop the run once `cancel` is set: the files being formatted are finished but no other is
+    /// started, the report of the run ending with [`Error::Cancelled`].
+    #[must_use]
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Whether the run was cancelled, see [`PrettyThanks::with_cancel`].
+    fn cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .map_or(false, |cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Tell `reporter` about the files as they are formatted, see [`reporter`].
+    #[must_use]
+    pub fn with_reporter(mut self, reporter: impl reporter::Reporter + 'static) -> Self {
+        self.reporter = Some(Arc::new(reporter));
+        self
+    }
+
+    /// Where the formatted `path` is written: the same path, or its path relative to the one
+    /// it was found in under [`Options::out_dir`].
+    fn destination(&self, path: &Utf8Path) -> Utf8PathBuf {
+        let out_dir = match &self.options.out_dir {
+            Some(out_dir) => out_dir,
+            None => return path.to_path_buf(),
+        };
+        let relative = self
+            .paths
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .find(|relative| !relative.as_str().is_empty());
+        match relative {
+            Some(relative) => out_dir.join(relative),
+            // Files passed on their own go at the top.
+            None => out_dir.join(path.file_name().unwrap_or(path.as_str())),
+        }
+    }
+
+    /// Format everything and print the outcome, failing if any error was hit.
+    pub fn run(&self) -> Result<()> {
+        let human = self.options.output_format == OutputFormat::Human;
+        // Also held while the changes are confirmed and written with `--interactive`.
+        let (report, _lock) = self.report_with(self.shows_progress());
+        if self.options.count {
+            return self.print_count(report);
+        }
+        match self.options.output_format {
+            OutputFormat::Human => {}
+            OutputFormat::Json => println!("{}", report.to_json()),
+            OutputFormat::Checkstyle => print!("{}", report.to_checkstyle()),
+            OutputFormat::Github => print!("{}", report.to_github()),
+            OutputFormat::Sarif => println!("{}", report.to_sarif()),
+        }
+        let summary = format!(
+            "formatting completed, original size: {} bytes, formatted size: {} bytes, time: {} ms",
+            report.original_size(),
+            report.formatted_size(),
+            report.elapsed.as_millis()
+        );
+        let table = (self.options.dry_run && human).then(|| {
+            let changed = report
+                .changed()
+                .map(|file| format!("would format {}\n", file.path));
+            changed.collect::<String>() + &report.summary_table()
+        });
+        let stdin = self.paths.iter().any(|path| path == "-");
+        let mut stats = String::new();
+        if human && !stdin {
+            if self.options.stats {
+                stats += &report.stats_table();
+            }
+            if self.options.profile {
+                stats += &report.profile_table();
+            }
+        }
+        let mut errors = Vec::new();
+        if let Some(path) = &self.options.report_junit {
+            if let Err(err) = fs::write(path, report.to_junit()) {
+                errors.push(Error::io(path, err));
+            }
+        }
+        // Set once every other file is to be written, or none.
+        let mut answered = None;
+        for entry in report.entries {
+            match entry {
+                Entry::File(file) => {
+                    let confirm = match &file.result {
+                        Ok(outcome) => self.options.interactive && outcome.changed,
+                        Err(_) => false,
+                    };
+                    let path = file.path.clone();
+                    let mut result = if human {
+                        self.print_file(file)
+                    } else {
+                        self.check_file(file)
+                    };
+                    if confirm && result.is_ok() {
+                        result = self.confirm(&path, &mut answered);
+                    }
+                    if let Err(err) = result {
+                        errors.push(err);
+                    }
+                }
+                Entry::Ignored(path) if human => info!("skipping ignored path {}", path),
+                Entry::Orphan(path) if human => {
+                    warn!("{} is not reachable from any crate root", path);
+                }
+                Entry::Duplicate(path) if human => {
+                    info!("skipping {}, already reached through another path", path);
+                }
+                Entry::Revisited(path) if human => {
+                    warn!("skipping {}, a symlink to a directory already walked", path);
+                }
+                Entry::NonUtf8(path) if human => {
+                    warn!("skipping {}, its name is not valid UTF-8", path.display());
+                }
+                Entry::Ignored(_)
+                | Entry::Orphan(_)
+                | Entry::Revisited(_)
+                | Entry::Duplicate(_)
+                | Entry::NonUtf8(_) => {}
+                Entry::Error(err) => errors.push(err),
+            }
+        }
+        if self.options.dry_run {
+            let (interrupted, errors) = errors.into_iter().partition::<Vec<_>, _>(|err| {
+                matches!(err, Error::Interrupted { .. } | Error::Cancelled { .. })
+            });
+            for err in errors {
+                warn!("{}", err);
+            }
+            if let Some(table) = table {
+                print!("{}", table);
+            }
+            print!("{}", stats);
+            return Error::from_errors(interrupted).map_or(Ok(()), Err);
+        }
+        print!("{}", stats);
+        // Keep stdout clean when it carries the formatted source.
+        if human && !stdin {
+            info!("{}", summary);
+        }
+        Error::from_errors(errors).map_or(Ok(()), Err)
+    }
+
+    /// Whether a progress bar is shown while formatting, only on terminals and when nothing
+    /// else is printed meanwhile.
+    fn shows_progress(&self) -> bool {
+        self.options.progress
+            && self.options.output_format == OutputFormat::Human
+            && !self.paths.iter().any(|path| path == "-")
+            && term::is_terminal(term::Stream::Stdout)
+            && term::is_terminal(term::Stream::Stderr)
+    }
+
+    /// Print the number of files of `report` that are not formatted, failing when there are more
+    /// than [`Options::max_unformatted`].
+    fn print_count(&self, report: Report) -> Result<()> {
+        let count = report.changed().count();
+        println!("{}", count);
+        let mut errors = report
+            .entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::File(FileReport {
+                    result: Err(err), ..
+                })
+                | Entry::Error(err) => Some(err),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match self.options.max_unformatted {
+            Some(max) if count > max => errors.push(Error::TooManyUnformatted { count, max }),
+            _ => {}
+        }
+        Error::from_errors(errors).map_or(Ok(()), Err)
+    }
+
+    /// Ask whether to write the changes to `path`, writing them if so, `answered` being the
+    /// answer for every file once it is [`interactive::Answer::All`] or
+    /// [`interactive::Answer::Quit`].
+    fn confirm(&self, path: &Utf8Path, answered: &mut Option<interactive::Answer>) -> Result<()> {
+        use interactive::Answer;
+
+        let answer = match *answered {
+            Some(answer) => answer,
+            None => interactive::ask(path.as_str(), &mut io::stdin().lock(), &mut io::stderr())
+                .map_err(|err| Error::io(Utf8Path::new("-"), err))?,
+        };
+        if matches!(answer, Answer::All | Answer::Quit) {
+            *answered = Some(answer);
+        }
+        if matches!(answer, Answer::Yes | Answer::All) {
+            let options = Options {
+                interactive: false,
+                ..self.options.clone()
+            };
+            format_file_to(path, &self.destination(path), &options)?;
+        }
+        Ok(())
+    }
+
+    /// Format everything without printing anything.
+    pub fn report(&self) -> Report {
+        self.report_with(false).0
+    }
+
+    /// Format everything, with the lock taken to write the files, if any.
+    fn report_with(&self, progress: bool) -> (Report, Option<lock::Lock>) {
+        let start = Instant::now();
+        let tasks = self.collect();
+        let (tasks, lock) = match self.tasks_lock(&tasks) {
+            Ok(lock) => (tasks, lock),
+            Err(err) => (vec![Task::Error(err)], None),
+        };
+        let tasks = self.resumed(tasks);
+        let files = tasks
+            .iter()
+            .filter_map(|task| match task {
+                Task::File(path, options) => {
+                    Some((path.as_path(), options.as_deref().unwrap_or(&self.options)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let progress = progress.then(|| term::Progress::new(files.len()));
+        let total = files.len();
+        let mut results = self.format_files(&files, progress.as_ref()).into_iter();
+        let mut entries = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            let entry = match task {
+                Task::Stdin => self.stdin_entry(),
+                Task::File(path, _) => match results.next() {
+                    Some(result) => Entry::File(FileReport { path, result }),
+                    // Formatting stopped at the first error, or was interrupted.
+                    None => break,
+                },
+                task => task.into_entry(),
+            };
+            let failed = matches!(
+                &entry,
+                Entry::Error(_) | Entry::File(FileReport { result: Err(_), .. })
+            );
+            entries.push(entry);
+            if failed && self.options.fail_fast {
+                break;
+            }
+        }
+        let formatted = entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::File(_)))
+            .count();
+        if let Some(signal) = signal::received() {
+            entries.push(Entry::Error(Error::Interrupted {
+                signal,
+                formatted,
+                total,
+            }));
+        } else if self.cancelled() {
+            entries.push(Entry::Error(Error::Cancelled { formatted, total }));
+        }
+        let report = Report {
+            entries,
+            elapsed: start.elapsed(),
+        };
+        if let Some(reporter) = &self.reporter {
+            reporter.run_finished(&report);
+        }
+        (report, lock)
+    }
+
+    /// Format stdin, telling the reporter about it.
+    fn stdin_entry(&self) -> Entry {
+        let path = self.stdin_path().to_path_buf();
+        if let Some(reporter) = &self.reporter {
+            reporter.file_started(&path);
+        }
+        let result = self.format_stdin();
+        if let Some(reporter) = &self.reporter {
+            reporter::finished(reporter.as_ref(), &path, &result);
+        }
+        Entry::File(FileReport { path, result })
+    }
+
+    /// Format everything on another thread, returning the entries as they are done, the files in
+    /// the order they are finished in rather than in name order.
+    ///
+    /// Dropping the iterator before it is over cancels the run: no other file is started, and
+    /// the ones being formatted are finished before the drop returns.
+    pub fn iter_results(&self) -> Results {
+        let thanks = PrettyThanks {
+            paths: self.paths.clone(),
+            options: self.options.clone(),
+            reporter: self.reporter.clone(),
+            cancel: self.cancel.clone(),
+        };
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let worker = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || thanks.stream(&sender, &stop))
+        };
+        Results {
+            receiver,
+            stop,
+            worker: Some(worker),
+        }
+    }
+
+    /// Send the entries of the run to `sender` as they are done, until `stop` is set.
+    fn stream(&self, sender: &mpsc::Sender<Entry>, stop: &AtomicBool) {
+        let tasks = self.collect();
+        let _lock = match self.tasks_lock(&tasks) {
+            Ok(lock) => lock,
+            Err(err) => {
+                let _ = sender.send(Entry::Error(err));
+                return;
+            }
+        };
+        let mut files = Vec::new();
+        for task in self.resumed(tasks) {
+            let entry = match task {
+                Task::File(path, options) => {
+                    files.push((path, options));
+                    continue;
+                }
+                Task::Stdin => self.stdin_entry(),
+                task => task.into_entry(),
+            };
+            if sender.send(entry).is_err() {
+                return;
+            }
+        }
+        let borrowed = files
+            .iter()
+            .map(|(path, options)| (path.as_path(), options.as_deref().unwrap_or(&self.options)))
+            .collect::<Vec<_>>();
+        // Senders can only be shared between threads through a lock before Rust 1.72.
+        let sender = Mutex::new(sender.clone());
+        self.format_files_with(&borrowed, None, stop, |idx, result| {
+            let path = files[idx].0.clone();
+            let sent = sender
+                .lock()
+                .expect("poisoned sender")
+                .send(Entry::File(FileReport { path, result }));
+            // Nobody wants the other results anymore.
+            if sent.is_err() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+
+    fn collect(&self) -> Vec<Task> {
+        let mut tasks = Vec::new();
+        for path in &self.paths {
+            self.collect_path(path, &mut tasks);
+            if self.stopped(&tasks) {
+                break;
+            }
+        }
+        // Formatting a file twice would count it twice, and race with itself on several threads.
+        let mut seen = BTreeSet::new();
+        for task in &mut tasks {
+            if let Task::File(path, _) = task {
+                if file_id(path).map_or(false, |id| !seen.insert(id)) {
+                    *task = Task::Duplicate(std::mem::take(path));
+                }
+            }
+        }
+        if self.options.out_dir.is_some() {
+            // Same-named files passed on their own, or found under several paths, would be
+            // written on top of each other.
+            let mut destinations = BTreeMap::new();
+            for task in &mut tasks {
+                if let Task::File(path, _) = task {
+                    let destination = self.destination(path);
+                    if let Some(other) = destinations.get(&destination) {
+                        *task = Task::Error(Error::SameDestination {
+                            path: std::mem::take(path),
+                            other: Utf8PathBuf::clone(other),
+                            destination,
+                        });
+                    } else {
+                        destinations.insert(destination, path.clone());
+                    }
+                }
+            }
+        }
+        tasks
+    }
+
+    /// Whether collecting must stop, having hit an error with [`Options::fail_fast`] or been
+    /// cancelled.
+    fn stopped(&self, tasks: &[Task]) -> bool {
+        self.cancelled() || self.options.fail_fast && matches!(tasks.last(), Some(Task::Error(_)))
+    }
+
+    fn collect_path(&self, path: &Utf8Path, tasks: &mut Vec<Task>) {
+        if path == "-" {
+            tasks.push(Task::Stdin);
+        } else if (self.options.is_rust_file(path) || markdown::is_markdown(path))
+            && (path.is_file() || path.is_symlink())
+        {
+            let selected = path
+                .canonicalize_utf8()
+                .map_or(true, |absolute| self.is_selected(&absolute, false));
+            if selected {
+                tasks.push(Task::File(path.to_path_buf(), None));
+            }
+        } else if path.is_dir() {
+            let root = match path.canonicalize_utf8() {
+                Ok(root) => root,
+                Err(err) => {
+                    tasks.push(Task::Error(Error::io(path, err)));
+                    return;
+                }
+            };
+            let ignores = Arc::new(Ignores::for_root(&root, !self.options.no_ignore));
+            let roots = if self.options.follow_mods {
+                modules::crate_roots(path)
+            } else {
+                Vec::new()
+            };
+            // Without crate roots in there, there's no module graph to follow.
+            let reachable = (!roots.is_empty()).then(|| modules::reachable(&roots));
+            let before = tasks.len();
+            self.collect_tree(path, ignores, reachable.as_ref(), tasks);
+            if tasks.len() == before && !self.filters() && !self.contains_rust_files(path) {
+                tasks.push(Task::Error(Error::NoRustFiles(path.to_path_buf())));
+            }
+        } else if path.symlink_metadata().is_err() {
+            tasks.push(Task::Error(Error::NotFound {
+                path: path.to_path_buf(),
+                suggestions: suggest::near_misses(path),
+            }));
+        } else {
+            tasks.push(Task::Error(Error::NotRustPath(path.to_path_buf())));
+        }
+    }
+
+    /// Whether files are left out while walking for something else than being ignored, like not
+    /// being staged or not matching `--include`, which may well leave nothing to format.
+    fn filters(&self) -> bool {
+        self.options.only.is_some()
+            || self.options.include.is_some()
+            || !self.options.exclude.is_empty()
+    }
+
+    /// Whether there is any Rust file under the directory `root`, ignored ones included, symlinks
+    /// not being followed.
+    fn contains_rust_files(&self, root: &Utf8Path) -> bool {
+        let mut stack = vec![root.as_std_path().to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                match entry.file_type() {
+                    Ok(kind) if kind.is_dir() => stack.push(path),
+                    Ok(kind) if kind.is_file() => {
+                        let rust = Utf8Path::from_path(&path)
+                            .map_or(false, |path| self.options.is_rust_file(path));
+                        if rust {
+                            return true;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    /// Collect the tree under the directory `root`, depth first and in file name order.
+    ///
+    /// Directories are walked off an explicit stack rather than recursively, so that deep trees
+    /// can't overflow the stack, and no deeper than [`Options::max_depth`]. The configuration
+    /// files of the directories below `root` apply to the files under them, over the options of
+    /// their parents, see [`Options::with_config`].
+    fn collect_tree(
+        &self,
+        root: &Utf8Path,
+        ignores: Arc<Ignores>,
+        reachable: Option<&BTreeSet<Utf8PathBuf>>,
+        tasks: &mut Vec<Task>,
+    ) {
+        // The directories walked so far, only reached again through symlinks.
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![(root.to_path_buf(), ignores, None, 0)];
+        while let Some((path, parent, options, depth)) = stack.pop() {
+            if self.stopped(tasks) {
+                break;
+            }
+            // The configuration of `root` itself is the one the run started with, if any.
+            let options = if depth > 0 {
+                self.nested_options(&path, options, tasks)
+            } else {
+                options
+            };
+            let result = self.collect_directory(
+                &path,
+                &parent,
+                options.as_ref(),
+                reachable,
+                &mut visited,
+                tasks,
+            );
+            match result {
+                Ok((subdirs, ignores)) => {
+                    if self.options.max_depth.map_or(true, |max| depth < max) {
+                        // Reversed, so that the first subdirectory is the first one walked.
+                        let queued = subdirs
+                            .into_iter()
+                            .rev()
+                            .map(|dir| (dir, Arc::clone(&ignores), options.clone(), depth + 1));
+                        stack.extend(queued);
+                    }
+                }
+                Err(err) => tasks.push(Task::Error(err)),
+            }
+        }
+    }
+
+    /// The options of the files in the directory `path`: `parent`, the ones of its parent
+    /// directory, with the configuration file of `path` applying over them if it has one. `None`
+    /// stands for [`PrettyThanks`] options.
+    fn nested_options(
+        &self,
+        path: &Utf8Path,
+        parent: Option<Arc<Options>>,
+        tasks: &mut Vec<Task>,
+    ) -> Option<Arc<Options>> {
+        match Config::find(path) {
+            Ok(Some(config)) => {
+                if let Some(path) = &config.path {
+                    debug!("applying {}", error::describe(path));
+                }
+                let options = parent.as_deref().unwrap_or(&self.options);
+                Some(Arc::new(options.with_config(&config)))
+            }
+            Ok(None) => parent,
+            Err(err) => {
+                tasks.push(Task::Error(err));
+                parent
+            }
+        }
+    }
+
+    /// Collect the files in the directory `path`, formatted with `options`, and return its
+    /// subdirectories to walk, with the ignore chain applying to them. Only failing to read the
+    /// directory at all is an error: errors about its entries are collected as tasks, so that the
+    /// rest can still be formatted.
+    fn collect_directory(
+        &self,
+        path: &Utf8Path,
+        parent: &Arc<Ignores>,
+        options: Option<&Arc<Options>>,
+        reachable: Option<&BTreeSet<Utf8PathBuf>>,
+        visited: &mut BTreeSet<Utf8PathBuf>,
+        tasks: &mut Vec<Task>,
+    ) -> Result<(Vec<Utf8PathBuf>, Arc<Ignores>)> {
+        // Ignore files are matched against absolute paths, whatever the user passed in.
+        let io_error = |err| Error::io(path, err);
+        let absolute = path.canonicalize_utf8().map_err(io_error)?;
+        if !visited.insert(absolute.clone()) {
+            tasks.push(Task::Revisited(path.to_path_buf()));
+            return Ok((Vec::new(), Arc::clone(parent)));
+        }
+        let ignores = parent.child(&absolute);
+        let (mut entries, mut non_utf8) = (Vec::new(), Vec::new());
+        for entry in path.read_dir_utf8().map_err(io_error)? {
+            match entry {
+                Ok(entry) => entries.push(entry),
+                Err(err) => match non_utf8_path(&err) {
+                    // Only worth a warning if it would have been formatted.
+                    Some(path) => {
+                        let lossy = Utf8PathBuf::from(path.to_string_lossy().into_owned());
+                        if path.is_dir() || self.options.is_walked_file(&lossy) {
+                            non_utf8.push(path);
+                        }
+                    }
+                    None => tasks.push(Task::Error(io_error(err))),
+                },
+            }
+        }
+        // The order of the file system differs between machines, runs must not.
+        non_utf8.sort();
+        tasks.extend(non_utf8.into_iter().map(Task::NonUtf8));
+        entries.sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            if self.stopped(tasks) {
+                break;
+            }
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(err) => {
+                    tasks.push(Task::Error(Error::io(entry.path(), err)));
+                    continue;
+                }
+            };
+            let is_dir = entry.path().is_dir();
+            let is_rust_file = self.options.is_walked_file(entry.path());
+            let followed = match self.options.follow_symlinks {
+                SymlinkPolicy::Never => false,
+                SymlinkPolicy::Files => !is_dir,
+                SymlinkPolicy::All => true,
+            };
+            if file_type.is_symlink() && !followed {
+                if is_dir || is_rust_file {
+                    tasks.push(Task::Ignored(entry.path().to_path_buf()));
+                }
+                continue;
+            }
+            if !self.is_selected(&absolute.join(entry.file_name()), is_dir) {
+                continue;
+            }
+            if is_rust_file && !is_dir && !self.is_included(&absolute.join(entry.file_name())) {
+                continue;
+            }
+            if (is_dir || is_rust_file) && self.is_ignored(&ignores, &absolute, &entry, is_dir) {
+                tasks.push(Task::Ignored(entry.path().to_path_buf()));
+                continue;
+            }
+            if is_rust_file && (file_type.is_file() || file_type.is_symlink()) {
+                // Markdown files are never reachable through `mod` declarations.
+                let orphan = !markdown::is_markdown(entry.path())
+                    && reachable.map_or(false, |reachable| {
+                        entry
+                            .path()
+                            .canonicalize_utf8()
+                            .map_or(true, |path| !reachable.contains(&path))
+                    });
+                if orphan {
+                    tasks.push(Task::Orphan(entry.path().to_path_buf()));
+                } else {
+                    tasks.push(Task::File(entry.path().to_path_buf(), options.cloned()));
+                }
+            } else if is_dir {
+                subdirs.push(entry.path().to_path_buf());
+            }
+        }
+        Ok((subdirs, Arc::new(ignores)))
+    }
+
+    /// Whether `absolute` is one of the selected files, or a directory containing some.
+    fn is_selected(&self, absolute: &Utf8Path, is_dir: bool) -> bool {
+        self.options.only.as_ref().map_or(true, |only| {
+            if is_dir {
+                only.range::<Utf8Path, _>((Bound::Included(absolute), Bound::Unbounded))
+                    .next()
+                    .map_or(false, |path| path.starts_with(absolute))
+            } else {
+                only.contains(absolute)
+            }
+        })
+    }
+
+    /// Whether the file `absolute` matches [`Options::include`].
+    fn is_included(&self, absolute: &Utf8Path) -> bool {
+        // Matching patterns are the "ignored" ones, whatever the list is used for.
+        self.options.include.as_ref().map_or(true, |include| {
+            include.matched(absolute, false) == ignore::Match::Ignore
+        })
+    }
+
+    fn is_ignored(
+        &self,
+        ignores: &Ignores,
+        dir: &Utf8Path,
+        entry: &camino::Utf8DirEntry,
+        is_dir: bool,
+    ) -> bool {
+        let absolute = dir.join(entry.file_name());
+        let excluded = self
+            .options
+            .exclude
+            .iter()
+            .any(|exclude| exclude.matched(&absolute, is_dir) == ignore::Match::Ignore);
+        excluded
+            || (is_dir && !self.options.no_ignore && ignore::is_builtin_ignored(entry.path()))
+            || ignores.is_ignored(&absolute, is_dir)
+    }
+
+    /// Format `files`, each with its options, on a pool of `jobs` threads, returning the results
+    /// in the same order. With [`Options::fail_fast`], they stop at the first error.
+    fn format_files(
+        &self,
+        files: &[(&Utf8Path, &Options)],
+        progress: Option<&term::Progress>,
+    ) -> Vec<Result<Outcome>> {
+        let results = Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+        self.format_files_with(files, progress, &AtomicBool::new(false), |idx, result| {
+            results.lock().expect("poisoned results")[idx] = Some(result);
+        });
+        // Files after the first error may have been formatted by other threads, or not at all.
+        let mut ordered = Vec::with_capacity(files.len());
+        for result in results.into_inner().expect("poisoned results") {
+            let stop = match &result {
+                Some(result) => result.is_err() && self.options.fail_fast,
+                None => true,
+            };
+            ordered.extend(result);
+            if stop {
+                break;
+            }
+        }
+        ordered
+    }
+
+    /// Format `files` like [`PrettyThanks::format_files`], handing every result to `done` with
+    /// the index of its file as soon as it is there. No other file is started once `stop` is set,
+    /// which the first error does with [`Options::fail_fast`].
+    fn format_files_with(
+        &self,
+        files: &[(&Utf8Path, &Options)],
+        progress: Option<&term::Progress>,
+        stop: &AtomicBool,
+        done: impl Fn(usize, Result<Outcome>) + Sync,
+    ) {
+        let cache = self.cache(files.len());
+        let journal = self.journal(files.len());
+        let format = |path: &Utf8Path, options: &Options| {
+            if let Some(progress) = progress {
+                progress.start(path);
+            }
+            let result = self.format_one(path, options, cache.as_ref());
+            if let Some(progress) = progress {
+                progress.finish();
+            }
+            if let (Some(journal), Ok(_)) = (&journal, &result) {
+                if let Err(err) = journal.record(path) {
+                    warn!("{}", err);
+                }
+            }
+            result
+        };
+        // Concurrent updates of the git index would fight over its lock.
+        let jobs = if self.options.staged {
+            1
+        } else {
+            self.options.jobs.clamp(1, files.len().max(1))
+        };
+        debug!("formatting {} files on {} threads", files.len(), jobs);
+        let next = AtomicUsize::new(0);
+        let work = || loop {
+            // Files being formatted are finished, but no other is started.
+            if stop.load(Ordering::Relaxed) || signal::received().is_some() || self.cancelled() {
+                break;
+            }
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            let (path, options) = match files.get(idx) {
+                Some(file) => *file,
+                None => break,
+            };
+            let result = format(path, options);
+            if result.is_err() && self.options.fail_fast {
+                stop.store(true, Ordering::Relaxed);
+            }
+            done(idx, result);
+        };
+        if jobs == 1 {
+            work();
+        } else {
+            thread::scope(|scope| {
+                for _ in 0..jobs {
+                    scope.spawn(work);
+                }
+            });
+        }
+        if let Some(Err(err)) = cache.map(|cache| cache.save()) {
+            warn!("{}", err);
+        }
+        // Kept for `--resume` unless every file was formatted.
+        let finished = !stop.load(Ordering::Relaxed) && signal::received().is_none();
+        if let Some(journal) = journal.filter(|_| finished && !self.cancelled()) {
+            if let Err(err) = journal.finish() {
+                warn!("{}", err);
+            }
+        }
+    }
+
+    /// `tasks` without the files formatted by the run resumed with [`Options::resume`].
+    fn resumed(&self, tasks: Vec<Task>) -> Vec<Task> {
+        let path = match &self.options.journal {
+            Some(path) if self.options.resume => self.at_root(path),
+            _ => return tasks,
+        };
+        let completed = resume::completed(&path, &resume::key(&self.paths, &self.options));
+        if completed.is_none() {
+            warn!(
+                "nothing to resume, {} is missing or was written by another run",
+                path
+            );
+        }
+        let completed = match completed {
+            Some(completed) => completed,
+            None => return tasks,
+        };
+        let total = tasks.len();
+        let tasks = tasks
+            .into_iter()
+            .filter(|task| !matches!(task, Task::File(path, _) if completed.contains(path)))
+            .collect::<Vec<_>>();
+        info!(
+            "resuming, skipping the {} files formatted by the interrupted run",
+            total - tasks.len()
+        );
+        tasks
+    }
+
+    /// The journal of a run formatting `files` files, `None` unless it writes them and has one,
+    /// and they are many enough or it resumes another run.
+    fn journal(&self, files: usize) -> Option<resume::Journal> {
+        // Resumed runs left with nothing to format remove the journal.
+        let usable = (files >= resume::MIN_FILES || self.options.resume) && self.options.writes();
+        let path = self.options.journal.as_ref().filter(|_| usable)?;
+        let key = resume::key(&self.paths, &self.options);
+        match resume::Journal::create(&self.at_root(path), &key, self.options.resume) {
+            Ok(journal) => Some(journal),
+            Err(err) => {
+                warn!("{}", err);
+                None
+            }
+        }
+    }
+
+    /// The lock of a run formatting `tasks`, `None` unless it writes files, right away or once
+    /// confirmed with [`Options::interactive`].
+    fn tasks_lock(&self, tasks: &[Task]) -> Result<Option<lock::Lock>> {
+        let writes = self.options.writes() || self.options.interactive;
+        self.lock(writes && tasks.iter().any(|task| matches!(task, Task::File(..))))
+    }
+
+    /// The lock to take before writing files if `writes` is set, `None` unless there's a lock
+    /// file, which is at the root of the tree when relative, see [`lock::root`].
+    fn lock(&self, writes: bool) -> Result<Option<lock::Lock>> {
+        let path = match &self.options.lock {
+            Some(path) if writes => path,
+            _ => return Ok(None),
+        };
+        lock::Lock::acquire(&self.at_root(path), self.options.wait_lock).map(Some)
+    }
+
+    /// Where the file of the run `path`, like its lock, cache or journal, is: at the root of the
+    /// tree it formats, see [`lock::root`], unless `path` is absolute.
+    fn at_root(&self, path: &Utf8Path) -> Utf8PathBuf {
+        lock::root(&self.paths).map_or_else(|| path.to_path_buf(), |root| root.join(path))
+    }
+
+    /// The cache of a run formatting `files` files, `None` unless there is one it can use.
+    fn cache(&self, files: usize) -> Option<cache::Cache> {
+        // Files printed with `--emit stdout` are read whether they are formatted already or not.
+        let usable = files > 0
+            && !self.options.staged
+            && self.options.out_dir.is_none()
+            && self.options.emit != Emit::Stdout;
+        let path = self.options.cache.as_ref().filter(|_| usable)?;
+        Some(cache::Cache::load(&self.at_root(path), &self.options))
+    }
+
+    /// Format the file `path` with `options`, the ones of the run or of its nested configuration,
+    /// telling the reporter about it.
+    fn format_one(
+        &self,
+        path: &Utf8Path,
+        options: &Options,
+        cache: Option<&cache::Cache>,
+    ) -> Result<Outcome> {
+        if let Some(reporter) = &self.reporter {
+            reporter.file_started(path);
+        }
+        // The cache only knows about files formatted with the options of the whole run.
+        let cached = cache.filter(|_| std::ptr::eq(options, &self.options));
+        let result = if let Some(cache) = cached {
+            self.format_cached(path, cache)
+        } else if self.options.staged {
+            git::format_staged(path, options)
+        } else {
+            format_file_to(path, &self.destination(path), options)
+        };
+        if let Some(reporter) = &self.reporter {
+            reporter::finished(reporter.as_ref(), path, &result);
+        }
+        result
+    }
+
+    /// Format `path` in place, unless `cache` knows its contents are formatted already.
+    fn format_cached(&self, path: &Utf8Path, cache: &cache::Cache) -> Result<Outcome> {
+        let start = Instant::now();
+        if let Some(outcome) = too_large(path, &self.options, start)? {
+            return Ok(outcome);
+        }
+        let (original, size, encoding) = read_source(path, &self.options)?;
+        let edition = self.options.edition_for(path);
+        if cache.contains(&original, edition)
+            && (encoding == Encoding::Utf8 || !self.options.write_utf8)
+        {
+            debug!("{} is cached as formatted", path);
+            return Ok(Outcome {
+                original: size,
+                formatted: size,
+                changed: false,
+                dropped_comments: 0,
+                comments_allowed: false,
+                skipped: false,
+                cached: true,
+                elapsed: start.elapsed(),
+                timings: Timings::default(),
+                output: String::new(),
+                transcoded: Some(encoding).filter(|encoding| *encoding != Encoding::Utf8),
+                removed_lines: 0,
+                added_lines: 0,
+                skip_reason: None,
+            });
+        }
+        let outcome = format_file_to(path, path, &self.options)?;
+        if !outcome.skipped {
+            if !outcome.changed {
+                cache.insert(&original, edition);
+            } else if self.options.writes() {
+                // What was written, rather than formatting it again.
+                if let Ok((formatted, _, _)) = read_source(path, &self.options) {
+                    cache.insert(&formatted, edition);
+                }
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// What stdin is reported as, `-` unless [`Options::stdin_filename`] names it.
+    fn stdin_path(&self) -> &Utf8Path {
+        self.options
+            .stdin_filename
+            .as_deref()
+            .unwrap_or_else(|| Utf8Path::new("-"))
+    }
+
+    fn format_stdin(&self) -> Result<Outcome> {
+        let start = Instant::now();
+        let mut original = String::new();
+        io::stdin()
+            .read_to_string(&mut original)
+            .map_err(|err| Error::io(Utf8Path::new("-"), err))?;
+        self.format_buffer(original, start)
+    }
+
+    /// Format `original`, read from stdin since `start`.
+    fn format_buffer(&self, original: String, start: Instant) -> Result<Outcome> {
+        let read = start.elapsed();
+        // Forget about whatever ran on this thread before.
+        take_phases();
+        let formatting = Instant::now();
+        let prints = !(self.options.check
+            || self.options.list
+            || self.options.count
+            || self.options.dry_run
+            || self.options.emit == Emit::None);
+        let (formatted, dropped_comments, skipped) =
+            match format_with_policy(self.stdin_path(), &original, &self.options) {
+                Err(Error::Parse { .. }) if self.options.tolerant => {
+                    let mut outcome =
+                        Outcome::untouched(original.len(), start, SkipReason::Unparsable);
+                    if prints && !self.options.shows_diff() {
+                        outcome.output = original;
+                    }
+                    return Ok(outcome);
+                }
+                result => result?,
+            };
+        let format = formatting.elapsed();
+        let (parse, print) = take_phases();
+        let output = if self.options.shows_diff() {
+            let name = self.options.stdin_filename.as_deref().map(Utf8Path::as_str);
+            diff::unified(
+                &original,
+                &formatted,
+                name.unwrap_or("<stdin>"),
+                name.unwrap_or("<stdout>"),
+                diff::CONTEXT_LINES,
+            )
+        } else if prints {
+            formatted.clone()
+        } else {
+            String::new()
+        };
+        let (removed_lines, added_lines) = self.options.line_counts(&original, &formatted);
+        Ok(Outcome {
+            original: original.len(),
+            formatted: formatted.len(),
+            changed: original != formatted,
+            dropped_comments,
+            comments_allowed: self.options.comment_policy == CommentPolicy::Allow,
+            skipped,
+            cached: false,
+            elapsed: start.elapsed(),
+            // Stdout is written by the caller.
+            timings: Timings {
+                read,
+                parse,
+                print,
+                format,
+                write: Duration::ZERO,
+            },
+            output,
+            transcoded: None,
+            removed_lines,
+            added_lines,
+            skip_reason: None,
+        })
+    }
+
+    /// Print the outcome of formatting a file.
+    pub(crate) fn print_file(&self, file: FileReport) -> Result<()> {
+        if let Ok(outcome) = &file.result {
+            if let Some(reason) = outcome.skip_reason {
+                warn!("skipping {}, {}", error::describe(&file.path), reason);
+            } else if outcome.skipped && outcome.dropped_comments == 0 {
+                info!(
+                    "skipping {}, marked as generated or skipped",
+                    error::describe(&file.path)
+                );
+            } else if outcome.skipped {
+                warn!(
+                    "skipping {}, formatting would drop {}",
+                    error::describe(&file.path),
+                    comments::count(outcome.dropped_comments)
+                );
+            } else if outcome.dropped_comments > 0 && outcome.comments_allowed {
+                info!(
+                    "formatting {} drops {}",
+                    error::describe(&file.path),
+                    comments::count(outcome.dropped_comments)
+                );
+            } else if outcome.dropped_comments > 0 {
+                warn!(
+                    "formatting {} drops {}",
+                    error::describe(&file.path),
+                    comments::count(outcome.dropped_comments)
+                );
+            }
+            if let Some(encoding) = outcome.transcoded {
+                if self.options.write_utf8 && self.options.writes() && !outcome.skipped {
+                    info!("converting file {} from {} to UTF-8", file.path, encoding);
+                } else {
+                    debug!("decoding file {} as {}", file.path, encoding);
+                }
+            }
+            let stdin = file.path == self.stdin_path();
+            if stdin || outcome.skip_reason.is_some() {
+                // Stdin is reported through its output only, the files left untouched above.
+            } else if outcome.changed {
+                info!(
+                    "{} file {}, original size {} bytes, formatted size {} bytes, time: {} ms",
+                    term::paint(term::Stream::Stdout, term::Color::Green, "formatting"),
+                    file.path,
+                    outcome.original,
+                    outcome.formatted,
+                    outcome.elapsed.as_millis()
+                );
+            } else {
+                info!(
+                    "formatting file {}, {}, time: {} ms",
+                    file.path,
+                    term::paint(term::Stream::Stdout, term::Color::Yellow, "unchanged"),
+                    outcome.elapsed.as_millis()
+                );
+            }
+            if !stdin {
+                let timings = outcome.timings;
+                let mut fields = vec![("path", file.path.as_str().into())];
+                fields.extend(timings.fields());
+                log::log_record(&log::Record {
+                    level: log::Level::Debug,
+                    message: format_args!(
+                        "  read: {:?}, parse: {:?}, print: {:?}, format: {:?}, write: {:?}",
+                        timings.read, timings.parse, timings.print, timings.format, timings.write
+                    ),
+                    fields: &fields,
+                });
+            }
+            if self.options.list && outcome.changed {
+                let path = if file.path == "-" {
+                    "<stdin>"
+                } else {
+                    file.path.as_str()
+                };
+                println!("{}", path);
+            }
+            let output = if self.options.shows_diff() || self.options.interactive {
+                term::paint_diff(term::Stream::Stdout, &outcome.output)
+            } else {
+                outcome.output.clone()
+            };
+            io::stdout()
+                .write_all(output.as_bytes())
+                .map_err(|err| Error::io(Utf8Path::new(error::STDOUT), err))?;
+        }
+        self.check_file(file)
+    }
+
+    /// Fail if formatting `file` failed, or if it is not formatted in check mode, or is the
+    /// buffer of an editor, named with [`Options::stdin_filename`], for it to tell it changed.
+    fn check_file(&self, file: FileReport) -> Result<()> {
+        let outcome = file.result?;
+        let buffer = self.options.stdin_filename.as_ref() == Some(&file.path);
+        if (self.options.check || self.options.error_on_unformatted || buffer) && outcome.changed {
+            return Err(Error::Unformatted(file.path));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn can_format() {
+        let temp = TempDir::new("format");
+        let temp_file = temp.join("prettythanks.rs");
+        fs::copy("fixtures/input.rs", &temp_file).unwrap();
+        let thanks = PrettyThanks::new(&[temp_file.as_str().into()], Options::default()).unwrap();
+        assert!(thanks.run().is_ok());
+    }
+
+    #[test]
+    fn can_format_multiple_paths() {
+        let temp = TempDir::new("multi");
+        fs::copy("fixtures/input.rs", temp.join("one.rs")).unwrap();
+        fs::copy("fixtures/input.rs", temp.join("two.rs")).unwrap();
+        let paths = ["one.rs", "two.rs", "missing.rs"]
+            .iter()
+            .map(|name| temp.join(name).as_str().to_string())
+            .collect::<Vec<_>>();
+        let thanks = PrettyThanks::new(&paths, Options::default()).unwrap();
+        let err = thanks.run().unwrap_err();
+        assert!(matches!(&err, Error::NotFound { path, .. } if path.ends_with("missing.rs")));
+        assert!(!err.to_string().contains("one.rs"));
+        assert_ne!(
+            fs::read_to_string(temp.join("two.rs")).unwrap(),
+            fs::read_to_string("fixtures/input.rs").unwrap()
+        );
+    }
+
+    #[test]
+    fn can_format_source() {
+        let formatted = format_str("fn main(){let a=1;}").unwrap();
+        assert_eq!(formatted, "fn main() {\n    let a = 1;\n}\n");
+        assert!(format_str("fn main(").is_err());
+    }
+
+    #[test]
+    fn can_format_snippets() {
+        let format = |source, mode| format_snippet(source, mode).unwrap();
+        assert_eq!(
+            format("struct A{a:u8}", ParseMode::Item),
+            "struct A {\n    a: u8,\n}\n"
+        );
+        assert_eq!(
+            format("match a{1=>b,_=>c}", ParseMode::Expr),
+            "match a {\n    1 => b,\n    _ => c,\n}\n"
+        );
+        assert_eq!(
+            format("let a=1;if a>0{b()}", ParseMode::Stmt),
+            "let a = 1;\nif a > 0 {\n    b()\n}\n"
+        );
+        assert_eq!(format("{a;b}", ParseMode::Block), "{\n    a;\n    b\n}\n");
+        assert!(format_snippet("let a = 1;", ParseMode::Expr).is_err());
+    }
+
+    #[test]
+    fn skips_ignored_paths() {
+        let temp = TempDir::new("ignore");
+        fs::create_dir_all(temp.join("target")).unwrap();
+        fs::write(temp.join("Cargo.toml"), "").unwrap();
+        fs::write(temp.join(".gitignore"), "ignored.rs\n").unwrap();
+        for file in ["ignored.rs", "kept.rs", "target/build.rs"] {
+            fs::copy("fixtures/input.rs", temp.join(file)).unwrap();
+        }
+        let paths = [temp.to_string()];
+        PrettyThanks::new(&paths, Options::default())
+            .unwrap()
+            .run()
+            .unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        assert_eq!(fs::read_to_string(temp.join("ignored.rs")).unwrap(), input);
+        assert_eq!(
+            fs::read_to_string(temp.join("target/build.rs")).unwrap(),
+            input
+        );
+        assert_ne!(fs::read_to_string(temp.join("kept.rs")).unwrap(), input);
+
+        let options = Options {
+            no_ignore: true,
+            ..Options::default()
+        };
+        PrettyThanks::new(&paths, options).unwrap().run().unwrap();
+        assert_ne!(fs::read_to_string(temp.join("ignored.rs")).unwrap(), input);
+    }
+
+    #[test]
+    fn honors_prettythanksignore() {
+        let temp = TempDir::new("ptignore");
+        fs::create_dir_all(temp.join("src/proto/nested")).unwrap();
+        fs::write(temp.join(ignore::IGNORE_FILE), "src/proto/**\n").unwrap();
+        fs::write(temp.join("src").join(ignore::IGNORE_FILE), "skip.rs\n").unwrap();
+        for file in ["src/proto/nested/gen.rs", "src/skip.rs", "src/lib.rs"] {
+            fs::copy("fixtures/input.rs", temp.join(file)).unwrap();
+        }
+        let options = Options {
+            no_ignore: true,
+            ..Options::default()
+        };
+        let paths = [temp.to_string()];
+        PrettyThanks::new(&paths, options).unwrap().run().unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.join("src/proto/nested/gen.rs")).unwrap(),
+            input
+        );
+        assert_eq!(fs::read_to_string(temp.join("src/skip.rs")).unwrap(), input);
+        assert_ne!(fs::read_to_string(temp.join("src/lib.rs")).unwrap(), input);
+    }
+
+    #[test]
+    fn check_fails_on_unformatted_files() {
+        let temp = TempDir::new("check");
+        let temp_file = temp.join("check.rs");
+        fs::copy("fixtures/input.rs", &temp_file).unwrap();
+        let paths = [temp_file.to_string()];
+        let options = Options {
+            check: true,
+            ..Options::default()
+        };
+        let err = PrettyThanks::new(&paths, options)
+            .unwrap()
+            .run()
+            .unwrap_err();
+        assert!(matches!(err, Error::Unformatted(_)));
+        PrettyThanks::new(&paths, Options::default())
+            .unwrap()
+            .run()
+            .unwrap();
+        let options = Options {
+            check: true,
+            ..Options::default()
+        };
+        PrettyThanks::new(&paths, options).unwrap().run().unwrap();
+    }
+
+    #[test]
+    fn streams_results() {
+        let temp = TempDir::new("stream");
+        fs::write(temp.join(".gitignore"), "ignored.rs\n").unwrap();
+        for file in ["a.rs", "b.rs", "c.rs", "ignored.rs"] {
+            fs::write(temp.join(file), "fn main(){}").unwrap();
+        }
+        let paths = [temp.to_string()];
+        let options = Options {
+            check: true,
+            ..Options::default()
+        };
+        let thanks = PrettyThanks::new(&paths, options).unwrap();
+        let mut names = thanks
+            .iter_results()
+            .map(|entry| match entry {
+                Entry::File(file) => {
+                    assert!(file.result.unwrap().changed);
+                    file.path.file_name().unwrap().to_string()
+                }
+                Entry::Ignored(path) => format!("ignored {}", path.file_name().unwrap()),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.rs", "b.rs", "c.rs", "ignored ignored.rs"]);
+        // Cancelled right away, waiting for the files in progress.
+        let mut results = thanks.iter_results();
+        assert!(results.next().is_some());
+        drop(results);
+    }
+
+    #[test]
+    fn cancels_runs() {
+        struct CancelFirst(Arc<AtomicBool>);
+
+        impl reporter::Reporter for CancelFirst {
+            fn file_formatted(&self, _: &Utf8Path, _: &Outcome) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let temp = TempDir::new("cancel");
+        for file in ["a.rs", "b.rs", "c.rs"] {
+            fs::write(temp.join(file), "fn main(){}").unwrap();
+        }
+        let paths = [temp.to_string()];
+        let options = Options {
+            jobs: 1,
+            ..Options::default()
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+        let report = PrettyThanks::new(&paths, options.clone())
+            .unwrap()
+            .with_cancel(Arc::clone(&cancel))
+            .with_reporter(CancelFirst(Arc::clone(&cancel)))
+            .report();
+        assert_eq!(report.files().count(), 1);
+        let errors = report.errors().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors, ["cancelled, 1 of 3 files formatted"]);
+        assert_eq!(
+            fs::read_to_string(temp.join("b.rs")).unwrap(),
+            "fn main(){}"
+        );
+        let report = format_tree_cancellable(Utf8Path::new(&paths[0]), &options, cancel);
+        assert_eq!(report.files().count(), 0);
+    }
+
+    #[test]
+    fn resumes_runs() {
+        let temp = TempDir::new("resume");
+        for file in ["a.rs", "b.rs"] {
+            fs::write(temp.join(file), "fn main(){}").unwrap();
+        }
+        let options = Options {
+            journal: Some(temp.join(resume::FILE_NAME)),
+            resume: true,
+            ..Options::default()
+        };
+        let paths = [temp.to_string()];
+        let thanks = PrettyThanks::new(&paths, options).unwrap();
+        let key = resume::key(&thanks.paths, &thanks.options);
+        let journal = resume::Journal::create(&temp.join(resume::FILE_NAME), &key, false).unwrap();
+        journal.record(&temp.join("a.rs")).unwrap();
+        drop(journal);
+        let report = thanks.report();
+        let names = report
+            .files()
+            .map(|file| file.path.file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b.rs"]);
+        assert_eq!(
+            fs::read_to_string(temp.join("a.rs")).unwrap(),
+            "fn main(){}"
+        );
+        assert!(!temp.join(resume::FILE_NAME).exists());
+        // Not kept for a few files, unless resuming.
+        let options = Options {
+            journal: Some(temp.join(resume::FILE_NAME)),
+            ..Options::default()
+        };
+        let thanks = PrettyThanks::new(&paths, options).unwrap();
+        assert!(thanks.journal(2).is_none());
+        assert!(thanks.journal(resume::MIN_FILES).is_some());
+    }
+
+    #[test]
+    fn counts_unformatted_files() {
+        let temp = TempDir::new("count");
+        for file in ["a.rs", "b.rs"] {
+            fs::copy("fixtures/input.rs", temp.join(file)).unwrap();
+        }
+        let paths = [temp.to_string()];
+        let count = |max_unformatted| {
+            let options = Options {
+                count: true,
+                max_unformatted,
+                ..Options::default()
+            };
+            PrettyThanks::new(&paths, options).unwrap().run()
+        };
+        count(Some(2)).unwrap();
+        let err = count(Some(1)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TooManyUnformatted { count: 2, max: 1 }
+        ));
+        assert_eq!(err.exit_code(), 1);
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        assert_eq!(fs::read_to_string(temp.join("a.rs")).unwrap(), input);
+        let options = Options {
+            max_unformatted: Some(1),
+            ..Options::default()
+        };
+        assert!(PrettyThanks::new(&paths, options).is_err());
+    }
+
+    #[test]
+    fn config_excludes_and_extensions() {
+        let temp = TempDir::new("config-options");
+        fs::create_dir_all(temp.join("gen")).unwrap();
+        for file in ["gen/a.rs", "lib.rs", "tpl.rs.in"] {
+            fs::copy("fixtures/input.rs", temp.join(file)).unwrap();
+        }
+        let config_path = temp.join("prettythanks.toml");
+        fs::write(
+            &config_path,
+            "exclude = [\"gen/\"]\nextensions = [\"rs\", \"rs.in\"]\n",
+        )
+        .unwrap();
+        let config = Config::load(&config_path).unwrap();
+        let options = Options::from_config(&config);
+        let paths = [temp.to_string()];
+        PrettyThanks::new(&paths, options).unwrap().run().unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        assert_eq!(fs::read_to_string(temp.join("gen/a.rs")).unwrap(), input);
+        assert_ne!(fs::read_to_string(temp.join("lib.rs")).unwrap(), input);
+        assert_ne!(fs::read_to_string(temp.join("tpl.rs.in")).unwrap(), input);
+    }
+
+    #[test]
+    fn markdown_files() {
+        let temp = TempDir::new("markdown");
+        fs::create_dir_all(temp.join("docs")).unwrap();
+        let readme = "Prose  stays.\n\n```rust\nfn  a(){}\n```\n";
+        for file in ["README.md", "docs/guide.md"] {
+            fs::write(temp.join(file), readme).unwrap();
+        }
+        let formatted = "Prose  stays.\n\n```rust\nfn a() {}\n```\n";
+        let paths = [temp.to_string(), temp.join("README.md").to_string()];
+        let err = PrettyThanks::new(&paths[..1], Options::default())
+            .unwrap()
+            .run()
+            .unwrap_err();
+        assert!(matches!(&err, Error::NoRustFiles(path) if *path == *temp));
+        assert_eq!(err.exit_code(), 4);
+        assert_eq!(fs::read_to_string(temp.join("README.md")).unwrap(), readme);
+        PrettyThanks::new(&paths[1..], Options::default())
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.join("README.md")).unwrap(),
+            formatted
+        );
+        let options = Options {
+            include_markdown: true,
+            ..Options::default()
+        };
+        PrettyThanks::new(&paths[..1], options)
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.join("docs/guide.md")).unwrap(),
+            formatted
+        );
+    }
+
+    #[test]
+    fn falls_back_to_rustfmt() {
+        // Only where rustfmt is installed.
+        if std::process::Command::new("rustfmt")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            return;
+        }
+        let path = Utf8Path::new("a.rs");
+        let source = "fn  f() { a.b.use; }\n";
+        assert!(format_with_policy(path, source, &Options::default()).is_err());
+        let options = Options {
+            fallback: Fallback::Rustfmt,
+            edition: Some(Edition::E2024),
+            ..Options::default()
+        };
+        let (formatted, _, _) = format_with_policy(path, source, &options).unwrap();
+        assert_eq!(formatted, "fn f() {\n    a.b.use;\n}\n");
+    }
+
+    #[test]
+    fn include_and_exclude_patterns() {
+        let temp = TempDir::new("patterns");
+        fs::create_dir_all(temp.join("src/vendor")).unwrap();
+        for file in ["src/a.rs", "src/b.rs", "src/vendor/c.rs", "d.rs"] {
+            fs::copy("fixtures/input.rs", temp.join(file)).unwrap();
+        }
+        let root = temp.canonicalize_utf8().unwrap();
+        let options = Options {
+            include: Some(Gitignore::parse(&root, "src/**/*.rs\n!b.rs")),
+            exclude: vec![Gitignore::parse(&root, "vendor/")],
+            ..Options::default()
+        };
+        PrettyThanks::new(&[temp.to_string()], options)
+            .unwrap()
+            .run()
+            .unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        assert_ne!(fs::read_to_string(temp.join("src/a.rs")).unwrap(), input);
+        for file in ["src/b.rs", "src/vendor/c.rs", "d.rs"] {
+            assert_eq!(fs::read_to_string(temp.join(file)).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn parallel_results_keep_order() {
+        let temp = TempDir::new("jobs");
+        let files = (0..16)
+            .map(|idx| {
+                let path = temp.join(format!("f{}.rs", idx));
+                fs::write(&path, format!("fn f{}() {{}}\n{}", idx, " ".repeat(idx))).unwrap();
+                path
+            })
+            .collect::<Vec<_>>();
+        let options = Options {
+            check: true,
+            jobs: 4,
+            ..Options::default()
+        };
+        let thanks = PrettyThanks::new(&[], options).unwrap();
+        let paths = files
+            .iter()
+            .map(|path| (path.as_path(), &thanks.options))
+            .collect::<Vec<_>>();
+        for (idx, result) in thanks.format_files(&paths, None).into_iter().enumerate() {
+            assert_eq!(
+                result.unwrap().original,
+                fs::read(&files[idx]).unwrap().len()
+            );
+        }
+    }
+
+    #[test]
+    fn format_tree_reports_files() {
+        let temp = TempDir::new("tree");
+        fs::write(temp.join("good.rs"), "fn good() {}\n").unwrap();
+        fs::write(temp.join("bad.rs"), "fn bad(").unwrap();
+        let options = Options {
+            check: true,
+            ..Options::default()
+        };
+        let report = format_tree(&temp, &options);
+        assert_eq!(report.files().count(), 2);
+        assert_eq!(report.changed().count(), 0);
+        let errors = report.errors().collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(errors[0], Error::Parse { path: Some(path), .. } if path.ends_with("bad.rs"))
+        );
+        let json = report.to_json().to_string();
+        assert!(json.contains(r#""status":"unchanged""#));
+        assert!(json.contains(r#""status":"error""#));
+        assert!(json.contains(r#""summary":{"files":2,"changed":0,"errors":1,"#));
+    }
+
+    #[test]
+    fn diff_leaves_file_untouched() {
+        let temp = TempDir::new("diff");
+        let temp_file = temp.join("diff.rs");
+        fs::copy("fixtures/input.rs", &temp_file).unwrap();
+        let thanks = PrettyThanks::new(
+            &[temp_file.as_str().into()],
+            Options {
+                diff: true,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        assert!(thanks.run().is_ok());
+        assert_eq!(
+            fs::read_to_string(&temp_file).unwrap(),
+            fs::read_to_string("fixtures/input.rs").unwrap()
+        );
+    }
+
+    #[test]
+    fn list_leaves_files_untouched() {
+        let temp = TempDir::new("list");
+        let temp_file = temp.join("list.rs");
+        fs::copy("fixtures/input.rs", &temp_file).unwrap();
+        let options = Options {
+            list: true,
+            ..Options::default()
+        };
+        assert!(!options.writes());
+        assert!(format_file(&temp_file, &options).unwrap().changed);
+        assert_eq!(
+            fs::read_to_string(&temp_file).unwrap(),
+            fs::read_to_string("fixtures/input.rs").unwrap()
+        );
+    }
+
+    #[test]
+    fn skips_writing_unchanged_files() {
+        let temp = TempDir::new("unchanged");
+        let temp_file = temp.join("unchanged.rs");
+        fs::write(&temp_file, "fn main() {}\n").unwrap();
+        let mtime = || fs::metadata(&temp_file).unwrap().modified().unwrap();
+        let before = mtime();
+        thread::sleep(Duration::from_millis(20));
+        let outcome = format_file(&temp_file, &Options::default()).unwrap();
+        assert!(!outcome.changed);
+        assert_eq!(mtime(), before);
+    }
+
+    #[test]
+    fn keeps_changes_saved_during_formatting() {
+        /// Saves the file like an editor would while it is being formatted.
+        struct Save(Utf8PathBuf);
+
+        impl transform::Transform for Save {
+            fn name(&self) -> &str {
+                "save"
+            }
+
+            fn apply(&self, _: &mut syn::File) {
+                fs::write(&self.0, "fn saved(){}").unwrap();
+            }
+        }
+
+        let temp = TempDir::new("saved");
+        let temp_file = temp.join("saved.rs");
+        fs::write(&temp_file, "fn main(){}").unwrap();
+        let mut options = Options::default();
+        options.transforms.push(Save(temp_file.clone()));
+        let outcome = format_file(&temp_file, &options).unwrap();
+        assert_eq!(outcome.skip_reason, Some(SkipReason::ChangedMeanwhile));
+        assert!(outcome.skipped);
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn saved(){}");
+    }
+
+    #[test]
+    fn skips_large_and_slow_files() {
+        /// Takes its time, like prettyplease on a pathological file.
+        struct Slow;
+
+        impl transform::Transform for Slow {
+            fn name(&self) -> &str {
+                "slow"
+            }
+
+            fn apply(&self, _: &mut syn::File) {
+                thread::sleep(Duration::from_millis(500));
+            }
+        }
+
+        let temp = TempDir::new("large");
+        let temp_file = temp.join("large.rs");
+        fs::write(&temp_file, "fn main(){}").unwrap();
+        let options = Options {
+            max_file_size: Some(10),
+            ..Options::default()
+        };
+        let outcome = format_file(&temp_file, &options).unwrap();
+        assert_eq!(outcome.skip_reason, Some(SkipReason::TooLarge));
+        assert_eq!(outcome.original, 11);
+        // Not even looked up in the cache, where it is recorded as formatted.
+        let cached = temp_file.with_file_name("large-cached.rs");
+        fs::write(&cached, "fn main() {}\n").unwrap();
+        let cache = temp_file.with_extension("cache");
+        let report = |max_file_size| {
+            let options = Options {
+                cache: Some(cache.clone()),
+                max_file_size,
+                ..Options::default()
+            };
+            let report = PrettyThanks::new(&[cached.to_string()], options)
+                .unwrap()
+                .report();
+            let file = report.files().next().unwrap();
+            file.result.as_ref().unwrap().skip_reason
+        };
+        assert_eq!(report(None), None);
+        assert_eq!(report(Some(10)), Some(SkipReason::TooLarge));
+        let mut options = Options {
+            timeout_per_file: Some(Duration::from_millis(10)),
+            ..Options::default()
+        };
+        options.transforms.push(Slow);
+        let outcome = format_file(&temp_file, &options).unwrap();
+        assert_eq!(outcome.skip_reason, Some(SkipReason::TimedOut));
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn main(){}");
+        options.timeout_per_file = Some(Duration::from_secs(60));
+        assert!(format_file(&temp_file, &options).unwrap().changed);
+        // The thread given up on is not counted anymore once done.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *ABANDONED.lock().unwrap() > 0 {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn keeps_the_cache_at_the_root() {
+        let root = TempDir::new("cache-root");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("Cargo.toml"), "[package]\nname = \"tree\"\n").unwrap();
+        fs::write(root.join("src/lib.rs"), "fn main(){}").unwrap();
+        let options = Options {
+            cache: Some(cache::FILE_NAME.into()),
+            ..Options::default()
+        };
+        let paths = [root.join("src/lib.rs").to_string()];
+        let report = PrettyThanks::new(&paths, options).unwrap().report();
+        assert!(report.files().all(|file| file.result.is_ok()));
+        assert!(root.join(cache::FILE_NAME).is_file());
+        assert!(!root.join("src").join(cache::FILE_NAME).exists());
+    }
+
+    #[test]
+    fn tolerates_only_stdin() {
+        let options = Options {
+            tolerant: true,
+            ..Options::default()
+        };
+        assert!(PrettyThanks::new(&["fixtures/input.rs".to_string()], options.clone()).is_err());
+        assert!(PrettyThanks::new(&["-".to_string()], options).is_ok());
+        assert_eq!(SkipReason::Unparsable.to_string(), "it doesn't parse");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_policy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new("read-only");
+        let temp_file = temp.join("read-only.rs");
+        fs::write(&temp_file, "fn main(){}").unwrap();
+        fs::set_permissions(&temp_file, fs::Permissions::from_mode(0o444)).unwrap();
+        let format = |readonly| {
+            format_file(
+                &temp_file,
+                &Options {
+                    readonly,
+                    ..Options::default()
+                },
+            )
+        };
+        let outcome = format(ReadOnlyPolicy::Skip).unwrap();
+        assert_eq!(outcome.skip_reason, Some(SkipReason::ReadOnly));
+        assert!(outcome.skipped);
+        assert!(matches!(
+            format(ReadOnlyPolicy::Error),
+            Err(Error::ReadOnly(_))
+        ));
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn main(){}");
+        assert_eq!(format(ReadOnlyPolicy::Force).unwrap().skip_reason, None);
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn main() {}");
+        assert!(fs::metadata(&temp_file).unwrap().permissions().readonly());
+        fs::set_permissions(&temp_file, fs::Permissions::from_mode(0o644)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_atomically_keeping_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new("atomic");
+        let file = temp.join("main.rs");
+        fs::write(&file, "fn main(){}").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        std::os::unix::fs::symlink(&file, temp.join("link.rs")).unwrap();
+        format_file(&temp.join("link.rs"), &Options::default()).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fn main() {}");
+        assert_eq!(
+            fs::metadata(&file).unwrap().permissions().mode() & 0o777,
+            0o640
+        );
+        assert!(fs::symlink_metadata(temp.join("link.rs"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_dir(&temp).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn applies_nested_configs() {
+        let temp = TempDir::new("nested-config");
+        fs::create_dir_all(temp.join("generated/deep")).unwrap();
+        for file in ["a.rs", "generated/b.rs", "generated/deep/c.rs"] {
+            fs::write(temp.join(file), "fn a(){} // lost\n").unwrap();
+        }
+        // The configuration of the formatted directory is the one the run starts with.
+        fs::write(
+            temp.join(config::CONFIG_FILES[0]),
+            "comment-policy = \"allow\"\n",
+        )
+        .unwrap();
+        fs::write(
+            temp.join("generated").join(config::CONFIG_FILES[1]),
+            "comment-policy = \"allow\"\ncheck = false\n",
+        )
+        .unwrap();
+        let options = Options {
+            comment_policy: CommentPolicy::Skip,
+            check: true,
+            ..Options::default()
+        };
+        let report = PrettyThanks::new(&[temp.to_string()], options)
+            .unwrap()
+            .report();
+        let outcomes = report
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                Entry::File(file) => {
+                    let outcome = file.result.as_ref().unwrap();
+                    (
+                        file.path.strip_prefix(&temp).unwrap().as_str(),
+                        outcome.skipped,
+                    )
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                ("a.rs", true),
+                ("generated/b.rs", false),
+                ("generated/deep/c.rs", false)
+            ]
+        );
+        // Only formatting settings apply to nested directories, the run still checks.
+        assert_eq!(
+            fs::read_to_string(temp.join("generated/b.rs")).unwrap(),
+            "fn a(){} // lost\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_symlinks_once() {
+        let temp = TempDir::new("symlinks");
+        fs::create_dir_all(temp.join("src")).unwrap();
+        fs::write(temp.join("src/a.rs"), "fn a() {}\n").unwrap();
+        std::os::unix::fs::symlink(&temp, temp.join("src/loop")).unwrap();
+        std::os::unix::fs::symlink(temp.join("src/a.rs"), temp.join("b.rs")).unwrap();
+        let entries = |follow_symlinks| {
+            let options = Options {
+                follow_symlinks,
+                check: true,
+                ..Options::default()
+            };
+            let paths = [temp.to_string()];
+            let report = PrettyThanks::new(&paths, options).unwrap().report();
+            report
+                .entries
+                .iter()
+                .map(|entry| match entry {
+                    Entry::File(file) => format!("file {}", file.path.strip_prefix(&temp).unwrap()),
+                    Entry::Ignored(path) => {
+                        format!("ignored {}", path.strip_prefix(&temp).unwrap())
+                    }
+                    Entry::Revisited(path) => {
+                        format!("revisited {}", path.strip_prefix(&temp).unwrap())
+                    }
+                    Entry::Duplicate(path) => {
+                        format!("duplicate {}", path.strip_prefix(&temp).unwrap())
+                    }
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            entries(SymlinkPolicy::Never),
+            ["ignored b.rs", "file src/a.rs", "ignored src/loop"]
+        );
+        assert_eq!(
+            entries(SymlinkPolicy::Files),
+            ["file b.rs", "duplicate src/a.rs", "ignored src/loop"]
+        );
+        assert_eq!(
+            entries(SymlinkPolicy::All),
+            ["file b.rs", "duplicate src/a.rs", "revisited src/loop"]
+        );
+        assert_eq!("all".parse(), Ok(SymlinkPolicy::All));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn formats_hard_links_once() {
+        let temp = TempDir::new("hard-links");
+        fs::write(temp.join("a.rs"), "fn  a(){}").unwrap();
+        fs::hard_link(temp.join("a.rs"), temp.join("b.rs")).unwrap();
+        let paths = [temp.join("a.rs").to_string(), temp.to_string()];
+        let report = PrettyThanks::new(&paths, Options::default())
+            .unwrap()
+            .report();
+        assert_eq!(report.files().count(), 1);
+        assert_eq!(
+            report
+                .entries
+                .iter()
+                .filter(|entry| matches!(entry, Entry::Duplicate(_)))
+                .count(),
+            2
+        );
+        assert_eq!(fs::read_to_string(temp.join("b.rs")).unwrap(), "fn a() {}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn skips_non_utf8_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let temp = TempDir::new("non-utf8");
+        fs::write(temp.join("a.rs"), "fn  a(){}").unwrap();
+        let odd = temp.as_std_path().join(OsStr::from_bytes(b"\xff.rs"));
+        fs::write(&odd, "fn  b(){}").unwrap();
+        fs::write(temp.as_std_path().join(OsStr::from_bytes(b"\xff.txt")), "").unwrap();
+        let paths = [temp.to_string()];
+        let report = PrettyThanks::new(&paths, Options::default())
+            .unwrap()
+            .report();
+        assert!(report.is_ok());
+        assert_eq!(report.files().count(), 1);
+        let skipped = report
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::NonUtf8(path) => Some(path),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(skipped, [&odd]);
+    }
+
+    #[test]
+    fn walks_up_to_max_depth() {
+        let temp = TempDir::new("max-depth");
+        fs::create_dir_all(temp.join("a/b/c")).unwrap();
+        for file in ["z.rs", "a/z.rs", "a/b/z.rs", "a/b/c/z.rs", "a/y.rs"] {
+            fs::write(temp.join(file), "fn z() {}\n").unwrap();
+        }
+        let files = |max_depth| {
+            let options = Options {
+                max_depth,
+                check: true,
+                ..Options::default()
+            };
+            let paths = [temp.to_string()];
+            let report = PrettyThanks::new(&paths, options).unwrap().report();
+            report
+                .files()
+                .map(|file| file.path.strip_prefix(&temp).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(files(Some(0)), ["z.rs"]);
+        assert_eq!(files(Some(1)), ["z.rs", "a/y.rs", "a/z.rs"]);
+        assert_eq!(
+            files(None),
+            ["z.rs", "a/y.rs", "a/z.rs", "a/b/z.rs", "a/b/c/z.rs"]
+        );
+    }
+
+    #[test]
+    fn reports_in_name_order() {
+        let temp = TempDir::new("order");
+        // Created backwards, whatever order the file system lists them in.
+        for idx in (0..20).rev() {
+            fs::create_dir_all(temp.join(format!("d{:02}", idx))).unwrap();
+            fs::write(temp.join(format!("d{:02}/f.rs", idx)), "fn  f(){}").unwrap();
+            fs::write(temp.join(format!("f{:02}.rs", idx)), "fn f(").unwrap();
+        }
+        let options = Options {
+            check: true,
+            jobs: 8,
+            ..Options::default()
+        };
+        let report = PrettyThanks::new(&[temp.to_string()], options)
+            .unwrap()
+            .report();
+        let files = report
+            .files()
+            .map(|file| file.path.strip_prefix(&temp).unwrap().to_string())
+            .collect::<Vec<_>>();
+        let mut expected = (0..20)
+            .map(|idx| format!("f{:02}.rs", idx))
+            .collect::<Vec<_>>();
+        expected.extend((0..20).map(|idx| format!("d{:02}/f.rs", idx)));
+        assert_eq!(files, expected);
+        let errors = report.errors().map(ToString::to_string).collect::<Vec<_>>();
+        assert!(errors.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn emits_without_writing() {
+        let temp = TempDir::new("emit");
+        let temp_file = temp.join("emit.rs");
+        fs::write(&temp_file, "fn  a(){}").unwrap();
+        let output = |emit| {
+            let options = Options {
+                emit,
+                ..Options::default()
+            };
+            let outcome = format_file(&temp_file, &options).unwrap();
+            assert!(outcome.changed);
+            outcome.output
+        };
+        assert_eq!(
+            output(Emit::Stdout),
+            format!("{}:\n\nfn a() {{}}", temp_file)
+        );
+        assert!(output(Emit::Diff).contains("\n+fn a() {}\n"));
+        assert_eq!(output(Emit::None), "");
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn  a(){}");
+    }
+
+    #[test]
+    fn counts_lines_for_reports() {
+        let temp = TempDir::new("lines");
+        let temp_file = temp.join("lines.rs");
+        let lines = |options: Options| {
+            fs::write(&temp_file, "fn  a(){}\nfn b() {}\n").unwrap();
+            let outcome = format_file(&temp_file, &options).unwrap();
+            (outcome.removed_lines, outcome.added_lines)
+        };
+        assert_eq!(lines(Options::default()), (0, 0));
+        let stats = Options {
+            stats: true,
+            ..Options::default()
+        };
+        assert_eq!(lines(stats), (1, 1));
+        let json = Options {
+            output_format: OutputFormat::Json,
+            ..Options::default()
+        };
+        assert_eq!(lines(json), (1, 1));
+    }
+
+    #[test]
+    fn formats_editor_buffers() {
+        let buffer = |source: &str, stdin_filename: Option<&str>| {
+            let options = Options {
+                stdin_filename: stdin_filename.map(Utf8PathBuf::from),
+                tolerant: true,
+                ..Options::default()
+            };
+            let thanks = PrettyThanks::new(&["-".to_string()], options).unwrap();
+            let result = thanks.format_buffer(source.to_string(), Instant::now());
+            let output = result.as_ref().map(|outcome| outcome.output.clone());
+            let path = thanks.stdin_path().to_path_buf();
+            (
+                output.unwrap(),
+                thanks.check_file(FileReport { path, result }),
+            )
+        };
+        // Named buffers tell they changed, for editors to know without comparing them.
+        let (output, checked) = buffer("fn  z(){}\n", Some("e.rs"));
+        assert_eq!(output, "fn z() {}\n");
+        let err = checked.unwrap_err();
+        assert_eq!(err.exit_code(), 1);
+        assert!(matches!(err, Error::Unformatted(path) if path == "e.rs"));
+        assert!(buffer("fn z() {}\n", Some("e.rs")).1.is_ok());
+        assert!(buffer("fn  z(){}\n", None).1.is_ok());
+        // Buffers that don't parse are printed back untouched, and not reported as changed.
+        let (output, checked) = buffer("fn z(\n", Some("e.rs"));
+        assert_eq!(output, "fn z(\n");
+        assert!(checked.is_ok());
+    }
+
+    #[test]
+    fn comment_policy() {
+        let temp = TempDir::new("comments");
+        let temp_file = temp.join("comments.rs");
+        let source = "// keep me\nfn main(){}";
+        fs::write(&temp_file, source).unwrap();
+        let format = |comment_policy| {
+            let options = Options {
+                comment_policy,
+                ..Options::default()
+            };
+            format_file(&temp_file, &options).unwrap()
+        };
+        let outcome = format(CommentPolicy::Skip);
+        assert!(outcome.skipped && !outcome.changed);
+        assert_eq!(outcome.dropped_comments, 1);
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), source);
+        let outcome = format(CommentPolicy::Warn);
+        assert!(!outcome.skipped && outcome.changed);
+        assert_eq!(outcome.dropped_comments, 1);
+        assert!(!outcome.comments_allowed);
+        assert_eq!(fs::read_to_string(&temp_file).unwrap(), "fn main() {}");
+        // Allowed comments are still counted, for reports to tell what was dropped.
+        fs::write(&temp_file, source).unwrap();
+        let outcome = format(CommentPolicy::Allow);
+        assert_eq!(
+            (outcome.dropped_comments, outcome.comments_allowed),
+            (1, true)
+        );
+        fs::write(&temp_file, source).unwrap();
+        assert_eq!(format(CommentPolicy::Preserve).dropped_comments, 0);
+    }
+
+    #[test]
+    fn asserts_idempotence() {
+        let options = Options {
+            assert_idempotent: true,
+            comment_policy: CommentPolicy::Preserve,
+            ..Options::default()
+        };
+        let source = fs::read_to_string("fixtures/input.rs").unwrap();
+        let (formatted, _, _) =
+            format_with_policy(Utf8Path::new("input.rs"), &source, &options).unwrap();
+        assert_ne!(formatted, source);
+    }
+
+    #[test]
+    fn keeps_line_endings() {
+        let format = |source: &str, line_ending| {
+            let options = Options {
+                line_ending,
+                ..Options::default()
+            };
+            format_with_policy(Utf8Path::new("input.rs"), source, &options)
+                .unwrap()
+                .0
+        };
+        let crlf = "fn  a(){}\r\nfn  b(){}\r\n";
+        assert_eq!(format(crlf, LineEnding::Auto), "fn a() {}\r\nfn b() {}\r\n");
+        assert_eq!(format(crlf, LineEnding::Lf), "fn a() {}\nfn b() {}\n");
+        assert_eq!(
+            format("fn  a(){}\nfn  b(){}", LineEnding::Crlf),
+            "fn a() {}\r\nfn b() {}\r\n"
+        );
+        // The final newline is only added when the source has one, or the line ending is forced.
+        assert_eq!(
+            format("fn  a(){}\r\nfn  b(){}", LineEnding::Auto),
+            "fn a() {}\r\nfn b() {}"
+        );
+        assert_eq!(format("fn  a(){}", LineEnding::Auto), "fn a() {}");
+        assert_eq!(format("fn  a(){}", LineEnding::Lf), "fn a() {}\n");
+        assert_eq!(format("", LineEnding::Auto), "");
+    }
+
+    #[test]
+    fn keeps_bom_and_shebang() {
+        let format = |source: &str| {
+            format_with_policy(Utf8Path::new("input.rs"), source, &Options::default())
+        };
+        assert_eq!(
+            format("\u{feff}fn  a(){}\n").unwrap().0,
+            "\u{feff}fn a() {}\n"
+        );
+        assert_eq!(
+            format("#!/usr/bin/env rust-script\n//! Docs.\nfn  a(){}\n")
+                .unwrap()
+                .0,
+            "#!/usr/bin/env rust-script\n//! Docs.\nfn a() {}\n"
+        );
+        assert_eq!(
+            format("#![allow(dead_code)]\nfn  a(){}\n").unwrap().0,
+            "#![allow(dead_code)]\nfn a() {}\n"
+        );
+        let err = format("#!/bin/sh\nfn a(\n").unwrap_err();
+        assert_eq!(err.location().map(|location| location.line), Some(2));
+    }
+
+    #[test]
+    fn summarizes_dry_runs() {
+        let temp = TempDir::new("dry-run");
+        fs::write(temp.join("a.rs"), "fn  a(){}").unwrap();
+        fs::write(temp.join("b.rs"), "fn b() {}\n").unwrap();
+        fs::write(temp.join("c.rs"), "fn").unwrap();
+        let options = Options {
+            dry_run: true,
+            ..Options::default()
+        };
+        let paths = [temp.to_string()];
+        let thanks = PrettyThanks::new(&paths, options).unwrap();
+        assert_eq!(
+            thanks.report().summary_table(),
+            "files scanned            3\n\
+             would change             1\n\
+             unchanged                1\n\
+             skipped                  0\n\
+             parse errors             1\n\
+             other errors             0\n\
+             size change       +0 bytes\n"
+        );
+        assert!(thanks.run().is_ok());
+        assert_eq!(fs::read_to_string(temp.join("a.rs")).unwrap(), "fn  a(){}");
+    }
+
+    #[test]
+    fn fail_fast_stops_at_first_error() {
+        let temp = TempDir::new("fail-fast");
+        fs::create_dir_all(temp.join("sub")).unwrap();
+        fs::write(temp.join("sub/bad.rs"), "fn main(").unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        let run = |fail_fast| {
+            fs::write(temp.join("good.rs"), &input).unwrap();
+            let paths = ["missing.rs", "sub", "good.rs"]
+                .iter()
+                .map(|name| temp.join(name).as_str().to_string())
+                .collect::<Vec<_>>();
+            let options = Options {
+                fail_fast,
+                jobs: 1,
+                ..Options::default()
+            };
+            let err = PrettyThanks::new(&paths, options)
+                .unwrap()
+                .run()
+                .unwrap_err();
+            (err, fs::read_to_string(temp.join("good.rs")).unwrap())
+        };
+        let (err, good) = run(false);
+        assert!(matches!(&err, Error::Multiple(errors) if errors.len() == 2));
+        assert_ne!(good, input);
+        let (err, good) = run(true);
+        assert!(matches!(&err, Error::NotFound { path, .. } if path.ends_with("missing.rs")));
+        assert_eq!(good, input);
+    }
+
+    #[test]
+    fn mirrors_into_out_dir() {
+        let temp = TempDir::new("out-dir");
+        fs::create_dir_all(temp.join("src/sub")).unwrap();
+        let input = fs::read_to_string("fixtures/input.rs").unwrap();
+        fs::write(temp.join("src/sub/a.rs"), &input).unwrap();
+        fs::write(temp.join("b.rs"), "fn b() {}\n").unwrap();
+        let options = Options {
+            out_dir: Some(temp.join("out")),
+            ..Options::default()
+        };
+        let paths = [temp.join("src").to_string(), temp.join("b.rs").to_string()];
+        PrettyThanks::new(&paths, options).unwrap().run().unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.join("src/sub/a.rs")).unwrap(),
+            input
+        );
+        assert_ne!(
+            fs::read_to_string(temp.join("out/sub/a.rs")).unwrap(),
+            input
+        );
+        assert_eq!(
+            fs::read_to_string(temp.join("out/b.rs")).unwrap(),
+            "fn b() {}\n"
+        );
+        // The file passed on its own would overwrite the one of the tree.
+        fs::write(temp.join("src/b.rs"), "fn  c(){}\n").unwrap();
+        let options = Options {
+            out_dir: Some(temp.join("out")),
+            ..Options::default()
+        };
+        let report = PrettyThanks::new(&paths, options).unwrap().report();
+        assert_eq!(report.files().count(), 2);
+        assert!(matches!(
+            report.errors().next(),
+            Some(Error::SameDestination { path, other, .. })
+                if *path == temp.join("b.rs") && *other == temp.join("src/b.rs")
+        ));
+        assert_eq!(
+            fs::read_to_string(temp.join("out/b.rs")).unwrap(),
+            "fn c() {}\n"
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Advisory locking of the trees being formatted, so that two runs started at once, like an
+//! editor formatting on save and the command line, don't write the same files on top of each
+//! other.
+//!
+//! The lock is a file holding the process id of the run, created at the root of the tree when
+//! the run starts writing files and removed once it is done, see [`root`]. A lock left behind by a
+//! run that died is taken over.
+
+use crate::{signal, Error, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Name of the lock file, created at the root of the tree unless `--no-lock` is passed.
+pub const FILE_NAME: &str = ".prettythanks-lock";
+
+/// How long to wait before checking a lock held by another run again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How old the guard of a takeover must be to be left behind by a run that died taking over, a
+/// takeover taking a few system calls.
+const STALE_GUARD: Duration = Duration::from_secs(10);
+
+/// A lock file held by this process, removed when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: Utf8PathBuf,
+}
+
+impl Lock {
+    /// Take the lock file at `path`, waiting for the run holding it to finish if `wait` is set.
+    ///
+    /// # Errors
+    ///
+    /// With [`Error::Locked`] if another run holds it and `wait` isn't set, or a signal arrives
+    /// while waiting, and if it can't be created.
+    pub fn acquire(path: &Utf8Path, wait: bool) -> Result<Self> {
+        let mut waiting = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(mut file) => {
+                    write!(file, "{}", std::process::id()).map_err(|err| Error::io(path, err))?;
+                    return Ok(Lock {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(Error::io(path, err)),
+            }
+            let pid = holder(path);
+            match pid {
+                Some(pid) if !alive(pid) => {
+                    if !take_over(path, pid)? {
+                        // Another run is taking it over, the lock is its own once done.
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                    continue;
+                }
+                _ if !wait || signal::received().is_some() => {
+                    return Err(Error::Locked {
+                        path: path.to_path_buf(),
+                        pid,
+                    });
+                }
+                _ => {}
+            }
+            if !waiting {
+                waiting = true;
+                warn!("waiting for another run holding {}", path);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("can't remove {}: {}", self.path, err);
+        }
+    }
+}
+
+/// The process id written in the lock file at `path`, `None` until the run creating it writes it.
+fn holder(path: &Utf8Path) -> Option<u32> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+}
+
+/// Remove the lock file at `path` left behind by the process `stale`, returning whether no other
+/// run was taking it over at the same time.
+///
+/// Runs taking over the same lock at once would remove the fresh lock of each other, so they do
+/// under a guard file, checking that the lock is still the stale one before removing it: fresh
+/// locks are only created once it is gone.
+fn take_over(path: &Utf8Path, stale: u32) -> Result<bool> {
+    let guard = Utf8PathBuf::from(format!("{}.takeover", path));
+    match OpenOptions::new().write(true).create_new(true).open(&guard) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            let modified = fs::metadata(&guard).and_then(|metadata| metadata.modified());
+            let age = modified.map(|modified| SystemTime::now().duration_since(modified));
+            if matches!(age, Ok(Ok(age)) if age > STALE_GUARD) {
+                let _ = fs::remove_file(&guard);
+            }
+            return Ok(false);
+        }
+        Err(err) => return Err(Error::io(&guard, err)),
+    }
+    let removed = if holder(path) == Some(stale) {
+        warn!("taking over {}, left behind by process {}", path, stale);
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::io(path, err)),
+            _ => Ok(true),
+        }
+    } else {
+        Ok(true)
+    };
+    if let Err(err) = fs::remove_file(&guard) {
+        warn!("can't remove {}: {}", guard, err);
+    }
+    removed
+}
+
+/// The root of the tree `paths` are in, where runs over any part of it take the same lock: the
+/// outermost cargo workspace above the innermost directory holding them all, or else the nearest
+/// package or configuration file, or the repository, looking no further than it. `None` if no
+/// path exists.
+pub fn root(paths: &[Utf8PathBuf]) -> Option<Utf8PathBuf> {
+    let mut common: Option<Utf8PathBuf> = None;
+    for path in paths.iter().filter(|path| path.as_str() != "-") {
+        let absolute = match path.canonicalize_utf8() {
+            Ok(absolute) if absolute.is_dir() => absolute,
+            Ok(absolute) => absolute.parent()?.to_path_buf(),
+            Err(_) => continue,
+        };
+        common = Some(match common {
+            Some(common) => common
+                .ancestors()
+                .find(|ancestor| absolute.starts_with(ancestor))?
+                .to_path_buf(),
+            None => absolute,
+        });
+    }
+    let common = common?;
+    let (mut workspace, mut nearest) = (None, None);
+    for dir in common.ancestors() {
+        let manifest = fs::read_to_string(dir.join("Cargo.toml")).ok();
+        if manifest
+            .as_deref()
+            .map_or(false, |manifest| manifest.contains("[workspace]"))
+        {
+            workspace = Some(dir);
+        }
+        let configured = crate::config::CONFIG_FILES
+            .iter()
+            .any(|name| dir.join(name).is_file());
+        if nearest.is_none() && (manifest.is_some() || configured) {
+            nearest = Some(dir);
+        }
+        if dir.join(".git").exists() {
+            nearest = nearest.or(Some(dir));
+            break;
+        }
+    }
+    Some(workspace.or(nearest).unwrap_or(&common).to_path_buf())
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    /// The error of `kill` for processes that don't exist, the same on Linux and the BSDs.
+    pub const ESRCH: i32 = 3;
+
+    extern "C" {
+        pub fn kill(pid: c_int, signal: c_int) -> c_int;
+    }
+}
+
+/// Whether the process `pid` is still running, checked by sending it no signal at all.
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    let pid = match std::os::raw::c_int::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return true,
+    };
+    // SAFETY: the signal 0 only checks that the process exists, nothing is sent.
+    let status = unsafe { sys::kill(pid, 0) };
+    status == 0 || io::Error::last_os_error().raw_os_error() != Some(sys::ESRCH)
+}
+
+/// Processes would need the Windows API to be looked up, every lock is assumed to be held.
+#[cfg(not(unix))]
+fn alive(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn locks_once() {
+        let temp = TempDir::new("lock");
+        let path = temp.join(FILE_NAME);
+        let lock = Lock::acquire(&path, false).unwrap();
+        let pid = std::process::id();
+        assert_eq!(fs::read_to_string(&path).unwrap(), pid.to_string());
+        let err = Lock::acquire(&path, false).unwrap_err();
+        assert!(matches!(err, Error::Locked { pid: Some(held), .. } if held == pid));
+        drop(lock);
+        assert!(!path.exists());
+        if cfg!(unix) {
+            // Left behind by a process that is long gone, as pids don't go this high.
+            fs::write(&path, "999999999").unwrap();
+            let _lock = Lock::acquire(&path, false).unwrap();
+            assert_eq!(fs::read_to_string(&path).unwrap(), pid.to_string());
+            // Taken over by another run meanwhile, which is left alone.
+            assert!(take_over(&path, 999_999_999).unwrap());
+            assert_eq!(holder(&path), Some(pid));
+        }
+    }
+
+    #[test]
+    fn finds_roots() {
+        let temp = TempDir::new("lock-root");
+        fs::create_dir_all(temp.join("crates/a/src")).unwrap();
+        fs::create_dir_all(temp.join("crates/b")).unwrap();
+        fs::create_dir(temp.join(".git")).unwrap();
+        fs::write(temp.join("crates/a/Cargo.toml"), "[package]\n").unwrap();
+        fs::write(temp.join("crates/a/src/lib.rs"), "").unwrap();
+        let temp = temp.canonicalize_utf8().unwrap();
+        let src = [temp.join("crates/a/src")];
+        assert_eq!(root(&src), Some(temp.join("crates/a")));
+        let both = [temp.join("crates/a/src/lib.rs"), temp.join("crates/b")];
+        assert_eq!(root(&both), Some(temp.clone()));
+        fs::write(temp.join("Cargo.toml"), "[workspace]\n").unwrap();
+        assert_eq!(root(&src), Some(temp.clone()));
+        assert_eq!(root(&["-".into()]), None);
+    }
+}
@@ -0,0 +1,260 @@
+//! Leveled messages about what is being formatted, which library users can redirect with
+//! [`set_logger`].
+
+use crate::{json::Value, term};
+use camino::Utf8Path;
+use std::{
+    env, fmt,
+    fs::File,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Mutex, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Environment variable holding the level to log at, overridden by `-q` and `-v`.
+pub const ENV_VAR: &str = "PRETTYTHANKS_LOG";
+
+/// How much is printed, each level including the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Nothing but errors, which are returned rather than logged, with `--quiet`.
+    Error,
+    /// Files formatted with caveats, like dropped comments, the default.
+    Warn,
+    /// What is being formatted, with `-v`.
+    Info,
+    /// Where the time goes for every file, with `-vv`.
+    Debug,
+}
+
+impl Level {
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+
+    /// The level set in [`ENV_VAR`], if any.
+    pub fn from_env() -> std::result::Result<Option<Self>, String> {
+        match env::var(ENV_VAR) {
+            Ok(level) if !level.is_empty() => level.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!(
+                "unknown log level `{}`, expected error, warn, info or debug",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        })
+    }
+}
+
+/// A logged message, with structured data about it.
+pub struct Record<'a> {
+    pub level: Level,
+    pub message: fmt::Arguments<'a>,
+    /// Like the path of the file the message is about and the time spent on it.
+    pub fields: &'a [(&'a str, Value)],
+}
+
+/// Receives the records enabled by [`set_level`].
+pub trait Logger: Send + Sync {
+    fn log(&self, record: &Record<'_>);
+}
+
+/// The logger used unless another one is set: warnings and errors on stderr, everything else on
+/// stdout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdLogger;
+
+impl Logger for StdLogger {
+    fn log(&self, record: &Record<'_>) {
+        let (prefix, color) = match record.level {
+            Level::Error => ("error", term::Color::Red),
+            Level::Warn => ("warning", term::Color::Yellow),
+            Level::Info | Level::Debug => {
+                println!("{}", record.message);
+                return;
+            }
+        };
+        eprintln!(
+            "{}: {}",
+            term::paint(term::Stream::Stderr, color, prefix),
+            record.message
+        );
+    }
+}
+
+/// Write every record to a file as a line of JSON, on top of passing the ones up to `level` to
+/// [`StdLogger`].
+pub struct FileLogger {
+    file: Mutex<File>,
+    level: Level,
+}
+
+impl FileLogger {
+    /// Truncate `path` and log to it, [`set_level`] must enable everything meant for the file.
+    pub fn create(path: &Utf8Path, level: Level) -> io::Result<Self> {
+        Ok(FileLogger {
+            file: Mutex::new(File::create(path)?),
+            level,
+        })
+    }
+}
+
+impl Logger for FileLogger {
+    fn log(&self, record: &Record<'_>) {
+        if record.level <= self.level {
+            StdLogger.log(record);
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut fields = vec![
+            ("timestamp_ms", timestamp.as_millis().into()),
+            ("level", record.level.to_string().into()),
+            ("message", strip_colors(&record.message.to_string()).into()),
+        ];
+        fields.extend(
+            record
+                .fields
+                .iter()
+                .map(|(key, value)| (*key, value.clone())),
+        );
+        let line = Value::object(fields);
+        // Losing a line of a debugging log isn't worth failing the run over.
+        let _ = writeln!(self.file.lock().expect("poisoned log file"), "{}", line);
+    }
+}
+
+/// `text` without the escape sequences [`term::paint`] adds.
+fn strip_colors(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = rest.find('m').map_or("", |end| &rest[end + 1..]);
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static LOGGER: RwLock<Option<Box<dyn Logger>>> = RwLock::new(None);
+
+/// Only log the records at `level` or below.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// The most verbose level logged.
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Send the records to `logger` instead of [`StdLogger`].
+pub fn set_logger(logger: impl Logger + 'static) {
+    *LOGGER.write().expect("poisoned logger") = Some(Box::new(logger));
+}
+
+/// Log `record` if its level is enabled.
+pub fn log_record(record: &Record<'_>) {
+    if record.level > self::level() {
+        return;
+    }
+    match &*LOGGER.read().expect("poisoned logger") {
+        Some(logger) => logger.log(record),
+        None => StdLogger.log(record),
+    }
+}
+
+/// Log `message` if `level` is enabled.
+pub fn log(level: Level, message: fmt::Arguments<'_>) {
+    log_record(&Record {
+        level,
+        message,
+        fields: &[],
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use std::sync::Arc;
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl Logger for Capture {
+        fn log(&self, record: &Record<'_>) {
+            let message = record.message.to_string();
+            if message.starts_with("test:") {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{:?} {}", record.level, message));
+            }
+        }
+    }
+
+    #[test]
+    fn hooks_logger() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        set_logger(Capture(messages.clone()));
+        set_level(Level::Info);
+        info!("test: {}", 1);
+        debug!("test: {}", 2);
+        warn!("test: {}", 3);
+        set_level(Level::Warn);
+        *LOGGER.write().unwrap() = None;
+        assert_eq!(*messages.lock().unwrap(), ["Info test: 1", "Warn test: 3"]);
+    }
+
+    #[test]
+    fn logs_to_file() {
+        let temp = TempDir::new("log");
+        let path = temp.join("prettythanks.log");
+        let logger = FileLogger::create(&path, Level::Error).unwrap();
+        logger.log(&Record {
+            level: Level::Debug,
+            message: format_args!("\x1b[32mformatting\x1b[0m file"),
+            fields: &[("path", "a.rs".into())],
+        });
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.ends_with(
+            r#""level":"debug","message":"formatting file","path":"a.rs"}
+"#
+        ));
+        assert_eq!("debug".parse(), Ok(Level::Debug));
+    }
+}
@@ -0,0 +1,421 @@
+//! A minimal language server, so that editors can format documents on save without temp files.
+//!
+//! Only the full document sync and the formatting requests are supported. Range formatting is
+//! best effort: the whole document is formatted and only the changes within the range are kept.
+
+use crate::{
+    comments::{self, CommentPolicy},
+    config::Config,
+    diff,
+    error::STDOUT,
+    format_with_policy,
+    json::{self, Value},
+    Error, Options, Result,
+};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+
+/// `MessageType.Warning` of `window/showMessage`.
+const WARNING: usize = 2;
+
+/// Serve the editor on stdin and stdout until it asks the server to exit.
+pub fn serve() -> Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve_with(&mut stdin.lock(), &mut stdout.lock())
+}
+
+/// Serve the editor reading its messages from `input` and writing the replies to `output`.
+pub fn serve_with(input: &mut impl BufRead, output: &mut impl Write) -> Result<()> {
+    let mut server = Server::default();
+    while let Some(message) =
+        read_message(input).map_err(|err| Error::io(Utf8Path::new("-"), err))?
+    {
+        let message = match json::parse(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                let reply = error_response(Value::Null, PARSE_ERROR, err);
+                write_message(output, &reply)
+                    .map_err(|err| Error::io(Utf8Path::new(STDOUT), err))?;
+                continue;
+            }
+        };
+        for reply in server.handle(&message) {
+            write_message(output, &reply).map_err(|err| Error::io(Utf8Path::new(STDOUT), err))?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Read the body of the next message, `None` once the editor closed the stream.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            match length {
+                Some(_) => break,
+                None => continue,
+            }
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+pub(crate) fn response(id: Value, result: Value) -> Value {
+    Value::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)])
+}
+
+pub(crate) fn error_response(id: Value, code: i64, message: impl Into<String>) -> Value {
+    let error = Value::object([
+        ("code", Value::Integer(code)),
+        ("message", message.into().into()),
+    ]);
+    Value::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+/// What failed while handling a request: an error code and message.
+type Failure = (i64, String);
+
+#[derive(Default)]
+struct Server {
+    /// The text of the open documents, by URI.
+    documents: HashMap<String, String>,
+    /// Notifications to send along with the reply to the current message.
+    notifications: Vec<Value>,
+    shutdown: bool,
+    exited: bool,
+}
+
+impl Server {
+    /// Handle a request or notification, returning the messages to send back.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message.get("method").and_then(Value::as_str);
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let mut replies = Vec::new();
+        match (message.get("id"), method) {
+            (Some(id), Some(method)) => {
+                let result = if self.shutdown {
+                    Err((INVALID_REQUEST, "the server is shutting down".to_string()))
+                } else {
+                    self.request(method, params)
+                };
+                replies.push(match result {
+                    Ok(result) => response(id.clone(), result),
+                    Err((code, message)) => error_response(id.clone(), code, message),
+                });
+            }
+            (None, Some(method)) => self.notification(method, params),
+            // Replies to requests the server never sends.
+            (Some(_), None) => {}
+            (None, None) => replies.push(error_response(
+                Value::Null,
+                INVALID_REQUEST,
+                "messages need a method",
+            )),
+        }
+        replies.append(&mut self.notifications);
+        replies
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> std::result::Result<Value, Failure> {
+        match method {
+            "initialize" => Ok(Value::object([
+                (
+                    "capabilities",
+                    Value::object([
+                        // Full document sync.
+                        ("textDocumentSync", 1usize.into()),
+                        ("documentFormattingProvider", true.into()),
+                        ("documentRangeFormattingProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Value::object([
+                        ("name", "prettythanks".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/formatting" => self.format(params, None),
+            "textDocument/rangeFormatting" => {
+                let range = params.get("range").ok_or_else(|| missing("range"))?;
+                let position = |name: &str| {
+                    let position = range.get(name)?;
+                    let line = usize::try_from(position.get("line")?.as_integer()?).ok()?;
+                    Some((line, position.get("character")?.as_integer()?))
+                };
+                let (start, _) = position("start").ok_or_else(|| missing("range.start"))?;
+                let (end, character) = position("end").ok_or_else(|| missing("range.end"))?;
+                // A range ending at the start of a line leaves that line out.
+                let end = if character > 0 { end + 1 } else { end };
+                self.format(params, Some(&(start..end)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method {}", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let document = params.get("textDocument");
+        let uri = document
+            .and_then(|document| document.get("uri"))
+            .and_then(Value::as_str);
+        match (method, uri) {
+            ("exit", _) => self.exited = true,
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = document
+                    .and_then(|document| document.get("text"))
+                    .and_then(Value::as_str);
+                if let Some(text) = text {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                // With full sync, the last change holds the whole document.
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(<[Value]>::last)
+                    .and_then(|change| change.get("text"))
+                    .and_then(Value::as_str);
+                if let Some(text) = text {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+            }
+            _ => {}
+        }
+    }
+
+    /// The edits formatting the document, only the ones within `lines` if any.
+    fn format(
+        &mut self,
+        params: &Value,
+        lines: Option<&Range<usize>>,
+    ) -> std::result::Result<Value, Failure> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| missing("textDocument.uri"))?;
+        let source = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("{} is not open", uri)))?;
+        let path = uri_path(uri);
+        let failed = |err: Error| (REQUEST_FAILED, err.to_string());
+        let options = options_for(path.as_deref()).map_err(failed)?;
+        let name = path.clone().unwrap_or_else(|| uri.into());
+        let (formatted, dropped_comments, skipped) =
+            format_with_policy(&name, source, &options).map_err(failed)?;
+        let old = source.split_inclusive('\n').collect::<Vec<_>>();
+        let new = formatted.split_inclusive('\n').collect::<Vec<_>>();
+        let edits = diff::changes(&old, &new)
+            .into_iter()
+            .filter(|change| {
+                lines.map_or(true, |lines| {
+                    change.old.start >= lines.start && change.old.end <= lines.end
+                })
+            })
+            .map(|change| {
+                Value::object([
+                    (
+                        "range",
+                        Value::object([
+                            ("start", position(&old, change.old.start)),
+                            ("end", position(&old, change.old.end)),
+                        ]),
+                    ),
+                    ("newText", new[change.new].concat().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+        if dropped_comments > 0 && options.comment_policy != CommentPolicy::Allow {
+            let message = if skipped {
+                format!(
+                    "not formatting, formatting would drop {}",
+                    comments::count(dropped_comments)
+                )
+            } else {
+                format!("formatting drops {}", comments::count(dropped_comments))
+            };
+            self.show_warning(message);
+        }
+        Ok(Value::Array(edits))
+    }
+
+    fn show_warning(&mut self, message: String) {
+        self.notifications.push(Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "window/showMessage".into()),
+            (
+                "params",
+                Value::object([("type", WARNING.into()), ("message", message.into())]),
+            ),
+        ]));
+    }
+}
+
+fn missing(param: &str) -> Failure {
+    (INVALID_PARAMS, format!("missing parameter {}", param))
+}
+
+/// The position of the start of the line `line` of the document split in `lines`, or of its end
+/// past the last line.
+fn position(lines: &[&str], line: usize) -> Value {
+    let (line, character) = match lines.last() {
+        Some(last) if line >= lines.len() && !last.ends_with('\n') => {
+            // Positions count UTF-16 code units.
+            (lines.len() - 1, last.encode_utf16().count())
+        }
+        _ => (line, 0),
+    };
+    Value::object([("line", line.into()), ("character", character.into())])
+}
+
+/// The path of a `file://` URI, which is where the configuration is looked up from.
+fn uri_path(uri: &str) -> Option<Utf8PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // `file:///C:/...` on Windows.
+    let path = if cfg!(windows) {
+        path.strip_prefix('/').unwrap_or(path)
+    } else {
+        path
+    };
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if byte == b'%' => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok().map(Utf8PathBuf::from)
+}
+
+/// The options set in the configuration file applying to `path`.
+fn options_for(path: Option<&Utf8Path>) -> Result<Options> {
+    let config = match path.and_then(Utf8Path::parent) {
+        Some(dir) => Config::discover(dir)?.unwrap_or_default(),
+        None => Config::default(),
+    };
+    Ok(Options::from_config(&config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+    }
+
+    #[test]
+    fn formats_documents() {
+        let uri = "file:///nowhere/my%20crate/main.rs";
+        let open = r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"URI","languageId":"rust","version":1,"text":"fn  a(){}\nfn b() {}\nfn  c(){}\n"}}}"#;
+        let format = r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/formatting","params":{"textDocument":{"uri":"URI"},"options":{"tabSize":4,"insertSpaces":true}}}"#;
+        let range = r#"{"jsonrpc":"2.0","id":3,"method":"textDocument/rangeFormatting","params":{"textDocument":{"uri":"URI"},"range":{"start":{"line":2,"character":0},"end":{"line":2,"character":4}}}}"#;
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            open,
+            format,
+            range,
+            r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#,
+        ]
+        .iter()
+        .map(|message| frame(&message.replace("URI", uri)))
+        .collect::<String>();
+        let mut output = Vec::new();
+        serve_with(&mut input.as_bytes(), &mut output).unwrap();
+        let mut output = output.as_slice();
+        let mut replies = Vec::new();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(json::parse(&reply).unwrap());
+        }
+        assert_eq!(replies.len(), 4);
+        assert!(replies[0]
+            .to_string()
+            .contains(r#""documentFormattingProvider":true"#));
+        let edit = |start, end, text: &str| {
+            format!(
+                r#"{{"range":{{"start":{{"line":{},"character":0}},"end":{{"line":{},"character":0}}}},"newText":"{}"}}"#,
+                start, end, text
+            )
+        };
+        assert_eq!(
+            replies[1].to_string(),
+            format!(
+                r#"{{"jsonrpc":"2.0","id":2,"result":[{},{}]}}"#,
+                edit(0, 1, r"fn a() {}\n"),
+                edit(2, 3, r"fn c() {}\n")
+            )
+        );
+        assert_eq!(
+            replies[2].to_string(),
+            format!(
+                r#"{{"jsonrpc":"2.0","id":3,"result":[{}]}}"#,
+                edit(2, 3, r"fn c() {}\n")
+            )
+        );
+        assert_eq!(
+            replies[3].to_string(),
+            r#"{"jsonrpc":"2.0","id":4,"result":null}"#
+        );
+        assert_eq!(
+            uri_path(uri).unwrap(),
+            Utf8Path::new("/nowhere/my crate/main.rs")
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Formatting of the bodies of macro invocations, see [`format`].
+
+use crate::tokens;
+use proc_macro2::{Delimiter, Group, LineColumn, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{
+    parse::Parser, punctuated::Punctuated, token::Comma, visit_mut::VisitMut, Block, Expr, Macro,
+    MacroDelimiter,
+};
+
+/// Where a macro invocation is, and what is in it.
+struct Invocation {
+    /// Length of the path of the macro.
+    name: usize,
+    delimiter: Delimiter,
+    open: LineColumn,
+    close: LineColumn,
+    tokens: TokenStream,
+}
+
+/// The invocations of a file, outside of `macro_rules!` definitions.
+#[derive(Default)]
+struct Invocations(Vec<Invocation>);
+
+impl VisitMut for Invocations {
+    fn visit_macro_mut(&mut self, mac: &mut Macro) {
+        if mac.path.is_ident("macro_rules") {
+            return;
+        }
+        let (delimiter, span) = match &mac.delimiter {
+            MacroDelimiter::Paren(paren) => (Delimiter::Parenthesis, paren.span),
+            MacroDelimiter::Brace(brace) => (Delimiter::Brace, brace.span),
+            MacroDelimiter::Bracket(bracket) => (Delimiter::Bracket, bracket.span),
+        };
+        self.0.push(Invocation {
+            name: mac
+                .path
+                .to_token_stream()
+                .to_string()
+                .replace(' ', "")
+                .len(),
+            delimiter,
+            open: span.open().start(),
+            close: span.close().start(),
+            tokens: mac.tokens.clone(),
+        });
+    }
+}
+
+/// Print `items` as a file of their own.
+fn unparse(items: Vec<syn::Item>) -> String {
+    prettyplease::unparse(&syn::File {
+        shebang: None,
+        attrs: Vec::new(),
+        items,
+    })
+}
+
+/// The arguments `args` of a macro called with parentheses or brackets, printed like the ones of
+/// a function call or the elements of an array, from the delimiter opening them on.
+fn print_args(args: &Punctuated<Expr, Comma>, delimiter: Delimiter, name: usize) -> String {
+    // A function name as long as the macro path and its `!`, so lines break in the same places.
+    let callee = syn::Ident::new(&"_".repeat(name + 1), proc_macro2::Span::call_site());
+    let args = args.iter();
+    let expr: Expr = match delimiter {
+        Delimiter::Bracket => syn::parse_quote!([#(#args),*]),
+        _ => syn::parse_quote!(#callee(#(#args),*)),
+    };
+    let printed = unparse(vec![syn::parse_quote!(const _: () = #expr;)]);
+    let printed = printed
+        .strip_prefix("const _: () = ")
+        .and_then(|printed| printed.strip_suffix(";\n"))
+        .unwrap_or(&printed);
+    printed.trim_start_matches('_').to_string()
+}
+
+/// The body of a macro called with braces, printed as items or as the statements of a function,
+/// one per line without indentation.
+fn print_body(tokens: &TokenStream) -> Option<String> {
+    if let Ok(file) = syn::parse2::<syn::File>(tokens.clone()) {
+        return Some(unparse(file.items));
+    }
+    let stmts = Block::parse_within.parse2(tokens.clone()).ok()?;
+    let printed = unparse(vec![syn::parse_quote!(fn f() { #(#stmts)* })]);
+    let lines = printed.lines().collect::<Vec<_>>();
+    let body = lines.get(1..lines.len().saturating_sub(1))?;
+    let mut output = String::new();
+    for line in body {
+        output.push_str(line.strip_prefix("    ").unwrap_or(line));
+        output.push('\n');
+    }
+    Some(output)
+}
+
+/// The delimited body of `invocation`, formatted and indented by `indent`, `None` if it doesn't
+/// parse as expressions, items or statements.
+fn print(invocation: &Invocation, indent: &str) -> Option<String> {
+    let printed = match invocation.delimiter {
+        Delimiter::Brace => {
+            let body = print_body(&invocation.tokens)?;
+            let mut printed = String::from("{\n");
+            for line in body.lines() {
+                if !line.is_empty() {
+                    printed.push_str(indent);
+                    printed.push_str("    ");
+                }
+                printed.push_str(line);
+                printed.push('\n');
+            }
+            printed.push_str(indent);
+            printed.push('}');
+            printed
+        }
+        delimiter => {
+            let args = Punctuated::<Expr, Comma>::parse_terminated
+                .parse2(invocation.tokens.clone())
+                .ok()?;
+            let printed = print_args(&args, delimiter, invocation.name);
+            printed.replace('\n', &format!("\n{}", indent))
+        }
+    };
+    // The tokens are not parsed again once printed, so they must stay the very same.
+    let original = TokenTree::Group(Group::new(invocation.delimiter, invocation.tokens.clone()));
+    let formatted = printed.parse::<TokenStream>().ok()?;
+    tokens::same_stream(original.into(), formatted).then_some(printed)
+}
+
+/// Byte offset of `position` in `source`, which starts at `lines`.
+fn offset(source: &str, lines: &[usize], position: LineColumn) -> Option<usize> {
+    let start = *lines.get(position.line.checked_sub(1)?)?;
+    let line = &source[start..];
+    let column = line
+        .char_indices()
+        .nth(position.column)
+        .map_or(line.len(), |(idx, _)| idx);
+    Some(start + column)
+}
+
+/// Format the arguments of the macro invocations of `source`, printed by prettyplease, that parse
+/// as expressions, and the bodies of the ones called with braces that parse as items or
+/// statements, leaving the others alone.
+///
+/// prettyplease only knows how to format the arguments of the macros of the standard library,
+/// and prints the tokens of the others on as few lines as it can. This also formats the likes of
+/// `assert_matches!(a, b)` or `quote! { fn f() {} }`, but not `macro_rules!` definitions.
+pub fn format(source: &str) -> String {
+    let mut file = match syn::parse_file(source) {
+        Ok(file) => file,
+        Err(_) => return source.to_string(),
+    };
+    let mut invocations = Invocations::default();
+    invocations.visit_file_mut(&mut file);
+    let lines = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect::<Vec<_>>();
+    let mut formatted = source.to_string();
+    // From the last one, so that the offsets of the others stay valid.
+    for invocation in invocations.0.iter().rev() {
+        let (open, close) = match (
+            offset(source, &lines, invocation.open),
+            offset(source, &lines, invocation.close),
+        ) {
+            (Some(open), Some(close)) if open < close => (open, close + 1),
+            _ => continue,
+        };
+        let line = &source[lines[invocation.open.line - 1]..];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        if let Some(printed) = print(invocation, indent) {
+            formatted.replace_range(open..close, &printed);
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_invocations() {
+        let source = crate::format_str(
+            "fn f() { assert_matches!(some_function_with_a_long_name(first_argument), Some(1), \
+             \"the value is {}\", value ); my_quote! { fn g(){ let x=1; } } m!(a => b); }",
+        )
+        .unwrap();
+        assert_eq!(
+            format(&source),
+            "fn f() {\n    assert_matches!(\n        some_function_with_a_long_name(first_argument),\n        \
+             Some(1),\n        \"the value is {}\",\n        value,\n    );\n    my_quote! {\n        fn \
+             g() {\n            let x = 1;\n        }\n    }\n    m!(a => b);\n}\n"
+        );
+        let rules = "macro_rules! m {\n    ($a:expr) => {\n        $a\n    };\n}\n";
+        assert_eq!(format(rules), rules);
+    }
+}
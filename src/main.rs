@@ -1,141 +1,1090 @@
-use camino::{Utf8Path, Utf8PathBuf};
-use std::{env, fs, time::Instant};
+// Positional format arguments are used throughout, keep it consistent.
+#![allow(clippy::uninlined_format_args)]
 
-type BoxError = Box<dyn std::error::Error>;
-type Result<T> = std::result::Result<T, BoxError>;
+use argh::{ArgsInfo, FromArgs};
+use camino::{Utf8Path, Utf8PathBuf};
+use prettythanks::{
+    backup, cache, cargo,
+    comments::CommentPolicy,
+    completions::{self, Shell},
+    config::{self, Config},
+    daemon,
+    edition::Edition,
+    encoding::Encoding,
+    expand, hook, http,
+    ignore::Gitignore,
+    lock,
+    log::{self, Level},
+    lsp, markdown, resume, signal,
+    term::{self, ColorChoice},
+    transform, Emit, Error, ErrorFormat, Fallback, LineEnding, Options, OutputFormat, ParseMode,
+    PrettyThanks, ReadOnlyPolicy, Result, SymlinkPolicy,
+};
+use std::{
+    env, fs,
+    io::{self, Read},
+    ops::RangeInclusive,
+    process,
+    time::Duration,
+};
 
 /// pretty-thanks - a frontend to dtolnay/prettyplease library.
-#[derive(argh::FromArgs)]
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[allow(clippy::struct_excessive_bools)]
 struct Args {
-    /// path to recursively format (default to the current directory).
+    /// paths to recursively format (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+    /// path to recursively format, can be repeated, use `-` for stdin.
     #[argh(option, short = 'p')]
-    path: Option<String>,
-    /// print out information about what is being formatted.
+    path: Vec<String>,
+    /// read the source from stdin and write the formatted result to stdout, a path passed along
+    /// naming it like `--stdin-filename` does.
+    #[argh(switch)]
+    stdin: bool,
+    /// only format the files listed in this file, one per line, `-` reading the list from
+    /// stdin, without walking directories.
+    #[argh(option)]
+    files_from: Option<String>,
+    /// the list of `--files-from` is separated by NUL characters, as printed by `find -print0`
+    /// and `git diff --name-only -z`.
+    #[argh(switch)]
+    null: bool,
+    /// print out information about what is being formatted, `-vv` adds where the time goes for
+    /// every file.
     #[argh(switch, short = 'v')]
-    verbose: bool,
+    verbose: u8,
+    /// only print errors, without warnings or a progress bar.
+    #[argh(switch, short = 'q')]
+    quiet: bool,
+    /// also write every log message to this file as a line of JSON, whatever the verbosity.
+    #[argh(option)]
+    log_file: Option<String>,
+    /// when to color the output: `auto` (default) on terminals unless `NO_COLOR` is set,
+    /// `always` or `never`.
+    #[argh(option)]
+    color: Option<ColorChoice>,
+    /// print a unified diff of the changes instead of rewriting files.
+    #[argh(switch, short = 'd')]
+    diff: bool,
+    /// check if files are formatted without rewriting them, failing otherwise.
+    #[argh(switch, short = 'c')]
+    check: bool,
+    /// format everything without rewriting anything, printing a summary of what would change
+    /// and never failing.
+    #[argh(switch)]
+    dry_run: bool,
+    /// show the diff of every file that is not formatted and ask whether to rewrite it, `all`
+    /// rewriting the others without asking and `quit` leaving them untouched.
+    #[argh(switch, short = 'i')]
+    interactive: bool,
+    /// print statistics once everything is formatted: counts, changed lines, slowest files and
+    /// largest size changes.
+    #[argh(switch)]
+    stats: bool,
+    /// print the time spent reading, parsing, printing and writing the slowest and the largest
+    /// files once everything is formatted, with their sizes.
+    #[argh(switch)]
+    profile: bool,
+    /// fail like `--check` does when files were not formatted, even when rewriting them.
+    #[argh(switch)]
+    error_on_unformatted: bool,
+    /// only print the paths of the files that are not formatted, one per line, without
+    /// rewriting them.
+    #[argh(switch, short = 'l')]
+    list: bool,
+    /// only print the number of files that are not formatted, without rewriting them.
+    #[argh(switch)]
+    count: bool,
+    /// fail when more files than this are not formatted, with `--count`.
+    #[argh(option)]
+    max_unformatted: Option<usize>,
+    /// format the files with this extension while walking directories instead of `rs`, can be
+    /// repeated.
+    #[argh(option)]
+    ext: Vec<String>,
+    /// only format the files matching this gitignore-style pattern, relative to the current
+    /// directory, while walking directories, can be repeated.
+    #[argh(option)]
+    include: Vec<String>,
+    /// skip the paths matching this gitignore-style pattern, relative to the current directory,
+    /// while walking directories, can be repeated.
+    #[argh(option)]
+    exclude_path: Vec<String>,
+    /// do not respect VCS ignore files and format build directories too.
+    #[argh(switch)]
+    no_ignore: bool,
+    /// format files marked as `@generated` or `DO NOT EDIT` too.
+    #[argh(switch)]
+    format_generated: bool,
+    /// only format the files git reports as changed in the working tree or untracked.
+    #[argh(switch)]
+    changed: bool,
+    /// only format the files changed since the merge base with this git revision, implies
+    /// `--changed`.
+    #[argh(option)]
+    since: Option<String>,
+    /// format the staged contents of the files with staged changes, updating both the git index
+    /// and the working tree without losing unstaged changes.
+    #[argh(switch)]
+    staged: bool,
+    /// only format the top level items overlapping the lines `START:END`, counted from 1, of the
+    /// single file passed in, leaving the rest of it untouched; can be repeated.
+    #[argh(option, from_str_fn(line_range))]
+    lines: Vec<RangeInclusive<usize>>,
+    /// only format the item at this path, like `module::Type::method`, of the single file passed
+    /// in, leaving the rest of it untouched.
+    #[argh(option)]
+    item: Option<String>,
+    /// also format the Rust code blocks of the Markdown files found while walking directories,
+    /// Markdown files passed in being always formatted.
+    #[argh(switch)]
+    include_markdown: bool,
+    /// only format the files reachable from the crate roots through `mod` declarations,
+    /// reporting the orphan ones.
+    #[argh(switch)]
+    follow_mods: bool,
+    /// which symlinks are followed while walking directories: `never`, `files` (default) or
+    /// `all`, symlinked directories being walked once each.
+    #[argh(option)]
+    follow_symlinks: Option<SymlinkPolicy>,
+    /// how many levels of subdirectories are walked, 0 formatting only the files directly in the
+    /// directories passed in.
+    #[argh(option)]
+    max_depth: Option<usize>,
+    /// format every file a second time in memory, failing with a diff if the result changes.
+    #[argh(switch)]
+    assert_idempotent: bool,
+    /// format the top level items that parse of files that don't, instead of failing on them.
+    #[argh(switch)]
+    best_effort: bool,
+    /// how to format files that don't parse, for syntax too new for syn: `none` (default) or
+    /// `rustfmt`, running rustfmt on them.
+    #[argh(option)]
+    fallback: Option<Fallback>,
+    /// also format the arguments and bodies of macro invocations that parse as expressions,
+    /// items or statements, leaving the others alone.
+    #[argh(switch)]
+    format_macros: bool,
+    /// sort the `use` declarations into `std`, external crates and current crate groups,
+    /// merging the ones importing from the same crate.
+    #[argh(switch)]
+    sort_imports: bool,
+    /// run this built-in transform on the sources before printing them: `sort-imports`,
+    /// `doc-comments` turning block doc comments into line ones, or `format-doctests`, can be
+    /// repeated.
+    #[argh(option)]
+    transform: Vec<String>,
+    /// format the Rust code blocks of doc comments too, keeping their hidden `#` lines hidden.
+    #[argh(switch)]
+    format_doctests: bool,
+    /// rewrap the text of doc comments to this column, leaving code blocks, headings and tables
+    /// alone, which implies `--transform doc-comments`.
+    #[argh(option)]
+    doc_width: Option<usize>,
+    /// do not draw a progress bar while formatting, even on a terminal.
+    #[argh(switch)]
+    no_progress: bool,
+    /// format every file, instead of skipping the ones recorded as formatted in
+    /// `.prettythanks-cache` at the root of the tree by an earlier run.
+    #[argh(switch)]
+    no_cache: bool,
+    /// format even when another run holds `.prettythanks-lock`, instead of failing.
+    #[argh(switch)]
+    no_lock: bool,
+    /// wait for another run holding `.prettythanks-lock` to finish, instead of failing.
+    #[argh(switch)]
+    wait_lock: bool,
+    /// skip the files formatted by the last run, when it was interrupted before formatting them
+    /// all, as recorded in `.prettythanks-progress`, kept by the runs over 1000 files or with
+    /// `--resume`.
+    #[argh(switch)]
+    resume: bool,
+    /// stop at the first error, instead of reporting it once everything else is formatted.
+    #[argh(switch)]
+    fail_fast: bool,
+    /// report errors once everything else is formatted, overriding `fail-fast` in the
+    /// configuration.
+    #[argh(switch)]
+    keep_going: bool,
+    /// leave the files larger than this many bytes untouched, like huge generated ones.
+    #[argh(option)]
+    max_file_size: Option<u64>,
+    /// leave the files taking longer than this many seconds to format untouched.
+    #[argh(option)]
+    timeout_per_file: Option<u64>,
+    /// copy files to `<file>.bak` before rewriting them, `--backup=SUFFIX` picks another suffix.
+    #[argh(switch)]
+    backup: bool,
+    /// suffix of the backups, implies `--backup`.
+    #[argh(option)]
+    backup_suffix: Option<String>,
+    /// what to do with read-only files: `skip` them with a warning (default), report them as
+    /// `error`s, or `force` rewriting them.
+    #[argh(option)]
+    readonly: Option<ReadOnlyPolicy>,
+    /// write the formatted files to this directory, mirroring the tree of every path formatted,
+    /// instead of rewriting them.
+    #[argh(option)]
+    out_dir: Option<String>,
+    /// keep running and reformat files as they are modified.
+    #[argh(switch, short = 'w')]
+    watch: bool,
+    /// number of files formatted in parallel (default to the number of CPUs).
+    #[argh(option, short = 'j')]
+    jobs: Option<usize>,
+    /// use this configuration file instead of looking for a prettythanks.toml.
+    #[argh(option)]
+    config: Option<String>,
+    /// print the settings the configuration file, `PRETTYTHANKS_*` environment variables and
+    /// command line flags add up to, with where each comes from, as TOML or as JSON with
+    /// `--output-format json`, and exit.
+    #[argh(switch)]
+    print_config: bool,
+    /// what formatting produces: rewritten `files` (default), or leaving the files untouched, a
+    /// `patch` for `git apply` on stdout, the formatted files on `stdout` after their paths, a
+    /// unified `diff` or `none`, only reporting errors.
+    #[argh(option)]
+    emit: Option<Emit>,
+    /// the edition of the sources, 2015, 2018, 2021 or 2024, found in the nearest `Cargo.toml`
+    /// by default.
+    #[argh(option)]
+    edition: Option<Edition>,
+    /// the line endings of formatted files: `auto` (default) keeping the most common ones of
+    /// each file, `lf` or `crlf`.
+    #[argh(option)]
+    line_ending: Option<LineEnding>,
+    /// the encoding of the files: `auto` (default) guessing it, `utf-8`, `utf-16le`, `utf-16be`
+    /// or `latin1`.
+    #[argh(option)]
+    encoding: Option<Encoding>,
+    /// write the files that are not UTF-8 back as UTF-8, instead of in their own encoding.
+    #[argh(switch)]
+    write_utf8: bool,
+    /// how to print the outcome, `human` (default), `json`, `checkstyle`, `github` or `sarif`.
+    #[argh(option)]
+    output_format: Option<OutputFormat>,
+    /// how to print errors, `human` (default) showing the source of parse errors, or `json` one
+    /// object per line on stderr.
+    #[argh(option)]
+    error_format: Option<ErrorFormat>,
+    /// also write a `JUnit` XML report to this file, every file being a test case.
+    #[argh(option)]
+    report_junit: Option<String>,
+    /// what to do with files losing regular comments: `warn` (default), `skip`, `allow` or
+    /// `preserve` to put them back next to their code.
+    #[argh(option)]
+    comment_policy: Option<CommentPolicy>,
+    /// what stdin is made of: a whole `file` (default), an `item`, an `expr`, a list of `stmt`
+    /// or a `block`.
+    #[argh(option)]
+    parse_mode: Option<ParseMode>,
+    /// the path of the file read from stdin, which errors and reports are about, and whose
+    /// configuration and edition are used.
+    #[argh(option)]
+    stdin_filename: Option<String>,
+    /// print stdin back unchanged, with a warning, when it doesn't parse instead of failing.
+    #[argh(switch)]
+    tolerant: bool,
+    /// only format the sources of this package of the cargo workspace, can be repeated.
+    #[argh(option)]
+    package: Vec<String>,
+    /// format the sources of all the packages of the cargo workspace.
+    #[argh(switch)]
+    workspace: bool,
+    /// leave this package of the cargo workspace alone, can be repeated, implies `--workspace`.
+    #[argh(option)]
+    exclude: Vec<String>,
+    #[argh(subcommand)]
+    command: Option<Command>,
 }
 
-struct PrettyThanks {
-    path: Utf8PathBuf,
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand)]
+enum Command {
+    InstallHook(InstallHook),
+    UninstallHook(UninstallHook),
+    Restore(Restore),
+    CleanCache(CleanCache),
+    Lsp(Lsp),
+    Daemon(Daemon),
+    Serve(Serve),
+    Completions(Completions),
+    Tui(Tui),
+    CompareRustfmt(CompareRustfmt),
+    Bench(Bench),
+    Verify(Verify),
+    Expand(Expand),
 }
 
-/// I know, this is ugly, but I want to keep dependencies to the minimum possible.
-static mut VERBOSE: bool = false;
+/// Install a git pre-commit hook checking that the staged Rust files are formatted.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "install-hook")]
+struct InstallHook {
+    /// replace an existing pre-commit hook not installed by prettythanks.
+    #[argh(switch)]
+    force: bool,
+    /// print a `.pre-commit-config.yaml` snippet for the pre-commit framework instead.
+    #[argh(switch)]
+    pre_commit_config: bool,
+}
 
-/// Only print if the `VERBOSE` flag is set.
-macro_rules! vprintln {
-    ($($arg:tt)*) => (
-        if unsafe { VERBOSE } {
-            ::std::println!($($arg)*);
-        }
-    )
+/// Remove the git pre-commit hook installed by `install-hook`.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "uninstall-hook")]
+struct UninstallHook {}
+
+/// Put back the files saved by `--backup`, removing the backups.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "restore")]
+struct Restore {
+    /// files or directories to restore the backups of (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+    /// suffix of the backups, `.bak` by default.
+    #[argh(option)]
+    suffix: Option<String>,
 }
 
-impl PrettyThanks {
-    fn new(path: Option<&str>) -> Result<Self> {
-        let path = match path.as_ref() {
-            Some(path) => path.into(),
-            None => env::current_dir()?.canonicalize()?.try_into()?,
-        };
-        Ok(PrettyThanks { path })
-    }
-
-    fn run(&self) -> Result<()> {
-        let start = Instant::now();
-        if self.path.extension() == Some("rs") && (self.path.is_file() || self.path.is_symlink()) {
-            let (original, formatted) = Self::format_file(&self.path)?;
-            vprintln!(
-                "formatting completed, original size: {} bytes, formatted size: {} bytes, time: {} ms",
-                original,
-                formatted,
-                start.elapsed().as_millis()
-            );
-            Ok(())
-        } else if self.path.is_dir() {
-            let (original, formatted) = Self::format_directory(&self.path)?;
-            vprintln!(
-                "formatting completed, original size: {} bytes, formatted size: {} bytes, time: {} ms",
-                original,
-                formatted,
-                start.elapsed().as_millis()
-            );
-            Ok(())
-        } else {
-            Err(format!("path {} is not a file, symlink or directory", self.path).into())
+/// Remove the formatting cache of the tree the current directory is in.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "clean-cache")]
+struct CleanCache {}
+
+/// Run a language server on stdin and stdout, formatting the documents of an editor.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "lsp")]
+struct Lsp {}
+
+/// Keep running, formatting what JSON-RPC requests on a Unix socket ask for.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "daemon")]
+struct Daemon {
+    /// the socket to listen on, `prettythanks.sock` in `$XDG_RUNTIME_DIR` or the temporary
+    /// directory by default.
+    #[argh(option)]
+    socket: Option<String>,
+}
+
+/// Keep running, formatting the sources posted to `/format` over HTTP.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "serve")]
+struct Serve {
+    /// the address to listen on, `127.0.0.1:8090` by default.
+    #[argh(option)]
+    addr: Option<String>,
+}
+
+/// Review what formatting would change file by file in a full screen terminal interface,
+/// writing the files accepted. Needs the `tui` feature.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "tui")]
+struct Tui {
+    /// paths to review (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+}
+
+/// Format files with both prettyplease and rustfmt, printing the ones they format differently,
+/// without touching them.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "compare-rustfmt")]
+struct CompareRustfmt {
+    /// paths to compare (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+    /// print the diffs from the formatting of rustfmt to the one of prettyplease instead.
+    #[argh(switch)]
+    diff: bool,
+}
+
+/// Format files in memory over and over without writing them, on one thread and then on all of
+/// them, printing how fast both went.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "bench")]
+struct Bench {
+    /// paths to benchmark (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+    /// how many times the files are formatted, 5 by default.
+    #[argh(option)]
+    iterations: Option<usize>,
+}
+
+/// Check that prettyplease prints every file in a way that parses again, keeps its tokens and
+/// doesn't change when printed again, printing the files it misbehaves on with a reproducer.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "verify")]
+struct Verify {
+    /// paths to verify (default to the current directory).
+    #[argh(positional)]
+    paths: Vec<String>,
+}
+
+/// Print the code of a package with its macros expanded by the compiler, pretty-printed.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "expand")]
+struct Expand {
+    /// the package of the cargo workspace to expand, the one of the current directory by
+    /// default.
+    #[argh(option, short = 'p')]
+    package: Option<String>,
+    /// only print this item and the implementations for it, like `module::Type`.
+    #[argh(option)]
+    item: Option<String>,
+    /// write the expansion to this file instead of stdout.
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+}
+
+/// Print a completion script for a shell.
+#[derive(argh::FromArgs, argh::ArgsInfo)]
+#[argh(subcommand, name = "completions")]
+struct Completions {
+    /// the shell to complete for: `bash`, `zsh`, `fish`, `powershell` or `elvish`.
+    #[argh(positional)]
+    shell: Shell,
+}
+
+/// How the value of an option or positional argument is completed.
+fn completion_value(name: &str) -> completions::Value {
+    use completions::Value;
+    match name {
+        "--color" => Value::Choices(&["auto", "always", "never"]),
+        "--emit" => Value::Choices(&["files", "patch", "stdout", "diff", "none"]),
+        "--fallback" => Value::Choices(&["none", "rustfmt"]),
+        "--readonly" => Value::Choices(&["skip", "error", "force"]),
+        "--edition" => Value::Choices(&["2015", "2018", "2021", "2024"]),
+        "--line-ending" => Value::Choices(&["auto", "lf", "crlf"]),
+        "--encoding" => Value::Choices(&["auto", "utf-8", "utf-16le", "utf-16be", "latin1"]),
+        "--output-format" => Value::Choices(&["human", "json", "checkstyle", "github", "sarif"]),
+        "--error-format" => Value::Choices(&["human", "json"]),
+        "--comment-policy" => Value::Choices(&["warn", "skip", "allow", "preserve"]),
+        "--parse-mode" => Value::Choices(&["file", "item", "expr", "stmt", "block"]),
+        "--follow-symlinks" => Value::Choices(&["never", "files", "all"]),
+        "--transform" => Value::Choices(&transform::BUILTIN),
+        "shell" => Value::Choices(&["bash", "zsh", "fish", "powershell", "elvish"]),
+        "paths" | "--path" | "--files-from" | "--log-file" | "--out-dir" | "--config"
+        | "--report-junit" | "--socket" | "--output" => Value::Path,
+        _ => Value::Any,
+    }
+}
+
+/// Merge the command line arguments with the configuration file, the former always wins.
+fn options(args: &Args, config: &Config) -> Result<Options> {
+    let mut options = Options::from_config(config);
+    options.diff |= args.diff;
+    options.check |= args.check;
+    options.dry_run |= args.dry_run;
+    options.interactive |= args.interactive;
+    options.stats |= args.stats;
+    options.profile |= args.profile;
+    options.list |= args.list;
+    options.count |= args.count;
+    options.max_unformatted = args.max_unformatted;
+    options.error_on_unformatted |= args.error_on_unformatted;
+    options.no_ignore |= args.no_ignore;
+    options.format_generated |= args.format_generated;
+    options.follow_mods |= args.follow_mods;
+    options.include_markdown |= args.include_markdown;
+    options.assert_idempotent |= args.assert_idempotent;
+    options.best_effort |= args.best_effort;
+    options.format_macros |= args.format_macros;
+    if let Some(fallback) = args.fallback {
+        options.fallback = fallback;
+    }
+    push_transforms(args, &mut options.transforms)?;
+    options.progress = !args.no_progress && !args.quiet;
+    run_files(args, config, &mut options);
+    options.lines.clone_from(&args.lines);
+    options.item.clone_from(&args.item);
+    options.fail_fast = (options.fail_fast || args.fail_fast) && !args.keep_going;
+    if !args.ext.is_empty() {
+        options.extensions = args
+            .ext
+            .iter()
+            .map(|ext| ext.trim_start_matches('.').to_string())
+            .collect();
+    }
+    if let Some(out_dir) = &args.out_dir {
+        options.out_dir = Some(out_dir.into());
+    }
+    if args.max_file_size.is_some() {
+        options.max_file_size = args.max_file_size;
+    }
+    if let Some(timeout) = args.timeout_per_file {
+        options.timeout_per_file = Some(Duration::from_secs(timeout));
+    }
+    if let Some(suffix) = &args.backup_suffix {
+        options.backup = Some(suffix.clone());
+    } else if args.backup && options.backup.is_none() {
+        options.backup = Some(backup::DEFAULT_SUFFIX.to_string());
+    }
+    if let Some(readonly) = args.readonly {
+        options.readonly = readonly;
+    }
+    if let Some(jobs) = args.jobs {
+        options.jobs = jobs;
+    }
+    if let Some(emit) = args.emit {
+        options.emit = emit;
+    }
+    if let Some(edition) = args.edition {
+        options.edition = Some(edition);
+    }
+    if let Some(line_ending) = args.line_ending {
+        options.line_ending = line_ending;
+    }
+    if let Some(encoding) = args.encoding {
+        options.encoding = encoding;
+    }
+    options.write_utf8 |= args.write_utf8;
+    if let Some(output_format) = args.output_format {
+        options.output_format = output_format;
+    }
+    if let Some(report_junit) = &args.report_junit {
+        options.report_junit = Some(report_junit.into());
+    }
+    if let Some(comment_policy) = args.comment_policy {
+        options.comment_policy = comment_policy;
+    }
+    stdin_options(args, &mut options);
+    if let Some(follow_symlinks) = args.follow_symlinks {
+        options.follow_symlinks = follow_symlinks;
+    }
+    if args.max_depth.is_some() {
+        options.max_depth = args.max_depth;
+    }
+    if !args.include.is_empty() || !args.exclude_path.is_empty() {
+        // Patterns are matched against absolute paths.
+        let cwd: Utf8PathBuf = env::current_dir()
+            .and_then(|cwd| cwd.canonicalize())
+            .map_err(|err| Error::Unsupported(format!("no current directory: {}", err)))?
+            .try_into()?;
+        if !args.include.is_empty() {
+            options.include = Some(Gitignore::parse(&cwd, &args.include.join("\n")));
         }
+        if !args.exclude_path.is_empty() {
+            options
+                .exclude
+                .push(Gitignore::parse(&cwd, &args.exclude_path.join("\n")));
+        }
+    }
+    Ok(options)
+}
+
+/// Set the files kept by the runs of the command line, the cache, the lock and the journal, all on
+/// by default unlike for the library, all at the root of the tree. The journal is only kept by the
+/// runs over many files or with `--resume`.
+fn run_files(args: &Args, config: &Config, options: &mut Options) {
+    // Files only partly formatted with `--lines` or `--item` must not be recorded as formatted.
+    let partly = !args.lines.is_empty() || args.item.is_some();
+    let cache = !args.no_cache && config.cache != Some(false) && !partly;
+    options.cache = cache.then(|| cache::FILE_NAME.into());
+    options.lock = (!args.no_lock).then(|| lock::FILE_NAME.into());
+    options.wait_lock = args.wait_lock;
+    options.journal = Some(resume::FILE_NAME.into());
+    options.resume = args.resume;
+}
+
+/// The name of the buffer read from stdin: `--stdin-filename`, or the path passed along with
+/// `--stdin` as editors do.
+fn stdin_filename(args: &Args) -> Option<&str> {
+    args.stdin_filename
+        .as_deref()
+        .or(match args.paths.as_slice() {
+            [path] if args.stdin && path != "-" => Some(path.as_str()),
+            _ => None,
+        })
+}
+
+/// Overlay the options only applying to stdin.
+fn stdin_options(args: &Args, options: &mut Options) {
+    if let Some(parse_mode) = args.parse_mode {
+        options.parse_mode = parse_mode;
     }
+    options.stdin_filename = stdin_filename(args).map(Utf8PathBuf::from);
+    options.tolerant = args.tolerant;
+}
 
-    fn format_file(path: &Utf8Path) -> Result<(usize, usize)> {
-        let start = Instant::now();
-        let original = fs::read_to_string(path)
-            .map_err(|err| format!("failed to read file {}: {}", path, err))?;
-        let ast = syn::parse_file(&original)
-            .map_err(|err| format!("failed to parse file {}: {}", path, err))?;
-        let formatted = prettyplease::unparse(&ast);
-        vprintln!(
-            "formatting file {}, original size {} bytes, formatted size {} bytes, time: {} ms",
-            path,
-            original.len(),
-            formatted.len(),
-            start.elapsed().as_millis()
-        );
-        fs::write(path, &formatted)
-            .map_err(|err| format!("failed to write file {}: {}", path, err))?;
-        Ok((original.len(), formatted.len()))
-    }
-
-    fn format_directory(path: &Utf8Path) -> Result<(usize, usize)> {
-        let (mut original, mut formatted) = (0usize, 0usize);
-        let mut errors = Vec::new();
-        for entry in path.read_dir_utf8()? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            if entry.path().extension() == Some("rs")
-                && (file_type.is_file() || file_type.is_symlink())
-            {
-                match Self::format_file(entry.path()) {
-                    Ok((o, f)) => {
-                        original += o;
-                        formatted += f;
-                    }
-                    Err(e) => errors.push((entry.path().to_string(), e)),
+/// Push the transforms asked for on the command line to `transforms`, after the ones of the
+/// configuration.
+fn push_transforms(args: &Args, transforms: &mut transform::Pipeline) -> Result<()> {
+    for name in &args.transform {
+        transforms.push_arc(transform::builtin(name).map_err(Error::Unsupported)?);
+    }
+    if args.sort_imports {
+        transforms.push(transform::SortImports);
+    }
+    if args.format_doctests {
+        transforms.push(transform::FormatDoctests);
+    }
+    if let Some(width) = args.doc_width {
+        transforms.push(transform::DocComments { width: Some(width) });
+    }
+    Ok(())
+}
+
+/// The configuration file at `path`, or the one discovered from `start`, with the
+/// `PRETTYTHANKS_*` environment variables applying over it.
+fn load_config(path: Option<&str>, start: &Utf8Path) -> Result<Config> {
+    let mut config = match path {
+        Some(path) => Config::load(Utf8Path::new(path))?,
+        None => Config::discover(start)?.unwrap_or_default(),
+    };
+    let cwd: Utf8PathBuf = env::current_dir()
+        .map_err(|err| Error::Unsupported(format!("no current directory: {}", err)))?
+        .try_into()?;
+    // Variables that aren't UTF-8 can't be ours.
+    let vars = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    config.apply_env(vars, &cwd)?;
+    Ok(config)
+}
+
+/// Run a subcommand in the repository containing the current directory.
+fn run_command(command: &Command) -> Result<()> {
+    let cwd = Utf8Path::new(".");
+    match command {
+        Command::InstallHook(InstallHook {
+            pre_commit_config: true,
+            ..
+        }) => print!("{}", hook::PRE_COMMIT_CONFIG),
+        Command::InstallHook(InstallHook { force, .. }) => {
+            println!("installed {}", hook::install(cwd, *force)?);
+        }
+        Command::UninstallHook(_) => println!("removed {}", hook::uninstall(cwd)?),
+        Command::Lsp(_) => lsp::serve()?,
+        Command::Daemon(Daemon { socket }) => {
+            let socket = socket
+                .as_ref()
+                .map_or_else(daemon::default_socket, Utf8PathBuf::from);
+            let config = load_config(None, cwd)?;
+            daemon::serve(&socket, &Options::from_config(&config))?;
+        }
+        Command::Serve(Serve { addr }) => {
+            let config = load_config(None, cwd)?;
+            http::serve(
+                addr.as_deref().unwrap_or(http::DEFAULT_ADDR),
+                &Options::from_config(&config),
+            )?;
+        }
+        Command::CleanCache(_) => {
+            let root = lock::root(&[cwd.to_path_buf()]).unwrap_or_else(|| cwd.to_path_buf());
+            let path = root.join(cache::FILE_NAME);
+            if cache::clean(&path)? {
+                println!("removed {path}");
+            } else {
+                println!("no {} to remove", cache::FILE_NAME);
+            }
+        }
+        Command::Completions(Completions { shell }) => print!(
+            "{}",
+            completions::generate(
+                *shell,
+                "prettythanks",
+                &Args::get_args_info(),
+                &completion_value
+            )
+        ),
+        // Formats like the command line without a subcommand.
+        Command::Tui(_) | Command::CompareRustfmt(_) | Command::Bench(_) | Command::Verify(_) => {
+            unreachable!("tui, compare-rustfmt, bench and verify are run with the other options")
+        }
+        Command::Expand(Expand {
+            package,
+            item,
+            output,
+        }) => {
+            let expanded = expand::expand(cwd, package.as_deref(), item.as_deref())?;
+            match output {
+                Some(output) => {
+                    fs::write(output, expanded).map_err(|source| Error::Io {
+                        path: output.into(),
+                        source,
+                    })?;
                 }
-            } else if file_type.is_dir() || file_type.is_symlink() {
-                let (o, f) = Self::format_directory(entry.path())?;
-                original += o;
-                formatted += f;
+                None => print!("{}", expanded),
+            }
+        }
+        Command::Restore(Restore { paths, suffix }) => {
+            let paths = if paths.is_empty() {
+                vec![cwd.to_path_buf()]
+            } else {
+                paths.iter().map(Utf8PathBuf::from).collect()
+            };
+            let suffix = suffix.as_deref().unwrap_or(backup::DEFAULT_SUFFIX);
+            for path in backup::restore(&paths, suffix)? {
+                println!("restored {}", path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether running as `cargo prettythanks`, cargo passing the subcommand name as first argument.
+fn is_cargo_subcommand() -> bool {
+    let mut args = env::args();
+    let program = args.next().unwrap_or_default();
+    Utf8Path::new(&program).file_stem() == Some("cargo-prettythanks")
+        && args.next().as_deref() == Some("prettythanks")
+}
+
+/// Parse the range of lines `START:END` of `--lines`.
+fn line_range(value: &str) -> std::result::Result<RangeInclusive<usize>, String> {
+    let invalid = || format!("invalid range of lines `{}`, expected START:END", value);
+    let (start, end) = value.split_once(':').ok_or_else(invalid)?;
+    let start = start.parse::<usize>().map_err(|_| invalid())?;
+    let end = end.parse::<usize>().map_err(|_| invalid())?;
+    if start == 0 || end < start {
+        return Err(format!(
+            "invalid range of lines `{}`, lines are counted from 1 and end after they start",
+            value
+        ));
+    }
+    Ok(start..=end)
+}
+
+/// The paths listed in the file `list`, or stdin for `-`, one per line or separated by NUL
+/// characters with `null`.
+fn files_from(list: &str, null: bool) -> Result<Vec<String>> {
+    let mut contents = String::new();
+    let result = if list == "-" {
+        io::stdin().read_to_string(&mut contents)
+    } else {
+        fs::File::open(list).and_then(|mut file| file.read_to_string(&mut contents))
+    };
+    result.map_err(|source| Error::Io {
+        path: list.into(),
+        source,
+    })?;
+    let paths = if null {
+        contents.split('\0').collect::<Vec<_>>()
+    } else {
+        contents
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect()
+    };
+    Ok(paths
+        .into_iter()
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Pick the log level and logger from the command line, the environment and the configuration.
+fn set_up_logging(args: &Args, config: &Config) -> Result<()> {
+    if args.quiet && args.verbose > 0 {
+        return Err(Error::Unsupported(
+            "--quiet and --verbose can't be used together".to_string(),
+        ));
+    }
+    let level = match args.verbose {
+        _ if args.quiet => Level::Error,
+        0 => match Level::from_env().map_err(Error::Unsupported)? {
+            Some(level) => level,
+            None if config.verbose == Some(true) => Level::Info,
+            None => Level::Warn,
+        },
+        1 => Level::Info,
+        _ => Level::Debug,
+    };
+    if let Some(path) = &args.log_file {
+        let logger = log::FileLogger::create(Utf8Path::new(path), level).map_err(|err| {
+            Error::Unsupported(format!("failed to create log file {}: {}", path, err))
+        })?;
+        log::set_logger(logger);
+        log::set_level(Level::Debug);
+    } else {
+        log::set_level(level);
+    }
+    Ok(())
+}
+
+/// The paths passed in, as arguments, to `tui` or `compare-rustfmt`, with `--stdin` or through
+/// `--files-from`.
+fn paths(args: &Args) -> Result<Vec<String>> {
+    let mut paths = args.path.clone();
+    paths.extend(args.paths.iter().cloned());
+    match &args.command {
+        Some(Command::Tui(tui)) => paths.extend(tui.paths.iter().cloned()),
+        Some(Command::CompareRustfmt(compare)) => paths.extend(compare.paths.iter().cloned()),
+        Some(Command::Bench(bench)) => paths.extend(bench.paths.iter().cloned()),
+        Some(Command::Verify(verify)) => paths.extend(verify.paths.iter().cloned()),
+        _ => {}
+    }
+    // Editors name the buffer they pipe in, and nothing else.
+    if args.stdin && args.paths.len() > 1 {
+        return Err(Error::Unsupported(
+            "--stdin only takes the path of the file read from stdin".to_string(),
+        ));
+    }
+    if args.stdin || (args.stdin_filename.is_some() && paths.is_empty()) {
+        paths = vec!["-".to_string()];
+    }
+    if let Some(list) = &args.files_from {
+        if !paths.is_empty() {
+            return Err(Error::Unsupported(
+                "--files-from can't be used with paths or --stdin".to_string(),
+            ));
+        }
+        paths = files_from(list, args.null)?;
+    } else if args.null {
+        return Err(Error::Unsupported(
+            "--null only applies to --files-from".to_string(),
+        ));
+    }
+    Ok(paths)
+}
+
+/// Fail if options only applying to a single source are used with other `paths`.
+fn check_single_source(args: &Args, paths: &[String]) -> Result<()> {
+    if args
+        .parse_mode
+        .map_or(false, |mode| mode != ParseMode::File)
+        && paths != ["-"]
+    {
+        return Err(Error::Unsupported(
+            "--parse-mode only applies to stdin".to_string(),
+        ));
+    }
+    let single_file = match paths {
+        [path] => !Utf8Path::new(path).is_dir(),
+        _ => false,
+    };
+    if !args.lines.is_empty() && args.item.is_some() {
+        return Err(Error::Unsupported(
+            "--lines and --item can't be used together".to_string(),
+        ));
+    }
+    if (!args.lines.is_empty() || args.item.is_some()) && !single_file {
+        return Err(Error::Unsupported(
+            "--lines and --item only apply to a single file or stdin".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn run(args: &Args, cargo: bool) -> Result<()> {
+    // The subcommands formatting like the command line without one.
+    let (tui, compare, bench) = match &args.command {
+        Some(Command::Tui(_)) => (true, None, None),
+        Some(Command::CompareRustfmt(compare)) => (false, Some(compare.diff), None),
+        Some(Command::Bench(bench)) => (false, None, Some(bench.iterations)),
+        Some(Command::Verify(_)) | None => (false, None, None),
+        Some(command) => return run_command(command),
+    };
+    let verify = matches!(args.command, Some(Command::Verify(_)));
+    let mut paths = paths(args)?;
+    check_single_source(args, &paths)?;
+    // Cargo knows where the sources of every target are, no need to walk the whole tree.
+    let select = !args.package.is_empty() || args.workspace || !args.exclude.is_empty();
+    if select && !paths.is_empty() {
+        return Err(Error::Unsupported(
+            "--package, --workspace and --exclude can't be used with paths".to_string(),
+        ));
+    }
+    if (cargo || select) && paths.is_empty() && args.files_from.is_none() {
+        let cwd: Utf8PathBuf = env::current_dir()
+            .map_err(|err| Error::Unsupported(format!("no current directory: {}", err)))?
+            .try_into()?;
+        let workspace = cargo::Workspace::discover(&cwd)?;
+        let members = workspace.select(&args.package, args.workspace, &args.exclude, &cwd)?;
+        let roots = cargo::source_roots(members.into_iter().flat_map(cargo::Package::source_roots));
+        paths = roots.into_iter().map(String::from).collect();
+    }
+    let start = match (paths.first(), stdin_filename(args)) {
+        (Some(path), _) if path != "-" => Utf8PathBuf::from(path),
+        (Some(_), Some(path)) => Utf8PathBuf::from(path),
+        _ => env::current_dir()
+            .map_err(|err| Error::Unsupported(format!("no current directory: {}", err)))?
+            .try_into()?,
+    };
+    let config = load_config(args.config.as_deref(), &start)?;
+    if args.fail_fast && args.keep_going {
+        return Err(Error::Unsupported(
+            "--fail-fast and --keep-going can't be used together".to_string(),
+        ));
+    }
+    set_up_logging(args, &config)?;
+    term::set_color(args.color.or(config.color).unwrap_or_default());
+    let mut options = options(args, &config)?;
+    if args.print_config {
+        print_config(args, &config, &options)?;
+        return Ok(());
+    }
+    if args.files_from.is_some() {
+        paths.retain(|path| {
+            let file = Utf8Path::new(path);
+            let keep = options.is_rust_file(file) || markdown::is_markdown(file);
+            if !keep {
+                log::log(
+                    Level::Warn,
+                    format_args!("skipping {}, not a Rust file", path),
+                );
             }
+            keep
+        });
+        if paths.is_empty() {
+            // No paths would mean the current directory.
+            return Ok(());
         }
-        if errors.is_empty() {
-            Ok((original, formatted))
+    }
+    if args.changed || args.since.is_some() || args.staged {
+        let dir = if start.is_dir() {
+            start.as_path()
+        } else {
+            start
+                .parent()
+                .filter(|dir| !dir.as_str().is_empty())
+                .unwrap_or(Utf8Path::new("."))
+        };
+        options.only = Some(if args.staged {
+            options.staged = true;
+            prettythanks::git::staged_files(dir)?
+        } else {
+            prettythanks::git::changed_files(dir, args.since.as_deref())?
+        });
+    }
+    let pretty_thanks = PrettyThanks::new(&paths, options)?;
+    if tui {
+        review(&pretty_thanks)
+    } else if let Some(diffs) = compare {
+        pretty_thanks.compare_rustfmt(diffs)
+    } else if verify {
+        pretty_thanks.verify()
+    } else if let Some(iterations) = bench {
+        let iterations = iterations.unwrap_or(prettythanks::bench::DEFAULT_ITERATIONS);
+        pretty_thanks.bench(iterations).map(drop)
+    } else if args.watch {
+        pretty_thanks.watch()
+    } else {
+        // Stop between files, watching and the subcommands keep dying on the first signal.
+        signal::install();
+        pretty_thanks.run()
+    }
+}
+
+/// The review of `tui`, only built with the `tui` feature.
+#[cfg(feature = "tui")]
+fn review(pretty_thanks: &PrettyThanks) -> Result<()> {
+    pretty_thanks.tui()
+}
+
+#[cfg(not(feature = "tui"))]
+fn review(_: &PrettyThanks) -> Result<()> {
+    Err(Error::Unsupported(
+        "tui needs prettythanks built with the `tui` feature".to_string(),
+    ))
+}
+
+/// Print the settings of `options`, built from `args` and `config`, with where they come from.
+fn print_config(args: &Args, config: &Config, options: &Options) -> Result<()> {
+    // The same configuration without any flag tells apart the settings the flags changed.
+    let flagless = Args::from_args(&["prettythanks"], &[])
+        .map_err(|early_exit| Error::Unsupported(early_exit.output))?;
+    let verbose = config.verbose == Some(true);
+    let color = config.color.unwrap_or_default();
+    let mut configured = vec![
+        ("verbose", verbose.into()),
+        ("color", color.to_string().into()),
+    ];
+    configured.extend(self::options(&flagless, config)?.settings());
+    let mut effective = vec![
+        (
+            "verbose",
+            (!args.quiet && (args.verbose > 0 || verbose)).into(),
+        ),
+        ("color", args.color.unwrap_or(color).to_string().into()),
+    ];
+    effective.extend(options.settings());
+    let settings = config.settings(&configured, effective);
+    if options.output_format == OutputFormat::Json {
+        println!("{}", config::settings_json(&settings));
+    } else {
+        print!("{}", config::settings_toml(&settings));
+    }
+    Ok(())
+}
+
+/// Parse the command line like `argh::from_env` does, also accepting `-vv` for `-v -v` and
+/// `--backup=SUFFIX` for `--backup-suffix SUFFIX`.
+fn parse_args(cargo: bool) -> Args {
+    let strings = env::args().collect::<Vec<_>>();
+    // Cargo passes the subcommand name after the binary.
+    let skip = if cargo { 2 } else { 1 };
+    let command = strings.get(skip - 1).map_or("prettythanks", |command| {
+        Utf8Path::new(command).file_name().unwrap_or(command)
+    });
+    let mut args = Vec::new();
+    let mut flags = true;
+    for arg in strings.iter().skip(skip) {
+        flags &= arg != "--";
+        if flags && arg.len() > 2 && arg.starts_with('-') && arg[1..].bytes().all(|b| b == b'v') {
+            args.extend(arg[1..].chars().map(|_| "-v"));
+        } else if let Some(suffix) = arg.strip_prefix("--backup=").filter(|_| flags) {
+            args.extend(["--backup-suffix", suffix]);
         } else {
-            Err(errors
-                .into_iter()
-                .map(|entry| format!("error: {}: {}", entry.0, entry.1))
-                .collect::<Vec<String>>()
-                .join("\n")
-                .into())
+            args.push(arg);
         }
     }
+    Args::from_args(&[command], &args).unwrap_or_else(|early_exit| {
+        if early_exit.status.is_ok() {
+            println!("{}", early_exit.output);
+            process::exit(0);
+        }
+        eprintln!(
+            "{}\nRun {} --help for more information.",
+            early_exit.output, command
+        );
+        // 1 means unformatted files, usage errors prevent the run like any other.
+        process::exit(3)
+    })
 }
 
-fn main() -> Result<()> {
-    let args: Args = argh::from_env();
-    unsafe { VERBOSE = args.verbose };
-    let pretty_thanks = PrettyThanks::new(args.path.as_deref())?;
-    pretty_thanks.run()
+pub fn main() {
+    let cargo = is_cargo_subcommand();
+    let args = parse_args(cargo);
+    // Errors loading the configuration are printed before it can pick the colors.
+    term::set_color(args.color.unwrap_or_default());
+    let error = || term::paint(term::Stream::Stderr, term::Color::Red, "error");
+    if let Err(err) = run(&args, cargo) {
+        let code = err.exit_code();
+        let errors = match err {
+            Error::Multiple(errors) => errors,
+            err => vec![err],
+        };
+        for err in errors {
+            match args.error_format.unwrap_or_default() {
+                ErrorFormat::Human => {
+                    eprintln!("{}: {}", error(), err);
+                    if let Some(snippet) = err.snippet() {
+                        eprint!("{}", snippet);
+                    }
+                }
+                ErrorFormat::Json => eprintln!("{}", err.to_json()),
+            }
+        }
+        process::exit(code);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env::temp_dir;
 
     #[test]
-    fn can_format() {
-        let temp_file = temp_dir().join("prettythanks.rs");
-        fs::copy("fixtures/input.rs", &temp_file).unwrap();
-        let thanks = PrettyThanks::new(temp_file.to_str()).unwrap();
-        assert!(thanks.run().is_ok());
+    fn names_stdin_buffers() {
+        let args = |args: &[&str]| Args::from_args(&["prettythanks"], args).unwrap();
+        let named = args(&["--stdin", "src/e.rs"]);
+        assert_eq!(stdin_filename(&named), Some("src/e.rs"));
+        assert_eq!(paths(&named).unwrap(), ["-"]);
+        let flag = args(&["--stdin-filename", "src/f.rs"]);
+        assert_eq!(stdin_filename(&flag), Some("src/f.rs"));
+        assert_eq!(paths(&flag).unwrap(), ["-"]);
+        assert_eq!(stdin_filename(&args(&["--stdin"])), None);
+        assert_eq!(stdin_filename(&args(&["src/e.rs"])), None);
+        assert!(paths(&args(&["--stdin", "a.rs", "b.rs"])).is_err());
     }
 }
@@ -0,0 +1,42 @@
+//! Formatting of the Rust code blocks of Markdown files, such as READMEs, see [`format`].
+
+use crate::doctests;
+use camino::Utf8Path;
+
+/// Whether `path` is a Markdown file, by its extension.
+pub fn is_markdown(path: &Utf8Path) -> bool {
+    matches!(path.extension(), Some("md" | "markdown"))
+}
+
+/// Format the code blocks marked `rust` of the Markdown document `source`, leaving everything
+/// else, the prose and the other code blocks, untouched.
+///
+/// Code blocks are formatted like the ones of doc comments, see [`doctests::format`], but the ones
+/// without an info string are not taken to be Rust ones.
+pub fn format(source: &str) -> String {
+    let lines = source.split('\n').collect::<Vec<_>>();
+    match doctests::format_blocks(&lines, false) {
+        Some(lines) => lines.join("\n"),
+        None => source.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_rust_blocks() {
+        let source = "# Title\n\nSome  *prose*.\n\n```rust\nfn main(){println!(\"hi\")}\n```\n\n\
+                      ```\nfn  bare(){}\n```\n\n````markdown\n```rust\nfn  nested(){}\n```\n\
+                      ````\n\n- item\n\n  ```rust,no_run\n  let  x=1;\n  ```\n";
+        assert_eq!(
+            format(source),
+            "# Title\n\nSome  *prose*.\n\n```rust\nfn main() {\n    println!(\"hi\")\n}\n```\n\n\
+             ```\nfn  bare(){}\n```\n\n````markdown\n```rust\nfn  nested(){}\n```\n````\n\n- \
+             item\n\n  ```rust,no_run\n  let x = 1;\n  ```\n"
+        );
+        assert!(is_markdown(Utf8Path::new("README.md")));
+        assert!(!is_markdown(Utf8Path::new("md.rs")));
+    }
+}
@@ -0,0 +1,170 @@
+//! Metadata of the rewritten files, carried over from the originals to the temporary files
+//! renamed over them, see [`copy`].
+
+use std::{fs::File, io};
+
+/// Give `to` the permissions of `from` and, as far as the process is allowed to, their owner,
+/// group and, on Linux, extended attributes.
+pub(crate) fn copy(from: &File, to: &File) -> io::Result<()> {
+    let metadata = from.metadata()?;
+    to.set_permissions(metadata.permissions())?;
+    #[cfg(unix)]
+    unix::copy_owner(&metadata, to);
+    #[cfg(target_os = "linux")]
+    linux::copy_xattrs(from, to)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs::{File, Metadata},
+        os::unix::{fs::MetadataExt, io::AsRawFd},
+    };
+
+    extern "C" {
+        fn fchown(fd: i32, owner: u32, group: u32) -> i32;
+    }
+
+    /// Leaves the owner or group unchanged.
+    const UNCHANGED: u32 = u32::MAX;
+
+    /// Give `to` the owner and group of `metadata`, or only its group, or neither, the new file
+    /// being owned by the user running prettythanks otherwise.
+    pub(super) fn copy_owner(metadata: &Metadata, to: &File) {
+        let fd = to.as_raw_fd();
+        let current = match to.metadata() {
+            Ok(current) => current,
+            Err(_) => return,
+        };
+        if (current.uid(), current.gid()) == (metadata.uid(), metadata.gid()) {
+            return;
+        }
+        // SAFETY: `fd` is an open file descriptor for as long as `to` is borrowed.
+        unsafe {
+            // Only root can give files away, other users can still pick one of their groups.
+            if fchown(fd, metadata.uid(), metadata.gid()) != 0 {
+                fchown(fd, UNCHANGED, metadata.gid());
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::{
+        fs::File,
+        io,
+        os::{raw::c_char, unix::io::AsRawFd},
+    };
+
+    extern "C" {
+        fn flistxattr(fd: i32, list: *mut c_char, size: usize) -> isize;
+        pub(super) fn fgetxattr(fd: i32, name: *const c_char, value: *mut u8, size: usize)
+            -> isize;
+        pub(super) fn fsetxattr(
+            fd: i32,
+            name: *const c_char,
+            value: *const u8,
+            size: usize,
+            flags: i32,
+        ) -> i32;
+    }
+
+    /// Errors of file systems without extended attributes.
+    const ENOTSUP: i32 = 95;
+    /// Errors of buffers too small for the value read.
+    const ERANGE: i32 = 34;
+
+    /// The bytes filled by `read` into a buffer of the size it needs, retrying if it grows
+    /// between calls.
+    fn read_all(read: impl Fn(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let size = read(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buffer = vec![0; size.unsigned_abs()];
+            let read = read(buffer.as_mut_ptr(), buffer.len());
+            if read >= 0 {
+                buffer.truncate(read.unsigned_abs());
+                return Ok(buffer);
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(ERANGE) {
+                return Err(err);
+            }
+        }
+    }
+
+    /// Set the extended attributes of `from` on `to`. The ones the process is not allowed to
+    /// set, like `security.*` ones of unprivileged users, are left out.
+    pub(super) fn copy_xattrs(from: &File, to: &File) -> io::Result<()> {
+        let (from, to) = (from.as_raw_fd(), to.as_raw_fd());
+        // SAFETY: `read_all` passes buffers of the size given, and the names are NUL terminated
+        // by `flistxattr`.
+        unsafe {
+            let names = match read_all(|list, size| flistxattr(from, list.cast(), size)) {
+                Ok(names) => names,
+                Err(err) if err.raw_os_error() == Some(ENOTSUP) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            for name in names.split_inclusive(|byte| *byte == 0) {
+                let name = name.as_ptr().cast::<c_char>();
+                let value = read_all(|value, size| fgetxattr(from, name, value, size))?;
+                fsetxattr(to, name, value.as_ptr(), value.len(), 0);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use std::fs;
+
+    #[cfg(unix)]
+    #[test]
+    fn copies_executable_bits() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = TempDir::new("executable");
+        let (from, to) = (temp.join("from"), temp.join("to"));
+        fs::write(&from, "#!/usr/bin/env run-cargo-script\nfn main() {}\n").unwrap();
+        fs::write(&to, "").unwrap();
+        fs::set_permissions(&from, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(&to, fs::Permissions::from_mode(0o600)).unwrap();
+        copy(&File::open(&from).unwrap(), &File::open(&to).unwrap()).unwrap();
+        assert_eq!(
+            fs::metadata(&to).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn copies_extended_attributes() {
+        use std::os::unix::io::AsRawFd;
+
+        let temp = TempDir::new("xattr");
+        let (from, to) = (temp.join("from"), temp.join("to"));
+        fs::write(&from, "fn main() {}\n").unwrap();
+        fs::write(&to, "").unwrap();
+        let from = File::open(&from).unwrap();
+        let name = b"user.prettythanks\0".as_ptr().cast();
+        // SAFETY: the name is NUL terminated and the value 3 bytes long.
+        let set = unsafe { linux::fsetxattr(from.as_raw_fd(), name, b"yes".as_ptr(), 3, 0) };
+        // Only where the temporary directory has extended attributes.
+        if set != 0 {
+            return;
+        }
+        let to = File::open(&to).unwrap();
+        copy(&from, &to).unwrap();
+        let mut value = [0; 3];
+        // SAFETY: the buffer is 3 bytes long.
+        let read = unsafe { linux::fgetxattr(to.as_raw_fd(), name, value.as_mut_ptr(), 3) };
+        assert_eq!((read, &value), (3, b"yes"));
+    }
+}
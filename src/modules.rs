@@ -0,0 +1,198 @@
+//! Discovery of the files of a crate by following its `mod` declarations from the crate roots.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{collections::BTreeSet, fs};
+use syn::{Attribute, Expr, ExprLit, Item, Lit, Meta};
+
+/// A module living in its own file.
+struct Module {
+    path: Utf8PathBuf,
+    /// Whether its submodules live next to it, as for crate roots and `mod.rs` files, instead of
+    /// in a directory named after it.
+    mod_rs: bool,
+}
+
+/// `*.rs` files in `dir` and `*/main.rs` files one level below, each of them a crate root.
+fn target_roots(dir: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let mut roots = Vec::new();
+    if let Ok(entries) = dir.read_dir_utf8() {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                let main = path.join("main.rs");
+                if main.is_file() {
+                    roots.push(main);
+                }
+            } else if path.extension() == Some("rs") {
+                roots.push(path.to_path_buf());
+            }
+        }
+    }
+    roots.sort();
+    roots
+}
+
+/// Crate roots found in `dir` following the cargo conventions, `dir` being either a package or
+/// one of its `src`, `tests`, `examples` or `benches` directories.
+pub fn crate_roots(dir: &Utf8Path) -> Vec<Utf8PathBuf> {
+    let existing = |paths: &[&str]| {
+        paths
+            .iter()
+            .map(|path| dir.join(path))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>()
+    };
+    if dir.join("Cargo.toml").is_file() {
+        let mut roots = existing(&["build.rs", "src/lib.rs", "src/main.rs"]);
+        for targets in ["src/bin", "tests", "examples", "benches"] {
+            roots.extend(target_roots(&dir.join(targets)));
+        }
+        return roots;
+    }
+    let mut roots = existing(&["lib.rs", "main.rs"]);
+    if roots.is_empty() {
+        return target_roots(dir);
+    }
+    roots.extend(target_roots(&dir.join("bin")));
+    roots
+}
+
+/// The value of a `#[path = "..."]` attribute.
+fn path_attr(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(meta) if meta.path.is_ident("path") => match &meta.value {
+            Expr::Lit(ExprLit {
+                lit: Lit::Str(path),
+                ..
+            }) => Some(path.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Collect the out of line modules declared in `items`, `dir` being the directory of their file
+/// and `base` the one their submodules live in.
+fn collect(items: &[Item], dir: &Utf8Path, base: &Utf8Path, inline: bool, out: &mut Vec<Module>) {
+    for item in items {
+        let module = match item {
+            Item::Mod(module) => module,
+            _ => continue,
+        };
+        let name = module.ident.to_string();
+        let name = name.trim_start_matches("r#");
+        let path = path_attr(&module.attrs);
+        match (&module.content, path) {
+            (Some((_, items)), path) => {
+                let base = base.join(path.as_deref().unwrap_or(name));
+                collect(items, dir, &base, true, out);
+            }
+            // Paths are relative to the file, unless inside an inline module.
+            (None, Some(path)) => out.push(Module {
+                path: if inline {
+                    base.join(path)
+                } else {
+                    dir.join(path)
+                },
+                mod_rs: true,
+            }),
+            (None, None) => {
+                let file = base.join(format!("{}.rs", name));
+                let nested = base.join(name).join("mod.rs");
+                if file.is_file() {
+                    out.push(Module {
+                        path: file,
+                        mod_rs: false,
+                    });
+                } else if nested.is_file() {
+                    out.push(Module {
+                        path: nested,
+                        mod_rs: true,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Canonical paths of the crate `roots` and of all the files reachable from them through `mod`
+/// declarations, including `#[path]` attributes.
+///
+/// Files that can't be read or parsed are listed, but their modules can't be followed.
+pub fn reachable(roots: &[Utf8PathBuf]) -> BTreeSet<Utf8PathBuf> {
+    let mut seen = BTreeSet::new();
+    let mut queue = roots
+        .iter()
+        .map(|path| Module {
+            path: path.clone(),
+            mod_rs: true,
+        })
+        .collect::<Vec<_>>();
+    while let Some(module) = queue.pop() {
+        let path = match module.path.canonicalize_utf8() {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let file = match fs::read_to_string(&path)
+            .ok()
+            .and_then(|source| syn::parse_file(&source).ok())
+        {
+            Some(file) => file,
+            None => continue,
+        };
+        let dir = path.parent().unwrap_or(Utf8Path::new("."));
+        let base = if module.mod_rs {
+            dir.to_path_buf()
+        } else {
+            dir.join(path.file_stem().unwrap_or_default())
+        };
+        collect(&file.items, dir, &base, false, &mut queue);
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn follows_mod_declarations() {
+        let temp = TempDir::new("modules");
+        for dir in ["src/a", "src/b", "src/d", "src/extra", "tests"] {
+            fs::create_dir_all(temp.join(dir)).unwrap();
+        }
+        let files = [
+            ("Cargo.toml", ""),
+            (
+                "src/lib.rs",
+                "mod a;\nmod b;\n#[path = \"extra/c.rs\"]\nmod c;\nmod d { mod e; }\n",
+            ),
+            ("src/a.rs", "mod f;\n"),
+            ("src/a/f.rs", ""),
+            ("src/b/mod.rs", "mod g;\n"),
+            ("src/b/g.rs", ""),
+            ("src/extra/c.rs", "mod h;\n"),
+            ("src/extra/h.rs", ""),
+            ("src/d/e.rs", ""),
+            ("src/orphan.rs", ""),
+            ("tests/it.rs", ""),
+        ];
+        for (path, source) in files {
+            fs::write(temp.join(path), source).unwrap();
+        }
+        let root = temp.canonicalize_utf8().unwrap();
+        let reachable = reachable(&crate_roots(&temp));
+        let mut expected = files
+            .iter()
+            .filter(|(path, _)| path.ends_with(".rs") && *path != "src/orphan.rs")
+            .map(|(path, _)| root.join(path))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(reachable.into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(crate_roots(&temp.join("tests")), [temp.join("tests/it.rs")]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Futures formatting files and trees from a tokio runtime without blocking its worker threads,
+//! with the `async` feature.
+//!
+//! Files are read, formatted and written on the blocking pool of tokio, where `tokio::fs` does
+//! its I/O too, in one go rather than hopping between the pools at every step. At most
+//! [`Options::jobs`] files of a tree are formatted at once.
+//!
+//! ```no_run
+//! # async fn run() {
+//! use camino::Utf8Path;
+//! use prettythanks::{nonblocking, Options};
+//!
+//! let report = nonblocking::format_tree_async(Utf8Path::new("src"), &Options::default()).await;
+//! for err in report.errors() {
+//!     eprintln!("{}", err);
+//! }
+//! # }
+//! ```
+
+use crate::{format_file, Entry, FileReport, Options, Outcome, PrettyThanks, Report, Result, Task};
+use camino::Utf8Path;
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tokio::{
+    sync::Semaphore,
+    task::{self, JoinHandle},
+};
+
+/// The value of the blocking task `handle`, resuming its panic if it panicked.
+async fn joined<T>(handle: JoinHandle<T>) -> T {
+    match handle.await {
+        Ok(value) => value,
+        // Blocking tasks are never cancelled.
+        Err(err) => panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Format the file `path` in place like [`format_file`](crate::format_file).
+pub async fn format_file_async(path: &Utf8Path, options: &Options) -> Result<Outcome> {
+    let (path, options) = (path.to_path_buf(), options.clone());
+    joined(task::spawn_blocking(move || format_file(&path, &options))).await
+}
+
+/// Recursively format a file or a directory like [`format_tree`](crate::format_tree), with the
+/// entries in the same order.
+pub async fn format_tree_async(path: &Utf8Path, options: &Options) -> Report {
+    let start = Instant::now();
+    let thanks = Arc::new(PrettyThanks {
+        paths: vec![path.to_path_buf()],
+        options: options.clone(),
+        reporter: None,
+        cancel: None,
+    });
+    let walker = Arc::clone(&thanks);
+    let (tasks, cache, lock) = joined(task::spawn_blocking(move || {
+        let tasks = walker.collect();
+        let (tasks, lock) = match walker.tasks_lock(&tasks) {
+            Ok(lock) => (tasks, lock),
+            Err(err) => (vec![Task::Error(err)], None),
+        };
+        let files = tasks
+            .iter()
+            .filter(|task| matches!(task, Task::File(..)))
+            .count();
+        let cache = walker.cache(files).map(Arc::new);
+        (tasks, cache, lock)
+    }))
+    .await;
+    let permits = Arc::new(Semaphore::new(options.jobs.max(1)));
+    // Set by the first error with `fail_fast`, no other file is started then.
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut pending = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let (path, nested) = match task {
+            Task::File(path, nested) => (path, nested),
+            Task::Stdin => {
+                let thanks = Arc::clone(&thanks);
+                pending.push(Ok(task::spawn_blocking(move || Some(thanks.stdin_entry()))));
+                continue;
+            }
+            task => {
+                pending.push(Err(task.into_entry()));
+                continue;
+            }
+        };
+        let permit = Arc::clone(&permits)
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let (thanks, cache, stop) = (Arc::clone(&thanks), cache.clone(), Arc::clone(&stop));
+        pending.push(Ok(task::spawn_blocking(move || {
+            let _permit = permit;
+            if stop.load(Ordering::Relaxed) {
+                return None;
+            }
+            let options = nested.as_deref().unwrap_or(&thanks.options);
+            let result = thanks.format_one(&path, options, cache.as_deref());
+            if result.is_err() && thanks.options.fail_fast {
+                stop.store(true, Ordering::Relaxed);
+            }
+            Some(Entry::File(FileReport { path, result }))
+        })));
+    }
+    let mut entries = Vec::with_capacity(pending.len());
+    for entry in pending {
+        let entry = match entry {
+            Ok(handle) => match joined(handle).await {
+                Some(entry) => entry,
+                None => break,
+            },
+            Err(entry) => entry,
+        };
+        let failed = matches!(&entry, Entry::File(FileReport { result: Err(_), .. }));
+        entries.push(entry);
+        // The files after the first error are left out, like they are by `format_tree`.
+        if failed && options.fail_fast {
+            break;
+        }
+    }
+    // Released once the cache is saved, every file being written then.
+    let saved = joined(task::spawn_blocking(move || {
+        let saved = cache.map_or(Ok(()), |cache| cache.save());
+        drop(lock);
+        saved
+    }));
+    if let Err(err) = saved.await {
+        warn!("{}", err);
+    }
+    Report {
+        entries,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use std::fs;
+
+    #[test]
+    fn formats_without_blocking() {
+        let temp = TempDir::new("async");
+        for file in ["a.rs", "b.rs", "c.rs"] {
+            fs::write(temp.join(file), "fn main(){}").unwrap();
+        }
+        fs::write(temp.join("d.rs"), "fn main(").unwrap();
+        let root: &Utf8Path = &temp;
+        let options = Options {
+            jobs: 2,
+            ..Options::default()
+        };
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let report = runtime.block_on(format_tree_async(root, &options));
+        let names = report
+            .files()
+            .map(|file| file.path.file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.rs", "b.rs", "c.rs", "d.rs"]);
+        assert_eq!(report.changed().count(), 3);
+        assert_eq!(report.errors().count(), 1);
+        assert_eq!(
+            fs::read_to_string(temp.join("b.rs")).unwrap(),
+            "fn main() {}"
+        );
+        let outcome = runtime
+            .block_on(format_file_async(&root.join("a.rs"), &options))
+            .unwrap();
+        assert!(!outcome.changed);
+    }
+}
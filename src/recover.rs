@@ -0,0 +1,196 @@
+//! Best effort formatting of files that don't parse, one top level item at a time.
+//!
+//! The items are found by a lexer that doesn't need the source to be valid Rust: an item ends
+//! with a `;` or a `}` outside of any delimiter, unless it has a `=` before, like constants.
+//! Unbalanced delimiters end at the next line starting with an item keyword, so that a broken
+//! item doesn't swallow the rest of the file. Everything between items, whitespace and regular
+//! comments, is kept as is.
+
+use crate::comments::{self, Kind};
+use std::ops::{Range, RangeInclusive};
+
+/// The tokens starting an item, found on their own at the start of a line.
+const ITEM_STARTS: &[&str] = &[
+    "#",
+    "const",
+    "enum",
+    "extern",
+    "fn",
+    "impl",
+    "macro_rules",
+    "mod",
+    "pub",
+    "static",
+    "struct",
+    "trait",
+    "type",
+    "union",
+    "unsafe",
+    "use",
+];
+
+/// The byte ranges of the top level items of `source`, doc comments and attributes included.
+fn items(source: &str) -> Vec<Range<usize>> {
+    let mut items = Vec::new();
+    let mut start = None;
+    let mut depth = 0usize;
+    let mut assignment = false;
+    let mut last_end = 0;
+    for lexeme in comments::lex(source) {
+        if lexeme.kind == Kind::Comment {
+            continue;
+        }
+        let line_start = lexeme.offset == 0 || source[..lexeme.offset].ends_with('\n');
+        if let Some(item_start) = start.filter(|_| depth > 0) {
+            if line_start && ITEM_STARTS.contains(&lexeme.text) {
+                items.push(item_start..last_end);
+                start = None;
+                depth = 0;
+                assignment = false;
+            }
+        }
+        last_end = lexeme.end();
+        let item_start = *start.get_or_insert(lexeme.offset);
+        match lexeme.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            "=" if depth == 0 => assignment = true,
+            _ => {}
+        }
+        let end = depth == 0
+            && match lexeme.text {
+                ";" => true,
+                "}" => !assignment,
+                _ => false,
+            };
+        if end {
+            items.push(item_start..lexeme.end());
+            start = None;
+            assignment = false;
+        }
+    }
+    if let Some(start) = start {
+        items.push(start..source.trim_end().len());
+    }
+    items
+}
+
+/// Format the items of `source` that `format` succeeds on, leaving the others untouched.
+///
+/// Returns the formatted source and the number of dropped comments, `None` if no item could be
+/// formatted.
+pub(crate) fn format<E>(
+    source: &str,
+    mut format: impl FnMut(&str) -> Result<(String, usize, bool), E>,
+) -> Option<(String, usize)> {
+    let mut out = String::with_capacity(source.len());
+    let mut formatted_any = false;
+    let mut dropped = 0;
+    let mut last = 0;
+    for item in items(source) {
+        out.push_str(&source[last..item.start]);
+        let text = &source[item.clone()];
+        match format(text) {
+            Ok((formatted, item_dropped, false)) => {
+                out.push_str(formatted.trim_end_matches('\n'));
+                dropped += item_dropped;
+                formatted_any = true;
+            }
+            _ => out.push_str(text),
+        }
+        last = item.end;
+    }
+    out.push_str(&source[last..]);
+    formatted_any.then_some((out, dropped))
+}
+
+/// Format the items of `source` overlapping one of the ranges of lines `lines`, counted from 1,
+/// with `format`, leaving the rest of it untouched.
+///
+/// Returns the formatted source and the number of dropped comments.
+pub(crate) fn format_lines<E>(
+    source: &str,
+    lines: &[RangeInclusive<usize>],
+    mut format: impl FnMut(&str) -> Result<(String, usize, bool), E>,
+) -> Result<(String, usize), E> {
+    let mut out = String::with_capacity(source.len());
+    let mut dropped = 0;
+    let mut last = 0;
+    let mut line = 1;
+    for item in items(source) {
+        line += source[last..item.start].matches('\n').count();
+        let text = &source[item.clone()];
+        let item_lines = line..=line + text.matches('\n').count();
+        let selected = lines
+            .iter()
+            .any(|lines| lines.start() <= item_lines.end() && item_lines.start() <= lines.end());
+        out.push_str(&source[last..item.start]);
+        if selected {
+            let (formatted, item_dropped, skipped) = format(text)?;
+            out.push_str(if skipped {
+                text
+            } else {
+                formatted.trim_end_matches('\n')
+            });
+            dropped += item_dropped;
+        } else {
+            out.push_str(text);
+        }
+        line = *item_lines.end();
+        last = item.end;
+    }
+    out.push_str(&source[last..]);
+    Ok((out, dropped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_items() {
+        let source = "#[derive(Debug)]\nstruct A { a: u8 }\n// comment\nconst B: A = A { a: 1 };\n\
+                      fn c( {\nfn d() {}\n";
+        let items = items(source)
+            .into_iter()
+            .map(|item| &source[item])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            [
+                "#[derive(Debug)]\nstruct A { a: u8 }",
+                "const B: A = A { a: 1 };",
+                "fn c( {",
+                "fn d() {}",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_the_items_that_parse() {
+        let source = "fn  a(){}\n\n// broken\nfn b( {}\nstruct  C;\n";
+        let (formatted, _) = format(source, |item| {
+            crate::format_str(item).map(|formatted| (formatted, 0, false))
+        })
+        .unwrap();
+        assert_eq!(formatted, "fn a() {}\n\n// broken\nfn b( {}\nstruct C;\n");
+    }
+
+    #[test]
+    fn formats_the_selected_lines() {
+        let source = "fn  a(){}\nfn  b(){\n}\n\nstruct  C;\n";
+        let format = |lines: &[RangeInclusive<usize>]| {
+            format_lines(source, lines, |item| {
+                crate::format_str(item).map(|formatted| (formatted, 0, false))
+            })
+            .unwrap()
+            .0
+        };
+        assert_eq!(format(&[3..=3]), "fn  a(){}\nfn b() {}\n\nstruct  C;\n");
+        assert_eq!(
+            format(&[1..=1, 5..=9]),
+            "fn a() {}\nfn  b(){\n}\n\nstruct C;\n"
+        );
+        assert_eq!(format(&[4..=4]), source);
+    }
+}
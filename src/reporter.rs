@@ -0,0 +1,165 @@
+//! Callbacks following a run file by file, for the GUIs and build systems embedding prettythanks
+//! to show their own progress rather than parsing what it prints, see
+//! [`PrettyThanks::with_reporter`](crate::PrettyThanks::with_reporter):
+//!
+//! ```
+//! use camino::Utf8Path;
+//! use prettythanks::{reporter::Reporter, Options, Outcome, PrettyThanks, Report};
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//!
+//! #[derive(Default)]
+//! struct Counter(AtomicUsize);
+//!
+//! impl Reporter for Counter {
+//!     fn file_formatted(&self, _: &Utf8Path, _: &Outcome) {
+//!         self.0.fetch_add(1, Ordering::Relaxed);
+//!     }
+//!
+//!     fn run_finished(&self, report: &Report) {
+//!         println!("{} of {} files formatted", self.0.load(Ordering::Relaxed), report.files().count());
+//!     }
+//! }
+//!
+//! let options = Options {
+//!     check: true,
+//!     ..Options::default()
+//! };
+//! let report = PrettyThanks::new(&["fixtures/input.rs".to_string()], options)?
+//!     .with_reporter(Counter::default())
+//!     .report();
+//! # Ok::<(), prettythanks::Error>(())
+//! ```
+//!
+//! Files are formatted on several threads, so the callbacks about them can be called from any of
+//! them, in any order.
+
+use crate::{Error, Outcome, Report};
+use camino::Utf8Path;
+use std::sync::Arc;
+
+/// What is told about a run as it goes, every callback doing nothing by default.
+pub trait Reporter: Send + Sync {
+    /// Formatting `path` starts, `-` being stdin.
+    fn file_started(&self, _path: &Utf8Path) {}
+
+    /// `path` was formatted, whether that changed it or not, maybe leaving parts of it untouched.
+    fn file_formatted(&self, _path: &Utf8Path, _outcome: &Outcome) {}
+
+    /// `path` was left untouched, see [`Outcome::skipped`] and [`Outcome::skip_reason`].
+    fn file_skipped(&self, _path: &Utf8Path, _outcome: &Outcome) {}
+
+    /// `path` can't be formatted.
+    fn file_errored(&self, _path: &Utf8Path, _error: &Error) {}
+
+    /// Every file is done, `report` being what is printed about them.
+    fn run_finished(&self, _report: &Report) {}
+}
+
+/// A reporter kept by the caller too, to read what it gathered once the run is over.
+impl<R: Reporter + ?Sized> Reporter for Arc<R> {
+    fn file_started(&self, path: &Utf8Path) {
+        (**self).file_started(path);
+    }
+
+    fn file_formatted(&self, path: &Utf8Path, outcome: &Outcome) {
+        (**self).file_formatted(path, outcome);
+    }
+
+    fn file_skipped(&self, path: &Utf8Path, outcome: &Outcome) {
+        (**self).file_skipped(path, outcome);
+    }
+
+    fn file_errored(&self, path: &Utf8Path, error: &Error) {
+        (**self).file_errored(path, error);
+    }
+
+    fn run_finished(&self, report: &Report) {
+        (**self).run_finished(report);
+    }
+}
+
+/// Tell `reporter` how formatting `path` went.
+pub(crate) fn finished(reporter: &dyn Reporter, path: &Utf8Path, result: &Result<Outcome, Error>) {
+    match result {
+        Ok(outcome) if outcome.skipped && !outcome.changed => reporter.file_skipped(path, outcome),
+        Ok(outcome) => reporter.file_formatted(path, outcome),
+        Err(err) => reporter.file_errored(path, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+    use crate::{Options, PrettyThanks};
+    use std::{fs, sync::Mutex};
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<String>>);
+
+    impl Reporter for Events {
+        fn file_started(&self, path: &Utf8Path) {
+            self.push(format!("started {}", path.file_name().unwrap()));
+        }
+
+        fn file_formatted(&self, path: &Utf8Path, outcome: &Outcome) {
+            let changed = if outcome.changed {
+                "changed"
+            } else {
+                "unchanged"
+            };
+            self.push(format!("{} {}", changed, path.file_name().unwrap()));
+        }
+
+        fn file_skipped(&self, path: &Utf8Path, _: &Outcome) {
+            self.push(format!("skipped {}", path.file_name().unwrap()));
+        }
+
+        fn file_errored(&self, path: &Utf8Path, _: &Error) {
+            self.push(format!("errored {}", path.file_name().unwrap()));
+        }
+
+        fn run_finished(&self, report: &Report) {
+            self.push(format!("finished {}", report.files().count()));
+        }
+    }
+
+    impl Events {
+        fn push(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn reports_files() {
+        let temp = TempDir::new("reporter");
+        fs::write(temp.join("a.rs"), "fn a(){}").unwrap();
+        fs::write(temp.join("b.rs"), "fn b() {}\n").unwrap();
+        fs::write(temp.join("c.rs"), "// @generated\nfn c(){}").unwrap();
+        fs::write(temp.join("d.rs"), "fn d(").unwrap();
+        let options = Options {
+            check: true,
+            jobs: 1,
+            ..Options::default()
+        };
+        let events = Arc::new(Events::default());
+        PrettyThanks::new(&[temp.to_string()], options)
+            .unwrap()
+            .with_reporter(Arc::clone(&events))
+            .report();
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [
+                "started a.rs",
+                "changed a.rs",
+                "started b.rs",
+                "unchanged b.rs",
+                "started c.rs",
+                "skipped c.rs",
+                "started d.rs",
+                "errored d.rs",
+                "finished 4",
+            ]
+        );
+    }
+}
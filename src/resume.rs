@@ -0,0 +1,121 @@
+//! Progress of the runs writing many files, so that a run interrupted over a large tree can be
+//! resumed with `--resume` rather than started over, see [`Journal`].
+//!
+//! The journal is a text file: a first line naming the paths of the run, the version of
+//! prettythanks and the options, any change of them making it unusable, then the path of a file
+//! formatted per line. It is removed once every file of the run is formatted.
+
+use crate::{cache, Error, Options, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    sync::Mutex,
+};
+
+/// Name of the journal, created at the root of the tree next to the cache, see
+/// [`crate::lock::root`].
+pub const FILE_NAME: &str = ".prettythanks-progress";
+
+/// Number of files from which the runs writing them keep a journal, smaller ones only doing so
+/// with `--resume`, not worth resuming otherwise.
+pub const MIN_FILES: usize = 1000;
+
+/// The files formatted so far by a run, recorded as they are in a journal file.
+#[derive(Debug)]
+pub struct Journal {
+    path: Utf8PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /// Start the journal at `path` for a run with the key `key`, keeping the files recorded by
+    /// the run it resumes if `resume` is set and the journal is of the same run.
+    ///
+    /// # Errors
+    ///
+    /// If the journal can't be written.
+    pub fn create(path: &Utf8Path, key: &str, resume: bool) -> Result<Self> {
+        let file = if resume && completed(path, key).is_some() {
+            OpenOptions::new().append(true).open(path)
+        } else {
+            File::create(path).and_then(|mut file| writeln!(file, "{}", key).map(|()| file))
+        };
+        Ok(Journal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file.map_err(|err| Error::io(path, err))?),
+        })
+    }
+
+    /// Record that the file `formatted` is formatted.
+    ///
+    /// # Errors
+    ///
+    /// If the journal can't be written.
+    pub fn record(&self, formatted: &Utf8Path) -> Result<()> {
+        let mut file = self.file.lock().expect("poisoned journal");
+        // Written at once, for a run killed in the middle to leave whole lines only.
+        file.write_all(format!("{}\n", formatted).as_bytes())
+            .map_err(|err| Error::io(&self.path, err))
+    }
+
+    /// Remove the journal, the run being over.
+    ///
+    /// # Errors
+    ///
+    /// If it can't be removed.
+    pub fn finish(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::io(&self.path, err)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The first line of the journal of a run over `paths` with `options`.
+pub(crate) fn key(paths: &[Utf8PathBuf], options: &Options) -> String {
+    let paths = paths.iter().map(|path| path.as_str()).collect::<Vec<_>>();
+    format!("paths={} {}", paths.join(","), cache::key(options))
+}
+
+/// The files recorded as formatted by the journal at `path`, `None` if it is missing, unreadable
+/// or of a run with another key.
+pub fn completed(path: &Utf8Path, key: &str) -> Option<BTreeSet<Utf8PathBuf>> {
+    let contents = fs::read_to_string(path).ok()?;
+    let mut lines = contents.lines();
+    if lines.next()? != key {
+        return None;
+    }
+    Some(lines.map(Utf8PathBuf::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn records_formatted_files() {
+        let temp = TempDir::new("journal");
+        let path = temp.join(FILE_NAME);
+        let paths = ["src".into()];
+        let key = key(&paths, &Options::default());
+        let journal = Journal::create(&path, &key, false).unwrap();
+        journal.record("src/a.rs".into()).unwrap();
+        drop(journal);
+        let journal = Journal::create(&path, &key, true).unwrap();
+        journal.record("src/b.rs".into()).unwrap();
+        let files = completed(&path, &key).unwrap();
+        assert_eq!(
+            files.into_iter().collect::<Vec<_>>(),
+            ["src/a.rs", "src/b.rs"]
+        );
+        assert_eq!(completed(&path, "another run"), None);
+        journal.finish().unwrap();
+        assert_eq!(completed(&path, &key), None);
+        // Starting afresh drops what was recorded.
+        Journal::create(&path, &key, false).unwrap();
+        assert_eq!(completed(&path, &key).unwrap().len(), 0);
+    }
+}
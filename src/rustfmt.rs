@@ -0,0 +1,165 @@
+//! Comparison of the formatting of prettyplease with the one of rustfmt, for teams weighing a
+//! switch, see [`PrettyThanks::compare_rustfmt`].
+
+use crate::{
+    diff, edition::Edition, error, format_with_policy, markdown, read_source, term, Error,
+    PrettyThanks, Result, Task,
+};
+use camino::Utf8Path;
+use std::{
+    env,
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// The rustfmt to run, `$RUSTFMT` like cargo fmt does, or the one on the `PATH`.
+fn program() -> String {
+    env::var("RUSTFMT").unwrap_or_else(|_| "rustfmt".to_string())
+}
+
+/// The source file `path`, `source` of the edition `edition`, formatted by rustfmt with the
+/// configuration files above it.
+///
+/// # Errors
+///
+/// If rustfmt can't be run or fails to format it.
+pub fn format(path: &Utf8Path, source: &str, edition: Edition) -> Result<String> {
+    // rustfmt looks for its configuration from the directory it runs in when reading stdin.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_str().is_empty() => dir,
+        _ => Utf8Path::new("."),
+    };
+    let spawn_error = |err| Error::Rustfmt(format!("failed to run rustfmt: {}", err));
+    let mut child = Command::new(program())
+        .args(["--edition", &edition.to_string(), "--emit", "stdout"])
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    // rustfmt reads the whole source before printing anything.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(source.as_bytes()).map_err(spawn_error)?;
+    }
+    let output = child.wait_with_output().map_err(spawn_error)?;
+    if !output.status.success() {
+        return Err(Error::Rustfmt(format!(
+            "rustfmt failed to format {}: {}",
+            error::describe(path),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| Error::Rustfmt("rustfmt printed invalid UTF-8".to_string()))
+}
+
+impl PrettyThanks {
+    /// Format the Rust files with both prettyplease and rustfmt, leaving them untouched, and print
+    /// the ones they format differently or, with `diffs`, the diffs from the formatting of rustfmt
+    /// to the one of prettyplease.
+    ///
+    /// # Errors
+    ///
+    /// If rustfmt is not installed, or with the errors hit formatting the files either way.
+    pub fn compare_rustfmt(&self, diffs: bool) -> Result<()> {
+        if self.paths.iter().any(|path| path == "-") {
+            return Err(Error::Unsupported(
+                "the comparison can't be used with stdin".to_string(),
+            ));
+        }
+        let installed = Command::new(program())
+            .arg("--version")
+            .output()
+            .map_or(false, |output| output.status.success());
+        if !installed {
+            return Err(Error::Rustfmt(format!(
+                "{} can't be run, install it with `rustup component add rustfmt`",
+                program()
+            )));
+        }
+        let mut errors = Vec::new();
+        let (mut compared, mut differing) = (0, 0);
+        for task in self.collect() {
+            let (path, options) = match task {
+                Task::File(path, options) if !markdown::is_markdown(&path) => (path, options),
+                Task::Error(err) => {
+                    errors.push(err);
+                    continue;
+                }
+                _ => continue,
+            };
+            let options = options.as_deref().unwrap_or(&self.options);
+            let (source, _, _) = match read_source(&path, options) {
+                Ok(read) => read,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let formatted = match format_with_policy(&path, &source, options) {
+                // Skipped files are left as they are by both.
+                Ok((_, _, true)) => continue,
+                Ok((formatted, _, false)) => formatted,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let edition = options.edition_for(&path).unwrap_or_default();
+            let rustfmt = match format(&path, &source, edition) {
+                Ok(rustfmt) => rustfmt,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            compared += 1;
+            if rustfmt == formatted {
+                continue;
+            }
+            differing += 1;
+            let output = if diffs {
+                term::paint_diff(
+                    term::Stream::Stdout,
+                    &diff::unified(
+                        &rustfmt,
+                        &formatted,
+                        &format!("{} (rustfmt)", path),
+                        &format!("{} (prettyplease)", path),
+                        diff::CONTEXT_LINES,
+                    ),
+                )
+            } else {
+                format!("{}\n", path)
+            };
+            io::stdout()
+                .write_all(output.as_bytes())
+                .map_err(|err| Error::io(Utf8Path::new(error::STDOUT), err))?;
+        }
+        info!(
+            "comparison completed, {} of {} files formatted differently by rustfmt",
+            differing, compared
+        );
+        Error::from_errors(errors).map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_with_rustfmt() {
+        // Only where rustfmt is installed.
+        if Command::new(program()).arg("--version").output().is_err() {
+            return;
+        }
+        let source = "fn main(){let x=1;}";
+        assert_eq!(
+            format(Utf8Path::new("main.rs"), source, Edition::E2021).unwrap(),
+            "fn main() {\n    let x = 1;\n}\n"
+        );
+        assert!(format(Utf8Path::new("main.rs"), "fn main(", Edition::E2021).is_err());
+    }
+}
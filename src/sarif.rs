@@ -0,0 +1,160 @@
+//! SARIF 2.1 logs, for GitHub code scanning and the other static analysis dashboards tracking
+//! results over time.
+
+use crate::{json::Value, patch_path, Entry, Error, Location, Report};
+use camino::Utf8Path;
+
+/// The rules results are reported against: identifier, name and description.
+const RULES: [(&str, &str, &str); 3] = [
+    (
+        "unformatted",
+        "UnformattedFile",
+        "The file is not formatted the way prettyplease prints it.",
+    ),
+    (
+        "parse-error",
+        "ParseError",
+        "The file is not valid Rust, so it can't be formatted.",
+    ),
+    (
+        "format-error",
+        "FormatError",
+        "The file can't be read, written or safely formatted.",
+    ),
+];
+
+fn rule(idx: usize) -> Value {
+    let (id, name, description) = RULES[idx];
+    Value::object([
+        ("id", id.into()),
+        ("name", name.into()),
+        (
+            "shortDescription",
+            Value::object([("text", description.into())]),
+        ),
+        (
+            "defaultConfiguration",
+            Value::object([("level", "error".into())]),
+        ),
+    ])
+}
+
+fn result(rule: usize, path: Option<&str>, location: Option<&Location>, message: String) -> Value {
+    let mut fields = vec![
+        ("ruleId", RULES[rule].0.into()),
+        ("ruleIndex", rule.into()),
+        ("level", "error".into()),
+        ("message", Value::object([("text", message.into())])),
+    ];
+    if let Some(path) = path.filter(|path| *path != "-") {
+        let artifact = Value::object([("uri", patch_path(path.into()).into())]);
+        let mut physical = vec![("artifactLocation", artifact)];
+        if let Some(location) = location {
+            physical.push((
+                "region",
+                Value::object([
+                    ("startLine", location.line.into()),
+                    ("startColumn", location.column.into()),
+                    ("endLine", location.end_line.into()),
+                    ("endColumn", location.end_column.into()),
+                ]),
+            ));
+        }
+        let location = Value::object(physical);
+        fields.push((
+            "locations",
+            Value::Array(vec![Value::object([("physicalLocation", location)])]),
+        ));
+    }
+    Value::object(fields)
+}
+
+fn error_result(err: &Error, path: Option<&str>) -> Value {
+    let rule = match err {
+        Error::Parse { .. } => 1,
+        _ => 2,
+    };
+    result(rule, path, err.location(), err.to_string())
+}
+
+impl Report {
+    /// The report as a SARIF log: a result for every file that is not formatted or can't be
+    /// formatted, and for every error hit while walking the tree.
+    pub fn to_sarif(&self) -> Value {
+        let mut results = Vec::new();
+        for entry in &self.entries {
+            match entry {
+                Entry::File(file) => match &file.result {
+                    Ok(outcome) if outcome.changed => results.push(result(
+                        0,
+                        Some(file.path.as_str()),
+                        None,
+                        format!("{} is not formatted", file.path),
+                    )),
+                    Ok(_) => {}
+                    Err(err) => results.push(error_result(err, Some(file.path.as_str()))),
+                },
+                Entry::Error(err) => {
+                    results.push(error_result(err, err.path().map(Utf8Path::as_str)));
+                }
+                Entry::Ignored(_)
+                | Entry::Orphan(_)
+                | Entry::Revisited(_)
+                | Entry::Duplicate(_)
+                | Entry::NonUtf8(_) => {}
+            }
+        }
+        let driver = Value::object([
+            ("name", "prettythanks".into()),
+            ("version", env!("CARGO_PKG_VERSION").into()),
+            ("informationUri", env!("CARGO_PKG_HOMEPAGE").into()),
+            ("rules", Value::Array((0..RULES.len()).map(rule).collect())),
+        ]);
+        let run = Value::object([
+            ("tool", Value::object([("driver", driver)])),
+            ("results", Value::Array(results)),
+        ]);
+        Value::object([
+            (
+                "$schema",
+                "https://json.schemastore.org/sarif-2.1.0.json".into(),
+            ),
+            ("version", "2.1.0".into()),
+            ("runs", Value::Array(vec![run])),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileReport;
+    use std::time::Duration;
+
+    #[test]
+    fn renders_sarif() {
+        let parse = syn::parse_file("fn").err().unwrap();
+        let report = Report {
+            entries: vec![Entry::File(FileReport {
+                path: "src/bad.rs".into(),
+                result: Err(Error::Parse {
+                    path: Some("src/bad.rs".into()),
+                    source: parse,
+                    location: None,
+                }),
+            })],
+            elapsed: Duration::ZERO,
+        };
+        let sarif = report.to_sarif();
+        assert_eq!(sarif.get("version").unwrap().as_str(), Some("2.1.0"));
+        let run = &sarif.get("runs").unwrap().as_array().unwrap()[0];
+        let results = run.get("results").unwrap().as_array().unwrap();
+        assert_eq!(
+            results[0].to_string(),
+            r#"{"ruleId":"parse-error","ruleIndex":1,"level":"error","message":{"text":"failed to parse file src/bad.rs: unexpected end of input, expected identifier"},"locations":[{"physicalLocation":{"artifactLocation":{"uri":"src/bad.rs"}}}]}"#
+        );
+        let rules = run.get("tool").and_then(|tool| tool.get("driver"));
+        let rules = rules.and_then(|driver| driver.get("rules")).unwrap();
+        assert_eq!(rules.as_array().unwrap().len(), 3);
+    }
+}
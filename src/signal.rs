@@ -0,0 +1,112 @@
+//! Interruption of runs by SIGINT and SIGTERM, stopping them between files rather than in the
+//! middle of a write, see [`install`].
+
+use std::sync::atomic::{AtomicI32, Ordering};
+
+pub const SIGINT: i32 = 2;
+pub const SIGTERM: i32 = 15;
+
+/// The signal received, 0 until one is.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// The name of the signal `signal`, as it is usually written.
+pub fn name(signal: i32) -> &'static str {
+    match signal {
+        SIGINT => "SIGINT",
+        SIGTERM => "SIGTERM",
+        _ => "a signal",
+    }
+}
+
+/// The signal received since [`install`] was called, if any.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    /// The default action, terminating the process for both signals handled.
+    pub const SIG_DFL: usize = 0;
+
+    extern "C" {
+        pub fn signal(signum: c_int, handler: usize) -> usize;
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(signal: std::os::raw::c_int) {
+    RECEIVED.store(signal, Ordering::Relaxed);
+    // A second signal kills the process, for runs that don't stop soon enough.
+    // SAFETY: signal is async-signal-safe.
+    unsafe {
+        sys::signal(SIGINT, sys::SIG_DFL);
+        sys::signal(SIGTERM, sys::SIG_DFL);
+    }
+}
+
+/// Record SIGINT and SIGTERM instead of terminating the process on the first one, for
+/// [`received`] to tell that the run must stop.
+///
+/// Files being formatted when the signal arrives are written, or left untouched, as a whole since
+/// they are replaced through renames.
+#[cfg(unix)]
+pub fn install() {
+    let handler = handle as extern "C" fn(std::os::raw::c_int) as usize;
+    // SAFETY: the handler only stores an integer and resets the handlers.
+    unsafe {
+        sys::signal(SIGINT, handler);
+        sys::signal(SIGTERM, handler);
+    }
+}
+
+/// Console control handlers would need the Windows API, which no dependency of ours wraps, the
+/// signals keep terminating the process.
+#[cfg(not(unix))]
+pub fn install() {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::{env, os::raw::c_int, os::unix::process::ExitStatusExt, process::Command};
+
+    extern "C" {
+        fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn names_signals() {
+        assert_eq!(name(SIGINT), "SIGINT");
+        assert_eq!(name(SIGTERM), "SIGTERM");
+        assert_eq!(name(9), "a signal");
+    }
+
+    /// Run by [`stops_on_signals`] in a process of its own, the signal stopping any run of the
+    /// other tests.
+    #[test]
+    #[ignore]
+    fn receives_signals() {
+        assert_eq!(received(), None);
+        install();
+        // SAFETY: raise only sends the signal to the process.
+        assert_eq!(unsafe { raise(SIGINT) }, 0);
+        assert_eq!(received(), Some(SIGINT));
+        // The second one terminates the process.
+        unsafe { raise(SIGTERM) };
+        unreachable!("still running after a second signal");
+    }
+
+    #[test]
+    fn stops_on_signals() {
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "signal::tests::receives_signals", "--ignored"])
+            .output()
+            .unwrap()
+            .status;
+        assert_eq!(status.signal(), Some(SIGTERM), "{:?}", status);
+    }
+}
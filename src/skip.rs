@@ -0,0 +1,250 @@
+//! Support for `#[rustfmt::skip]` and the `// prettythanks:skip-file` marker.
+//!
+//! Skipped items are replaced by placeholder macro calls before formatting, and their original
+//! text is spliced back in place of the placeholders afterwards.
+
+use proc_macro2::Span;
+use syn::{
+    spanned::Spanned, Attribute, Block, ImplItem, ImplItemConst, ImplItemFn, ImplItemMacro,
+    ImplItemType, Item, Stmt, TraitItem, TraitItemConst, TraitItemFn, TraitItemMacro,
+    TraitItemType,
+};
+
+/// Comment marker leaving a whole file untouched.
+pub const SKIP_FILE_MARKER: &str = "prettythanks:skip-file";
+
+/// Number of lines at the top of a file looked up for a generated code marker.
+pub const GENERATED_LINES: usize = 5;
+
+/// Whether `source` is generated code, marked with `@generated` or `DO NOT EDIT` in a comment in
+/// its first [`GENERATED_LINES`] lines.
+pub fn is_generated(source: &str) -> bool {
+    source.lines().take(GENERATED_LINES).any(|line| {
+        let line = line.trim_start();
+        (line.starts_with("//") || line.starts_with("/*") || line.starts_with('*'))
+            && (line.contains("@generated") || line.contains("DO NOT EDIT"))
+    })
+}
+
+/// Prefix of the placeholders standing for skipped items.
+const PLACEHOLDER: &str = "__prettythanks_skip_";
+
+/// What a source asks to leave untouched.
+pub(crate) enum Skip<'a> {
+    /// Everything can be formatted.
+    Nothing,
+    /// The whole file is skipped.
+    File,
+    /// Some items are skipped.
+    Items(Masked<'a>),
+}
+
+/// A source whose skipped items are replaced by placeholders.
+pub(crate) struct Masked<'a> {
+    /// The source to format instead of the original one.
+    pub source: String,
+    originals: Vec<&'a str>,
+}
+
+impl Masked<'_> {
+    /// Put the original text of the skipped items back into the formatted `masked` source.
+    ///
+    /// Returns `None` if prettyplease mangled a placeholder, which should never happen.
+    pub fn restore(&self, formatted: &str) -> Option<String> {
+        let mut output = formatted.to_string();
+        for (idx, original) in self.originals.iter().enumerate() {
+            let placeholder = placeholder(idx);
+            let start = output.find(&placeholder)?;
+            output.replace_range(start..start + placeholder.len(), original);
+        }
+        Some(output)
+    }
+}
+
+fn placeholder(idx: usize) -> String {
+    format!("{}{}!();", PLACEHOLDER, idx)
+}
+
+pub(crate) fn is_skip(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let segments = &attr.path().segments;
+        segments.len() == 2 && segments[0].ident == "rustfmt" && segments[1].ident == "skip"
+    })
+}
+
+/// Find what `source` asks to leave untouched, without parsing it if it can't skip anything.
+pub(crate) fn scan(source: &str) -> syn::Result<Skip<'_>> {
+    if source.contains(SKIP_FILE_MARKER)
+        && crate::comments::regular_comments(source)
+            .iter()
+            .any(|comment| comment.text.contains(SKIP_FILE_MARKER))
+    {
+        return Ok(Skip::File);
+    }
+    if !source.contains("rustfmt") {
+        return Ok(Skip::Nothing);
+    }
+    // `syn::parse_file` strips these, which would shift all the offsets.
+    let mut base = if source.starts_with('\u{feff}') { 3 } else { 0 };
+    if let Some(rest) = source[base..].strip_prefix("#!") {
+        if !rest.trim_start().starts_with('[') {
+            base += source[base..].find('\n').unwrap_or(source.len() - base);
+        }
+    }
+    let file = syn::parse_str::<syn::File>(&source[base..])?;
+    if is_skip(&file.attrs) {
+        return Ok(Skip::File);
+    }
+    let mut spans = Vec::new();
+    for item in &file.items {
+        collect_item(item, &mut spans);
+    }
+    if spans.is_empty() {
+        return Ok(Skip::Nothing);
+    }
+    let mut masked = String::with_capacity(source.len());
+    let mut originals = Vec::with_capacity(spans.len());
+    let mut last = 0;
+    for span in spans {
+        let range = span.byte_range();
+        let (start, end) = (base + range.start, base + range.end);
+        masked.push_str(&source[last..start]);
+        masked.push_str(&placeholder(originals.len()));
+        originals.push(&source[start..end]);
+        last = end;
+    }
+    masked.push_str(&source[last..]);
+    Ok(Skip::Items(Masked {
+        source: masked,
+        originals,
+    }))
+}
+
+/// Collect the spans of the skipped items in `item`, which are never nested in each other.
+fn collect_item(item: &Item, spans: &mut Vec<Span>) {
+    let attrs = match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => return,
+    };
+    if is_skip(attrs) {
+        spans.push(item.span());
+        return;
+    }
+    match item {
+        Item::Fn(item) => collect_block(&item.block, spans),
+        Item::Mod(item) => {
+            for item in item.content.iter().flat_map(|(_, items)| items) {
+                collect_item(item, spans);
+            }
+        }
+        Item::Impl(item) => {
+            for item in &item.items {
+                match item {
+                    ImplItem::Fn(func) if !is_skip(&func.attrs) => {
+                        collect_block(&func.block, spans);
+                    }
+                    ImplItem::Const(ImplItemConst { attrs, .. })
+                    | ImplItem::Fn(ImplItemFn { attrs, .. })
+                    | ImplItem::Macro(ImplItemMacro { attrs, .. })
+                    | ImplItem::Type(ImplItemType { attrs, .. })
+                        if is_skip(attrs) =>
+                    {
+                        spans.push(item.span());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Item::Trait(item) => {
+            for item in &item.items {
+                match item {
+                    TraitItem::Fn(func) if !is_skip(&func.attrs) => {
+                        if let Some(block) = &func.default {
+                            collect_block(block, spans);
+                        }
+                    }
+                    TraitItem::Const(TraitItemConst { attrs, .. })
+                    | TraitItem::Fn(TraitItemFn { attrs, .. })
+                    | TraitItem::Macro(TraitItemMacro { attrs, .. })
+                    | TraitItem::Type(TraitItemType { attrs, .. })
+                        if is_skip(attrs) =>
+                    {
+                        spans.push(item.span());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_block(block: &Block, spans: &mut Vec<Span>) {
+    for stmt in &block.stmts {
+        match stmt {
+            Stmt::Item(item) => collect_item(item, spans),
+            Stmt::Local(local) if is_skip(&local.attrs) => spans.push(stmt.span()),
+            Stmt::Macro(mac) if is_skip(&mac.attrs) => spans.push(stmt.span()),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_skipped_items() {
+        let source = "#[rustfmt::skip]\nconst TABLE: [u8; 4] = [\n    1, 2,\n    3, 4,\n];\nfn main(){\n    #[rustfmt::skip]\n    let a = [1,\n             2];\n    let b=3;\n}\n";
+        let masked = match scan(source).unwrap() {
+            Skip::Items(masked) => masked,
+            _ => panic!("expected skipped items"),
+        };
+        let formatted = crate::format_str(&masked.source).unwrap();
+        assert_eq!(
+            masked.restore(&formatted).unwrap(),
+            "#[rustfmt::skip]\nconst TABLE: [u8; 4] = [\n    1, 2,\n    3, 4,\n];\nfn main() {\n    #[rustfmt::skip]\n    let a = [1,\n             2];\n    let b = 3;\n}\n"
+        );
+    }
+
+    #[test]
+    fn detects_generated_files() {
+        assert!(is_generated("// @generated by protoc\nfn main() {}\n"));
+        assert!(is_generated(
+            "/*\n * Code generated by bindgen. DO NOT EDIT.\n */\n"
+        ));
+        assert!(!is_generated("fn main() {}\n\n\n\n\n// @generated\n"));
+        assert!(!is_generated("const A: &str = \"@generated\";\n"));
+    }
+
+    #[test]
+    fn skips_marked_files() {
+        assert!(matches!(
+            scan("// prettythanks:skip-file\nfn main(){}").unwrap(),
+            Skip::File
+        ));
+        assert!(matches!(
+            scan("#![rustfmt::skip]\nfn main(){}").unwrap(),
+            Skip::File
+        ));
+        assert!(matches!(
+            scan("const A: &str = \"prettythanks:skip-file\";").unwrap(),
+            Skip::Nothing
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+//! Statistics about a run: counts, changed lines, and the files that took longest to format or
+//! changed the most, and where the time went for the slowest and largest ones. Files found
+//! formatted in the cache are left out of the timings, which were not measured for them.
+
+use crate::{json::Value, FileReport, Outcome, Report};
+use camino::Utf8Path;
+use std::{cmp::Reverse, fmt::Write, time::Duration};
+
+/// How many files the rankings list.
+pub const TOP_FILES: usize = 5;
+
+impl Report {
+    fn file_outcomes(&self) -> impl Iterator<Item = (&Utf8Path, &Outcome)> {
+        self.files()
+            .filter_map(|file: &FileReport| Some((file.path.as_path(), file.result.as_ref().ok()?)))
+    }
+
+    /// The outcomes of the files formatted rather than found formatted in the cache.
+    fn timed_outcomes(&self) -> impl Iterator<Item = (&Utf8Path, &Outcome)> {
+        self.file_outcomes().filter(|(_, outcome)| !outcome.cached)
+    }
+
+    /// Total number of lines formatting removed.
+    pub fn removed_lines(&self) -> usize {
+        self.outcomes().map(|outcome| outcome.removed_lines).sum()
+    }
+
+    /// Total number of lines formatting added.
+    pub fn added_lines(&self) -> usize {
+        self.outcomes().map(|outcome| outcome.added_lines).sum()
+    }
+
+    /// The `count` files that took longest to format, slowest first.
+    pub fn slowest(&self, count: usize) -> Vec<(&Utf8Path, Duration)> {
+        let mut files = self
+            .timed_outcomes()
+            .map(|(path, outcome)| (path, outcome.elapsed))
+            .collect::<Vec<_>>();
+        files.sort_by_key(|(_, elapsed)| Reverse(*elapsed));
+        files.truncate(count);
+        files
+    }
+
+    /// The `count` largest files, in bytes before formatting, largest first.
+    pub fn largest(&self, count: usize) -> Vec<(&Utf8Path, &Outcome)> {
+        let mut files = self.file_outcomes().collect::<Vec<_>>();
+        files.sort_by_key(|(_, outcome)| Reverse(outcome.original));
+        files.truncate(count);
+        files
+    }
+
+    /// The `count` files whose size changed the most, in bytes, biggest change first.
+    pub fn largest_deltas(&self, count: usize) -> Vec<(&Utf8Path, i64)> {
+        let size = |size: usize| i64::try_from(size).unwrap_or(i64::MAX);
+        let mut files = self
+            .file_outcomes()
+            .map(|(path, outcome)| (path, size(outcome.formatted) - size(outcome.original)))
+            .filter(|(_, delta)| *delta != 0)
+            .collect::<Vec<_>>();
+        files.sort_by_key(|(_, delta)| Reverse(delta.abs()));
+        files.truncate(count);
+        files
+    }
+
+    /// The statistics as a human readable table.
+    pub fn stats_table(&self) -> String {
+        let changed = self.changed().count();
+        let errors = self.errors().count();
+        let mut table = String::new();
+        let rows = [
+            ("files scanned", self.files().count()),
+            ("changed", changed),
+            ("unchanged", self.outcomes().count() - changed),
+            ("errors", errors),
+        ];
+        for (name, value) in rows {
+            let _ = writeln!(table, "{:<14}{:>12}", name, value);
+        }
+        let _ = writeln!(table, "{:<14}{:>12}", "lines added", self.added_lines());
+        let _ = writeln!(table, "{:<14}{:>12}", "lines removed", self.removed_lines());
+        let slowest = self.slowest(TOP_FILES);
+        if !slowest.is_empty() {
+            table.push_str("slowest files:\n");
+            for (path, elapsed) in slowest {
+                let _ = writeln!(table, "{:>10} ms  {}", elapsed.as_millis(), path);
+            }
+        }
+        let deltas = self.largest_deltas(TOP_FILES);
+        if !deltas.is_empty() {
+            table.push_str("largest size changes:\n");
+            for (path, delta) in deltas {
+                let _ = writeln!(table, "{:>+7} bytes  {}", delta, path);
+            }
+        }
+        table
+    }
+
+    /// The time spent reading, parsing, printing and writing the slowest and the largest files,
+    /// with their sizes, as a human readable table.
+    pub fn profile_table(&self) -> String {
+        let mut slowest = self.timed_outcomes().collect::<Vec<_>>();
+        let mut largest = slowest.clone();
+        slowest.sort_by_key(|(_, outcome)| Reverse(outcome.elapsed));
+        slowest.truncate(TOP_FILES);
+        largest.sort_by_key(|(_, outcome)| Reverse(outcome.original));
+        largest.truncate(TOP_FILES);
+        let mut table = String::new();
+        for (title, files) in [("slowest", slowest), ("largest", largest)] {
+            if files.is_empty() {
+                continue;
+            }
+            let _ = writeln!(table, "{} files, in ms and bytes:", title);
+            let _ = writeln!(
+                table,
+                "{:>9}{:>9}{:>9}{:>9}{:>9}{:>10}{:>10}  path",
+                "read", "parse", "print", "write", "total", "before", "after"
+            );
+            let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+            for (path, outcome) in files {
+                let timings = outcome.timings;
+                let _ = writeln!(
+                    table,
+                    "{:>9.3}{:>9.3}{:>9.3}{:>9.3}{:>9.3}{:>10}{:>10}  {}",
+                    ms(timings.read),
+                    ms(timings.parse),
+                    ms(timings.print),
+                    ms(timings.write),
+                    ms(outcome.elapsed),
+                    outcome.original,
+                    outcome.formatted,
+                    path
+                );
+            }
+        }
+        let cached = self.outcomes().filter(|outcome| outcome.cached).count();
+        if cached > 0 {
+            let _ = writeln!(table, "{} files found formatted in the cache", cached);
+        }
+        table
+    }
+
+    /// The rankings of the statistics, for the JSON report.
+    pub(crate) fn stats_json(&self) -> Value {
+        let slowest = self.slowest(TOP_FILES).into_iter().map(|(path, elapsed)| {
+            Value::object([
+                ("path", path.as_str().into()),
+                ("duration_ms", elapsed.as_millis().into()),
+            ])
+        });
+        let deltas = self
+            .largest_deltas(TOP_FILES)
+            .into_iter()
+            .map(|(path, delta)| {
+                Value::object([
+                    ("path", path.as_str().into()),
+                    ("size_delta", Value::Integer(delta)),
+                ])
+            });
+        Value::object([
+            ("slowest", Value::Array(slowest.collect())),
+            ("largest_size_deltas", Value::Array(deltas.collect())),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entry, Timings};
+
+    #[test]
+    fn ranks_files() {
+        let file = |path: &str, original, formatted, millis| {
+            Entry::File(FileReport {
+                path: path.into(),
+                result: Ok(Outcome {
+                    original,
+                    formatted,
+                    changed: original != formatted,
+                    dropped_comments: 0,
+                    comments_allowed: false,
+                    skipped: false,
+                    cached: false,
+                    elapsed: Duration::from_millis(millis),
+                    timings: Timings::default(),
+                    output: String::new(),
+                    transcoded: None,
+                    removed_lines: 1,
+                    added_lines: 2,
+                    skip_reason: None,
+                }),
+            })
+        };
+        let report = Report {
+            entries: vec![
+                file("a.rs", 10, 12, 3),
+                file("b.rs", 10, 10, 7),
+                file("c.rs", 20, 5, 1),
+            ],
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(
+            report.stats_table(),
+            "files scanned            3\n\
+             changed                  2\n\
+             unchanged                1\n\
+             errors                   0\n\
+             lines added              6\n\
+             lines removed            3\n\
+             slowest files:\n         \
+             7 ms  b.rs\n         \
+             3 ms  a.rs\n         \
+             1 ms  c.rs\n\
+             largest size changes:\n    \
+             -15 bytes  c.rs\n     \
+             +2 bytes  a.rs\n"
+        );
+    }
+
+    #[test]
+    fn profiles_files() {
+        let file = |path: &str, original, millis| {
+            Entry::File(FileReport {
+                path: path.into(),
+                result: Ok(Outcome {
+                    original,
+                    formatted: original + 1,
+                    changed: true,
+                    dropped_comments: 0,
+                    comments_allowed: false,
+                    skipped: false,
+                    cached: false,
+                    elapsed: Duration::from_millis(millis),
+                    timings: Timings {
+                        read: Duration::from_micros(250),
+                        parse: Duration::from_millis(millis / 2),
+                        print: Duration::from_millis(millis / 4),
+                        format: Duration::from_millis(millis - 1),
+                        write: Duration::from_micros(500),
+                    },
+                    output: String::new(),
+                    transcoded: None,
+                    removed_lines: 1,
+                    added_lines: 1,
+                    skip_reason: None,
+                }),
+            })
+        };
+        // Found in the cache, larger than the others but not timed.
+        let mut cached = file("c.rs", 5000, 1);
+        if let Entry::File(FileReport {
+            result: Ok(outcome),
+            ..
+        }) = &mut cached
+        {
+            outcome.cached = true;
+            outcome.timings = Timings::default();
+        }
+        let report = Report {
+            entries: vec![file("a.rs", 100, 4), file("b.rs", 2000, 8), cached],
+            elapsed: Duration::ZERO,
+        };
+        let header = "     read    parse    print    write    total    before     after  path\n";
+        assert_eq!(
+            report.profile_table(),
+            format!(
+                "slowest files, in ms and bytes:\n{header}\
+                 \x20   0.250    4.000    2.000    0.500    8.000      2000      2001  b.rs\n\
+                 \x20   0.250    2.000    1.000    0.500    4.000       100       101  a.rs\n\
+                 largest files, in ms and bytes:\n{header}\
+                 \x20   0.250    4.000    2.000    0.500    8.000      2000      2001  b.rs\n\
+                 \x20   0.250    2.000    1.000    0.500    4.000       100       101  a.rs\n\
+                 1 files found formatted in the cache\n",
+                header = header
+            )
+        );
+    }
+}
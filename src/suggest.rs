@@ -0,0 +1,114 @@
+//! Suggestions for the paths that don't exist, from the entries next to the first part of them
+//! that is missing, for typos like `scr/lib.rs` to be pointed at `src/lib.rs`.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::fs;
+
+/// How many paths are suggested at most.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The existing paths `path` may have been meant as, closest first.
+pub(crate) fn near_misses(path: &Utf8Path) -> Vec<Utf8PathBuf> {
+    // The deepest ancestor there, then the part of `path` missing from it.
+    let mut missing = path.components().collect::<Vec<_>>();
+    let mut rest = Vec::new();
+    let parent = loop {
+        let component = match missing.pop() {
+            Some(component) => component,
+            None => return Vec::new(),
+        };
+        rest.push(component);
+        let parent = missing.iter().collect::<Utf8PathBuf>();
+        if parent.as_str().is_empty() || parent.is_dir() {
+            break parent;
+        }
+    };
+    let name = rest.pop().expect("a missing component").as_str();
+    let rest = rest.iter().rev().collect::<Utf8PathBuf>();
+    let dir = if parent.as_str().is_empty() {
+        Utf8Path::new(".")
+    } else {
+        parent.as_path()
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    // Allow a typo every three characters, and at least one.
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut candidates = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|candidate| {
+            let distance = distance(name, &candidate);
+            (distance <= max_distance).then_some((distance, candidate))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates
+        .into_iter()
+        .map(|(_, candidate)| {
+            let suggestion = parent.join(candidate);
+            // Joining nothing would add a trailing slash.
+            if rest.as_str().is_empty() {
+                suggestion
+            } else {
+                suggestion.join(&rest)
+            }
+        })
+        .filter(|suggestion| suggestion.exists())
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// The number of characters to insert, remove, replace or swap with the next one to turn `a` into
+/// `b`, swaps being the most common typos.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    // `d[i][j]` is the distance from the first `i` characters of `a` to the first `j` of `b`.
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replaced = d[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut best = replaced.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = best;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    #[test]
+    fn suggests_near_misses() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("lib.rs", "lib.rs"), 0);
+        assert_eq!(distance("scr", "src"), 1);
+        let temp = TempDir::new("suggest");
+        fs::create_dir_all(temp.join("src")).unwrap();
+        fs::write(temp.join("src/lib.rs"), "").unwrap();
+        fs::write(temp.join("src/main.rs"), "").unwrap();
+        assert_eq!(
+            near_misses(&temp.join("src/lb.rs")),
+            [temp.join("src/lib.rs")]
+        );
+        assert_eq!(
+            near_misses(&temp.join("scr/lib.rs")),
+            [temp.join("src/lib.rs")]
+        );
+        assert!(near_misses(&temp.join("scr/other.rs")).is_empty());
+        assert!(near_misses(&temp.join("nothing/like/it.rs")).is_empty());
+    }
+}
@@ -0,0 +1,60 @@
+//! Temporary directories of the tests, see [`TempDir`].
+
+use camino::{Utf8Path, Utf8PathBuf};
+use std::{
+    env, fs,
+    ops::Deref,
+    path::Path,
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Directories created so far by this process.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory of a test, removed once dropped. Named after the process and the number
+/// of directories it created, so that tests running at the same time, in this process or
+/// another one, never share one.
+pub struct TempDir {
+    path: Utf8PathBuf,
+}
+
+impl TempDir {
+    /// A new directory, `name` telling which test it is for.
+    pub fn new(name: &str) -> Self {
+        let count = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path =
+            env::temp_dir().join(format!("prettythanks-{}-{}-{}", name, process::id(), count));
+        let path = Utf8PathBuf::try_from(path).expect("non UTF-8 temporary directory");
+        // Left by a process of the same id, killed before cleaning up.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Utf8Path;
+
+    fn deref(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        self.path.as_std_path()
+    }
+}
+
+impl AsRef<Utf8Path> for TempDir {
+    fn as_ref(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
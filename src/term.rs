@@ -0,0 +1,301 @@
+//! Terminal handling: detection of interactive streams, colors and the progress bar.
+
+use camino::Utf8Path;
+use std::{
+    env, fmt,
+    io::{self, Write},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// A standard stream.
+#[derive(Clone, Copy, Debug)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Whether `stream` is attached to a terminal.
+#[cfg(unix)]
+pub fn is_terminal(stream: Stream) -> bool {
+    extern "C" {
+        fn isatty(fd: i32) -> i32;
+    }
+    let fd = match stream {
+        Stream::Stdout => 1,
+        Stream::Stderr => 2,
+    };
+    // SAFETY: `isatty` only inspects the file descriptor, whatever it is.
+    unsafe { isatty(fd) == 1 }
+}
+
+/// Whether `stream` is attached to a terminal, never assumed outside of unix.
+#[cfg(not(unix))]
+pub fn is_terminal(_stream: Stream) -> bool {
+    false
+}
+
+/// When to use colors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Only on terminals, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!(
+                "unknown color choice `{}`, expected auto, always or never",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        })
+    }
+}
+
+static COLOR_STDOUT: AtomicBool = AtomicBool::new(false);
+static COLOR_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Decide whether the output printed on each stream is colored.
+pub fn set_color(choice: ColorChoice) {
+    let no_color = env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty());
+    let enabled = |stream| match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && is_terminal(stream),
+    };
+    COLOR_STDOUT.store(enabled(Stream::Stdout), Ordering::Relaxed);
+    COLOR_STDERR.store(enabled(Stream::Stderr), Ordering::Relaxed);
+}
+
+pub(crate) fn color_enabled(stream: Stream) -> bool {
+    match stream {
+        Stream::Stdout => COLOR_STDOUT.load(Ordering::Relaxed),
+        Stream::Stderr => COLOR_STDERR.load(Ordering::Relaxed),
+    }
+}
+
+/// The colors in use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+    Bold,
+    Reverse,
+}
+
+impl Color {
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Cyan => "36",
+            Color::Bold => "1",
+            Color::Reverse => "7",
+        }
+    }
+}
+
+/// `text` in `color`, if colors are enabled for `stream`.
+pub fn paint(stream: Stream, color: Color, text: &str) -> String {
+    if color_enabled(stream) {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Color the lines of a unified diff printed on `stream`, if colors are enabled for it.
+pub fn paint_diff(stream: Stream, diff: &str) -> String {
+    if !color_enabled(stream) {
+        return diff.to_string();
+    }
+    let mut out = String::with_capacity(diff.len());
+    for line in diff.split_inclusive('\n') {
+        let color = if line.starts_with("---") || line.starts_with("+++") {
+            Color::Bold
+        } else if line.starts_with("@@") {
+            Color::Cyan
+        } else if line.starts_with('-') {
+            Color::Red
+        } else if line.starts_with('+') {
+            Color::Green
+        } else {
+            out.push_str(line);
+            continue;
+        };
+        // Keep the newline out of the escape sequences.
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        out.push_str(&paint(stream, color, text));
+        out.push_str(newline);
+    }
+    out
+}
+
+/// Nothing is drawn before this, so quick runs don't flicker.
+const DELAY: Duration = Duration::from_millis(200);
+/// Minimum time between two redraws.
+const REFRESH: Duration = Duration::from_millis(100);
+/// Width of the bar itself, without the counters and the file name.
+const BAR_WIDTH: usize = 24;
+
+struct State {
+    done: usize,
+    current: String,
+    drawn: Option<Instant>,
+}
+
+/// A progress bar drawn on stderr, with the number of files done, the current one and an
+/// estimate of the time left. The line is cleared once dropped.
+pub(crate) struct Progress {
+    total: usize,
+    start: Instant,
+    state: Mutex<State>,
+}
+
+impl Progress {
+    pub(crate) fn new(total: usize) -> Self {
+        Progress {
+            total,
+            start: Instant::now(),
+            state: Mutex::new(State {
+                done: 0,
+                current: String::new(),
+                drawn: None,
+            }),
+        }
+    }
+
+    /// Formatting `path` started.
+    pub(crate) fn start(&self, path: &Utf8Path) {
+        let mut state = self.state.lock().expect("poisoned progress");
+        state.current = path.to_string();
+        self.draw(&mut state);
+    }
+
+    /// Formatting a file is over.
+    pub(crate) fn finish(&self) {
+        let mut state = self.state.lock().expect("poisoned progress");
+        state.done += 1;
+        self.draw(&mut state);
+    }
+
+    fn draw(&self, state: &mut State) {
+        let now = Instant::now();
+        let elapsed = now - self.start;
+        if elapsed < DELAY || state.drawn.map_or(false, |drawn| now - drawn < REFRESH) {
+            return;
+        }
+        state.drawn = Some(now);
+        let line = self.render(state, elapsed, columns());
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[K{}", line);
+        let _ = stderr.flush();
+    }
+
+    /// The progress line, fitting in `columns` by eating the start of long paths.
+    fn render(&self, state: &State, elapsed: Duration, columns: usize) -> String {
+        let filled = (BAR_WIDTH * state.done) / self.total.max(1);
+        let eta = if state.done == 0 {
+            String::new()
+        } else {
+            let left = elapsed.as_millis() * (self.total - state.done) as u128 / state.done as u128;
+            format!(" ETA {}s", (left + 999) / 1000)
+        };
+        let mut line = format!(
+            "[{}{}] {}/{}{} ",
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            state.done,
+            self.total,
+            eta
+        );
+        // The cursor sitting on the last column would wrap the line on some terminals.
+        let room = columns.saturating_sub(line.len() + 1);
+        let skip = state.current.chars().count().saturating_sub(room);
+        line.extend(state.current.chars().skip(skip));
+        line
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        let drawn = self
+            .state
+            .get_mut()
+            .map_or(false, |state| state.drawn.is_some());
+        if drawn {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
+
+/// Width of the terminal, from `COLUMNS` when exported by the shell.
+fn columns() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paints_diffs() {
+        set_color(ColorChoice::Always);
+        assert_eq!(
+            paint_diff(Stream::Stdout, "--- a\n+++ a\n@@ -1 +1 @@\n-x\n+y\n z\n"),
+            "\x1b[1m--- a\x1b[0m\n\x1b[1m+++ a\x1b[0m\n\x1b[36m@@ -1 +1 @@\x1b[0m\n\x1b[31m-x\x1b[0m\n\x1b[32m+y\x1b[0m\n z\n"
+        );
+        set_color(ColorChoice::Never);
+        assert_eq!(paint(Stream::Stderr, Color::Red, "error"), "error");
+    }
+
+    #[test]
+    fn renders_progress() {
+        let progress = Progress::new(4);
+        let state = State {
+            done: 1,
+            current: "src/some/long/path.rs".to_string(),
+            drawn: None,
+        };
+        assert_eq!(
+            progress.render(&state, Duration::from_secs(2), 80),
+            "[======                  ] 1/4 ETA 6s src/some/long/path.rs"
+        );
+        assert_eq!(
+            progress.render(&state, Duration::from_secs(2), 51),
+            "[======                  ] 1/4 ETA 6s long/path.rs"
+        );
+    }
+}
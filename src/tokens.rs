@@ -0,0 +1,294 @@
+//! Check that formatting only changed the layout of a source, never its tokens.
+//!
+//! prettyplease rewrites a few constructs without changing their meaning: it adds and removes
+//! trailing commas, wraps match arms in braces and terminates statements with semicolons. Both
+//! sides are normalized the same way before comparing their tokens.
+
+use crate::edition::{self, Edition};
+use proc_macro2::{Delimiter, Group, Punct, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::mem;
+use syn::{
+    punctuated::Punctuated,
+    token::{Paren, Semi},
+    visit_mut::{self, VisitMut},
+    Arm, Attribute, BinOp, Expr, ExprBlock, ExprClosure, ExprLit, ExprMacro, ExprParen, ExprTuple,
+    ItemMacro, Lit, LitStr, MacroDelimiter, Meta, MetaNameValue, ReturnType, Stmt, StmtMacro,
+    WhereClause,
+};
+
+/// A token, without its span.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Punct(char),
+    Literal(String),
+    Open(Delimiter),
+    Close(Delimiter),
+}
+
+/// Flatten `stream` into `tokens`, dropping trailing commas, before a closing delimiter or the
+/// `>` closing generics.
+fn flatten(stream: TokenStream, tokens: &mut Vec<Token>) {
+    for tree in stream {
+        match tree {
+            TokenTree::Ident(ident) => tokens.push(Token::Ident(ident.to_string())),
+            TokenTree::Punct(punct) => {
+                if punct.as_char() == '>' && tokens.last() == Some(&Token::Punct(',')) {
+                    tokens.pop();
+                }
+                tokens.push(Token::Punct(punct.as_char()));
+            }
+            TokenTree::Literal(literal) => tokens.push(Token::Literal(literal.to_string())),
+            TokenTree::Group(group) => {
+                tokens.push(Token::Open(group.delimiter()));
+                flatten(group.stream(), tokens);
+                if tokens.last() == Some(&Token::Punct(',')) {
+                    tokens.pop();
+                }
+                tokens.push(Token::Close(group.delimiter()));
+            }
+        }
+    }
+}
+
+/// Undo the rewrites prettyplease is allowed to do.
+struct Normalize;
+
+fn is_block_like(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Block(_)
+            | Expr::Const(_)
+            | Expr::ForLoop(_)
+            | Expr::If(_)
+            | Expr::Loop(_)
+            | Expr::Match(_)
+            | Expr::TryBlock(_)
+            | Expr::Unsafe(_)
+            | Expr::While(_)
+    )
+}
+
+/// Whether `expr` evaluates to `()` whether it's terminated by a semicolon or not, prettyplease
+/// adding one to those.
+fn is_unit(expr: &Expr) -> bool {
+    match expr {
+        Expr::Assign(_) | Expr::Break(_) | Expr::Continue(_) | Expr::Return(_) | Expr::Yield(_) => {
+            true
+        }
+        Expr::Binary(binary) => matches!(
+            binary.op,
+            BinOp::AddAssign(_)
+                | BinOp::SubAssign(_)
+                | BinOp::MulAssign(_)
+                | BinOp::DivAssign(_)
+                | BinOp::RemAssign(_)
+                | BinOp::BitXorAssign(_)
+                | BinOp::BitAndAssign(_)
+                | BinOp::BitOrAssign(_)
+                | BinOp::ShlAssign(_)
+                | BinOp::ShrAssign(_)
+        ),
+        _ => false,
+    }
+}
+
+/// The expression `body` boils down to once the blocks and parentheses prettyplease adds or
+/// removes around match arms and closure bodies are peeled off, `()` for an empty block.
+fn unwrap_body(body: Expr) -> Expr {
+    match body {
+        Expr::Block(ExprBlock {
+            attrs,
+            label: None,
+            mut block,
+        }) if attrs.is_empty() && block.stmts.len() <= 1 => match block.stmts.pop() {
+            None => Expr::Tuple(ExprTuple {
+                attrs,
+                paren_token: Paren::default(),
+                elems: Punctuated::new(),
+            }),
+            Some(Stmt::Expr(expr, None)) => unwrap_body(expr),
+            Some(Stmt::Expr(expr, Some(_))) if is_unit(&expr) => expr,
+            Some(Stmt::Macro(StmtMacro {
+                attrs,
+                mac,
+                semi_token: None,
+            })) => Expr::Macro(ExprMacro { attrs, mac }),
+            Some(stmt) => {
+                block.stmts.push(stmt);
+                Expr::Block(ExprBlock {
+                    attrs,
+                    label: None,
+                    block,
+                })
+            }
+        },
+        Expr::Paren(ExprParen { attrs, expr, .. }) if attrs.is_empty() => unwrap_body(*expr),
+        body => body,
+    }
+}
+
+/// Use braces around the expansion of every rule of a `macro_rules!` and terminate the last one
+/// with a semicolon, as prettyplease does.
+fn normalize_rules(rules: TokenStream) -> TokenStream {
+    let mut normalized = Vec::new();
+    for tree in rules {
+        let tree = match tree {
+            TokenTree::Group(group) if matches!(normalized.last(), Some(TokenTree::Punct(punct)) if punct.as_char() == '>') => {
+                TokenTree::Group(Group::new(Delimiter::Brace, group.stream()))
+            }
+            tree => tree,
+        };
+        normalized.push(tree);
+    }
+    if matches!(normalized.last(), Some(TokenTree::Group(_))) {
+        normalized.push(TokenTree::Punct(Punct::new(';', Spacing::Alone)));
+    }
+    normalized.into_iter().collect()
+}
+
+impl VisitMut for Normalize {
+    /// prettyplease trims the lines of doc comments.
+    fn visit_attribute_mut(&mut self, attr: &mut Attribute) {
+        if let Meta::NameValue(MetaNameValue {
+            path,
+            value: Expr::Lit(ExprLit {
+                lit: Lit::Str(doc), ..
+            }),
+            ..
+        }) = &mut attr.meta
+        {
+            if path.is_ident("doc") {
+                let value = doc.value();
+                let lines = value.lines().map(str::trim_end).collect::<Vec<_>>();
+                *doc = LitStr::new(&lines.join("\n"), doc.span());
+            }
+        }
+    }
+
+    fn visit_arm_mut(&mut self, arm: &mut Arm) {
+        visit_mut::visit_arm_mut(self, arm);
+        arm.comma = None;
+        let body = mem::replace(&mut *arm.body, Expr::Verbatim(TokenStream::new()));
+        *arm.body = unwrap_body(body);
+    }
+
+    fn visit_expr_closure_mut(&mut self, closure: &mut ExprClosure) {
+        visit_mut::visit_expr_closure_mut(self, closure);
+        if let ReturnType::Default = closure.output {
+            let body = mem::replace(&mut *closure.body, Expr::Verbatim(TokenStream::new()));
+            *closure.body = unwrap_body(body);
+        }
+    }
+
+    fn visit_item_macro_mut(&mut self, item: &mut ItemMacro) {
+        visit_mut::visit_item_macro_mut(self, item);
+        if item.mac.path.is_ident("macro_rules") {
+            item.mac.tokens = normalize_rules(mem::take(&mut item.mac.tokens));
+        }
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        visit_mut::visit_stmt_mut(self, stmt);
+        match stmt {
+            Stmt::Expr(expr, semi) if is_block_like(expr) => *semi = None,
+            Stmt::Expr(expr, semi) if is_unit(expr) => *semi = Some(Semi::default()),
+            Stmt::Macro(mac) if matches!(mac.mac.delimiter, MacroDelimiter::Brace(_)) => {
+                mac.semi_token = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_where_clause_mut(&mut self, clause: &mut WhereClause) {
+        visit_mut::visit_where_clause_mut(self, clause);
+        clause.predicates.pop_punct();
+    }
+}
+
+/// The normalized tokens of the source file `source` of the edition `edition` once `transform` is
+/// applied to it, `None` if it can't be parsed.
+fn tokens(
+    source: &str,
+    edition: Edition,
+    transform: impl FnOnce(&mut syn::File),
+) -> Option<Vec<Token>> {
+    let mut file = edition::parse_file(source, edition).ok()?;
+    transform(&mut file);
+    Normalize.visit_file_mut(&mut file);
+    let mut tokens = Vec::new();
+    flatten(file.into_token_stream(), &mut tokens);
+    Some(tokens)
+}
+
+/// Whether the source file `formatted` has the same tokens as `original` once `transform` is
+/// applied to it, whatever their spacing and comments, both being `edition` sources.
+pub(crate) fn same_tokens(
+    original: &str,
+    formatted: &str,
+    edition: Edition,
+    transform: impl FnOnce(&mut syn::File),
+) -> bool {
+    match (
+        tokens(original, edition, transform),
+        tokens(formatted, edition, |_| {}),
+    ) {
+        (Some(original), Some(formatted)) => original == formatted,
+        _ => false,
+    }
+}
+
+/// Whether the token streams `original` and `formatted` are the same, whatever their spacing and
+/// trailing commas, for tokens that are not parsed like the bodies of macros.
+pub(crate) fn same_stream(original: TokenStream, formatted: TokenStream) -> bool {
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    flatten(original, &mut left);
+    flatten(formatted, &mut right);
+    left == right
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_tokens() {
+        assert!(same_tokens(
+            "fn  main( a:u8, ){ // hi\n match a { 1=>(), 2=>{ b(); } _=>c(a,b,) } m!{} }",
+            "fn main(a: u8) {\n    match a {\n        1 => {}\n        2 => {\n            b();\n        }\n        _ => {\n            c(a, b)\n        }\n    }\n    m! {};\n}\n",
+            Edition::default(),
+            |_| {},
+        ));
+        assert!(same_tokens(
+            "/// Docs.\nstruct A<T,>(T);",
+            "/// Docs.\nstruct A<T>(T);\n",
+            Edition::default(),
+            |_| {},
+        ));
+        assert!(same_tokens(
+            "macro_rules! m { () => ( x ) } fn f() { if a { b = c } else { d } v.map(|x| m!{x}) }",
+            "macro_rules! m {\n    () => {\n        x\n    };\n}\nfn f() {\n    if a {\n        b = c;\n    } else {\n        d\n    }\n    v.map(|x| { m! { x } })\n}\n",
+            Edition::default(),
+            |_| {},
+        ));
+        assert!(same_tokens(
+            "/// Line  \nfn a() {}",
+            "/// Line\nfn a() {}\n",
+            Edition::default(),
+            |_| {}
+        ));
+        assert!(!same_tokens(
+            "fn main() { a + b }",
+            "fn main() {\n    a - b\n}\n",
+            Edition::default(),
+            |_| {},
+        ));
+        assert!(!same_tokens(
+            "const A: u8 = 1;",
+            "const A: u8 = 0x1;\n",
+            Edition::default(),
+            |_| {}
+        ));
+    }
+}
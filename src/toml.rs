@@ -0,0 +1,523 @@
+//! A small TOML reader, covering what configuration files and cargo manifests actually use.
+//!
+//! Dates and times are kept as plain strings, since nothing in here needs them.
+
+use std::collections::BTreeMap;
+
+pub type Table = BTreeMap<String, Value>;
+
+/// A parsed TOML value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Return the array as a list of strings, or `None` if any of the items is not a string.
+    pub fn as_str_array(&self) -> Option<Vec<String>> {
+        self.as_array()?
+            .iter()
+            .map(|value| value.as_str().map(String::from))
+            .collect()
+    }
+
+    /// Human readable name of the value type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+            Value::Table(_) => "table",
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+/// Parse a TOML document into its root table.
+pub fn parse(src: &str) -> Result<Table, String> {
+    let mut parser = Parser {
+        src,
+        pos: 0,
+        line: 1,
+    };
+    parser
+        .document()
+        .map_err(|err| format!("line {}: {}", parser.line, err))
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", c))
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skip whitespace, newlines and comments, as allowed inside arrays.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.skip_whitespace();
+            self.skip_comment();
+            if !(self.eat('\n') || self.eat('\r')) {
+                break;
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        self.skip_comment();
+        self.eat('\r');
+        if self.peek().is_none() || self.eat('\n') {
+            Ok(())
+        } else {
+            Err("expected a newline after the value".to_string())
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        // Path of the table currently being filled.
+        let mut current: Vec<String> = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.bump();
+                    let array = self.eat('[');
+                    self.skip_whitespace();
+                    current = self.key()?;
+                    self.skip_whitespace();
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                        let (last, parents) = current.split_last().expect("keys are never empty");
+                        let parent = table_at(&mut root, parents)?;
+                        let entry = parent
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(Vec::new()));
+                        match entry {
+                            Value::Array(items) => items.push(Value::Table(Table::new())),
+                            _ => return Err(format!("key `{}` is not an array", last)),
+                        }
+                    } else {
+                        table_at(&mut root, &current)?;
+                    }
+                    self.end_of_line()?;
+                }
+                Some(_) => {
+                    let key = self.key()?;
+                    self.skip_whitespace();
+                    self.expect('=')?;
+                    self.skip_whitespace();
+                    let value = self.value()?;
+                    let table = table_at(&mut root, &current)?;
+                    insert(table, &key, value)?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+    }
+
+    /// Parse a possibly dotted key.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                    {
+                        self.bump();
+                    }
+                    if start == self.pos {
+                        return Err("expected a key".to_string());
+                    }
+                    self.src[start..self.pos].to_string()
+                }
+            };
+            parts.push(part);
+            self.skip_whitespace();
+            if !self.eat('.') {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.rest().starts_with("\"\"\"") => {
+                self.multiline_basic_string().map(Value::String)
+            }
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') if self.rest().starts_with("'''") => {
+                self.multiline_literal_string().map(Value::String)
+            }
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t') if self.rest().starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Boolean(true))
+            }
+            Some('f') if self.rest().starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Boolean(false))
+            }
+            Some(_) => self.scalar(),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    /// Numbers, dates and special floats.
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-_.:".contains(c)) {
+            self.bump();
+        }
+        // Date-times separated by a space.
+        if self.peek() == Some(' ')
+            && self.src[start..self.pos].len() == 10
+            && self.rest()[1..].starts_with(|c: char| c.is_ascii_digit())
+        {
+            self.bump();
+            while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || "+-_.:".contains(c))
+            {
+                self.bump();
+            }
+        }
+        let raw = &self.src[start..self.pos];
+        if raw.is_empty() {
+            return Err("expected a value".to_string());
+        }
+        let number = raw.replace('_', "");
+        let integer = if let Some(hex) = number.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(oct) = number.strip_prefix("0o") {
+            i64::from_str_radix(oct, 8).ok()
+        } else if let Some(bin) = number.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2).ok()
+        } else {
+            number.parse().ok()
+        };
+        if let Some(integer) = integer {
+            return Ok(Value::Integer(integer));
+        }
+        match number.as_str() {
+            "inf" | "+inf" => return Ok(Value::Float(f64::INFINITY)),
+            "-inf" => return Ok(Value::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Float(f64::NAN)),
+            _ => {}
+        }
+        if let Ok(float) = number.parse() {
+            return Ok(Value::Float(float));
+        }
+        if raw.starts_with(|c: char| c.is_ascii_digit()) && raw.contains(['-', ':']) {
+            return Ok(Value::String(raw.to_string()));
+        }
+        Err(format!("invalid value `{}`", raw))
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia();
+            if self.eat(']') {
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_trivia();
+            if !self.eat(',') {
+                self.skip_trivia();
+                self.expect(']')?;
+                return Ok(Value::Array(items));
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut table = Table::new();
+        self.skip_whitespace();
+        if self.eat('}') {
+            return Ok(Value::Table(table));
+        }
+        loop {
+            let key = self.key()?;
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = self.value()?;
+            insert(&mut table, &key, value)?;
+            self.skip_whitespace();
+            if self.eat('}') {
+                return Ok(Value::Table(table));
+            }
+            self.expect(',')?;
+            self.skip_whitespace();
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.bump().ok_or("unterminated escape sequence")?;
+        Ok(match c {
+            'b' => '\u{8}',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\u{c}',
+            'r' => '\r',
+            'e' => '\u{1b}',
+            '"' => '"',
+            '\\' => '\\',
+            'u' | 'U' => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex = self.rest().get(..len).ok_or("invalid unicode escape")?;
+                let code = u32::from_str_radix(hex, 16).map_err(|_| "invalid unicode escape")?;
+                self.pos += len;
+                char::from_u32(code).ok_or("invalid unicode scalar value")?
+            }
+            c => return Err(format!("invalid escape sequence `\\{}`", c)),
+        })
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.escape()?),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn multiline_basic_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.eat('\r');
+        self.eat('\n');
+        let mut out = String::new();
+        loop {
+            if self.rest().starts_with("\"\"\"") && !self.rest().starts_with("\"\"\"\"") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.bump() {
+                None => return Err("unterminated string".to_string()),
+                Some('\\') if matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) => {
+                    // Line ending backslash: trim all the following whitespace.
+                    while matches!(self.peek(), Some(' ' | '\t' | '\r' | '\n')) {
+                        self.bump();
+                    }
+                }
+                Some('\\') => out.push(self.escape()?),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('\'') => return Ok(self.src[start..self.pos - 1].to_string()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn multiline_literal_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.eat('\r');
+        self.eat('\n');
+        let start = self.pos;
+        loop {
+            if self.rest().starts_with("'''") && !self.rest().starts_with("''''") {
+                let out = self.src[start..self.pos].to_string();
+                self.pos += 3;
+                return Ok(out);
+            }
+            self.bump().ok_or("unterminated string")?;
+        }
+    }
+}
+
+/// Return the table at `path`, creating intermediate tables and descending into the last element
+/// of arrays of tables.
+fn table_at<'t>(mut table: &'t mut Table, path: &[String]) -> Result<&'t mut Table, String> {
+    for key in path {
+        let entry = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(table) => table,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Table(table)) => table,
+                _ => return Err(format!("key `{}` is not a table", key)),
+            },
+            _ => return Err(format!("key `{}` is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Table, key: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = key.split_last().expect("keys are never empty");
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("duplicate key `{}`", last));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_manifest() {
+        let root = parse(
+            r#"
+# a comment
+[package]
+name = "prettythanks" # trailing
+version = '0.1.0'
+keywords = [
+    "formatting",
+    "pretty", # with a comment
+]
+
+[dependencies]
+syn = { version = "2.0", default-features = false, features = ["parsing"] }
+
+[[bin]]
+name = "one"
+[[bin]]
+name = "two"
+
+[package.metadata.prettythanks]
+jobs = 4
+check = true
+"#,
+        )
+        .unwrap();
+        let table = |value: &Value| match value {
+            Value::Table(table) => table.clone(),
+            _ => panic!("not a table"),
+        };
+        let package = table(&root["package"]);
+        assert_eq!(package["name"].as_str(), Some("prettythanks"));
+        assert_eq!(
+            package["keywords"].as_str_array(),
+            Some(vec!["formatting".to_string(), "pretty".to_string()])
+        );
+        assert_eq!(
+            table(&table(&root["dependencies"])["syn"])["default-features"].as_bool(),
+            Some(false)
+        );
+        assert_eq!(root["bin"].as_array().unwrap().len(), 2);
+        let metadata = table(&table(&package["metadata"])["prettythanks"]);
+        assert_eq!(metadata["jobs"], Value::Integer(4));
+    }
+
+    #[test]
+    fn reports_errors_with_line() {
+        assert_eq!(
+            parse("a = 1\nb = \n").unwrap_err(),
+            "line 2: expected a value"
+        );
+        assert!(parse("a = 1\na = 2\n")
+            .unwrap_err()
+            .contains("duplicate key"));
+    }
+}
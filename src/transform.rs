@@ -0,0 +1,193 @@
+//! Rewrites of the parsed sources, run between parsing and printing them.
+//!
+//! The built-in transforms are picked by name in the configuration file and on the command line,
+//! see [`builtin`]. Others can be added to [`Options::transforms`](crate::Options::transforms)
+//! when using the library:
+//!
+//! ```
+//! use prettythanks::{transform::Transform, Options};
+//!
+//! struct NoConsts;
+//!
+//! impl Transform for NoConsts {
+//!     fn name(&self) -> &str {
+//!         "no-consts"
+//!     }
+//!
+//!     fn apply(&self, file: &mut syn::File) {
+//!         file.items.retain(|item| !matches!(item, syn::Item::Const(_)));
+//!     }
+//! }
+//!
+//! let mut options = Options::default();
+//! options.transforms.push(NoConsts);
+//! ```
+//!
+//! Transforms change the tokens of the sources, the check that formatting doesn't is done against
+//! the transformed sources instead.
+
+use crate::{docs, doctests, imports};
+use std::{fmt, sync::Arc};
+
+/// A rewrite of a parsed source file.
+pub trait Transform: Send + Sync {
+    /// The name of the transform, telling it apart from the others. It is part of the cache key,
+    /// so it should change when what the transform does changes.
+    fn name(&self) -> &str;
+
+    /// What the transform does, for the cache key: its name, followed by its settings if it has
+    /// any.
+    fn key(&self) -> String {
+        self.name().to_string()
+    }
+
+    /// Rewrite `file`.
+    fn apply(&self, file: &mut syn::File);
+}
+
+/// Sort the `use` declarations, see [`imports::sort`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SortImports;
+
+impl Transform for SortImports {
+    fn name(&self) -> &'static str {
+        "sort-imports"
+    }
+
+    fn apply(&self, file: &mut syn::File) {
+        imports::sort(file);
+    }
+}
+
+/// Turn block doc comments into line ones and trim them, see [`docs::normalize`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DocComments {
+    /// The column to rewrap their text to, if any.
+    pub width: Option<usize>,
+}
+
+impl Transform for DocComments {
+    fn name(&self) -> &'static str {
+        "doc-comments"
+    }
+
+    fn key(&self) -> String {
+        match self.width {
+            Some(width) => format!("{}={}", self.name(), width),
+            None => self.name().to_string(),
+        }
+    }
+
+    fn apply(&self, file: &mut syn::File) {
+        docs::normalize(file, self.width);
+    }
+}
+
+/// Format the Rust code blocks of doc comments, see [`doctests::format`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FormatDoctests;
+
+impl Transform for FormatDoctests {
+    fn name(&self) -> &'static str {
+        "format-doctests"
+    }
+
+    fn apply(&self, file: &mut syn::File) {
+        doctests::format(file);
+    }
+}
+
+/// The names of the built-in transforms.
+pub const BUILTIN: [&str; 3] = ["sort-imports", "doc-comments", "format-doctests"];
+
+/// The built-in transform called `name`.
+pub fn builtin(name: &str) -> Result<Arc<dyn Transform>, String> {
+    match name {
+        "sort-imports" => Ok(Arc::new(SortImports)),
+        "doc-comments" => Ok(Arc::new(DocComments::default())),
+        "format-doctests" => Ok(Arc::new(FormatDoctests)),
+        _ => Err(format!(
+            "unknown transform `{}`, expected {} or {}",
+            name,
+            BUILTIN[..BUILTIN.len() - 1].join(", "),
+            BUILTIN[BUILTIN.len() - 1]
+        )),
+    }
+}
+
+/// The transforms run on every source, in order.
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    transforms: Vec<Arc<dyn Transform>>,
+}
+
+impl Pipeline {
+    /// Run `transform` after the others, or instead of the one with the same name if there is one.
+    pub fn push(&mut self, transform: impl Transform + 'static) {
+        self.push_arc(Arc::new(transform));
+    }
+
+    /// Like [`Pipeline::push`], for shared transforms such as the ones of [`builtin`].
+    pub fn push_arc(&mut self, transform: Arc<dyn Transform>) {
+        match self
+            .transforms
+            .iter_mut()
+            .find(|other| other.name() == transform.name())
+        {
+            Some(other) => *other = transform,
+            None => self.transforms.push(transform),
+        }
+    }
+
+    /// The names of the transforms, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.transforms.iter().map(|transform| transform.name())
+    }
+
+    /// The keys of the transforms, in order, see [`Transform::key`].
+    pub fn keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.transforms.iter().map(|transform| transform.key())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    /// Run every transform on `file`.
+    pub fn apply(&self, file: &mut syn::File) {
+        for transform in &self.transforms {
+            transform.apply(file);
+        }
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_transforms_in_order() {
+        let mut pipeline = Pipeline::default();
+        pipeline.push_arc(builtin("doc-comments").unwrap());
+        pipeline.push(SortImports);
+        pipeline.push(DocComments { width: Some(80) });
+        assert_eq!(
+            format!("{:?}", pipeline),
+            r#"["doc-comments", "sort-imports"]"#
+        );
+        assert_eq!(
+            pipeline.keys().collect::<Vec<_>>(),
+            ["doc-comments=80", "sort-imports"]
+        );
+        let mut file = syn::parse_file("use b; /** A\n * b */ use a;").unwrap();
+        pipeline.apply(&mut file);
+        assert_eq!(prettyplease::unparse(&file), "/// A b\nuse a;\nuse b;\n");
+        assert!(builtin("sort").is_err());
+    }
+}
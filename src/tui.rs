@@ -0,0 +1,565 @@
+//! A full screen review of what formatting would change, for large one-time conversions: the
+//! files to format on the left, the diff of the selected one on the right, and a summary of what
+//! was decided at the bottom.
+//!
+//! Drawn with ratatui on crossterm, only built with the `tui` feature.
+
+use crate::{format_file_to, term, Entry, Error, Options, PrettyThanks, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    Frame, Terminal,
+};
+use std::io::{self, Stdout};
+
+/// The keys, as printed at the bottom of the screen.
+const HELP: &str = "j/k move  d/u scroll  a accept  r reject  f format now  A accept all  \
+                    q write accepted and quit  Q quit";
+
+/// A key press meaning something to the review.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Accept,
+    Reject,
+    Format,
+    AcceptAll,
+    /// Write the accepted files and leave.
+    Quit,
+    /// Leave without writing anything else.
+    Abort,
+}
+
+impl Key {
+    fn from_event(event: KeyEvent) -> Option<Self> {
+        if event.kind != KeyEventKind::Press {
+            return None;
+        }
+        match event.code {
+            // Ctrl-C, as raw mode doesn't turn it into a signal.
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Key::Abort)
+            }
+            KeyCode::Char('k') | KeyCode::Up => Some(Key::Up),
+            KeyCode::Char('j') | KeyCode::Down => Some(Key::Down),
+            KeyCode::Char('u') | KeyCode::PageUp => Some(Key::PageUp),
+            KeyCode::Char('d' | ' ') | KeyCode::PageDown => Some(Key::PageDown),
+            KeyCode::Char('a') => Some(Key::Accept),
+            KeyCode::Char('r') => Some(Key::Reject),
+            KeyCode::Char('f') => Some(Key::Format),
+            KeyCode::Char('A') => Some(Key::AcceptAll),
+            KeyCode::Char('q') => Some(Key::Quit),
+            KeyCode::Char('Q') => Some(Key::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// What was decided about a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Decision {
+    Pending,
+    Accepted,
+    Rejected,
+    /// Formatted already, with `f`.
+    Formatted,
+    /// Not formattable, only there to show why.
+    Failed,
+}
+
+impl Decision {
+    fn marker(self) -> &'static str {
+        match self {
+            Decision::Pending => "[ ]",
+            Decision::Accepted => "[y]",
+            Decision::Rejected => "[n]",
+            Decision::Formatted => "[f]",
+            Decision::Failed => "[!]",
+        }
+    }
+}
+
+struct Item {
+    path: Utf8PathBuf,
+    /// The diff, or the error.
+    preview: Vec<String>,
+    decision: Decision,
+}
+
+struct State {
+    items: Vec<Item>,
+    /// Files already formatted, only counted.
+    unchanged: usize,
+    /// The selected item, and the first one shown.
+    list: ListState,
+    /// First line of the preview shown.
+    scroll: usize,
+    /// Lines of the preview shown, as last drawn.
+    rows: usize,
+    /// The outcome of the last `f`.
+    message: String,
+    /// Whether the selection and the diffs are colored.
+    color: bool,
+}
+
+impl State {
+    fn new(color: bool) -> Self {
+        State {
+            items: Vec::new(),
+            unchanged: 0,
+            list: ListState::default().with_selected(Some(0)),
+            scroll: 0,
+            rows: 1,
+            message: String::new(),
+            color,
+        }
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    fn count(&self, decision: Decision) -> usize {
+        let items = self.items.iter();
+        items.filter(|item| item.decision == decision).count()
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} to review: {} accepted, {} rejected, {} formatted, {} pending, {} failed; {} \
+             already formatted",
+            self.items.len(),
+            self.count(Decision::Accepted),
+            self.count(Decision::Rejected),
+            self.count(Decision::Formatted),
+            self.count(Decision::Pending),
+            self.count(Decision::Failed),
+            self.unchanged
+        )
+    }
+
+    /// Select the item `idx`, or the last one.
+    fn select(&mut self, idx: usize) {
+        self.list
+            .select(Some(idx.min(self.items.len().saturating_sub(1))));
+        self.scroll = 0;
+    }
+
+    /// Mark the selected file as `decision`, unless it was formatted or failed already.
+    fn decide(&mut self, decision: Decision) {
+        let selected = self.selected();
+        if let Some(item) = self.items.get_mut(selected) {
+            if matches!(
+                item.decision,
+                Decision::Pending | Decision::Accepted | Decision::Rejected
+            ) {
+                item.decision = decision;
+            }
+        }
+    }
+
+    /// Apply `key`, returning it if it is left to the caller: formatting the selected file or
+    /// leaving.
+    fn press(&mut self, key: Key) -> Option<Key> {
+        self.message.clear();
+        let page = (self.rows / 2).max(1);
+        match key {
+            Key::Up => self.select(self.selected().saturating_sub(1)),
+            Key::Down => self.select(self.selected() + 1),
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(page),
+            Key::PageDown => {
+                let len = self
+                    .items
+                    .get(self.selected())
+                    .map_or(0, |item| item.preview.len());
+                self.scroll = (self.scroll + page).min(len.saturating_sub(1));
+            }
+            Key::Accept => {
+                self.decide(Decision::Accepted);
+                self.select(self.selected() + 1);
+            }
+            Key::Reject => {
+                self.decide(Decision::Rejected);
+                self.select(self.selected() + 1);
+            }
+            Key::AcceptAll => {
+                for item in &mut self.items {
+                    if item.decision == Decision::Pending {
+                        item.decision = Decision::Accepted;
+                    }
+                }
+            }
+            Key::Format | Key::Quit | Key::Abort => return Some(key),
+        }
+        None
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, message, help] = Layout::vertical([
+            Constraint::Min(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let list_width = (main.width / 3).clamp(12, 40).min(main.width);
+        let [list, preview] =
+            Layout::horizontal([Constraint::Length(list_width), Constraint::Min(0)]).areas(main);
+        let entries = self
+            .items
+            .iter()
+            .map(|item| ListItem::new(format!("{} {}", item.decision.marker(), item.path)));
+        let highlight = if self.color {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        let entries = List::new(entries)
+            .block(Block::default().borders(Borders::RIGHT))
+            .highlight_symbol(">")
+            .highlight_style(highlight);
+        frame.render_stateful_widget(entries, list, &mut self.list);
+        self.rows = usize::from(preview.height).max(1);
+        let lines = self
+            .items
+            .get(self.selected())
+            .map_or(&[][..], |item| &item.preview)
+            .iter()
+            .skip(self.scroll)
+            .take(self.rows)
+            .map(|line| {
+                let line = line.replace('\t', "    ");
+                let color = match line.as_bytes().first() {
+                    _ if !self.color => None,
+                    Some(b'+') => Some(Color::Green),
+                    Some(b'-') => Some(Color::Red),
+                    Some(b'@') => Some(Color::Cyan),
+                    _ => None,
+                };
+                match color {
+                    Some(color) => Line::styled(line, Style::default().fg(color)),
+                    None => Line::raw(line),
+                }
+            })
+            .collect::<Vec<_>>();
+        frame.render_widget(Paragraph::new(lines), preview);
+        let summary = if self.message.is_empty() {
+            self.summary()
+        } else {
+            format!("{} | {}", self.message, self.summary())
+        };
+        frame.render_widget(Paragraph::new(summary), message);
+        frame.render_widget(Paragraph::new(HELP), help);
+    }
+}
+
+/// The terminal in raw mode on the alternate screen, put back once dropped.
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        let unsupported = |_| {
+            Error::Unsupported("the review needs stdin and stdout to be a terminal".to_string())
+        };
+        terminal::enable_raw_mode().map_err(unsupported)?;
+        let terminal = execute!(io::stdout(), EnterAlternateScreen)
+            .and_then(|()| Terminal::new(CrosstermBackend::new(io::stdout())));
+        match terminal {
+            Ok(terminal) => Ok(Screen { terminal }),
+            Err(err) => {
+                leave();
+                Err(unsupported(err))
+            }
+        }
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.terminal.show_cursor();
+        leave();
+    }
+}
+
+/// Put the terminal back as it was before [`Screen::enter`].
+fn leave() {
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+impl PrettyThanks {
+    /// Review what formatting would change in a full screen terminal interface, writing the
+    /// files accepted once done.
+    pub fn tui(&self) -> Result<()> {
+        if self.paths.iter().any(|path| path == "-") || self.options.staged {
+            return Err(Error::Unsupported(
+                "the review can't be used with stdin or --staged".to_string(),
+            ));
+        }
+        if !term::is_terminal(term::Stream::Stdout) {
+            return Err(Error::Unsupported(
+                "the review needs stdout to be a terminal".to_string(),
+            ));
+        }
+        let preview = PrettyThanks {
+            paths: self.paths.clone(),
+            options: Options {
+                diff: true,
+                check: false,
+                list: false,
+                dry_run: false,
+                interactive: false,
+                progress: false,
+                ..self.options.clone()
+            },
+            reporter: self.reporter.clone(),
+            cancel: self.cancel.clone(),
+        };
+        let mut state = State::new(term::color_enabled(term::Stream::Stdout));
+        for entry in preview.report().entries {
+            let (path, preview, decision) = match entry {
+                Entry::File(file) => match file.result {
+                    Ok(outcome) if outcome.changed && !outcome.skipped => {
+                        let diff = outcome.output.lines().map(String::from).collect();
+                        (file.path, diff, Decision::Pending)
+                    }
+                    Ok(_) => {
+                        state.unchanged += 1;
+                        continue;
+                    }
+                    Err(err) => (file.path, error_lines(&err), Decision::Failed),
+                },
+                Entry::Error(err) => {
+                    let path = err
+                        .path()
+                        .map_or_else(Utf8PathBuf::new, Utf8Path::to_path_buf);
+                    (path, error_lines(&err), Decision::Failed)
+                }
+                _ => continue,
+            };
+            state.items.push(Item {
+                path,
+                preview,
+                decision,
+            });
+        }
+        if state.items.is_empty() {
+            println!(
+                "nothing to review, {} files already formatted",
+                state.unchanged
+            );
+            return Ok(());
+        }
+        let mut errors = Vec::new();
+        let quit = {
+            let mut screen = Screen::enter()?;
+            self.review(&mut screen, &mut state, &mut errors)?
+        };
+        let mut written = state.count(Decision::Formatted);
+        if quit == Key::Quit && state.count(Decision::Accepted) > 0 {
+            let _lock = self.lock(true)?;
+            for item in &state.items {
+                if item.decision == Decision::Accepted {
+                    match format_file_to(&item.path, &self.destination(&item.path), &self.options) {
+                        Ok(_) => written += 1,
+                        Err(err) => errors.push(err),
+                    }
+                }
+            }
+        }
+        println!(
+            "formatted {} files, left {} untouched",
+            written,
+            state.items.len() - written - state.count(Decision::Failed)
+        );
+        Error::from_errors(errors).map_or(Ok(()), Err)
+    }
+
+    /// Draw and handle keys until asked to leave, returning how.
+    fn review(
+        &self,
+        screen: &mut Screen,
+        state: &mut State,
+        errors: &mut Vec<Error>,
+    ) -> Result<Key> {
+        loop {
+            screen
+                .terminal
+                .draw(|frame| state.draw(frame))
+                .map_err(|err| Error::io(Utf8Path::new(crate::error::STDOUT), err))?;
+            // Anything else, like a resize, only redraws.
+            let key = match event::read().map_err(|err| Error::io(Utf8Path::new("-"), err))? {
+                Event::Key(event) => Key::from_event(event),
+                _ => None,
+            };
+            match key.and_then(|key| state.press(key)) {
+                Some(Key::Format) => self.format_selected(state, errors),
+                Some(key) => return Ok(key),
+                None => {}
+            }
+        }
+    }
+
+    /// Format the selected file right away, unless it was already or can't be.
+    fn format_selected(&self, state: &mut State, errors: &mut Vec<Error>) {
+        let selected = state.selected();
+        let item = &mut state.items[selected];
+        if matches!(item.decision, Decision::Formatted | Decision::Failed) {
+            return;
+        }
+        let target = self.destination(&item.path);
+        let formatted = self
+            .lock(true)
+            .and_then(|_lock| format_file_to(&item.path, &target, &self.options));
+        match formatted {
+            Ok(_) => {
+                state.message = format!("formatted {}", item.path);
+                item.decision = Decision::Formatted;
+            }
+            Err(err) => {
+                state.message = err.to_string();
+                errors.push(err);
+            }
+        }
+    }
+}
+
+fn error_lines(err: &Error) -> Vec<String> {
+    let mut lines = vec![format!("error: {}", err)];
+    if let Some(snippet) = err.snippet() {
+        lines.extend(snippet.lines().map(String::from));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    fn state(decisions: &[Decision]) -> State {
+        let mut state = State::new(false);
+        for (idx, &decision) in decisions.iter().enumerate() {
+            state.items.push(Item {
+                path: format!("{}.rs", char::from(b'a' + u8::try_from(idx).unwrap())).into(),
+                preview: vec!["-fn  a(){}".to_string(), "+fn a() {}".to_string()],
+                decision,
+            });
+        }
+        state
+    }
+
+    /// The text of the screen `state` draws on a `width` by `height` terminal.
+    fn screen(state: &mut State, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| state.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..height)
+            .map(|y| {
+                let line = (0..width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>();
+                line.trim_end().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn maps_keys() {
+        let key = |code, modifiers| Key::from_event(KeyEvent::new(code, modifiers));
+        assert_eq!(key(KeyCode::Down, KeyModifiers::NONE), Some(Key::Down));
+        assert_eq!(key(KeyCode::Char('j'), KeyModifiers::NONE), Some(Key::Down));
+        assert_eq!(
+            key(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            Some(Key::AcceptAll)
+        );
+        assert_eq!(
+            key(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Some(Key::Abort)
+        );
+        assert_eq!(key(KeyCode::Char('c'), KeyModifiers::NONE), None);
+        assert_eq!(key(KeyCode::Char('z'), KeyModifiers::NONE), None);
+        let mut release = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        release.kind = KeyEventKind::Release;
+        assert_eq!(Key::from_event(release), None);
+    }
+
+    #[test]
+    fn handles_keys() {
+        let mut state = state(&[Decision::Pending, Decision::Failed, Decision::Pending]);
+        assert_eq!(state.press(Key::Up), None);
+        assert_eq!(state.selected(), 0);
+        // Accepting moves on, past the failed file which can't be decided.
+        assert_eq!(state.press(Key::Accept), None);
+        assert_eq!(state.selected(), 1);
+        state.press(Key::Reject);
+        assert_eq!(state.items[1].decision, Decision::Failed);
+        assert_eq!(state.selected(), 2);
+        state.press(Key::Down);
+        assert_eq!(state.selected(), 2);
+        state.press(Key::Reject);
+        state.press(Key::Up);
+        state.press(Key::Up);
+        state.press(Key::Reject);
+        let decisions = state
+            .items
+            .iter()
+            .map(|item| item.decision)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            decisions,
+            [Decision::Rejected, Decision::Failed, Decision::Rejected]
+        );
+        state.items[0].decision = Decision::Pending;
+        state.press(Key::AcceptAll);
+        assert_eq!(state.count(Decision::Accepted), 1);
+        assert_eq!(state.count(Decision::Rejected), 1);
+        // Scrolling stops at the last line of the preview.
+        state.press(Key::PageDown);
+        state.press(Key::PageDown);
+        assert_eq!(state.scroll, 1);
+        state.press(Key::PageUp);
+        assert_eq!(state.scroll, 0);
+        state.message = "formatted a.rs".to_string();
+        assert_eq!(state.press(Key::Format), Some(Key::Format));
+        assert!(state.message.is_empty());
+        assert_eq!(state.press(Key::Quit), Some(Key::Quit));
+        assert_eq!(state.press(Key::Abort), Some(Key::Abort));
+    }
+
+    #[test]
+    fn renders_screen() {
+        let mut state = state(&[Decision::Pending, Decision::Pending]);
+        state.unchanged = 3;
+        state.press(Key::Accept);
+        let lines = screen(&mut state, 36, 4);
+        assert_eq!(
+            lines,
+            [
+                " [y] a.rs  │-fn  a(){}",
+                ">[ ] b.rs  │+fn a() {}",
+                "2 to review: 1 accepted, 0 rejected,",
+                "j/k move  d/u scroll  a accept  r re",
+            ]
+        );
+        // The list follows the selection.
+        let lines = screen(&mut state, 36, 3);
+        assert_eq!(lines[0], ">[ ] b.rs  │-fn  a(){}");
+        state.press(Key::PageDown);
+        let lines = screen(&mut state, 36, 3);
+        assert_eq!(lines[0], ">[ ] b.rs  │+fn a() {}");
+    }
+}
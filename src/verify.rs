@@ -0,0 +1,179 @@
+//! Verification of prettyplease itself: every source printed by it must parse again, keep its
+//! tokens, and print the same once printed again, see [`PrettyThanks::verify`].
+//!
+//! Unlike formatting, comments, transforms and skip markers are left out, so that what is found
+//! can be reported upstream as it is.
+
+use crate::{
+    diff,
+    edition::{self, Edition},
+    markdown, read_source, split_prefix, tokens, Error, PrettyThanks, Result, Task,
+};
+use std::fmt;
+use syn::spanned::Spanned;
+
+/// What prettyplease gets wrong printing a source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// What it prints doesn't parse, with the error parsing it.
+    Unparsable(String),
+    /// What it prints doesn't have the same tokens as the source.
+    TokensChanged,
+    /// Printing what it prints changes it again, with the diff from the first pass to the second.
+    NotIdempotent(String),
+}
+
+impl fmt::Display for Misbehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Misbehavior::Unparsable(err) => {
+                write!(f, "prettyplease prints code that doesn't parse: {}", err)
+            }
+            Misbehavior::TokensChanged => f.write_str("prettyplease changes its tokens"),
+            Misbehavior::NotIdempotent(diff) => write!(
+                f,
+                "printing it again with prettyplease changes it:\n{}",
+                diff.trim_end()
+            ),
+        }
+    }
+}
+
+/// A source prettyplease misbehaves on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// What it gets wrong.
+    pub misbehavior: Misbehavior,
+    /// The first top level item of the source it gets wrong on its own, if any.
+    pub reproducer: Option<String>,
+}
+
+/// How prettyplease misbehaves printing the already parsed `file`, if it does.
+fn misbehaves(source: &str, file: &syn::File, edition: Edition) -> Option<Misbehavior> {
+    let printed = prettyplease::unparse(file);
+    let reparsed = match edition::parse_file(&printed, edition) {
+        Ok(reparsed) => reparsed,
+        Err(err) => return Some(Misbehavior::Unparsable(err.to_string())),
+    };
+    if !tokens::same_tokens(source, &printed, edition, |_| {}) {
+        return Some(Misbehavior::TokensChanged);
+    }
+    let again = prettyplease::unparse(&reparsed);
+    (again != printed).then(|| {
+        Misbehavior::NotIdempotent(diff::unified(
+            &printed,
+            &again,
+            "first pass",
+            "second pass",
+            diff::CONTEXT_LINES,
+        ))
+    })
+}
+
+/// Check prettyplease on `source`, a source file of the edition `edition`, `None` if it behaves.
+///
+/// # Errors
+///
+/// If `source` doesn't parse, which is not for prettyplease to deal with.
+pub fn check(source: &str, edition: Edition) -> syn::Result<Option<Finding>> {
+    // The offsets of the items are in what is left once the prefix is dropped.
+    let (_, rest) = split_prefix(source);
+    let file = edition::parse_file(rest, edition)?;
+    let misbehavior = match misbehaves(rest, &file, edition) {
+        Some(misbehavior) => misbehavior,
+        None => return Ok(None),
+    };
+    let reproducer = file.items.iter().find_map(|item| {
+        let text = rest.get(item.span().byte_range())?;
+        let file = edition::parse_file(text, edition).ok()?;
+        misbehaves(text, &file, edition).map(|_| text.to_string())
+    });
+    Ok(Some(Finding {
+        misbehavior,
+        reproducer,
+    }))
+}
+
+impl PrettyThanks {
+    /// Check prettyplease on every Rust file found, printing the ones it misbehaves on with what
+    /// it gets wrong, and the smallest item reproducing it when there is one.
+    ///
+    /// Files that don't parse are skipped, and nothing is written.
+    ///
+    /// # Errors
+    ///
+    /// With [`Error::Misbehaving`] if prettyplease misbehaves on any file, and the errors hit
+    /// reading the files.
+    pub fn verify(&self) -> Result<()> {
+        if self.paths.iter().any(|path| path == "-") {
+            return Err(Error::Unsupported(
+                "the verification can't be used with stdin".to_string(),
+            ));
+        }
+        let mut errors = Vec::new();
+        let (mut verified, mut misbehaving) = (0, 0);
+        for task in self.collect() {
+            let (path, options) = match task {
+                Task::File(path, options) if !markdown::is_markdown(&path) => (path, options),
+                Task::Error(err) => {
+                    errors.push(err);
+                    continue;
+                }
+                _ => continue,
+            };
+            let options = options.as_deref().unwrap_or(&self.options);
+            let source = match read_source(&path, options) {
+                Ok((source, _, _)) => source,
+                Err(err) => {
+                    errors.push(err);
+                    continue;
+                }
+            };
+            let finding = match check(&source, options.edition_for(&path).unwrap_or_default()) {
+                Ok(finding) => finding,
+                Err(err) => {
+                    info!("skipping {}, it doesn't parse: {}", path, err);
+                    continue;
+                }
+            };
+            verified += 1;
+            let finding = match finding {
+                Some(finding) => finding,
+                None => continue,
+            };
+            misbehaving += 1;
+            println!("{}: {}", path, finding.misbehavior);
+            if let Some(reproducer) = finding.reproducer {
+                println!("reproduced by:\n{}", reproducer.trim_end());
+            }
+        }
+        info!(
+            "verification completed, prettyplease misbehaves on {} of {} files",
+            misbehaving, verified
+        );
+        if misbehaving > 0 {
+            errors.push(Error::Misbehaving(misbehaving));
+        }
+        Error::from_errors(errors).map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+
+    #[test]
+    fn checks_prettyplease() {
+        let source = "#!/usr/bin/env run-cargo-script\nfn main(){let x=1;}\nstruct A;\n";
+        assert_eq!(check(source, Edition::E2021).unwrap(), None);
+        assert!(check("fn main(", Edition::E2021).is_err());
+        let misbehavior = Misbehavior::NotIdempotent("-a\n+b\n".to_string());
+        assert_eq!(
+            misbehavior.to_string(),
+            "printing it again with prettyplease changes it:\n-a\n+b"
+        );
+        let thanks = PrettyThanks::new(&["fixtures".to_string()], Options::default()).unwrap();
+        thanks.verify().unwrap();
+    }
+}
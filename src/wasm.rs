@@ -0,0 +1,14 @@
+//! JavaScript bindings, for `wasm32-unknown-unknown` builds with the `wasm` feature.
+
+use wasm_bindgen::prelude::*;
+
+/// Format the Rust source file `source`, failing with the message of the error if it doesn't
+/// parse.
+///
+/// # Errors
+///
+/// If `source` is not valid Rust.
+#[wasm_bindgen]
+pub fn format(source: &str) -> Result<String, JsValue> {
+    crate::format_str(source).map_err(|err| JsValue::from_str(&err.to_string()))
+}
@@ -0,0 +1,174 @@
+//! Polling based watch mode, reformatting files as they are saved.
+//!
+//! Polling keeps us free of platform specific notification APIs and their dependencies, and is
+//! cheap enough for source trees.
+
+use crate::{Error, FileReport, Options, PrettyThanks, Result, Task};
+use camino::Utf8PathBuf;
+use std::{collections::BTreeMap, fs, sync::Arc, thread, time::Duration, time::SystemTime};
+
+/// How often the tree is scanned for modifications.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a file must stay untouched before it is formatted, so editors can finish writing.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Sorted by path, so that changed files are formatted in the same order as the first time.
+/// Along with their modification times are the options of their nested configuration files.
+type Snapshot = BTreeMap<Utf8PathBuf, (SystemTime, Option<Arc<Options>>)>;
+
+impl PrettyThanks {
+    /// Format everything once, then keep formatting files as they change, forever.
+    pub fn watch(&self) -> Result<()> {
+        if self.paths.iter().any(|path| path == "-") {
+            return Err(Error::Unsupported("stdin can't be watched".to_string()));
+        }
+        if let Err(err) = self.run() {
+            log_error(&err);
+        }
+        println!("watching for changes...");
+        let mut snapshot = self.snapshot();
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = self.snapshot();
+            let changed = changed(&snapshot, &current);
+            if changed.is_empty() {
+                snapshot = current;
+                continue;
+            }
+            // Give editors the time to finish their write, files still changing are picked up
+            // by the next poll.
+            thread::sleep(DEBOUNCE);
+            let settled = self.snapshot();
+            let (stable, unstable) = settle(changed, &current, &settled);
+            let files = stable
+                .iter()
+                .map(|path| {
+                    let options = settled
+                        .get(path)
+                        .and_then(|(_, options)| options.as_deref());
+                    (path.as_path(), options.unwrap_or(&self.options))
+                })
+                .collect::<Vec<_>>();
+            // Changed files are formatted by a later round while another run holds the lock.
+            let _lock = match self.lock(self.options.writes() && !files.is_empty()) {
+                Ok(lock) => lock,
+                Err(err) => {
+                    log_error(&err);
+                    continue;
+                }
+            };
+            for ((path, _), result) in files.iter().zip(self.format_files(&files, None)) {
+                let file = FileReport {
+                    path: path.to_path_buf(),
+                    result,
+                };
+                if let Err(err) = self.print_file(file) {
+                    log_error(&err);
+                }
+            }
+            // Taken after writing, so our own writes never trigger another round.
+            snapshot = self.snapshot();
+            for path in &unstable {
+                snapshot.remove(path);
+            }
+        }
+    }
+
+    /// Modification times of all the files that would be formatted.
+    fn snapshot(&self) -> Snapshot {
+        self.collect()
+            .into_iter()
+            .filter_map(|task| match task {
+                Task::File(path, options) => {
+                    let mtime = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+                    Some((path, (mtime, options)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The files of `after` new or modified since `before`, the deleted ones having nothing left to
+/// format.
+fn changed(before: &Snapshot, after: &Snapshot) -> Vec<Utf8PathBuf> {
+    after
+        .iter()
+        .filter(|(path, (mtime, _))| before.get(*path).map(|(mtime, _)| mtime) != Some(mtime))
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// The `changed` files left as they were in `current` by the time of `settled`, then the others,
+/// still being written or deleted since.
+fn settle(
+    changed: Vec<Utf8PathBuf>,
+    current: &Snapshot,
+    settled: &Snapshot,
+) -> (Vec<Utf8PathBuf>, Vec<Utf8PathBuf>) {
+    changed.into_iter().partition(|path| {
+        settled.get(path).map(|(mtime, _)| mtime) == current.get(path).map(|(mtime, _)| mtime)
+    })
+}
+
+/// Log `err` like the binary prints the errors failing a run, those of [`Error::Multiple`] one by
+/// one, watching going on.
+fn log_error(err: &Error) {
+    if let Error::Multiple(errors) = err {
+        errors.iter().for_each(log_error);
+    } else if let Some(snippet) = err.snippet() {
+        error!("{}\n{}", err, snippet.trim_end());
+    } else {
+        error!("{}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp::TempDir;
+
+    fn snapshot(files: &[(&str, u64)]) -> Snapshot {
+        let files = files.iter().map(|&(path, secs)| {
+            let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            (path.into(), (mtime, None))
+        });
+        files.collect()
+    }
+
+    #[test]
+    fn finds_changed_files() {
+        let before = snapshot(&[("a.rs", 1), ("b.rs", 1), ("c.rs", 1)]);
+        // `a.rs` saved again, `b.rs` untouched, `c.rs` deleted and `d.rs` created.
+        let after = snapshot(&[("a.rs", 2), ("b.rs", 1), ("d.rs", 1)]);
+        assert_eq!(changed(&before, &after), ["a.rs", "d.rs"]);
+        assert!(changed(&after, &after).is_empty());
+        // Still being written, or deleted, while debouncing.
+        let settled = snapshot(&[("a.rs", 2), ("d.rs", 3)]);
+        let (stable, unstable) = settle(changed(&before, &after), &after, &settled);
+        assert_eq!(
+            (stable, unstable),
+            (vec!["a.rs".into()], vec!["d.rs".into()])
+        );
+        let settled = snapshot(&[("d.rs", 1)]);
+        let (stable, unstable) = settle(changed(&before, &after), &after, &settled);
+        assert_eq!(
+            (stable, unstable),
+            (vec!["d.rs".into()], vec!["a.rs".into()])
+        );
+    }
+
+    #[test]
+    fn snapshots_files() {
+        let temp = TempDir::new("watch");
+        fs::write(temp.join("a.rs"), "fn main() {}\n").unwrap();
+        fs::write(temp.join("notes.txt"), "").unwrap();
+        let thanks = PrettyThanks::new(&[temp.to_string()], Options::default()).unwrap();
+        let before = thanks.snapshot();
+        assert_eq!(before.keys().collect::<Vec<_>>(), [&temp.join("a.rs")]);
+        fs::write(temp.join("b.rs"), "fn main() {}\n").unwrap();
+        fs::remove_file(temp.join("a.rs")).unwrap();
+        let after = thanks.snapshot();
+        assert_eq!(changed(&before, &after), [temp.join("b.rs")]);
+    }
+}
@@ -0,0 +1,266 @@
+//! XML reports, for the CI systems and dashboards that ingest them natively.
+
+use crate::{Entry, Error, Report};
+use std::{fmt::Write, time::Duration};
+
+/// `text` with the characters XML gives a meaning to replaced by entities.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            // Other control characters are not allowed at all, even as references.
+            c if c.is_control() && c != '\t' => escaped.push('\u{fffd}'),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Report {
+    /// The report as a Checkstyle document: every file that is not formatted or can't be formatted
+    /// is a violation, the errors hit while walking the tree are reported against their path.
+    /// Violations are on the first line, except for parse errors.
+    pub fn to_checkstyle(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<checkstyle version=\"4.3\">\n");
+        let mut violation = |path: &str, source: &str, message: &str, err: Option<&Error>| {
+            let position = match err.and_then(Error::location) {
+                Some(location) => {
+                    format!("line=\"{}\" column=\"{}\"", location.line, location.column)
+                }
+                None => "line=\"1\"".to_string(),
+            };
+            let _ = writeln!(
+                out,
+                "  <file name=\"{}\">\n    <error {} severity=\"error\" message=\"{}\" \
+                 source=\"prettythanks.{}\"/>\n  </file>",
+                escape(path),
+                position,
+                escape(message),
+                source
+            );
+        };
+        for entry in &self.entries {
+            match entry {
+                Entry::File(file) => match &file.result {
+                    Ok(outcome) if outcome.changed => {
+                        let message = "file is not formatted";
+                        violation(file.path.as_str(), "unformatted", message, None);
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        violation(file.path.as_str(), "error", &err.to_string(), Some(err));
+                    }
+                },
+                Entry::Error(err) => {
+                    let path = err.path().map_or("", |path| path.as_str());
+                    violation(path, "error", &err.to_string(), Some(err));
+                }
+                Entry::Ignored(_)
+                | Entry::Orphan(_)
+                | Entry::Revisited(_)
+                | Entry::Duplicate(_)
+                | Entry::NonUtf8(_) => {}
+            }
+        }
+        out.push_str("</checkstyle>\n");
+        out
+    }
+
+    /// The report as a `JUnit` document, every file being a test case failing if it is not
+    /// formatted or can't be formatted, and every error hit while walking the tree a failed one.
+    pub fn to_junit(&self) -> String {
+        let mut cases = String::new();
+        let (mut tests, mut failures, mut skipped) = (0, 0, 0);
+        let mut case = |name: &str, elapsed: Duration, body: Option<String>| {
+            let _ = write!(
+                cases,
+                "    <testcase name=\"{}\" classname=\"prettythanks\" time=\"{:.3}\"",
+                escape(name),
+                elapsed.as_secs_f64()
+            );
+            match body {
+                Some(body) => {
+                    let _ = writeln!(cases, ">\n      {}\n    </testcase>", body);
+                }
+                None => cases.push_str("/>\n"),
+            }
+        };
+        let failure = |kind: &str, message: &str, details: &str| {
+            format!(
+                "<failure type=\"{}\" message=\"{}\">{}</failure>",
+                kind,
+                escape(message),
+                escape(details)
+            )
+        };
+        let error_failure = |err: &Error| {
+            let kind = match err {
+                Error::Parse { .. } => "parse-error",
+                _ => "error",
+            };
+            failure(kind, &err.to_string(), "")
+        };
+        for entry in &self.entries {
+            let (name, elapsed, body) = match entry {
+                Entry::File(file) => match &file.result {
+                    Ok(outcome) if outcome.skipped => {
+                        skipped += 1;
+                        (&file.path, outcome.elapsed, Some("<skipped/>".to_string()))
+                    }
+                    Ok(outcome) if outcome.changed => {
+                        failures += 1;
+                        let message = "file is not formatted";
+                        let body = failure("unformatted", message, &outcome.output);
+                        (&file.path, outcome.elapsed, Some(body))
+                    }
+                    Ok(outcome) => (&file.path, outcome.elapsed, None),
+                    Err(err) => {
+                        failures += 1;
+                        (&file.path, Duration::ZERO, Some(error_failure(err)))
+                    }
+                },
+                Entry::Error(err) => {
+                    failures += 1;
+                    let name = err.path().map_or("", |path| path.as_str());
+                    tests += 1;
+                    case(name, Duration::ZERO, Some(error_failure(err)));
+                    continue;
+                }
+                Entry::Ignored(_)
+                | Entry::Orphan(_)
+                | Entry::Revisited(_)
+                | Entry::Duplicate(_)
+                | Entry::NonUtf8(_) => continue,
+            };
+            tests += 1;
+            case(name.as_str(), elapsed, body);
+        }
+        let attributes = format!(
+            "name=\"prettythanks\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" \
+             time=\"{:.3}\"",
+            tests,
+            failures,
+            skipped,
+            self.elapsed.as_secs_f64()
+        );
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites {attributes}>\n  \
+             <testsuite {attributes}>\n{}  </testsuite>\n</testsuites>\n",
+            cases,
+            attributes = attributes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Error, FileReport, Outcome, Timings};
+    use std::time::Duration;
+
+    #[test]
+    fn renders_checkstyle() {
+        let outcome = |changed| Outcome {
+            original: 10,
+            formatted: 10,
+            changed,
+            dropped_comments: 0,
+            comments_allowed: false,
+            skipped: false,
+            cached: false,
+            elapsed: Duration::ZERO,
+            timings: Timings::default(),
+            output: String::new(),
+            transcoded: None,
+            removed_lines: 0,
+            added_lines: 0,
+            skip_reason: None,
+        };
+        let report = Report {
+            entries: vec![
+                Entry::File(FileReport {
+                    path: "src/a&b.rs".into(),
+                    result: Ok(outcome(true)),
+                }),
+                Entry::File(FileReport {
+                    path: "src/ok.rs".into(),
+                    result: Ok(outcome(false)),
+                }),
+                Entry::Error(Error::NotRustPath("README.md".into())),
+            ],
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(
+            report.to_checkstyle(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="src/a&amp;b.rs">
+    <error line="1" severity="error" message="file is not formatted" source="prettythanks.unformatted"/>
+  </file>
+  <file name="README.md">
+    <error line="1" severity="error" message="path README.md is not a file, symlink or directory" source="prettythanks.error"/>
+  </file>
+</checkstyle>
+"#
+        );
+    }
+
+    #[test]
+    fn renders_junit() {
+        let parse = syn::parse_file("fn").err().unwrap();
+        let report = Report {
+            entries: vec![
+                Entry::File(FileReport {
+                    path: "src/a.rs".into(),
+                    result: Ok(Outcome {
+                        original: 10,
+                        formatted: 10,
+                        changed: true,
+                        dropped_comments: 0,
+                        comments_allowed: false,
+                        skipped: false,
+                        cached: false,
+                        elapsed: Duration::from_millis(2),
+                        timings: Timings::default(),
+                        output: "-fn  a(){}\n+fn a() {}\n".to_string(),
+                        transcoded: None,
+                        removed_lines: 0,
+                        added_lines: 0,
+                        skip_reason: None,
+                    }),
+                }),
+                Entry::File(FileReport {
+                    path: "src/bad.rs".into(),
+                    result: Err(Error::Parse {
+                        path: Some("src/bad.rs".into()),
+                        source: parse,
+                        location: None,
+                    }),
+                }),
+            ],
+            elapsed: Duration::from_millis(5),
+        };
+        assert_eq!(
+            report.to_junit(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="prettythanks" tests="2" failures="2" errors="0" skipped="0" time="0.005">
+  <testsuite name="prettythanks" tests="2" failures="2" errors="0" skipped="0" time="0.005">
+    <testcase name="src/a.rs" classname="prettythanks" time="0.002">
+      <failure type="unformatted" message="file is not formatted">-fn  a(){}&#10;+fn a() {}&#10;</failure>
+    </testcase>
+    <testcase name="src/bad.rs" classname="prettythanks" time="0.000">
+      <failure type="parse-error" message="failed to parse file src/bad.rs: unexpected end of input, expected identifier"></failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}